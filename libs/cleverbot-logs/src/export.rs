@@ -0,0 +1,98 @@
+use crate::{CleverBotLogEntry, Error};
+
+use chrono::SecondsFormat;
+
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+  /// One JSON object per line.
+  Jsonl,
+  /// Comma-separated values, with a header row.
+  Csv
+}
+
+impl ExportFormat {
+  pub const fn extension(self) -> &'static str {
+    match self {
+      ExportFormat::Jsonl => "jsonl",
+      ExportFormat::Csv => "csv"
+    }
+  }
+}
+
+impl FromStr for ExportFormat {
+  type Err = ParseExportFormatError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "jsonl" | "json" => Ok(ExportFormat::Jsonl),
+      "csv" => Ok(ExportFormat::Csv),
+      _ => Err(ParseExportFormatError)
+    }
+  }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown export format, expected one of 'jsonl' or 'csv'")]
+pub struct ParseExportFormatError;
+
+/// The exported form of an entry, with a human-readable time.
+#[derive(Debug, Serialize)]
+struct ExportEntry<'a> {
+  thread: u64,
+  user: Option<u64>,
+  time: String,
+  message: &'a str,
+  response: &'a str
+}
+
+impl<'a> From<&'a CleverBotLogEntry> for ExportEntry<'a> {
+  fn from(entry: &'a CleverBotLogEntry) -> Self {
+    ExportEntry {
+      thread: entry.thread,
+      user: entry.user,
+      time: entry.time.to_rfc3339_opts(SecondsFormat::Millis, true),
+      message: &entry.message,
+      response: &entry.response
+    }
+  }
+}
+
+/// Writes entries in the given format, returning how many were written.
+pub fn export_entries<W, I>(writer: W, format: ExportFormat, entries: I) -> Result<usize, Error>
+where W: Write, I: IntoIterator<Item = Result<CleverBotLogEntry, Error>> {
+  let mut writer = BufWriter::new(writer);
+  if format == ExportFormat::Csv {
+    writeln!(writer, "thread,user,time,message,response")?;
+  };
+
+  let mut count = 0;
+  for entry in entries {
+    let entry = entry?;
+    let entry = ExportEntry::from(&entry);
+    match format {
+      ExportFormat::Jsonl => {
+        serde_json::to_writer(&mut writer, &entry)?;
+        writeln!(writer)?;
+      },
+      ExportFormat::Csv => {
+        let user = entry.user.map_or_else(String::new, |user| user.to_string());
+        writeln!(writer, "{},{user},{},{},{}", entry.thread, entry.time, csv_escape(entry.message), csv_escape(entry.response))?;
+      }
+    };
+
+    count += 1;
+  };
+
+  writer.flush()?;
+  Ok(count)
+}
+
+fn csv_escape(field: &str) -> String {
+  match field.contains([',', '"', '\n', '\r']) {
+    true => format!("\"{}\"", field.replace('"', "\"\"")),
+    false => field.to_owned()
+  }
+}
@@ -0,0 +1,212 @@
+use crate::{CleverBotLogEntry, CleverBotLogger, Entries, Error, encode_entries};
+
+use chrono::Local;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use fs_err::{File, OpenOptions};
+
+use std::io::{self, BufRead, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::vec::IntoIter;
+
+/// The current log file, along with the compressed logs that have been rotated out of it.
+#[derive(Debug, Clone)]
+pub struct LogFiles {
+  current: PathBuf,
+  archive_dir: PathBuf
+}
+
+impl LogFiles {
+  pub fn new(current: impl Into<PathBuf>, archive_dir: impl Into<PathBuf>) -> Self {
+    LogFiles { current: current.into(), archive_dir: archive_dir.into() }
+  }
+
+  pub fn current(&self) -> &Path {
+    &self.current
+  }
+
+  pub fn archive_dir(&self) -> &Path {
+    &self.archive_dir
+  }
+
+  /// Opens the current log file for writing, first moving its contents into a compressed archive
+  /// if it has reached `size_limit` bytes. Returns the logger and the path of the new archive, if one was made.
+  pub fn create_or_rotate(&self, size_limit: u64) -> io::Result<(CleverBotLogger, Option<PathBuf>)> {
+    let logger = CleverBotLogger::create(&self.current)?;
+    if logger.file.metadata()?.len() < size_limit {
+      return Ok((logger, None));
+    };
+
+    fs_err::create_dir_all(&self.archive_dir)?;
+    let now = Local::now().format("%Y-%m-%d-%H-%M-%S");
+    let out_path = self.archive_dir.join(format!("{now}.log.gz"));
+
+    // copy the contents of the current log to the archive, with compression
+    let mut reader = BufReader::new(OpenOptions::new().read(true).write(true).open(&self.current)?);
+    let mut writer = GzEncoder::new(BufWriter::new(File::create_new(&out_path)?), Compression::new(6));
+    io::copy(&mut reader, &mut writer)?;
+    writer.finish()?;
+
+    // clear the contents of the current log
+    reader.get_ref().set_len(0)?;
+
+    Ok((logger, Some(out_path)))
+  }
+
+  /// Every log file, oldest first, ending with the current log.
+  pub fn paths(&self) -> io::Result<Vec<PathBuf>> {
+    let mut archives = match fs_err::read_dir(&self.archive_dir) {
+      Ok(read_dir) => read_dir
+        .map(|entry| entry.map(|entry| entry.path()))
+        .filter(|path| path.as_ref().map_or(true, |path| path.to_string_lossy().ends_with(".log.gz")))
+        .collect::<io::Result<Vec<PathBuf>>>()?,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+      Err(err) => return Err(err)
+    };
+
+    // archive names are timestamps, so they sort chronologically
+    archives.sort();
+    if self.current.exists() {
+      archives.push(self.current.clone());
+    };
+
+    Ok(archives)
+  }
+
+  /// Removes every entry for which `keep` returns false from every log file, returning how many were removed.
+  ///
+  /// Log files are rewritten to a temporary file first, so that none are ever left half-written.
+  /// This replaces the current log, so any [`CleverBotLogger`] appending to it must be reopened afterwards.
+  pub fn retain<F>(&self, mut keep: F) -> Result<usize, Error>
+  where F: FnMut(&CleverBotLogEntry) -> bool {
+    let mut removed = 0;
+    for path in self.paths()? {
+      let entries = LogFilesEntries::open(&path)?.collect::<Result<Vec<CleverBotLogEntry>, Error>>()?;
+      let total = entries.len();
+      let entries = entries.into_iter().filter(|entry| keep(entry)).collect::<Vec<CleverBotLogEntry>>();
+      if entries.len() == total { continue };
+      removed += total - entries.len();
+
+      let temp_path = temp_path(&path);
+      if path == self.current {
+        encode_entries(File::create(&temp_path)?, &entries)?;
+        fs_err::rename(&temp_path, &path)?;
+      } else if entries.is_empty() {
+        fs_err::remove_file(&path)?;
+      } else {
+        let mut writer = GzEncoder::new(File::create(&temp_path)?, Compression::new(6));
+        encode_entries(&mut writer, &entries)?;
+        writer.finish()?;
+        fs_err::rename(&temp_path, &path)?;
+      };
+    };
+
+    Ok(removed)
+  }
+
+  /// Streams every entry from every log file, oldest first.
+  pub fn entries(&self) -> io::Result<LogFilesEntries> {
+    Ok(LogFilesEntries { paths: self.paths()?.into_iter(), entries: None })
+  }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+  let mut temp_path = path.as_os_str().to_owned();
+  temp_path.push(".tmp");
+  PathBuf::from(temp_path)
+}
+
+/// Streams entries from a series of log files, see [`LogFiles::entries`].
+pub struct LogFilesEntries {
+  paths: IntoIter<PathBuf>,
+  entries: Option<Entries<Box<dyn BufRead + Send>>>
+}
+
+impl LogFilesEntries {
+  fn open(path: &Path) -> io::Result<Entries<Box<dyn BufRead + Send>>> {
+    let file = File::open(path)?;
+    let reader: Box<dyn BufRead + Send> = match path.to_string_lossy().ends_with(".gz") {
+      true => Box::new(BufReader::new(GzDecoder::new(file))),
+      false => Box::new(BufReader::new(file))
+    };
+
+    Ok(Entries::new(reader))
+  }
+}
+
+impl Iterator for LogFilesEntries {
+  type Item = Result<CleverBotLogEntry, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      if let Some(entry) = self.entries.as_mut().and_then(Iterator::next) {
+        return Some(entry);
+      };
+
+      let path = self.paths.next()?;
+      match LogFilesEntries::open(&path) {
+        Ok(entries) => self.entries = Some(entries),
+        Err(err) => return Some(Err(Error::Io(err)))
+      };
+    }
+  }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::DateTime;
+
+  fn entry(time: i64) -> CleverBotLogEntry {
+    CleverBotLogEntry {
+      thread: 0,
+      user: Some(time as u64 % 2),
+      time: DateTime::from_timestamp_millis(time).unwrap(),
+      message: format!("message {time}"),
+      response: format!("response {time}")
+    }
+  }
+
+  fn times(log_files: &LogFiles) -> Vec<i64> {
+    log_files.entries().unwrap()
+      .map(|entry| entry.unwrap().time.timestamp_millis())
+      .collect()
+  }
+
+  #[test]
+  fn retain_rewrites_current_and_archived_logs() {
+    let dir = std::env::temp_dir().join(format!("cleverbot-logs-retain-{}", std::process::id()));
+    let _ = fs_err::remove_dir_all(&dir);
+    fs_err::create_dir_all(&dir).unwrap();
+    let log_files = LogFiles::new(dir.join("current.log"), dir.join("archive"));
+
+    let (logger, _) = log_files.create_or_rotate(u64::MAX).unwrap();
+    for time in 1..=4 { logger.log(&entry(time)).unwrap() };
+    std::mem::drop(logger);
+
+    // moves the first four entries into an archive
+    let (logger, archive) = log_files.create_or_rotate(0).unwrap();
+    assert!(archive.is_some());
+    for time in 5..=8 { logger.log(&entry(time)).unwrap() };
+    std::mem::drop(logger);
+
+    let removed = log_files.retain(|entry| entry.user == Some(0)).unwrap();
+    assert_eq!(removed, 4);
+    assert_eq!(times(&log_files), [2, 4, 6, 8]);
+
+    // the current log has been replaced, so it is reopened before logging to it again
+    let logger = CleverBotLogger::open(log_files.current()).unwrap();
+    logger.log(&entry(10)).unwrap();
+    assert_eq!(times(&log_files), [2, 4, 6, 8, 10]);
+
+    assert_eq!(log_files.retain(|entry| entry.time.timestamp_millis() > 4).unwrap(), 2);
+    assert_eq!(times(&log_files), [6, 8, 10]);
+    assert_eq!(log_files.paths().unwrap(), [log_files.current()]);
+    assert!(!temp_path(log_files.current()).exists());
+
+    fs_err::remove_dir_all(&dir).unwrap();
+  }
+}
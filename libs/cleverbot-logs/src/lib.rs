@@ -1,11 +1,21 @@
 extern crate base64;
 pub extern crate chrono;
 extern crate ciborium;
+extern crate flate2;
 #[macro_use]
 extern crate serde;
+extern crate serde_json;
 #[macro_use]
 extern crate thiserror;
 
+mod export;
+mod files;
+mod reader;
+
+pub use crate::export::{ExportFormat, ParseExportFormatError, export_entries};
+pub use crate::files::{LogFiles, LogFilesEntries};
+pub use crate::reader::{Entries, EntryFilter};
+
 use base64::engine::general_purpose::STANDARD;
 use chrono::{DateTime, Utc};
 
@@ -46,12 +56,17 @@ pub enum Error {
   #[error(transparent)]
   Deserialize(#[from] ciborium::de::Error<io::Error>),
   #[error(transparent)]
-  Serialize(#[from] ciborium::ser::Error<io::Error>)
+  Serialize(#[from] ciborium::ser::Error<io::Error>),
+  #[error(transparent)]
+  Json(#[from] serde_json::Error)
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CleverBotLogEntry {
   pub thread: u64,
+  /// The user who sent the message, which older entries did not record.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub user: Option<u64>,
   #[serde(with = "chrono::serde::ts_milliseconds")]
   pub time: DateTime<Utc>,
   pub message: String,
@@ -86,8 +101,5 @@ pub fn decode_entries_buffered<R: Read>(reader: R) -> Result<Vec<CleverBotLogEnt
 }
 
 pub fn decode_entries<R: BufRead>(reader: R) -> Result<Vec<CleverBotLogEntry>, Error> {
-  reader.lines()
-    .map(|result| result.map_err(Error::Io))
-    .map(|result| result.and_then(|string| CleverBotLogEntry::decode(string.as_bytes())))
-    .collect::<Result<Vec<CleverBotLogEntry>, Error>>()
+  Entries::new(reader).collect::<Result<Vec<CleverBotLogEntry>, Error>>()
 }
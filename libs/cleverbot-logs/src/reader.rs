@@ -0,0 +1,74 @@
+use crate::{CleverBotLogEntry, Error};
+
+use chrono::{DateTime, Utc};
+
+use std::io::{BufRead, Lines};
+
+/// Decodes log entries one line at a time, without reading the whole log into memory.
+#[derive(Debug)]
+pub struct Entries<R> {
+  lines: Lines<R>
+}
+
+impl<R: BufRead> Entries<R> {
+  pub fn new(reader: R) -> Self {
+    Entries { lines: reader.lines() }
+  }
+}
+
+impl<R: BufRead> Iterator for Entries<R> {
+  type Item = Result<CleverBotLogEntry, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    // blank lines are skipped, such as one left behind by an interrupted write
+    let line = self.lines.by_ref().find(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))?;
+    Some(line.map_err(Error::Io).and_then(|line| CleverBotLogEntry::decode(line.as_bytes())))
+  }
+}
+
+/// Selects log entries by thread, time range, and text.
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilter {
+  pub thread: Option<u64>,
+  /// Only entries logged at or after this time are selected.
+  pub since: Option<DateTime<Utc>>,
+  /// Only entries logged before this time are selected.
+  pub until: Option<DateTime<Utc>>,
+  /// Only entries whose message or response contain this text (ignoring case) are selected.
+  pub contains: Option<String>
+}
+
+impl EntryFilter {
+  pub fn new() -> Self {
+    EntryFilter::default()
+  }
+
+  pub fn thread(mut self, thread: u64) -> Self {
+    self.thread = Some(thread);
+    self
+  }
+
+  pub fn since(mut self, since: DateTime<Utc>) -> Self {
+    self.since = Some(since);
+    self
+  }
+
+  pub fn until(mut self, until: DateTime<Utc>) -> Self {
+    self.until = Some(until);
+    self
+  }
+
+  pub fn contains(mut self, contains: impl Into<String>) -> Self {
+    self.contains = Some(contains.into().to_lowercase());
+    self
+  }
+
+  pub fn matches(&self, entry: &CleverBotLogEntry) -> bool {
+    self.thread.is_none_or(|thread| entry.thread == thread)
+      && self.since.is_none_or(|since| entry.time >= since)
+      && self.until.is_none_or(|until| entry.time < until)
+      && self.contains.as_deref().is_none_or(|contains| {
+        entry.message.to_lowercase().contains(contains) || entry.response.to_lowercase().contains(contains)
+      })
+  }
+}
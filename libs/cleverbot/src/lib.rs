@@ -4,17 +4,26 @@ extern crate md5;
 extern crate percent_encoding;
 extern crate reqwest;
 #[macro_use]
+extern crate serde;
+#[macro_use]
 extern crate thiserror;
 
+mod pool;
+
+pub use crate::pool::{CleverBotAgentPool, EgressHealth};
+
 use cacheable::{CacheAsync, CacheableAsync, async_trait};
 use chrono::{DateTime, Utc};
 use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_encode};
 use reqwest::{Client, Proxy, Error as ReqwestError, StatusCode};
 
+use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::fmt;
 
-const USER_AGENT: &str = "Opera/9.48 (Windows NT 6.0; sl-SI) Presto/2.11.249 Version/10.00";
+/// The website that requests are sent to, unless an agent is given a different one.
+pub const BASE_URL: &str = "https://www.cleverbot.com";
+pub const USER_AGENT: &str = "Opera/9.48 (Windows NT 6.0; sl-SI) Presto/2.11.249 Version/10.00";
 
 
 
@@ -22,7 +31,8 @@ const USER_AGENT: &str = "Opera/9.48 (Windows NT 6.0; sl-SI) Presto/2.11.249 Ver
 /// A single instance of this should be treated as if it were a single instance of your browser.
 pub struct CleverBotAgent {
   data: CacheAsync<CleverBotData>,
-  client: Client
+  client: Client,
+  base_url: Cow<'static, str>
 }
 
 impl CleverBotAgent {
@@ -33,6 +43,13 @@ impl CleverBotAgent {
     Self::with_client(client)
   }
 
+  pub fn with_user_agent(user_agent: impl AsRef<str>) -> Self {
+    let client = Client::builder()
+      .user_agent(user_agent.as_ref())
+      .build().unwrap();
+    Self::with_client(client)
+  }
+
   pub fn with_proxy(proxy: Proxy, user_agent: impl AsRef<str>) -> Self {
     let client = Client::builder()
       .user_agent(user_agent.as_ref())
@@ -42,12 +59,24 @@ impl CleverBotAgent {
   }
 
   pub const fn with_client(client: Client) -> Self {
-    CleverBotAgent { data: CacheAsync::new(), client }
+    CleverBotAgent { data: CacheAsync::new(), client, base_url: Cow::Borrowed(BASE_URL) }
+  }
+
+  /// Sends requests to a different website than [`BASE_URL`], such as a local stand-in for testing.
+  pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+    self.set_base_url(base_url.into());
+    self
   }
 
-  async fn get(&mut self) -> Result<(&mut CleverBotData, &Client), Error> {
-    self.data.try_get_or_init((&self.client, Utc::now()))
-      .await.map(|data| (data, &self.client))
+  pub(crate) fn set_base_url(&mut self, base_url: String) {
+    self.base_url = Cow::Owned(base_url.trim_end_matches('/').to_owned());
+    // anything cached came from the previous website
+    self.data = CacheAsync::new();
+  }
+
+  async fn get(&mut self) -> Result<(&mut CleverBotData, &Client, &str), Error> {
+    self.data.try_get_or_init((&self.client, &self.base_url, Utc::now()))
+      .await.map(|data| (data, &self.client, &*self.base_url))
   }
 }
 
@@ -61,6 +90,7 @@ impl fmt::Debug for CleverBotAgent {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     f.debug_struct("CleverBotAgent")
       .field("data", &self.data)
+      .field("base_url", &self.base_url)
       .finish_non_exhaustive()
   }
 }
@@ -79,7 +109,7 @@ impl CleverBotPreviousData {
     let mut contents = response.split('\r');
     let reply = contents.next()?.to_owned();
     let cbsid = contents.next()?.to_owned();
-    let xai = format!("{},{}", &cbsid[0..3], contents.next()?);
+    let xai = format!("{},{}", cbsid.get(0..3)?, contents.next()?);
     Some(CleverBotPreviousData { cbsid, xai, last_reply: reply })
   }
 }
@@ -108,9 +138,9 @@ impl CleverBotData {
     now.signed_duration_since(self.last_update).num_milliseconds() > self.max_age
   }
 
-  async fn request(client: &Client, now: DateTime<Utc>) -> Result<Self, Error> {
+  async fn request(client: &Client, base_url: &str, now: DateTime<Utc>) -> Result<Self, Error> {
     let date = now.format("%Y%m%d");
-    let url = format!("https://www.cleverbot.com/extras/conversation-social-min.js?{date}");
+    let url = format!("{base_url}/extras/conversation-social-min.js?{date}");
     let response = client.get(url).send().await?;
     let cookie = response.headers().get("set-cookie")
       .and_then(|value| value.to_str().ok())
@@ -122,14 +152,14 @@ impl CleverBotData {
 
 #[async_trait]
 impl CacheableAsync for CleverBotData {
-  type Args<'a> = (&'a Client, DateTime<Utc>);
+  type Args<'a> = (&'a Client, &'a str, DateTime<Utc>);
   type Error = Error;
 
-  async fn operation((client, now): Self::Args<'_>) -> Result<Self, Self::Error> {
-    Self::request(client, now).await
+  async fn operation((client, base_url, now): Self::Args<'_>) -> Result<Self, Self::Error> {
+    Self::request(client, base_url, now).await
   }
 
-  fn is_invalid(&self, &(_, now): &Self::Args<'_>) -> bool {
+  fn is_invalid(&self, &(_, _, now): &Self::Args<'_>) -> bool {
     self.is_expired(now)
   }
 }
@@ -139,7 +169,7 @@ fn parse_max_age(max_age: &str) -> Option<i64> {
 }
 
 /// Saves the user's conversation history for convenience.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CleverBotContext {
   pub history: VecDeque<String>,
   pub history_size_limit: usize
@@ -176,18 +206,18 @@ impl Default for CleverBotContext {
 }
 
 pub async fn send(agent: &mut CleverBotAgent, history: &[String], message: &str) -> Result<String, Error> {
-  let (agent_data, agent_client) = agent.get().await?;
+  let (agent_data, agent_client, base_url) = agent.get().await?;
 
   let mut payload = String::new();
   payload.push_str(&format!("stimulus={}&", escape(message.as_bytes())));
   for (i, history_message) in history.iter().rev().enumerate() {
-    payload.push_str(&format!("vText{}={}", i + 2, escape(history_message.as_bytes())))
+    payload.push_str(&format!("vText{}={}&", i + 2, escape(history_message.as_bytes())))
   };
 
   payload.push_str("cb_settings_scripting=no&islearning=1&icognoid=wsf&icognocheck=");
   payload.push_str(&format!("{:x}", md5::compute(&payload[7..33])));
 
-  let mut url = "https://www.cleverbot.com/webservicemin?uc=UseOfficialCleverbotAPI".to_owned();
+  let mut url = format!("{base_url}/webservicemin?uc=UseOfficialCleverbotAPI");
   if let Some(CleverBotPreviousData { cbsid, xai, last_reply }) = &agent_data.previous {
     let last_reply = encode_uri_component(last_reply.as_bytes());
     let stimulus = encode_uri_component(message.as_bytes());
@@ -0,0 +1,146 @@
+use crate::{CleverBotAgent, CleverBotContext, Error, USER_AGENT};
+
+use reqwest::Proxy;
+
+/// A set of agents, each sending requests through a different proxy (or none),
+/// so that replies keep working when one of them is blocked.
+///
+/// Each proxy has a health score, which rises when requests through it succeed and falls when they fail.
+/// When a request fails, the failing agent is recreated with the next user-agent in the pool,
+/// and the request is retried through the healthiest proxy that has not been tried yet.
+#[derive(Debug)]
+pub struct CleverBotAgentPool {
+  egresses: Vec<Egress>,
+  user_agents: Vec<String>,
+  next_user_agent: usize,
+  current: usize,
+  base_url: Option<String>
+}
+
+impl CleverBotAgentPool {
+  /// How much of an egress's health is replaced by the outcome of each request.
+  const HEALTH_WEIGHT: f64 = 0.25;
+
+  /// Creates a pool from a list of proxy URLs, where `direct` means no proxy.
+  /// An empty list of proxies only connects directly, and an empty list of user-agents uses [`USER_AGENT`].
+  pub fn new<P, U>(proxies: P, user_agents: U) -> Result<Self, Error>
+  where P: IntoIterator<Item = String>, U: IntoIterator<Item = String> {
+    let mut user_agents = user_agents.into_iter().collect::<Vec<String>>();
+    if user_agents.is_empty() {
+      user_agents.push(USER_AGENT.to_owned());
+    };
+
+    let mut egresses = proxies.into_iter()
+      .map(|name| Egress::new(name, &user_agents[0]))
+      .collect::<Result<Vec<Egress>, Error>>()?;
+    if egresses.is_empty() {
+      egresses.push(Egress::new(Egress::DIRECT.to_owned(), &user_agents[0])?);
+    };
+
+    Ok(CleverBotAgentPool { egresses, user_agents, next_user_agent: 1, current: 0, base_url: None })
+  }
+
+  /// Sends requests to a different website, see [`CleverBotAgent::with_base_url`].
+  pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+    let base_url = base_url.into();
+    for egress in self.egresses.iter_mut() {
+      egress.agent.set_base_url(base_url.clone());
+    };
+
+    self.base_url = Some(base_url);
+    self
+  }
+
+  /// Sends a message within the given context, trying each egress at most once.
+  /// Returns the error from the last egress that was tried if every one of them failed.
+  pub async fn send(&mut self, context: &mut CleverBotContext, message: &str) -> Result<String, Error> {
+    let mut tried = vec![false; self.egresses.len()];
+    loop {
+      tried[self.current] = true;
+      let egress = &mut self.egresses[self.current];
+      match context.send(&mut egress.agent, message).await {
+        Ok(reply) => {
+          egress.record(true);
+          return Ok(reply);
+        },
+        Err(error) => {
+          egress.record(false);
+          let user_agent = self.rotate_user_agent().to_owned();
+          let egress = &mut self.egresses[self.current];
+          egress.agent = Egress::create_agent(egress.proxy.as_ref(), &user_agent);
+          if let Some(base_url) = &self.base_url {
+            egress.agent.set_base_url(base_url.clone());
+          };
+
+          match self.healthiest(|index| !tried[index]) {
+            Some(next) => self.current = next,
+            None => {
+              self.current = self.healthiest(|_| true).unwrap_or(0);
+              return Err(error);
+            }
+          };
+        }
+      };
+    };
+  }
+
+  /// The health of each egress, from 0 (always failing) to 1 (always succeeding).
+  pub fn health(&self) -> impl Iterator<Item = EgressHealth<'_>> {
+    self.egresses.iter().map(|egress| EgressHealth { name: &egress.name, health: egress.health })
+  }
+
+  fn rotate_user_agent(&mut self) -> &str {
+    let user_agent = &self.user_agents[self.next_user_agent % self.user_agents.len()];
+    self.next_user_agent = (self.next_user_agent + 1) % self.user_agents.len();
+    user_agent
+  }
+
+  /// Finds the healthiest egress matching the predicate, preferring the earliest one when tied.
+  fn healthiest(&self, predicate: impl Fn(usize) -> bool) -> Option<usize> {
+    (0..self.egresses.len())
+      .filter(|&index| predicate(index))
+      .rev().max_by(|&a, &b| self.egresses[a].health.total_cmp(&self.egresses[b].health))
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EgressHealth<'a> {
+  /// The proxy URL, or `direct`.
+  pub name: &'a str,
+  pub health: f64
+}
+
+#[derive(Debug)]
+struct Egress {
+  name: String,
+  proxy: Option<Proxy>,
+  agent: CleverBotAgent,
+  health: f64
+}
+
+impl Egress {
+  const DIRECT: &'static str = "direct";
+
+  fn new(name: String, user_agent: &str) -> Result<Self, Error> {
+    let proxy = match name.as_str() {
+      Self::DIRECT => None,
+      url => Some(Proxy::all(url)?)
+    };
+
+    let agent = Self::create_agent(proxy.as_ref(), user_agent);
+    Ok(Egress { name, proxy, agent, health: 1.0 })
+  }
+
+  fn create_agent(proxy: Option<&Proxy>, user_agent: &str) -> CleverBotAgent {
+    match proxy.cloned() {
+      Some(proxy) => CleverBotAgent::with_proxy(proxy, user_agent),
+      None => CleverBotAgent::with_user_agent(user_agent)
+    }
+  }
+
+  fn record(&mut self, success: bool) {
+    let outcome = if success { 1.0 } else { 0.0 };
+    let weight = CleverBotAgentPool::HEALTH_WEIGHT;
+    self.health = self.health * (1.0 - weight) + outcome * weight;
+  }
+}
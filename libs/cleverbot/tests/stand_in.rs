@@ -0,0 +1,247 @@
+//! Exercises the agent end to end against a local stand-in for the CleverBot website,
+//! which reproduces its cookie handshake and response format.
+
+extern crate cleverbot;
+extern crate tokio;
+
+use cleverbot::{CleverBotAgent, CleverBotAgentPool, CleverBotContext, Error};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const CBSID: &str = "CBS0123456";
+const XAI: &str = "XAI9";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Behavior {
+  Normal,
+  /// The cookie endpoint doesn't set a cookie.
+  NoCookie,
+  /// Replies can't be parsed.
+  Garbled,
+  /// Replies fail with the given status code.
+  Status(u16)
+}
+
+#[derive(Debug, Clone)]
+struct Request {
+  method: String,
+  target: String,
+  cookie: Option<String>,
+  body: String
+}
+
+#[derive(Debug)]
+struct State {
+  behavior: Behavior,
+  /// Sent as the cookie's `Max-Age`, which the agent treats as milliseconds.
+  max_age: i64,
+  cookies_issued: usize,
+  requests: Vec<Request>
+}
+
+struct StandIn {
+  base_url: String,
+  state: Arc<Mutex<State>>,
+  task: JoinHandle<()>
+}
+
+impl StandIn {
+  async fn start(behavior: Behavior, max_age: i64) -> Self {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let state = Arc::new(Mutex::new(State { behavior, max_age, cookies_issued: 0, requests: Vec::new() }));
+    let task = tokio::spawn({
+      let state = state.clone();
+      async move {
+        loop {
+          let (stream, _) = listener.accept().await.unwrap();
+          tokio::spawn(serve(stream, state.clone()));
+        };
+      }
+    });
+
+    StandIn { base_url, state, task }
+  }
+
+  fn agent(&self) -> CleverBotAgent {
+    CleverBotAgent::new().with_base_url(&self.base_url)
+  }
+
+  fn cookies_issued(&self) -> usize {
+    self.state.lock().unwrap().cookies_issued
+  }
+
+  /// Requests for replies, ignoring requests for cookies.
+  fn replies_requested(&self) -> Vec<Request> {
+    self.state.lock().unwrap().requests.iter()
+      .filter(|request| request.method == "POST")
+      .cloned().collect()
+  }
+}
+
+impl Drop for StandIn {
+  fn drop(&mut self) {
+    self.task.abort();
+  }
+}
+
+async fn serve(mut stream: TcpStream, state: Arc<Mutex<State>>) {
+  let mut buffer = Vec::new();
+  let header_end = loop {
+    let mut chunk = [0; 1024];
+    let len = stream.read(&mut chunk).await.unwrap();
+    if len == 0 { return };
+    buffer.extend_from_slice(&chunk[..len]);
+    if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+      break position + 4;
+    };
+  };
+
+  let head = String::from_utf8_lossy(&buffer[..header_end]).into_owned();
+  let mut lines = head.split("\r\n");
+  let mut request_line = lines.next().unwrap().split(' ');
+  let method = request_line.next().unwrap().to_owned();
+  let target = request_line.next().unwrap().to_owned();
+  let headers = lines.filter_map(|line| line.split_once(": "))
+    .map(|(name, value)| (name.to_ascii_lowercase(), value.to_owned()))
+    .collect::<Vec<(String, String)>>();
+  let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, value)| value.clone());
+
+  let content_length = header("content-length").map_or(0, |value| value.parse::<usize>().unwrap());
+  while buffer.len() < header_end + content_length {
+    let mut chunk = [0; 1024];
+    let len = stream.read(&mut chunk).await.unwrap();
+    if len == 0 { break };
+    buffer.extend_from_slice(&chunk[..len]);
+  };
+
+  let body = String::from_utf8_lossy(&buffer[header_end..]).into_owned();
+  let request = Request { method, target, cookie: header("cookie"), body };
+  let response = respond(&mut state.lock().unwrap(), request);
+  stream.write_all(response.as_bytes()).await.unwrap();
+  stream.shutdown().await.ok();
+}
+
+fn respond(state: &mut State, request: Request) -> String {
+  let path = request.target.split('?').next().unwrap().to_owned();
+  let response = match (request.method.as_str(), path.as_str(), state.behavior) {
+    ("GET", "/extras/conversation-social-min.js", Behavior::NoCookie) => http(200, None, ""),
+    ("GET", "/extras/conversation-social-min.js", _) => {
+      state.cookies_issued += 1;
+      let cookie = format!("XVIS=TE{};path=/;Max-Age={}", state.cookies_issued, state.max_age);
+      http(200, Some(&cookie), "")
+    },
+    ("POST", "/webservicemin", Behavior::Status(status)) => http(status, None, "unavailable"),
+    ("POST", "/webservicemin", Behavior::Garbled) => http(200, None, "garbled"),
+    ("POST", "/webservicemin", _) => {
+      let number = state.requests.iter().filter(|request| request.method == "POST").count() + 1;
+      http(200, None, &format!("Reply {number}\r{CBSID}\r{XAI}"))
+    },
+    _ => http(404, None, "")
+  };
+
+  state.requests.push(request);
+  response
+}
+
+fn http(status: u16, cookie: Option<&str>, body: &str) -> String {
+  let cookie = cookie.map_or(String::new(), |cookie| format!("Set-Cookie: {cookie}\r\n"));
+  format!("HTTP/1.1 {status} Stand-In\r\n{cookie}Content-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())
+}
+
+#[tokio::test]
+async fn handshake_and_conversation() {
+  let stand_in = StandIn::start(Behavior::Normal, 86400000).await;
+  let mut agent = stand_in.agent();
+  let mut context = CleverBotContext::new();
+
+  assert_eq!(context.send(&mut agent, "Hello there").await.unwrap(), "Reply 1");
+  assert_eq!(context.send(&mut agent, "How are you?").await.unwrap(), "Reply 2");
+  assert_eq!(context.history, ["Hello there", "Reply 1", "How are you?", "Reply 2"]);
+  assert_eq!(stand_in.cookies_issued(), 1);
+
+  let requests = stand_in.replies_requested();
+  assert_eq!(requests.len(), 2);
+  for request in &requests {
+    assert_eq!(request.cookie.as_deref(), Some("XVIS=TE1; _cbsid=-1"));
+  };
+
+  // the first request has no previous exchange to refer back to
+  assert_eq!(requests[0].body, concat!(
+    "stimulus=Hello%20there&",
+    "cb_settings_scripting=no&islearning=1&icognoid=wsf&icognocheck=3b3806c832920be19308d5661cf7cc96"
+  ));
+  assert!(!requests[0].target.contains("cbsid="));
+
+  // the second refers back to the first reply, and includes the history, most recent first
+  assert_eq!(requests[1].body, concat!(
+    "stimulus=How%20are%20you%3F&vText2=Reply%201&vText3=Hello%20there&",
+    "cb_settings_scripting=no&islearning=1&icognoid=wsf&icognocheck=9310033884b8332ac4f0dea342691ad8"
+  ));
+  assert!(requests[1].target.contains("&out=Reply%201&in=How%20are%20you%3F&"));
+  assert!(requests[1].target.contains(&format!("&cbsid={CBSID}&xai={},{XAI}&", &CBSID[..3])));
+}
+
+#[tokio::test]
+async fn cookie_is_refreshed_after_max_age() {
+  let stand_in = StandIn::start(Behavior::Normal, 1).await;
+  let mut agent = stand_in.agent();
+  let mut context = CleverBotContext::new();
+
+  context.send(&mut agent, "first").await.unwrap();
+  tokio::time::sleep(Duration::from_millis(20)).await;
+  context.send(&mut agent, "second").await.unwrap();
+
+  assert_eq!(stand_in.cookies_issued(), 2);
+  let requests = stand_in.replies_requested();
+  assert_eq!(requests[1].cookie.as_deref(), Some("XVIS=TE2; _cbsid=-1"));
+  // a fresh cookie starts a fresh session on the website's end
+  assert!(!requests[1].target.contains("cbsid="));
+}
+
+#[tokio::test]
+async fn missing_cookie() {
+  let stand_in = StandIn::start(Behavior::NoCookie, 86400000).await;
+  let result = CleverBotContext::new().send(&mut stand_in.agent(), "hello").await;
+  assert!(matches!(result, Err(Error::MissingCookie)), "{result:?}");
+  assert!(stand_in.replies_requested().is_empty());
+}
+
+#[tokio::test]
+async fn invalid_response() {
+  let stand_in = StandIn::start(Behavior::Garbled, 86400000).await;
+  let mut context = CleverBotContext::new();
+  let result = context.send(&mut stand_in.agent(), "hello").await;
+  assert!(matches!(&result, Err(Error::InvalidResponse(response)) if response == "garbled"), "{result:?}");
+  // failed exchanges are not added to the history
+  assert!(context.history.is_empty());
+}
+
+#[tokio::test]
+async fn response_error() {
+  let stand_in = StandIn::start(Behavior::Status(503), 86400000).await;
+  let result = CleverBotContext::new().send(&mut stand_in.agent(), "hello").await;
+  assert!(matches!(&result, Err(Error::ResponseError(status, _)) if status.as_u16() == 503), "{result:?}");
+}
+
+#[tokio::test]
+async fn pool_fails_over_to_a_working_proxy() {
+  let stand_in = StandIn::start(Behavior::Normal, 86400000).await;
+  // nothing is listening on this port once the listener is dropped
+  let unreachable = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+  let proxies = [format!("http://{unreachable}"), "direct".to_owned()];
+  let mut pool = CleverBotAgentPool::new(proxies, Vec::new()).unwrap()
+    .with_base_url(&stand_in.base_url);
+  let mut context = CleverBotContext::new();
+
+  assert_eq!(pool.send(&mut context, "hello").await.unwrap(), "Reply 1");
+  assert_eq!(pool.send(&mut context, "again").await.unwrap(), "Reply 2");
+
+  let health = pool.health().map(|egress| (egress.name.to_owned(), egress.health)).collect::<Vec<_>>();
+  assert!(health[0].1 < 1.0, "{health:?}");
+  assert_eq!(health[1], ("direct".to_owned(), 1.0));
+}
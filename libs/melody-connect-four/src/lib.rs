@@ -115,6 +115,20 @@ impl<I: Copy + Eq + Ord + Hash> Manager<I> {
     }
   }
 
+  /// Forgets everything about a player: their stats, their challenges, and any game they are playing (without recording a result).
+  /// Returns whether there was anything to forget.
+  pub fn remove_player(&mut self, player: I) -> bool {
+    let mut removed = self.stats.remove(&player).is_some();
+    removed |= self.challenges.remove(&player).is_some_and(|challenges| !challenges.is_empty());
+    for challenges in self.challenges.values_mut() {
+      removed |= challenges.remove(&player);
+    };
+
+    let games = self.user_games.len();
+    self.user_games.retain(|players, _| !players.contains(&player));
+    removed | (self.user_games.len() != games)
+  }
+
   /// Ends the game without a winner or a loser.
   pub fn end_user_game_draw(&mut self, players: impl Into<UOrd2<I>>) -> Option<UserGame<I>> {
     self.user_games.remove(&players.into().map(|v| v))
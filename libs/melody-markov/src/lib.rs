@@ -0,0 +1,192 @@
+pub extern crate rand;
+#[macro_use]
+extern crate serde;
+
+use rand::Rng;
+
+use std::collections::HashMap;
+use std::iter::{once, repeat_n};
+
+
+
+/// The order used by [`MarkovChain::default`].
+pub const DEFAULT_ORDER: usize = 2;
+
+/// A word-level Markov chain, which generates text resembling whatever it has been trained on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarkovChain {
+  order: usize,
+  /// Maps each state (the last `order` tokens) to the tokens that have followed it, and how many times.
+  transitions: HashMap<Vec<Token>, HashMap<Token, u32>>
+}
+
+impl MarkovChain {
+  /// Creates an empty chain, where each word is chosen based on the `order` words before it.
+  ///
+  /// # Panics
+  /// Panics if `order` is zero.
+  pub fn new(order: usize) -> Self {
+    assert!(order > 0, "markov chain order must be non-zero");
+    MarkovChain { order, transitions: HashMap::new() }
+  }
+
+  pub fn order(&self) -> usize {
+    self.order
+  }
+
+  /// The number of distinct states that this chain has learned.
+  pub fn len(&self) -> usize {
+    self.transitions.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.transitions.is_empty()
+  }
+
+  /// Learns from a single piece of text, such as one message.
+  pub fn train(&mut self, text: &str) {
+    for (state, next) in self.transitions_of(text) {
+      *self.transitions.entry(state).or_default().entry(next).or_insert(0) += 1;
+    };
+  }
+
+  /// Reverses [`MarkovChain::train`] for a piece of text it was previously trained on.
+  pub fn untrain(&mut self, text: &str) {
+    for (state, next) in self.transitions_of(text) {
+      let Some(next_counts) = self.transitions.get_mut(&state) else { continue };
+      if let Some(count) = next_counts.get_mut(&next) {
+        *count = count.saturating_sub(1);
+        if *count == 0 { next_counts.remove(&next); };
+      };
+
+      if next_counts.is_empty() { self.transitions.remove(&state); };
+    };
+  }
+
+  /// Generates text from the beginning of a sentence, returning `None` if nothing could be generated.
+  pub fn generate<R: Rng + ?Sized>(&self, rng: &mut R, max_words: usize) -> Option<String> {
+    self.walk(rng, vec![Token::Start; self.order], max_words)
+  }
+
+  /// Generates text that continues on from one of the words in `seed`,
+  /// falling back to [`MarkovChain::generate`] if none of those words are known.
+  pub fn generate_seeded<R: Rng + ?Sized>(&self, rng: &mut R, seed: &str, max_words: usize) -> Option<String> {
+    let seed_words = seed.split_whitespace().map(normalize)
+      .filter(|word| !word.is_empty())
+      .collect::<Vec<String>>();
+    let candidates = self.transitions.keys()
+      .filter(|state| state.last().and_then(Token::as_word).is_some_and(|word| seed_words.contains(&normalize(word))))
+      .collect::<Vec<&Vec<Token>>>();
+
+    match candidates.is_empty() {
+      true => self.generate(rng, max_words),
+      false => {
+        let state = candidates[rng.random_range(0..candidates.len())];
+        self.walk(rng, state.clone(), max_words)
+      }
+    }
+  }
+
+  /// Every transition in a piece of text, from each state to the token following it.
+  fn transitions_of(&self, text: &str) -> Vec<(Vec<Token>, Token)> {
+    let words = text.split_whitespace().map(Token::word).collect::<Vec<Token>>();
+    if words.is_empty() { return Vec::new() };
+
+    let tokens = repeat_n(Token::Start, self.order)
+      .chain(words).chain(once(Token::End))
+      .collect::<Vec<Token>>();
+    tokens.windows(self.order + 1)
+      .map(|window| (window[..self.order].to_vec(), window[self.order].clone()))
+      .collect()
+  }
+
+  fn walk<R: Rng + ?Sized>(&self, rng: &mut R, mut state: Vec<Token>, max_words: usize) -> Option<String> {
+    // the words in the starting state are included in the output
+    let mut words = state.iter().filter_map(Token::as_word).map(str::to_owned).collect::<Vec<String>>();
+    while words.len() < max_words {
+      let Some(next) = self.transitions.get(&state).and_then(|next| choose_weighted(rng, next)) else { break };
+      match next {
+        Token::Word(word) => words.push(word.to_string()),
+        Token::Start | Token::End => break
+      };
+
+      state.remove(0);
+      state.push(next.clone());
+    };
+
+    (!words.is_empty()).then(|| words.join(" "))
+  }
+}
+
+impl Default for MarkovChain {
+  fn default() -> Self {
+    MarkovChain::new(DEFAULT_ORDER)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+enum Token {
+  Start,
+  Word(Box<str>),
+  End
+}
+
+impl Token {
+  fn word(word: &str) -> Self {
+    Token::Word(word.into())
+  }
+
+  fn as_word(&self) -> Option<&str> {
+    match self {
+      Token::Word(word) => Some(word),
+      Token::Start | Token::End => None
+    }
+  }
+}
+
+fn choose_weighted<'a, R: Rng + ?Sized>(rng: &mut R, next: &'a HashMap<Token, u32>) -> Option<&'a Token> {
+  let total = next.values().map(|&count| count as u64).sum::<u64>();
+  if total == 0 { return None };
+
+  let mut target = rng.random_range(0..total);
+  next.iter().find_map(|(token, &count)| match target.checked_sub(count as u64) {
+    Some(remaining) => { target = remaining; None },
+    None => Some(token)
+  })
+}
+
+/// Words are compared case-insensitively and without surrounding punctuation when seeding.
+fn normalize(word: &str) -> String {
+  word.trim_matches(|ch: char| !ch.is_alphanumeric()).to_lowercase()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn untrain_reverses_train() {
+    let mut chain = MarkovChain::default();
+    chain.train("the quick brown fox");
+    let before = chain.clone();
+
+    chain.train("the quick red fox jumps");
+    chain.untrain("the quick red fox jumps");
+    assert_eq!(chain, before);
+
+    chain.untrain("the quick brown fox");
+    assert!(chain.is_empty());
+  }
+
+  #[test]
+  fn untrain_ignores_unknown_text() {
+    let mut chain = MarkovChain::default();
+    chain.train("the quick brown fox");
+    let before = chain.clone();
+
+    chain.untrain("a lazy dog");
+    assert_eq!(chain, before);
+  }
+}
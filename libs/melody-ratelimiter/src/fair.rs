@@ -0,0 +1,250 @@
+use crate::Resource;
+
+use tokio::sync::{oneshot, Mutex, MutexGuard};
+use tokio::time::Instant;
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Like [`RateLimiter`][crate::RateLimiter], but requests are queued separately for each key,
+/// and the keys take turns, so that one busy key can't hold up every other key.
+#[derive(Debug)]
+pub struct FairRateLimiter<K, T> {
+  inner: Arc<FairRateLimiterInner<K, T>>
+}
+
+impl<K: Eq + Hash + Clone + Send + 'static, T: Send + 'static> FairRateLimiter<K, T> {
+  pub fn new(value: T, delay: Duration) -> Self {
+    FairRateLimiter {
+      inner: Arc::new(FairRateLimiterInner {
+        resource: Mutex::new(Resource {
+          value, deadline: Instant::now()
+        }),
+        queue: std::sync::Mutex::new(FairQueue {
+          busy: false,
+          order: VecDeque::new(),
+          waiters: HashMap::new()
+        }),
+        delay
+      })
+    }
+  }
+
+  /// Gets a time-slice from this ratelimiter on behalf of the given key, when possible.
+  pub async fn get(&self, key: K) -> FairTimeSlice<'_, T> {
+    let turn = self.turn(key).await;
+    let guard = self.inner.resource.lock().await;
+    tokio::time::sleep_until(guard.deadline).await;
+    FairTimeSlice { guard, _turn: turn, delay: self.inner.delay }
+  }
+
+  /// The number of requests waiting for their turn.
+  pub fn waiting(&self) -> usize {
+    let queue = self.inner.queue.lock().unwrap();
+    queue.waiters.values().map(VecDeque::len).sum()
+  }
+
+  async fn turn(&self, key: K) -> Turn {
+    let receiver = {
+      let mut queue = self.inner.queue.lock().unwrap();
+      if !queue.busy {
+        queue.busy = true;
+        return Turn { inner: Some(self.inner.clone()) };
+      };
+
+      let (sender, receiver) = oneshot::channel();
+      if !queue.waiters.contains_key(&key) {
+        queue.order.push_back(key.clone());
+      };
+
+      queue.waiters.entry(key).or_default().push_back(sender);
+      receiver
+    };
+
+    // the sender is only dropped once the ratelimiter is gone, which can't happen while borrowed
+    receiver.await.expect("fair ratelimiter queue was dropped")
+  }
+}
+
+impl<K, T> Clone for FairRateLimiter<K, T> {
+  fn clone(&self) -> Self {
+    FairRateLimiter { inner: self.inner.clone() }
+  }
+}
+
+#[derive(Debug)]
+struct FairRateLimiterInner<K, T> {
+  resource: Mutex<Resource<T>>,
+  queue: std::sync::Mutex<FairQueue<K>>,
+  delay: Duration
+}
+
+trait HandOff: Send + Sync {
+  /// Passes the turn on to the next waiting key in round-robin order.
+  fn hand_off(self: Arc<Self>);
+}
+
+impl<K: Eq + Hash + Clone + Send + 'static, T: Send + 'static> HandOff for FairRateLimiterInner<K, T> {
+  fn hand_off(self: Arc<Self>) {
+    let mut queue = self.queue.lock().unwrap();
+    while let Some(key) = queue.order.pop_front() {
+      let Some(waiters) = queue.waiters.get_mut(&key) else { continue };
+      let sender = waiters.pop_front();
+      match waiters.is_empty() {
+        true => { queue.waiters.remove(&key); },
+        false => queue.order.push_back(key)
+      };
+
+      let Some(sender) = sender else { continue };
+      match sender.send(Turn { inner: Some(self.clone()) }) {
+        Ok(()) => return,
+        // the request was abandoned, so the turn shouldn't be handed off again when it's dropped
+        Err(mut turn) => { turn.inner.take(); }
+      };
+    };
+
+    queue.busy = false;
+  }
+}
+
+#[derive(Debug)]
+struct FairQueue<K> {
+  /// Whether some request currently holds the turn.
+  busy: bool,
+  /// Keys with waiting requests, in the order they will be served.
+  order: VecDeque<K>,
+  waiters: HashMap<K, VecDeque<oneshot::Sender<Turn>>>
+}
+
+/// Permission to use the resource, which is passed on to the next request when dropped.
+struct Turn {
+  inner: Option<Arc<dyn HandOff>>
+}
+
+impl fmt::Debug for Turn {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Turn").finish_non_exhaustive()
+  }
+}
+
+impl Drop for Turn {
+  fn drop(&mut self) {
+    if let Some(inner) = self.inner.take() {
+      inner.hand_off();
+    };
+  }
+}
+
+/// A time-slice from a fair ratelimiter, see [`TimeSlice`][crate::TimeSlice].
+#[derive(Debug)]
+pub struct FairTimeSlice<'t, T> {
+  // the resource must be unlocked before the turn is handed off
+  guard: MutexGuard<'t, Resource<T>>,
+  _turn: Turn,
+  delay: Duration
+}
+
+impl<'t, T> FairTimeSlice<'t, T> {
+  /// Discards this time-slice.
+  pub fn consume(self) {}
+
+  /// Discards this time-slice without invoking a delay on the next request.
+  pub fn cancel(mut self) {
+    self.delay = Duration::ZERO;
+  }
+}
+
+impl<'t, T> Deref for FairTimeSlice<'t, T> {
+  type Target = T;
+
+  fn deref(&self) -> &Self::Target {
+    &self.guard.value
+  }
+}
+
+impl<'t, T> DerefMut for FairTimeSlice<'t, T> {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.guard.value
+  }
+}
+
+impl<'t, T> Drop for FairTimeSlice<'t, T> {
+  fn drop(&mut self) {
+    self.guard.deadline = Instant::now() + self.delay;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use tokio::task::JoinHandle;
+
+  /// Spawns a request for the given key, waiting until it is queued.
+  async fn spawn_request(
+    limiter: &FairRateLimiter<&'static str, ()>,
+    served: &Arc<std::sync::Mutex<Vec<String>>>,
+    key: &'static str, label: &str
+  ) -> JoinHandle<()> {
+    let waiting = limiter.waiting();
+    let (limiter_clone, served, label) = (limiter.clone(), served.clone(), label.to_owned());
+    let handle = tokio::spawn(async move {
+      let _slice = limiter_clone.get(key).await;
+      served.lock().unwrap().push(label);
+    });
+
+    while limiter.waiting() == waiting {
+      tokio::task::yield_now().await;
+    };
+
+    handle
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn keys_take_turns() {
+    let limiter = FairRateLimiter::new((), Duration::from_millis(10));
+    let served = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let slice = limiter.get("first").await;
+    let mut handles = Vec::new();
+    for (key, label) in [("a", "a1"), ("a", "a2"), ("a", "a3"), ("b", "b1"), ("b", "b2"), ("c", "c1")] {
+      handles.push(spawn_request(&limiter, &served, key, label).await);
+    };
+
+    assert_eq!(limiter.waiting(), 6);
+    drop(slice);
+    for handle in handles {
+      handle.await.unwrap();
+    };
+
+    assert_eq!(*served.lock().unwrap(), ["a1", "b1", "c1", "a2", "b2", "a3"]);
+    assert_eq!(limiter.waiting(), 0);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn abandoned_requests_do_not_stall_the_queue() {
+    let limiter = FairRateLimiter::new((), Duration::from_millis(10));
+    let served = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let slice = limiter.get("first").await;
+    let abandoned = spawn_request(&limiter, &served, "a", "a1").await;
+    let waiting = spawn_request(&limiter, &served, "b", "b1").await;
+    abandoned.abort();
+    assert!(abandoned.await.unwrap_err().is_cancelled());
+
+    drop(slice);
+    tokio::time::timeout(Duration::from_secs(1), waiting).await
+      .expect("the queue stalled").unwrap();
+    assert_eq!(*served.lock().unwrap(), ["b1"]);
+
+    // once nobody is waiting, the next request is served right away
+    let slice = tokio::time::timeout(Duration::from_secs(1), limiter.get("c")).await
+      .expect("the turn was never given back");
+    drop(slice);
+    assert_eq!(limiter.waiting(), 0);
+  }
+}
@@ -0,0 +1,129 @@
+use tokio::time::Instant;
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A set of token buckets, one for each key, such as a user or a channel.
+///
+/// Each bucket holds up to `capacity` tokens and regains one token every `refill` interval.
+/// Every request consumes a token, so a key may make `capacity` requests in a burst,
+/// and after that, one request every `refill` interval.
+#[derive(Debug)]
+pub struct KeyedRateLimiter<K> {
+  buckets: Mutex<HashMap<K, TokenBucket>>,
+  capacity: u32,
+  refill: Duration
+}
+
+impl<K: Eq + Hash> KeyedRateLimiter<K> {
+  /// Buckets that have completely refilled are forgotten once there are more than this many.
+  const PRUNE_THRESHOLD: usize = 1024;
+
+  /// # Panics
+  /// Panics if `capacity` is zero.
+  pub fn new(capacity: u32, refill: Duration) -> Self {
+    assert!(capacity > 0, "token bucket capacity must be non-zero");
+    KeyedRateLimiter { buckets: Mutex::new(HashMap::new()), capacity, refill }
+  }
+
+  /// Checks whether the key could make a request right now, without consuming a token.
+  /// Otherwise returns how long the key must wait until it can.
+  pub fn check(&self, key: &K) -> Result<(), Duration> {
+    let now = Instant::now();
+    let buckets = self.buckets.lock().unwrap();
+    match buckets.get(key) {
+      Some(bucket) => self.refilled(bucket, now).wait_time(self.refill),
+      None => Ok(())
+    }
+  }
+
+  /// Consumes a token for the key if one is available.
+  /// Otherwise returns how long the key must wait until one is.
+  pub fn try_acquire(&self, key: K) -> Result<(), Duration> {
+    let now = Instant::now();
+    let mut buckets = self.buckets.lock().unwrap();
+    if buckets.len() > Self::PRUNE_THRESHOLD {
+      buckets.retain(|_, bucket| self.refilled(bucket, now).tokens < self.capacity as f64);
+    };
+
+    let bucket = buckets.entry(key).or_insert(TokenBucket { tokens: self.capacity as f64, updated: now });
+    *bucket = self.refilled(bucket, now);
+    bucket.wait_time(self.refill)?;
+    bucket.tokens -= 1.0;
+    Ok(())
+  }
+
+  fn refilled(&self, bucket: &TokenBucket, now: Instant) -> TokenBucket {
+    let elapsed = now.saturating_duration_since(bucket.updated);
+    let regained = match self.refill.is_zero() {
+      true => f64::INFINITY,
+      false => elapsed.as_secs_f64() / self.refill.as_secs_f64()
+    };
+
+    TokenBucket { tokens: (bucket.tokens + regained).min(self.capacity as f64), updated: now }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+  tokens: f64,
+  updated: Instant
+}
+
+impl TokenBucket {
+  fn wait_time(&self, refill: Duration) -> Result<(), Duration> {
+    match self.tokens >= 1.0 {
+      true => Ok(()),
+      false => Err(refill.mul_f64(1.0 - self.tokens))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const REFILL: Duration = Duration::from_secs(1);
+
+  #[tokio::test(start_paused = true)]
+  async fn bursts_then_refills() {
+    let limiter = KeyedRateLimiter::new(3, REFILL);
+    for _ in 0..3 {
+      assert_eq!(limiter.try_acquire("alice"), Ok(()));
+    };
+
+    assert_eq!(limiter.try_acquire("alice"), Err(REFILL));
+    // other keys have buckets of their own
+    assert_eq!(limiter.try_acquire("bob"), Ok(()));
+
+    tokio::time::advance(REFILL / 2).await;
+    assert_eq!(limiter.try_acquire("alice"), Err(REFILL / 2));
+    tokio::time::advance(REFILL / 2).await;
+    assert_eq!(limiter.try_acquire("alice"), Ok(()));
+    assert!(limiter.try_acquire("alice").is_err());
+
+    // buckets never hold more than their capacity
+    tokio::time::advance(REFILL * 10).await;
+    for _ in 0..3 {
+      assert_eq!(limiter.try_acquire("alice"), Ok(()));
+    };
+
+    assert!(limiter.try_acquire("alice").is_err());
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn check_does_not_consume() {
+    let limiter = KeyedRateLimiter::new(1, REFILL);
+    assert_eq!(limiter.check(&"alice"), Ok(()));
+    assert_eq!(limiter.check(&"alice"), Ok(()));
+    assert_eq!(limiter.try_acquire("alice"), Ok(()));
+    assert_eq!(limiter.check(&"alice"), Err(REFILL));
+    assert_eq!(limiter.check(&"alice"), Err(REFILL));
+
+    tokio::time::advance(REFILL).await;
+    assert_eq!(limiter.check(&"alice"), Ok(()));
+    assert_eq!(limiter.try_acquire("alice"), Ok(()));
+  }
+}
@@ -1,3 +1,9 @@
+mod fair;
+mod keyed;
+
+pub use crate::fair::{FairRateLimiter, FairTimeSlice};
+pub use crate::keyed::KeyedRateLimiter;
+
 use tokio::sync::{Mutex, MutexGuard};
 use tokio::time::Instant;
 
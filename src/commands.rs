@@ -1,7 +1,13 @@
+mod chatbot;
 mod connect_four;
+mod dice_roll;
+mod emoji_stats;
 mod feed;
 mod general;
+mod initiative;
+mod message_chains;
 mod music_player;
+mod privacy;
 mod roles;
 
 use crate::prelude::*;
@@ -20,10 +26,15 @@ const COMMANDS: &[fn() -> MelodyCommand] = &[
   self::general::echo,
   self::general::troll,
   self::general::avatar,
-  self::general::emoji_stats,
+  self::emoji_stats::emoji_stats,
   self::general::ban_id,
   self::general::console,
-  self::general::roll,
+  self::dice_roll::roll,
+  self::initiative::initiative,
+  self::chatbot::chatbot,
+  self::chatbot::markov,
+  self::privacy::privacy,
+  self::message_chains::message_chains,
   self::feed::feeds,
   self::music_player::music_player,
   self::connect_four::connect_four,
@@ -0,0 +1,482 @@
+use crate::prelude::*;
+use crate::data::Core;
+use crate::feature::cleverbot::{ChannelListMode, ChatbotBackendKind};
+use super::{MelodyContext, CommandMetaData};
+
+use poise::ChoiceParameter;
+use poise::reply::CreateReply;
+use serenity::builder::CreateAllowedMentions;
+use serenity::model::id::ChannelId;
+
+
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  subcommands(
+    "chatbot_backend",
+    "chatbot_markov_learning",
+    "chatbot_reset",
+    "chatbot_config"
+  ),
+  name_localized("en-US", "chatbot"),
+  description_localized("en-US", "Configures how the bot replies when it is mentioned"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", [
+      "/chatbot backend [backend]",
+      "/chatbot markov-learning <enabled>",
+      "/chatbot reset",
+      "/chatbot config show",
+      "/chatbot config enabled <enabled>",
+      "/chatbot config channel-mode <mode>",
+      "/chatbot config channel-add <channel>",
+      "/chatbot config channel-remove <channel>",
+      "/chatbot config mention-only <enabled>",
+      "/chatbot config reply-chance <percent>"
+    ])
+    .examples_localized("en-US", [
+      "/chatbot backend Markov",
+      "/chatbot markov-learning true",
+      "/chatbot reset",
+      "/chatbot config show",
+      "/chatbot config enabled false",
+      "/chatbot config channel-mode 'Allow only listed channels'",
+      "/chatbot config channel-add #bot-chat",
+      "/chatbot config channel-remove #bot-chat",
+      "/chatbot config mention-only false",
+      "/chatbot config reply-chance 5"
+    ])
+)]
+pub async fn chatbot(_ctx: MelodyContext<'_>) -> MelodyResult {
+  Err(MelodyError::COMMAND_PRECONDITION_VIOLATION_ROOT_COMMAND)
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "backend",
+  name_localized("en-US", "backend"),
+  description_localized("en-US", "Chooses which chatbot replies in this server"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/chatbot backend [backend]"])
+    .examples_localized("en-US", ["/chatbot backend Markov"])
+)]
+async fn chatbot_backend(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "backend")]
+  #[description_localized("en-US", "The chatbot to use, omit to use the bot's default")]
+  backend: Option<ChatbotBackendKind>
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let chatbot = &core.state.chatbot;
+
+  let response = match backend {
+    Some(backend) if !chatbot.is_available(backend) => {
+      format!("The {} backend has not been set up for this bot", backend.name())
+    },
+    backend => {
+      core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+        persist_guild.chatbot.backend = backend;
+        Ok(())
+      }).await?;
+
+      match backend {
+        Some(backend) => format!("Set the chatbot backend to {}", backend.name()),
+        None => format!("Reset the chatbot backend to the default ({})", chatbot.default_backend().name())
+      }
+    }
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "markov-learning",
+  name_localized("en-US", "markov-learning"),
+  description_localized("en-US", "Chooses whether the Markov chatbot may learn from messages sent in this server"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/chatbot markov-learning <enabled>"])
+    .examples_localized("en-US", ["/chatbot markov-learning true"])
+)]
+async fn chatbot_markov_learning(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "enabled")]
+  #[description_localized("en-US", "Whether messages in this server should be learned from")]
+  enabled: bool
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.chatbot.markov_learning = enabled;
+    Ok(())
+  }).await?;
+
+  let response = match enabled {
+    true => "The Markov chatbot will now learn from messages sent in this server",
+    false => "The Markov chatbot will no longer learn from messages sent in this server"
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "reset",
+  name_localized("en-US", "reset"),
+  description_localized("en-US", "Makes the chatbot forget its conversations in this channel or thread"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/chatbot reset"])
+    .examples_localized("en-US", ["/chatbot reset"])
+)]
+async fn chatbot_reset(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+
+  core.state.chatbot.reset(ctx.channel_id()).await;
+
+  ctx.reply("The chatbot has forgotten its conversations in this channel").await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  name_localized("en-US", "markov"),
+  description_localized("en-US", "Generates text from what the Markov chatbot has learned"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/markov [prompt]"])
+    .examples_localized("en-US", ["/markov", "/markov 'the weather'"])
+)]
+pub async fn markov(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "prompt")]
+  #[description_localized("en-US", "Words that the generated text should start from")]
+  #[max_length = 1000]
+  prompt: Option<String>
+) -> MelodyResult {
+  let core = Core::from(ctx);
+
+  let response = core.state.chatbot.markov().generate(prompt.as_deref()).await
+    .unwrap_or_else(|| "I haven't learned enough to say anything yet".to_owned());
+
+  let reply = CreateReply::default()
+    .allowed_mentions(CreateAllowedMentions::new())
+    .content(response);
+  ctx.send(reply).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  subcommands(
+    "chatbot_config_show",
+    "chatbot_config_enabled",
+    "chatbot_config_channel_mode",
+    "chatbot_config_channel_add",
+    "chatbot_config_channel_remove",
+    "chatbot_config_mention_only",
+    "chatbot_config_reply_chance"
+  ),
+  rename = "config",
+  name_localized("en-US", "config"),
+  description_localized("en-US", "Controls where and when the chatbot replies in this server"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", [
+      "/chatbot config show",
+      "/chatbot config enabled <enabled>",
+      "/chatbot config channel-mode <mode>",
+      "/chatbot config channel-add <channel>",
+      "/chatbot config channel-remove <channel>",
+      "/chatbot config mention-only <enabled>",
+      "/chatbot config reply-chance <percent>"
+    ])
+    .examples_localized("en-US", [
+      "/chatbot config show",
+      "/chatbot config enabled false",
+      "/chatbot config channel-mode 'Allow only listed channels'",
+      "/chatbot config channel-add #bot-chat",
+      "/chatbot config channel-remove #bot-chat",
+      "/chatbot config mention-only false",
+      "/chatbot config reply-chance 5"
+    ])
+)]
+async fn chatbot_config(_ctx: MelodyContext<'_>) -> MelodyResult {
+  Err(MelodyError::COMMAND_PRECONDITION_VIOLATION_ROOT_COMMAND)
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "show",
+  name_localized("en-US", "show"),
+  description_localized("en-US", "Displays the chatbot settings for this server"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/chatbot config show"])
+    .examples_localized("en-US", ["/chatbot config show"])
+)]
+async fn chatbot_config_show(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let chatbot = &core.state.chatbot;
+
+  let settings = core.operate_persist_guild(guild_id, async |persist_guild| {
+    Ok(persist_guild.chatbot.clone())
+  }).await?;
+
+  let backend = settings.backend.filter(|&backend| chatbot.is_available(backend))
+    .unwrap_or(chatbot.default_backend());
+  let channels = match settings.channels.is_empty() {
+    true => "(none)".to_owned(),
+    false => settings.channels.iter().map(|channel_id| channel_id.mention().to_string()).join(", ")
+  };
+  let unprompted = match settings.mention_only {
+    true => "never (mention only)".to_owned(),
+    false => format!("{}% of messages", settings.reply_chance * 100.0)
+  };
+
+  let response = [
+    format!("Enabled: {}", if settings.enabled { "yes" } else { "no" }),
+    format!("Backend: {}", backend.name()),
+    format!("Channel mode: {}", settings.channel_list_mode.name()),
+    format!("Listed channels: {channels}"),
+    format!("Unprompted replies: {unprompted}"),
+    format!("Markov learning: {}", if settings.markov_learning { "enabled" } else { "disabled" })
+  ].join("\n");
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "enabled",
+  name_localized("en-US", "enabled"),
+  description_localized("en-US", "Turns the chatbot on or off for this server"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/chatbot config enabled <enabled>"])
+    .examples_localized("en-US", ["/chatbot config enabled false"])
+)]
+async fn chatbot_config_enabled(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "enabled")]
+  #[description_localized("en-US", "Whether the chatbot should reply in this server")]
+  enabled: bool
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.chatbot.enabled = enabled;
+    Ok(())
+  }).await?;
+
+  let response = match enabled {
+    true => "The chatbot is now enabled in this server",
+    false => "The chatbot is now disabled in this server"
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "channel-mode",
+  name_localized("en-US", "channel-mode"),
+  description_localized("en-US", "Chooses whether listed channels are the only ones the chatbot replies in, or ones it ignores"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/chatbot config channel-mode <mode>"])
+    .examples_localized("en-US", ["/chatbot config channel-mode 'Allow only listed channels'"])
+)]
+async fn chatbot_config_channel_mode(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "mode")]
+  #[description_localized("en-US", "How the channel list should be treated")]
+  mode: ChannelListMode
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.chatbot.channel_list_mode = mode;
+    Ok(())
+  }).await?;
+
+  let response = match mode {
+    ChannelListMode::Deny => "The chatbot will now reply everywhere except the listed channels",
+    ChannelListMode::Allow => "The chatbot will now only reply in the listed channels"
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "channel-add",
+  name_localized("en-US", "channel-add"),
+  description_localized("en-US", "Adds a channel to the chatbot's channel list"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/chatbot config channel-add <channel>"])
+    .examples_localized("en-US", ["/chatbot config channel-add #bot-chat"])
+)]
+async fn chatbot_config_channel_add(
+  ctx: MelodyContext<'_>,
+  #[rename = "channel"]
+  #[name_localized("en-US", "channel")]
+  #[description_localized("en-US", "The channel to add")]
+  channel_id: ChannelId
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  let added = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    Ok(persist_guild.chatbot.channels.insert(channel_id))
+  }).await?;
+
+  let response = match added {
+    true => format!("Added {} to the chatbot's channel list", channel_id.mention()),
+    false => format!("{} is already in the chatbot's channel list", channel_id.mention())
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "channel-remove",
+  name_localized("en-US", "channel-remove"),
+  description_localized("en-US", "Removes a channel from the chatbot's channel list"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/chatbot config channel-remove <channel>"])
+    .examples_localized("en-US", ["/chatbot config channel-remove #bot-chat"])
+)]
+async fn chatbot_config_channel_remove(
+  ctx: MelodyContext<'_>,
+  #[rename = "channel"]
+  #[name_localized("en-US", "channel")]
+  #[description_localized("en-US", "The channel to remove")]
+  channel_id: ChannelId
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  let removed = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    Ok(persist_guild.chatbot.channels.remove(&channel_id))
+  }).await?;
+
+  let response = match removed {
+    true => format!("Removed {} from the chatbot's channel list", channel_id.mention()),
+    false => format!("{} is not in the chatbot's channel list", channel_id.mention())
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "mention-only",
+  name_localized("en-US", "mention-only"),
+  description_localized("en-US", "Chooses whether the chatbot only replies when it is mentioned"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/chatbot config mention-only <enabled>"])
+    .examples_localized("en-US", ["/chatbot config mention-only false"])
+)]
+async fn chatbot_config_mention_only(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "enabled")]
+  #[description_localized("en-US", "Whether the chatbot should only reply when mentioned or replied to")]
+  enabled: bool
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  let reply_chance = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.chatbot.mention_only = enabled;
+    Ok(persist_guild.chatbot.reply_chance)
+  }).await?;
+
+  let response = match enabled {
+    true => "The chatbot will now only reply when it is mentioned".to_owned(),
+    false => format!("The chatbot will now also reply to {}% of other messages", reply_chance * 100.0)
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "reply-chance",
+  name_localized("en-US", "reply-chance"),
+  description_localized("en-US", "Sets how often the chatbot replies to messages that don't mention it"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .info_localized_concat("en-US", [
+      "This only has an effect when mention-only is disabled."
+    ])
+    .usage_localized("en-US", ["/chatbot config reply-chance <percent>"])
+    .examples_localized("en-US", ["/chatbot config reply-chance 5"])
+)]
+async fn chatbot_config_reply_chance(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "percent")]
+  #[description_localized("en-US", "The percentage of messages to reply to")]
+  #[min = 0]
+  #[max = 100]
+  percent: f64
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let percent = percent.clamp(0.0, 100.0);
+
+  let mention_only = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.chatbot.reply_chance = percent / 100.0;
+    Ok(persist_guild.chatbot.mention_only)
+  }).await?;
+
+  let mut response = format!("The chatbot will reply to {percent}% of messages that don't mention it");
+  if mention_only {
+    response.push_str(" (once mention-only is disabled)");
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
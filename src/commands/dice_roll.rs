@@ -0,0 +1,806 @@
+use crate::prelude::*;
+use crate::data::Core;
+use crate::feature::dice_roll::{
+  render_histogram, DicePlayer, DicePlayerError, DistributionError,
+  Roll, RollDistribution, RollHistoryEntry, RollSeed
+};
+use crate::utils::{Blockify, Timestamp, TimestampFormat};
+use super::{MelodyContext, CommandMetaData};
+
+use poise::reply::CreateReply;
+use serenity::builder::{CreateAttachment, CreateEmbed, CreateMessage};
+use serenity::model::channel::Attachment;
+use serenity::model::guild::Role;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+use std::borrow::Cow;
+use std::fmt;
+
+
+
+#[poise::command(
+  slash_command,
+  subcommands(
+    "roll_dice",
+    "roll_stats",
+    "roll_macro",
+    "roll_save",
+    "roll_delete",
+    "roll_macros",
+    "roll_sheet",
+    "roll_export",
+    "roll_import",
+    "roll_history",
+    "roll_verify",
+    "roll_gm_role"
+  ),
+  description_localized("en-US", "Rolls dice, or calculates the odds of a roll"),
+  custom_data = CommandMetaData::new()
+    .info_localized_concat("en-US", [
+      "Rolls may use variables such as `@str_mod`, which are filled in from your character sheet in the current server.",
+      "Macros and character sheets are kept separately for each server.",
+      "Every roll is made with a random seed that is shown alongside it, so that it can be checked with `/roll verify`.",
+      "Secret rolls are only shown to you and members of the server's GM role, but still appear in `/roll history`",
+      "with a commitment (a hash of their seed, notation and roller), so that they can be verified once their seed is revealed."
+    ])
+    .usage_localized("en-US", [
+      "/roll dice <dice notation> [secret]",
+      "/roll stats <dice notation>",
+      "/roll macro <name> [secret]",
+      "/roll save <name> <dice notation>",
+      "/roll delete <name>",
+      "/roll macros",
+      "/roll sheet show",
+      "/roll sheet set <variable> <value>",
+      "/roll sheet unset <variable>",
+      "/roll export",
+      "/roll import <file> [replace]",
+      "/roll history [user] [count]",
+      "/roll verify <dice notation> <seed> [user]",
+      "/roll gm-role [role]"
+    ])
+    .examples_localized("en-US", [
+      "/roll dice '2d6 + 1d4 + 3'",
+      "/roll dice 'd20 + 4' true",
+      "/roll stats 'd20 + 5 advantage >= 15'",
+      "/roll save attack '1d20 + @str_mod adv'",
+      "/roll macro attack",
+      "/roll sheet set str_mod 3"
+    ])
+)]
+pub async fn roll(_ctx: MelodyContext<'_>) -> MelodyResult {
+  Err(MelodyError::COMMAND_PRECONDITION_VIOLATION_ROOT_COMMAND)
+}
+
+#[poise::command(
+  slash_command,
+  rename = "dice",
+  name_localized("en-US", "dice"),
+  description_localized("en-US", "Rolls a configurable dice"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/roll dice <dice notation> [secret]"])
+    .examples_localized("en-US", [
+      "/roll dice '3d20'",
+      "/roll dice 'd20 + 2 advantage'",
+      "/roll dice 'd20 - 2 disadvantage'",
+      "/roll dice '6d6 max 2'",
+      "/roll dice '6d6 min 2'",
+      "/roll dice '2d8 + 3'",
+      "/roll dice '2d6 + 1d4 + 3'",
+      "/roll dice '4d6kh3'",
+      "/roll dice '2d20kh1 >= 15'",
+      "/roll dice 'd6!'",
+      "/roll dice '10d10>=7'",
+      "/roll dice '4dF'",
+      "/roll dice 'd%'",
+      "/roll dice '(1d8 + 2) * 2'",
+      "/roll dice 'coin'",
+      "/roll dice 'd20 + 4' true"
+    ])
+)]
+async fn roll_dice(
+  ctx: MelodyContext<'_>,
+  #[description_localized("en-US", "The notation for the dice roll to be made")]
+  #[max_length = 1000]
+  notation: String,
+  #[name_localized("en-US", "secret")]
+  #[description_localized("en-US", "Whether only you and the server's GMs should see this roll (default false)")]
+  secret: Option<bool>
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let player = get_dice_player(&core, ctx.guild_id(), ctx.author().id).await?;
+  match player.parse_roll(&notation) {
+    Ok(roll) => make_roll(ctx, &core, roll, None, secret.unwrap_or(false)).await,
+    Err(error) => send_roll_error(ctx, error, secret.unwrap_or(false)).await
+  }
+}
+
+#[poise::command(
+  slash_command,
+  rename = "stats",
+  name_localized("en-US", "stats"),
+  description_localized("en-US", "Calculates the odds of a dice roll, without rolling it"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/roll stats <dice notation>"])
+    .examples_localized("en-US", [
+      "/roll stats '3d6'",
+      "/roll stats '4d6kh3'",
+      "/roll stats 'd20 + 5 advantage >= 15'",
+      "/roll stats '10d10>=7 >= 4'",
+      "/roll stats '(1d8 + 2) * 2'"
+    ])
+)]
+async fn roll_stats(
+  ctx: MelodyContext<'_>,
+  #[description_localized("en-US", "The notation for the dice roll to be calculated")]
+  #[max_length = 1000]
+  notation: String
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let player = get_dice_player(&core, ctx.guild_id(), ctx.author().id).await?;
+  let roll = match player.parse_roll(&notation) {
+    Ok(roll) => roll,
+    Err(error) => {
+      let response = Blockify(format_args!("Error: {error}")).to_string();
+      ctx.reply(response).await.context("failed to send reply")?;
+      return Ok(());
+    }
+  };
+
+  ctx.defer().await.context("failed to defer response")?;
+  let target = match &roll {
+    Roll::Dice(_, target) => *target,
+    Roll::Coin(..) => None
+  };
+
+  let distribution = tokio::task::spawn_blocking(move || {
+    let distribution = roll.distribution()?;
+    let histogram = render_histogram(&distribution.outcomes, target)
+      .map_err(|error| error!("failed to render histogram: {error}")).ok();
+    Ok::<_, DistributionError>((roll, distribution, histogram))
+  }).await.unwrap();
+
+  let reply = match distribution {
+    Ok((roll, distribution, histogram)) => {
+      let mut reply = CreateReply::default().reply(true);
+      let mut embed = stats_embed(&roll, &distribution);
+      if let Some(histogram) = histogram {
+        reply = reply.attachment(CreateAttachment::bytes(histogram, "histogram.png"));
+        embed = embed.image("attachment://histogram.png");
+      };
+
+      reply.embed(embed)
+    },
+    Err(error) => {
+      let response = Blockify(format_args!("Error: {error}")).to_string();
+      CreateReply::default().reply(true).content(response)
+    }
+  };
+
+  ctx.send(reply).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "macro",
+  name_localized("en-US", "macro"),
+  description_localized("en-US", "Rolls one of your saved macros"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/roll macro <name> [secret]"])
+    .examples_localized("en-US", ["/roll macro attack", "/roll macro stealth true"])
+)]
+async fn roll_macro(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "name")]
+  #[description_localized("en-US", "The name of the macro to roll")]
+  #[max_length = 100]
+  name: String,
+  #[name_localized("en-US", "secret")]
+  #[description_localized("en-US", "Whether only you and the server's GMs should see this roll (default false)")]
+  secret: Option<bool>
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let player = get_dice_player(&core, Some(guild_id), ctx.author().id).await?;
+  match player.parse_macro(&name) {
+    Ok(roll) => make_roll(ctx, &core, roll, Some(name.trim().to_ascii_lowercase()), secret.unwrap_or(false)).await,
+    Err(error) => send_roll_error(ctx, error, secret.unwrap_or(false)).await
+  }
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "save",
+  name_localized("en-US", "save"),
+  description_localized("en-US", "Saves a roll as a macro, so that it can be rolled again by name"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/roll save <name> <dice notation>"])
+    .examples_localized("en-US", [
+      "/roll save attack '1d20 + @str_mod + @prof adv'",
+      "/roll save fireball '8d6'"
+    ])
+)]
+async fn roll_save(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "name")]
+  #[description_localized("en-US", "The name to save the macro as")]
+  #[max_length = 100]
+  name: String,
+  #[name_localized("en-US", "notation")]
+  #[description_localized("en-US", "The notation for the dice roll to be saved")]
+  #[max_length = 1000]
+  notation: String
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let result = operate_dice_player(&core, guild_id, ctx.author().id, |player| {
+    player.save_macro(&name, &notation)
+  }).await?;
+
+  let response = match result {
+    Ok(Some(previous)) => format!("Replaced your macro `{name}`, which was {}", Blockify(previous)),
+    Ok(None) => format!("Saved your macro `{name}`, roll it with `/roll macro {name}`"),
+    Err(error) => Blockify(format_args!("Error: {error}")).to_string()
+  };
+
+  ctx.send(CreateReply::default().ephemeral(true).content(response))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "delete",
+  name_localized("en-US", "delete"),
+  description_localized("en-US", "Deletes one of your saved macros"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/roll delete <name>"])
+    .examples_localized("en-US", ["/roll delete attack"])
+)]
+async fn roll_delete(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "name")]
+  #[description_localized("en-US", "The name of the macro to delete")]
+  #[max_length = 100]
+  name: String
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let result = operate_dice_player(&core, guild_id, ctx.author().id, |player| {
+    player.delete_macro(&name)
+  }).await?;
+
+  let response = match result {
+    Ok(Some(..)) => format!("Deleted your macro `{name}`"),
+    Ok(None) => format!("You have no macro named `{name}`"),
+    Err(error) => Blockify(format_args!("Error: {error}")).to_string()
+  };
+
+  ctx.send(CreateReply::default().ephemeral(true).content(response))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "macros",
+  name_localized("en-US", "macros"),
+  description_localized("en-US", "Lists your saved macros"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/roll macros"])
+    .examples_localized("en-US", ["/roll macros"])
+)]
+async fn roll_macros(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let player = get_dice_player(&core, Some(guild_id), ctx.author().id).await?;
+
+  let response = match player.macros.is_empty() {
+    true => "You have no saved macros, save one with `/roll save`".to_owned(),
+    false => player.macros.iter()
+      .map(|(name, notation)| format!("`{name}`: {}", Blockify(notation)))
+      .join("\n")
+  };
+
+  ctx.send(CreateReply::default().ephemeral(true).content(response))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  subcommands(
+    "roll_sheet_show",
+    "roll_sheet_set",
+    "roll_sheet_unset"
+  ),
+  rename = "sheet",
+  name_localized("en-US", "sheet"),
+  description_localized("en-US", "Manages the variables on your character sheet"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", [
+      "/roll sheet show",
+      "/roll sheet set <variable> <value>",
+      "/roll sheet unset <variable>"
+    ])
+    .examples_localized("en-US", [
+      "/roll sheet show",
+      "/roll sheet set str_mod 3",
+      "/roll sheet unset str_mod"
+    ])
+)]
+async fn roll_sheet(_ctx: MelodyContext<'_>) -> MelodyResult {
+  Err(MelodyError::COMMAND_PRECONDITION_VIOLATION_ROOT_COMMAND)
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "show",
+  name_localized("en-US", "show"),
+  description_localized("en-US", "Displays the variables on your character sheet"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/roll sheet show"])
+    .examples_localized("en-US", ["/roll sheet show"])
+)]
+async fn roll_sheet_show(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let player = get_dice_player(&core, Some(guild_id), ctx.author().id).await?;
+
+  let response = match player.sheet.is_empty() {
+    true => "Your character sheet is empty, add variables to it with `/roll sheet set`".to_owned(),
+    false => player.sheet.iter()
+      .map(|(name, value)| format!("`@{name}` = {value}"))
+      .join("\n")
+  };
+
+  ctx.send(CreateReply::default().ephemeral(true).content(response))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "set",
+  name_localized("en-US", "set"),
+  description_localized("en-US", "Sets a variable on your character sheet"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/roll sheet set <variable> <value>"])
+    .examples_localized("en-US", ["/roll sheet set str_mod 3", "/roll sheet set prof 2"])
+)]
+async fn roll_sheet_set(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "variable")]
+  #[description_localized("en-US", "The name of the variable, used in rolls as @name")]
+  #[max_length = 100]
+  variable: String,
+  #[name_localized("en-US", "value")]
+  #[description_localized("en-US", "The value of the variable")]
+  value: i64
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let result = operate_dice_player(&core, guild_id, ctx.author().id, |player| {
+    player.set_variable(&variable, value)
+  }).await?;
+
+  let response = match result {
+    Ok(Some(previous)) => format!("Changed `{variable}` from {previous} to {value}"),
+    Ok(None) => format!("Set `{variable}` to {value}"),
+    Err(error) => Blockify(format_args!("Error: {error}")).to_string()
+  };
+
+  ctx.send(CreateReply::default().ephemeral(true).content(response))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "unset",
+  name_localized("en-US", "unset"),
+  description_localized("en-US", "Removes a variable from your character sheet"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/roll sheet unset <variable>"])
+    .examples_localized("en-US", ["/roll sheet unset str_mod"])
+)]
+async fn roll_sheet_unset(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "variable")]
+  #[description_localized("en-US", "The name of the variable to remove")]
+  #[max_length = 100]
+  variable: String
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let result = operate_dice_player(&core, guild_id, ctx.author().id, |player| {
+    player.unset_variable(&variable)
+  }).await?;
+
+  let response = match result {
+    Ok(Some(..)) => format!("Removed `{variable}` from your character sheet"),
+    Ok(None) => format!("Your character sheet has no variable named `{variable}`"),
+    Err(error) => Blockify(format_args!("Error: {error}")).to_string()
+  };
+
+  ctx.send(CreateReply::default().ephemeral(true).content(response))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "export",
+  name_localized("en-US", "export"),
+  description_localized("en-US", "Exports your macros and character sheet as a JSON file"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/roll export"])
+    .examples_localized("en-US", ["/roll export"])
+)]
+async fn roll_export(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let player = get_dice_player(&core, Some(guild_id), ctx.author().id).await?;
+
+  let reply = match player.is_empty() {
+    true => CreateReply::default().ephemeral(true)
+      .content("You have no macros or character sheet to export"),
+    false => CreateReply::default().ephemeral(true)
+      .content("Here are your macros and character sheet, use `/roll import` to load them in another server")
+      .attachment(CreateAttachment::bytes(player.to_json(), "dice.json"))
+  };
+
+  ctx.send(reply).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "import",
+  name_localized("en-US", "import"),
+  description_localized("en-US", "Imports macros and a character sheet from a JSON file made by /roll export"),
+  custom_data = CommandMetaData::new()
+    .info_localized_concat("en-US", [
+      "Imported macros and variables replace any you already have with the same name.",
+      "When `replace` is set, all of your existing macros and variables are removed first."
+    ])
+    .usage_localized("en-US", ["/roll import <file> [replace]"])
+    .examples_localized("en-US", ["/roll import dice.json", "/roll import dice.json true"])
+)]
+async fn roll_import(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "file")]
+  #[description_localized("en-US", "The JSON file to import")]
+  file: Attachment,
+  #[name_localized("en-US", "replace")]
+  #[description_localized("en-US", "Whether to remove your existing macros and variables first (default false)")]
+  replace: Option<bool>
+) -> MelodyResult {
+  const MAX_FILE_SIZE: u32 = 64 * 1024;
+
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let response = if file.size > MAX_FILE_SIZE {
+    format!("That file is too large, it may be at most {} KiB", MAX_FILE_SIZE / 1024)
+  } else {
+    let json = file.download().await.context("failed to download attachment")?;
+    let result = match DicePlayer::from_json(&json) {
+      Ok(imported) => operate_dice_player(&core, guild_id, ctx.author().id, |player| {
+        let (macros, variables) = (imported.macros.len(), imported.sheet.len());
+        if replace.unwrap_or(false) { *player = DicePlayer::default() };
+        player.import(imported).map(|()| (macros, variables))
+      }).await?,
+      Err(error) => Err(error)
+    };
+
+    match result {
+      Ok((macros, variables)) => format!("Imported {macros} macros and {variables} variables"),
+      Err(error) => Blockify(format_args!("Error: {error}")).to_string()
+    }
+  };
+
+  ctx.send(CreateReply::default().ephemeral(true).content(response))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "history",
+  name_localized("en-US", "history"),
+  description_localized("en-US", "Lists the most recent rolls made in this channel"),
+  custom_data = CommandMetaData::new()
+    .info_localized_concat("en-US", [
+      "Secret rolls are listed with a commitment instead of their result and seed.",
+      "Once the seed of a secret roll is revealed, `/roll verify` shows its result along with its commitment, which can be compared with this one."
+    ])
+    .usage_localized("en-US", ["/roll history [user] [count]"])
+    .examples_localized("en-US", ["/roll history", "/roll history @Reg 5"])
+)]
+async fn roll_history(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "user")]
+  #[description_localized("en-US", "Only list rolls made by this user")]
+  user: Option<UserId>,
+  #[name_localized("en-US", "count")]
+  #[description_localized("en-US", "How many rolls to list (default 10)")]
+  #[min = 1]
+  #[max = 15]
+  count: Option<usize>
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let channel_id = ctx.channel_id();
+  let count = count.unwrap_or(10).clamp(1, 15);
+
+  let entries = core.operate_persist_guild(guild_id, async |persist_guild| {
+    Ok(persist_guild.roll_history.get(&channel_id).map_or_else(Vec::new, |roll_history| {
+      roll_history.iter()
+        .filter(|entry| user.is_none_or(|user| entry.user == user))
+        .take(count).cloned().collect::<Vec<RollHistoryEntry>>()
+    }))
+  }).await?;
+
+  if entries.is_empty() {
+    ctx.reply("No rolls have been made in this channel").await.context("failed to send reply")?;
+    return Ok(());
+  };
+
+  let description = entries.iter().map(|entry| {
+    let timestamp = Timestamp::new(entry.time, TimestampFormat::Relative);
+    if entry.secret {
+      format!("{timestamp} {} made a secret roll\n-# Commitment `{}`", entry.user.mention(), entry.commitment())
+    } else {
+      let success = match entry.success {
+        Some(true) => ", **SUCCESS**",
+        Some(false) => ", **FAILURE**",
+        None => ""
+      };
+
+      let roll = truncate(&entry.roll, 60);
+      format!("{timestamp} {} rolled `{roll}` = **{}**{success}\n-# Seed `{}`", entry.user.mention(), entry.total, entry.seed)
+    }
+  }).join("\n");
+
+  let embed = CreateEmbed::default()
+    .title("Roll history")
+    .description(description);
+  ctx.send(CreateReply::default().reply(true).embed(embed))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  rename = "verify",
+  name_localized("en-US", "verify"),
+  description_localized("en-US", "Makes a roll again with the seed it was made with, to check its result"),
+  custom_data = CommandMetaData::new()
+    .info_localized_concat("en-US", [
+      "The notation should be exactly as it appears in `/roll history`, or in the secret roll's message.",
+      "The commitment of the seed, notation and roller is also shown, so that it can be compared with a secret roll's commitment in `/roll history`."
+    ])
+    .usage_localized("en-US", ["/roll verify <dice notation> <seed> [user]"])
+    .examples_localized("en-US", ["/roll verify 'd20 + 4' 4f1c...9a0e", "/roll verify 'd20 + 4' 4f1c...9a0e @Nanachi"])
+)]
+async fn roll_verify(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "notation")]
+  #[description_localized("en-US", "The notation of the roll, with its variables filled in")]
+  #[max_length = 1000]
+  notation: String,
+  #[name_localized("en-US", "seed")]
+  #[description_localized("en-US", "The seed the roll was made with")]
+  #[max_length = 100]
+  seed: String,
+  #[name_localized("en-US", "user")]
+  #[description_localized("en-US", "The user who made the roll (default you)")]
+  user: Option<UserId>
+) -> MelodyResult {
+  let user = user.unwrap_or(ctx.author().id);
+  let result = match (notation.parse::<Roll>(), seed.parse::<RollSeed>()) {
+    (Ok(roll), Ok(seed)) => {
+      let commitment = seed.commitment(&roll.to_string(), user);
+      seed.execute(roll)
+        .map(|executed_roll| format!("{executed_roll}\n-# Seed `{seed}`, commitment `{commitment}` for rolls by {}", user.mention()))
+        .map_err(|error| error.to_string())
+    },
+    (Err(error), _) => Err(error.to_string()),
+    (_, Err(error)) => Err(error.to_string())
+  };
+
+  let response = match result {
+    Ok(response) if response.len() > 2000 => "The resulting message was too long to send...".to_owned(),
+    Ok(response) => response,
+    Err(error) => Blockify(format_args!("Error: {error}")).to_string()
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  rename = "gm-role",
+  name_localized("en-US", "gm-role"),
+  description_localized("en-US", "Sets the role whose members are sent a copy of every secret roll"),
+  custom_data = CommandMetaData::new()
+    .info_localized("en-US", "Leaving out the role stops secret rolls from being sent to anyone.")
+    .usage_localized("en-US", ["/roll gm-role [role]"])
+    .examples_localized("en-US", ["/roll gm-role @Dungeon Master", "/roll gm-role"])
+)]
+async fn roll_gm_role(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "role")]
+  #[description_localized("en-US", "The GM role")]
+  role: Option<Role>
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let role_id = role.as_ref().map(|role| role.id);
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.roll_gm_role = role_id;
+    Ok(())
+  }).await?;
+
+  let response = match role {
+    Some(role) => format!("Secret rolls will now be sent to members of {}", role.mention()),
+    None => "Secret rolls will no longer be sent to anyone".to_owned()
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+/// Gets a user's macros and character sheet, which are empty outside of guilds.
+pub(super) async fn get_dice_player(core: &Core, guild_id: Option<GuildId>, user_id: UserId) -> MelodyResult<DicePlayer> {
+  let Some(guild_id) = guild_id else { return Ok(DicePlayer::default()) };
+  core.operate_persist_guild(guild_id, async |persist_guild| {
+    Ok(persist_guild.dice_players.get(&user_id).cloned().unwrap_or_default())
+  }).await
+}
+
+async fn operate_dice_player<R>(
+  core: &Core, guild_id: GuildId, user_id: UserId,
+  operation: impl FnOnce(&mut DicePlayer) -> Result<R, DicePlayerError>
+) -> MelodyResult<Result<R, DicePlayerError>> {
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    let player = persist_guild.dice_players.entry(user_id).or_default();
+    let result = operation(player);
+    if player.is_empty() {
+      persist_guild.dice_players.remove(&user_id);
+    };
+
+    Ok(result)
+  }).await
+}
+
+/// Makes a roll with a new seed, recording it in the channel's roll history.
+/// Secret rolls are only shown to whoever made them, and sent to members of the guild's GM role.
+async fn make_roll(ctx: MelodyContext<'_>, core: &Core, roll: Roll, label: Option<String>, secret: bool) -> MelodyResult {
+  let seed = RollSeed::generate();
+  let executed_roll = match seed.execute(roll.clone()) {
+    Ok(executed_roll) => executed_roll,
+    Err(error) => return send_roll_error(ctx, error, secret).await
+  };
+
+  if let Some(guild_id) = ctx.guild_id() {
+    let entry = RollHistoryEntry::new(ctx.author().id, &roll, &executed_roll, secret, seed);
+    record_roll(core, guild_id, ctx.channel_id(), entry).await?;
+  };
+
+  let mut response = match label {
+    Some(label) => format!("`{label}`: {executed_roll}"),
+    None => executed_roll.to_string()
+  };
+
+  if response.len() > 1800 {
+    response = format!("`{}` = **{}**", truncate(&roll.to_string(), 1000), executed_roll.total());
+  };
+
+  if !secret {
+    response.push_str(&format!("\n-# Seed `{seed}`"));
+    ctx.reply(response).await.context("failed to send reply")?;
+    return Ok(());
+  };
+
+  response.push_str(&format!("\n-# Secret roll of `{}`, seed `{seed}`", truncate(&roll.to_string(), 100)));
+  if let Some(guild_id) = ctx.guild_id() {
+    let message = format!("{} made a secret roll in {}: {response}", ctx.author().id.mention(), ctx.channel_id().mention());
+
+    let gms = send_to_gms(core, guild_id, ctx.author().id, message).await?;
+    response.push_str(&format!(", sent to {gms} GMs"));
+  };
+
+  ctx.send(CreateReply::default().ephemeral(true).content(response))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+async fn send_roll_error(ctx: MelodyContext<'_>, error: impl fmt::Display, secret: bool) -> MelodyResult {
+  let response = Blockify(format_args!("Error: {error}")).to_string();
+  ctx.send(CreateReply::default().reply(true).ephemeral(secret).content(response))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+pub(super) async fn record_roll(core: &Core, guild_id: GuildId, channel_id: ChannelId, entry: RollHistoryEntry) -> MelodyResult {
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.roll_history.entry(channel_id).or_default().push(entry);
+    Ok(())
+  }).await
+}
+
+/// Sends a copy of a secret roll to the members of the guild's GM role, other than whoever made it.
+/// Returns how many members it was sent to.
+async fn send_to_gms(core: &Core, guild_id: GuildId, roller: UserId, message: String) -> MelodyResult<usize> {
+  const MAX_GMS: usize = 10;
+
+  let gm_role = core.operate_persist_guild(guild_id, async |persist_guild| {
+    Ok(persist_guild.roll_gm_role)
+  }).await?;
+  let Some(gm_role) = gm_role else { return Ok(0) };
+
+  let gms = core.cache.guild(guild_id).map_or_else(Vec::new, |guild| {
+    guild.members.values()
+      .filter(|member| member.roles.contains(&gm_role) && member.user.id != roller && !member.user.bot)
+      .map(|member| member.user.id)
+      .take(MAX_GMS)
+      .collect::<Vec<UserId>>()
+  });
+
+  let mut sent = 0;
+  for gm in gms {
+    let result = gm.direct_message(core, CreateMessage::new().content(&message)).await
+      .context("failed to send secret roll to GM").log_error();
+    if result.is_some() { sent += 1 };
+  };
+
+  Ok(sent)
+}
+
+fn truncate(s: &str, max_chars: usize) -> Cow<'_, str> {
+  match s.char_indices().nth(max_chars) {
+    Some((i, _)) => format!("{}…", &s[..i]).into(),
+    None => s.into()
+  }
+}
+
+fn stats_embed(roll: &Roll, distribution: &RollDistribution) -> CreateEmbed {
+  let outcomes = &distribution.outcomes;
+  let mut embed = CreateEmbed::default()
+    .title(format!("Odds of {roll}"))
+    .field("Average", format!("{:.2}", outcomes.mean()), true)
+    .field("Standard deviation", format!("{:.2}", outcomes.std_dev()), true)
+    .field("Range", format!("{} to {}", outcomes.min(), outcomes.max()), true);
+  if let Some(success_chance) = distribution.success_chance {
+    embed = embed.field("Chance of success", format_percent(success_chance), true);
+  };
+
+  let (mode, mode_chance) = outcomes.outcomes()
+    .fold((outcomes.min(), 0.0), |mode, outcome| if outcome.1 > mode.1 { outcome } else { mode });
+  embed.field("Most likely", format!("{mode} ({})", format_percent(mode_chance)), true)
+}
+
+fn format_percent(chance: f64) -> String {
+  match chance * 100.0 {
+    percent if percent > 0.0 && percent < 0.01 => "<0.01%".to_owned(),
+    percent if percent < 100.0 && percent > 99.99 => ">99.99%".to_owned(),
+    percent => format!("{percent:.2}%")
+  }
+}
@@ -0,0 +1,387 @@
+use crate::prelude::*;
+use crate::data::Core;
+use crate::feature::chart::{Bar, BarChart, LineChart, Series, ICON_SIZE};
+use crate::feature::emoji_stats::{backfill_emoji_stats, parse_since, EmojiBackfillProgress, EmojiKey, EmojiKind, EmojiSource, RETENTION_DAYS};
+use crate::utils::{Blockify, Timestamp, TimestampFormat};
+use super::{MelodyContext, CommandMetaData};
+
+use chrono::{Days, NaiveDate, NaiveTime, Utc};
+use poise::ChoiceParameter;
+use poise::reply::{CreateReply, ReplyHandle};
+use serenity::builder::{CreateAttachment, EditMessage};
+use serenity::model::channel::Message;
+use serenity::model::id::UserId;
+
+
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  subcommands(
+    "emoji_stats_show",
+    "emoji_stats_backfill"
+  ),
+  rename = "emoji-stats",
+  name_localized("en-US", "emoji-stats"),
+  description_localized("en-US", "Gets usage statistics of emojis for this server"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", [
+      "/emoji-stats show [view] [kind] [source] [since] [user] [chart] [page]",
+      "/emoji-stats backfill"
+    ])
+    .examples_localized("en-US", [
+      "/emoji-stats show",
+      "/emoji-stats show view:Never used since:30d",
+      "/emoji-stats show chart:Daily uses since:8w",
+      "/emoji-stats backfill"
+    ])
+)]
+pub async fn emoji_stats(_ctx: MelodyContext<'_>) -> MelodyResult {
+  Err(MelodyError::COMMAND_PRECONDITION_VIOLATION_ROOT_COMMAND)
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "show",
+  name_localized("en-US", "show"),
+  description_localized("en-US", "Gets usage statistics of emojis for this server"),
+  custom_data = CommandMetaData::new()
+    .info_localized_concat("en-US", [
+      "Uses are counted by day for the last 180 days, so `since` may go back at most that far.",
+      "The least used and never used views can help with deciding which emoji and sticker slots to free up.",
+      "Unicode emojis are only listed once they have been used, and skin tones are counted as the same emoji.",
+      "The listed emojis are also charted, either as bars or as their daily uses (for the first 5 of them, over the last 30 days by default)."
+    ])
+    .usage_localized("en-US", ["/emoji-stats show [view] [kind] [source] [since] [user] [chart] [page]"])
+    .examples_localized("en-US", [
+      "/emoji-stats show",
+      "/emoji-stats show page:3",
+      "/emoji-stats show view:Never used since:30d",
+      "/emoji-stats show view:Least used since:4w",
+      "/emoji-stats show kind:Stickers view:Never used",
+      "/emoji-stats show kind:Unicode emojis source:Reactions",
+      "/emoji-stats show user:@Nanachi",
+      "/emoji-stats show chart:Daily uses since:8w"
+    ])
+)]
+async fn emoji_stats_show(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "view")]
+  #[description_localized("en-US", "Which emojis to list (default most used)")]
+  view: Option<EmojiStatsView>,
+  #[name_localized("en-US", "kind")]
+  #[description_localized("en-US", "Only list custom emojis, Unicode emojis or stickers (default all)")]
+  kind: Option<EmojiKind>,
+  #[name_localized("en-US", "source")]
+  #[description_localized("en-US", "Only count uses in messages or in reactions (default both)")]
+  source: Option<EmojiSource>,
+  #[name_localized("en-US", "since")]
+  #[description_localized("en-US", "How far back to count uses, like 30d or 4w (default all time)")]
+  #[max_length = 16]
+  since: Option<String>,
+  #[name_localized("en-US", "user")]
+  #[description_localized("en-US", "Only count uses by this user")]
+  user: Option<UserId>,
+  #[name_localized("en-US", "chart")]
+  #[description_localized("en-US", "How to chart the listed emojis (default bars)")]
+  chart: Option<EmojiStatsChart>,
+  #[name_localized("en-US", "page")]
+  #[description_localized("en-US", "The page of results to display (results are grouped 20 at a time)")]
+  #[min = 1]
+  #[max = 65536]
+  page: Option<usize>
+) -> MelodyResult {
+  const PER_PAGE: usize = 20;
+  const DAILY_USES_DAYS: u64 = 30;
+
+  let core = Core::from(ctx);
+
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let page = page.unwrap_or(1) - 1;
+  let view = view.unwrap_or_default();
+  let today = Utc::now().date_naive();
+
+  let since = match since {
+    Some(since) => match parse_since(&since, today) {
+      Some(since) => Some(since),
+      None => {
+        let response = format!("Invalid duration, use a number of days or weeks like `30d` or `4w`, up to {RETENTION_DAYS} days");
+        ctx.reply(response).await.context("failed to send reply")?;
+        return Ok(());
+      }
+    },
+    None => None
+  };
+
+  // never used emojis have nothing to chart
+  let chart = chart.unwrap_or_default();
+  let chart = if view == EmojiStatsView::NeverUsed { EmojiStatsChart::Off } else { chart };
+  if chart != EmojiStatsChart::Off {
+    ctx.defer().await.context("failed to defer response")?;
+  };
+
+  let (emoji_statistics, daily_uses) = core.operate_persist_guild(guild_id, async |persist_guild| {
+    let uses = persist_guild.emoji_stats.get_emoji_uses(since, user, source);
+    let daily_uses = (chart == EmojiStatsChart::DailyUses).then(|| {
+      let start = since.unwrap_or(today - Days::new(DAILY_USES_DAYS - 1));
+      persist_guild.emoji_stats.get_daily_emoji_uses(start, user, source)
+    });
+
+    core.cache.guild(guild_id).map(|guild| {
+      let count = |key: &EmojiKey| uses.get(key).copied().unwrap_or(0);
+      let custom = guild.emojis.values().map(|emoji| {
+        let key = EmojiKey::Custom(emoji.id);
+        EmojiStatsEntry { name: emoji.name.clone(), display: emoji.to_string(), count: count(&key), key }
+      });
+      let stickers = guild.stickers.values().map(|sticker| {
+        let key = EmojiKey::Sticker(sticker.id);
+        EmojiStatsEntry { name: sticker.name.clone(), display: format!("sticker {}", Blockify(&sticker.name)), count: count(&key), key }
+      });
+      // there are far too many unicode emojis to list those that were never used
+      let unicode = uses.iter().filter_map(|(key, &count)| match key {
+        EmojiKey::Unicode(emoji) => Some(EmojiStatsEntry {
+          name: emojis::get(emoji).map_or(emoji.clone(), |emoji| emoji.name().to_owned()),
+          display: emoji.clone(),
+          key: key.clone(),
+          count
+        }),
+        _ => None
+      });
+
+      let emoji_statistics = view.select(custom.chain(stickers).chain(unicode).filter(|entry| {
+        kind.is_none_or(|kind| entry.key.kind() == kind)
+      }));
+
+      (emoji_statistics, daily_uses)
+    }).ok_or(MelodyError::command_cache_failure("guild"))
+  }).await?;
+
+  let page_start = page * PER_PAGE;
+  let page_entries = emoji_statistics.into_iter()
+    .skip(page_start).take(PER_PAGE)
+    .collect::<Vec<EmojiStatsEntry>>();
+  let entries = page_entries.iter()
+    .enumerate()
+    .map(|(i, entry)| match view {
+      EmojiStatsView::NeverUsed => format!("`#{}` {}", page_start + i + 1, entry.display),
+      _ => format!("`#{}` {} ({} times)", page_start + i + 1, entry.display, entry.count)
+    })
+    .collect::<Vec<String>>();
+
+  let mut description = view.name().to_owned();
+  if let Some(kind) = kind {
+    description.push_str(&format!(" {}", kind.name().to_lowercase()));
+  };
+
+  if let Some(source) = source {
+    description.push_str(&format!(" in {}", source.name().to_lowercase()));
+  };
+
+  // mentions and timestamps can't be drawn, so the chart's title only has the date
+  let title = match daily_uses.as_ref().and_then(|daily_uses| daily_uses.first()).map(|&(day, _)| day).or(since) {
+    Some(day) => format!("{description} since {}", day.format("%Y-%m-%d")),
+    None => description.clone()
+  };
+
+  let mut header = description;
+  if let Some(user) = user {
+    header.push_str(&format!(" by {}", user.mention()));
+  };
+
+  if let Some(since) = since {
+    let since = Timestamp::new(since.and_time(NaiveTime::MIN).and_utc(), TimestampFormat::LongDate);
+    header.push_str(&format!(" since {since}"));
+  };
+
+  let response = match entries.is_empty() {
+    true => format!("**{header}**\n(No results)"),
+    false => format!("**{header}**\n{}", entries.join("\n"))
+  };
+
+  let mut reply = CreateReply::default().reply(true).content(response);
+  if !page_entries.is_empty() {
+    if let Some(chart) = render_chart(&core, chart, title, &page_entries, daily_uses).await {
+      reply = reply.attachment(CreateAttachment::bytes(chart, "emoji-stats.png"));
+    };
+  };
+
+  ctx.send(reply).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  rename = "backfill",
+  name_localized("en-US", "backfill"),
+  description_localized("en-US", "Counts emoji uses from the history of this server's channels"),
+  custom_data = CommandMetaData::new()
+    .info_localized_concat("en-US", [
+      "Goes through the history of every channel and thread the bot can read, including archived threads and forum posts, counting uses from before emoji stats started being counted in this server.",
+      "Reactions found this way can't be attributed to anyone, and are counted on the day their message was sent.",
+      "This can take a long while in large servers. If it is interrupted, running it again picks up where it left off, without counting any message twice."
+    ])
+    .usage_localized("en-US", ["/emoji-stats backfill"])
+    .examples_localized("en-US", ["/emoji-stats backfill"])
+)]
+async fn emoji_stats_backfill(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  let reply = ctx.reply("Backfilling emoji stats...").await.context("failed to send reply")?;
+  let mut report = BackfillReport { core: &core, ctx, reply, message: None };
+
+  let result = backfill_emoji_stats(&core, guild_id, async |progress: &EmojiBackfillProgress| {
+    report.update(format!("Backfilling emoji stats... {}", format_backfill_progress(progress))).await.log_error();
+  }).await;
+
+  let response = match &result {
+    Ok(Ok(progress)) if progress.channels_skipped > 0 => format!(
+      "Finished backfilling emoji stats, {}\n{} channels couldn't be read, run this again once the bot can read them to backfill them too",
+      format_backfill_progress(progress), progress.channels_skipped
+    ),
+    Ok(Ok(progress)) => format!("Finished backfilling emoji stats, {}", format_backfill_progress(progress)),
+    Ok(Err(error)) => error.to_string(),
+    Err(..) => "Backfilling emoji stats was interrupted, run this again to pick up where it left off".to_owned()
+  };
+
+  report.update(response).await.log_error();
+  result.map(|_| ())
+}
+
+/// Where a backfill's progress is reported. Interaction tokens expire after 15 minutes, after which
+/// the reply can no longer be edited, so from then on progress is reported in a message of its own.
+struct BackfillReport<'a> {
+  core: &'a Core,
+  ctx: MelodyContext<'a>,
+  reply: ReplyHandle<'a>,
+  message: Option<Message>
+}
+
+impl BackfillReport<'_> {
+  async fn update(&mut self, content: String) -> MelodyResult {
+    if let Some(message) = &mut self.message {
+      message.edit(self.core, EditMessage::new().content(content))
+        .await.context("failed to edit backfill progress message")?;
+    } else if self.reply.edit(self.ctx, CreateReply::default().content(content.clone())).await.is_err() {
+      let message = self.ctx.channel_id().say(self.core, content)
+        .await.context("failed to send backfill progress message")?;
+      self.message = Some(message);
+    };
+
+    Ok(())
+  }
+}
+
+fn format_backfill_progress(progress: &EmojiBackfillProgress) -> String {
+  format!(
+    "{} of {} channels done, {} messages read, {} uses counted",
+    progress.channels_done, progress.channels_total, progress.messages, progress.uses
+  )
+}
+
+/// Charts the listed emojis, with their images downloaded first.
+async fn render_chart(
+  core: &Core,
+  chart: EmojiStatsChart,
+  title: String,
+  entries: &[EmojiStatsEntry],
+  daily_uses: Option<Vec<(NaiveDate, HashMap<EmojiKey, usize>)>>
+) -> Option<Vec<u8>> {
+  const MAX_SERIES: usize = 5;
+
+  let entries = match chart {
+    EmojiStatsChart::Bars => entries,
+    EmojiStatsChart::DailyUses => &entries[..entries.len().min(MAX_SERIES)],
+    EmojiStatsChart::Off => return None
+  };
+
+  let icons = futures::future::join_all(entries.iter().map(|entry| {
+    core.state.emoji_images.get(&entry.key, ICON_SIZE)
+  })).await;
+
+  let result = match daily_uses {
+    Some(daily_uses) => {
+      let last = daily_uses.len().saturating_sub(1);
+      let x_labels = [0, last / 2, last].into_iter().dedup()
+        .filter_map(|i| daily_uses.get(i).map(|&(day, _)| (i, day.format("%b %-d").to_string())))
+        .collect();
+      let series = entries.iter().zip(icons)
+        .map(|(entry, icon)| Series {
+          label: entry.name.clone(),
+          icon,
+          values: daily_uses.iter()
+            .map(|(_, uses)| uses.get(&entry.key).copied().unwrap_or(0) as f64)
+            .collect()
+        })
+        .collect();
+      let chart = LineChart { title: Some(title), x_labels, series };
+      tokio::task::spawn_blocking(move || chart.render()).await.unwrap()
+    },
+    None => {
+      let bars = entries.iter().zip(icons)
+        .map(|(entry, icon)| Bar { label: entry.name.clone(), icon, value: entry.count as f64 })
+        .collect();
+      let chart = BarChart { title: Some(title), bars };
+      tokio::task::spawn_blocking(move || chart.render()).await.unwrap()
+    }
+  };
+
+  result.map_err(|error| error!("failed to render emoji stats chart: {error}")).ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, ChoiceParameter)]
+pub enum EmojiStatsChart {
+  #[default]
+  #[name = "Bars"]
+  #[name_localized("en-US", "Bars")]
+  Bars,
+  #[name = "Daily uses"]
+  #[name_localized("en-US", "Daily uses")]
+  DailyUses,
+  #[name = "None"]
+  #[name_localized("en-US", "None")]
+  Off
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, ChoiceParameter)]
+pub enum EmojiStatsView {
+  #[default]
+  #[name = "Most used"]
+  #[name_localized("en-US", "Most used")]
+  MostUsed,
+  #[name = "Least used"]
+  #[name_localized("en-US", "Least used")]
+  LeastUsed,
+  #[name = "Never used"]
+  #[name_localized("en-US", "Never used")]
+  NeverUsed
+}
+
+impl EmojiStatsView {
+  /// Filters and orders a guild's emojis and their uses for this view.
+  fn select(self, emojis: impl IntoIterator<Item = EmojiStatsEntry>) -> Vec<EmojiStatsEntry> {
+    let mut emojis = emojis.into_iter()
+      .filter(|entry| (entry.count == 0) == (self == EmojiStatsView::NeverUsed))
+      .collect::<Vec<EmojiStatsEntry>>();
+    emojis.sort_by(|a, b| a.name.cmp(&b.name));
+    match self {
+      EmojiStatsView::MostUsed => emojis.sort_by(|a, b| Ord::cmp(&a.count, &b.count).reverse()),
+      EmojiStatsView::LeastUsed => emojis.sort_by(|a, b| Ord::cmp(&a.count, &b.count)),
+      EmojiStatsView::NeverUsed => ()
+    };
+
+    emojis
+  }
+}
+
+#[derive(Debug, Clone)]
+struct EmojiStatsEntry {
+  key: EmojiKey,
+  name: String,
+  display: String,
+  count: usize
+}
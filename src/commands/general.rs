@@ -1,12 +1,9 @@
 use crate::prelude::*;
 use crate::data::Core;
-use crate::feature::dice_roll::Roll;
-use crate::utils::Blockify;
 use super::{MelodyContext, CommandMetaData};
 
 use chrono::{Utc, Duration};
 use log::Level;
-use melody_random::SecureRng;
 use poise::reply::CreateReply;
 use serenity::http::Http;
 use serenity::model::guild::Member;
@@ -145,52 +142,6 @@ pub async fn avatar(
   Ok(())
 }
 
-#[poise::command(
-  slash_command,
-  guild_only,
-  rename = "emoji-stats",
-  name_localized("en-US", "emoji-stats"),
-  description_localized("en-US", "Gets usage statistics of emojis for this server"),
-  custom_data = CommandMetaData::new()
-    .usage_localized("en-US", ["/emoji-stats [page]"])
-    .examples_localized("en-US", ["/emoji-stats 3"])
-)]
-pub async fn emoji_stats(
-  ctx: MelodyContext<'_>,
-  #[name_localized("en-US", "page")]
-  #[description_localized("en-US", "The page of results to display (results are grouped 20 at a time)")]
-  #[min = 1]
-  #[max = 65536]
-  page: Option<usize>
-) -> MelodyResult {
-  const PER_PAGE: usize = 20;
-
-  let core = Core::from(ctx);
-
-  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
-  let page = page.unwrap_or(1) - 1;
-
-  let emoji_statistics = core.operate_persist_guild(guild_id, async |persist_guild| {
-    core.cache.guild(guild_id).map(|guild| {
-      persist_guild.emoji_stats.get_emoji_uses(|emoji_id| guild.emojis.get(&emoji_id))
-    }).ok_or(MelodyError::command_cache_failure("guild"))
-  }).await?;
-
-  let page_start = page * PER_PAGE;
-  let entries = emoji_statistics.into_iter()
-    .enumerate().skip(page_start).take(PER_PAGE as usize)
-    .map(|(i, (emoji, count))| format!("`#{}` {emoji} ({count} times)", i + 1))
-    .collect::<Vec<String>>();
-
-  let response = match entries.is_empty() {
-    true => "(No results)".to_owned(),
-    false => entries.join("\n")
-  };
-
-  ctx.reply(response).await.context("failed to send reply")?;
-  Ok(())
-}
-
 #[poise::command(
   slash_command,
   guild_only,
@@ -297,45 +248,6 @@ pub async fn console(
   Ok(())
 }
 
-#[poise::command(
-  slash_command,
-  description_localized("en-US", "Rolls a configurable dice"),
-  custom_data = CommandMetaData::new()
-    .usage_localized("en-US", ["/roll <dice notation>"])
-    .examples_localized("en-US", [
-      "/roll '3d20'",
-      "/roll 'd20 + 2 advantage'",
-      "/roll 'd20 - 2 disadvantage'",
-      "/roll '6d6 max 2'",
-      "/roll '6d6 min 2'",
-      "/roll '2d8 + 3'",
-      "/roll 'coin'"
-    ])
-)]
-pub async fn roll(
-  ctx: MelodyContext<'_>,
-  #[description_localized("en-US", "The notation for the dice roll to be made")]
-  #[max_length = 1000]
-  notation: String
-) -> MelodyResult {
-  let response = match notation.parse::<Roll>() {
-    Ok(roll) => {
-      let mut rng = SecureRng::new();
-      let roll_message = roll.execute(&mut rng).to_string();
-      if roll_message.len() > 2000 {
-        "The resulting message was too long to send...".to_owned()
-      } else {
-        roll_message
-      }
-    },
-    Err(error) => {
-      Blockify(format_args!("Error: {error}")).to_string()
-    }
-  };
-
-  ctx.reply(response).await.context("failed to send reply")?;
-  Ok(())
-}
 
 
 
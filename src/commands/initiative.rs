@@ -0,0 +1,546 @@
+use crate::prelude::*;
+use crate::data::Core;
+use crate::feature::dice_roll::{
+  update_initiative_message, Combatant, DicePlayer, ExecutedRoll, HitPoints,
+  HitPointsChange, InitiativeTracker, RollHistoryEntry, RollSeed
+};
+use crate::utils::Blockify;
+use super::{MelodyContext, CommandMetaData};
+use super::dice_roll::{get_dice_player, record_roll};
+
+use poise::reply::CreateReply;
+use serenity::builder::EditMessage;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+use std::fmt;
+
+
+
+#[poise::command(
+  slash_command,
+  subcommands(
+    "initiative_start",
+    "initiative_add",
+    "initiative_remove",
+    "initiative_set",
+    "initiative_next",
+    "initiative_previous",
+    "initiative_hp",
+    "initiative_condition",
+    "initiative_show",
+    "initiative_end"
+  ),
+  guild_only,
+  description_localized("en-US", "Track initiative, turns and hit points during combat"),
+  custom_data = CommandMetaData::new()
+    .info_localized_concat("en-US", [
+      "Each channel may have one encounter at a time, which is displayed in a single message that is kept up to date.",
+      "Whoever starts an encounter is its GM, and may change any combatant or end it.",
+      "Anyone else may add their own combatants, change them, and pass the turn when it is theirs.",
+      "Initiative may be a fixed number or a roll, and rolls may use the variables on your `/roll sheet`."
+    ])
+    .usage_localized("en-US", [
+      "/initiative start",
+      "/initiative add <name> <initiative> [hp]",
+      "/initiative remove <name>",
+      "/initiative set <name> <initiative>",
+      "/initiative next",
+      "/initiative previous",
+      "/initiative hp <name> <change>",
+      "/initiative condition <name> <condition>",
+      "/initiative show",
+      "/initiative end"
+    ])
+    .examples_localized("en-US", [
+      "/initiative add Goblin 'd20 + 2' 7",
+      "/initiative add Thalia 'd20 + @dex_mod'",
+      "/initiative hp Goblin -5",
+      "/initiative condition Thalia prone"
+    ])
+)]
+pub async fn initiative(_ctx: MelodyContext<'_>) -> MelodyResult {
+  Err(MelodyError::COMMAND_PRECONDITION_VIOLATION_ROOT_COMMAND)
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "start",
+  name_localized("en-US", "start"),
+  description_localized("en-US", "Starts a new encounter in this channel, with you as its GM"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/initiative start"])
+    .examples_localized("en-US", ["/initiative start"])
+)]
+async fn initiative_start(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let channel_id = ctx.channel_id();
+  let tracker = InitiativeTracker::new(ctx.author().id);
+
+  let started = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    if persist_guild.initiative_trackers.contains_key(&channel_id) { return Ok(false) };
+    persist_guild.initiative_trackers.insert(channel_id, tracker.clone());
+    Ok(true)
+  }).await?;
+
+  if !started {
+    let response = "There is already an encounter in this channel, use `/initiative end` to end it first";
+    ctx.send(CreateReply::default().ephemeral(true).content(response))
+      .await.context("failed to send reply")?;
+    return Ok(());
+  };
+
+  let reply = CreateReply::default()
+    .embed(tracker.create_embed())
+    .components(tracker.create_components());
+  let message = ctx.send(reply).await.context("failed to send reply")?
+    .into_message().await.context("failed to get reply message")?;
+
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    if let Some(tracker) = persist_guild.initiative_trackers.get_mut(&channel_id) {
+      tracker.message = Some(message.id);
+    };
+
+    Ok(())
+  }).await?;
+
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "add",
+  name_localized("en-US", "add"),
+  description_localized("en-US", "Adds a combatant to the encounter in this channel"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/initiative add <name> <initiative> [hp]"])
+    .examples_localized("en-US", [
+      "/initiative add Goblin 'd20 + 2' 7",
+      "/initiative add Thalia 'd20 + @dex_mod'",
+      "/initiative add Ogre 9 59"
+    ])
+)]
+async fn initiative_add(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "name")]
+  #[description_localized("en-US", "The name of the combatant")]
+  #[max_length = 32]
+  name: String,
+  #[name_localized("en-US", "initiative")]
+  #[description_localized("en-US", "The combatant's initiative, or a roll for it")]
+  #[max_length = 1000]
+  initiative: String,
+  #[name_localized("en-US", "hp")]
+  #[description_localized("en-US", "The combatant's hit points")]
+  hp: Option<i64>
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let user_id = ctx.author().id;
+  let player = get_dice_player(&core, Some(guild_id), user_id).await?;
+
+  let result = match roll_initiative(&player, &initiative, user_id) {
+    Ok((initiative, roll)) => {
+      let hit_points = hp.map(|hp| HitPoints { current: hp, max: hp });
+      let combatant = Combatant::new(name, user_id, initiative, hit_points);
+      let result = operate_tracker(&core, guild_id, ctx.channel_id(), |tracker| {
+        let response = match &roll {
+          Some((executed_roll, entry)) => format!(
+            "**{}** rolled for initiative: {executed_roll}\n-# Seed `{}`",
+            combatant.name, entry.seed
+          ),
+          None => format!("**{}** joined the encounter with an initiative of **{initiative}**", combatant.name)
+        };
+
+        tracker.add(combatant).map_err(error_response)?;
+        Ok(response)
+      }).await?;
+
+      if let (Ok(..), Some((_, entry))) = (&result, roll) {
+        record_roll(&core, guild_id, ctx.channel_id(), entry).await?;
+      };
+
+      result
+    },
+    Err(error) => Err(error)
+  };
+
+  let reply = match result {
+    Ok(response) if response.len() <= 2000 => CreateReply::default().content(response),
+    Ok(..) => CreateReply::default().content("The resulting message was too long to send..."),
+    Err(error) => CreateReply::default().ephemeral(true).content(error)
+  };
+
+  ctx.send(reply).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "remove",
+  name_localized("en-US", "remove"),
+  description_localized("en-US", "Removes a combatant from the encounter in this channel"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/initiative remove <name>"])
+    .examples_localized("en-US", ["/initiative remove Goblin"])
+)]
+async fn initiative_remove(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "name")]
+  #[description_localized("en-US", "The name of the combatant to remove")]
+  #[max_length = 32]
+  name: String
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let user_id = ctx.author().id;
+  let result = operate_tracker(&core, guild_id, ctx.channel_id(), |tracker| {
+    check_can_edit(tracker, &name, user_id)?;
+    let combatant = tracker.remove(&name).map_err(error_response)?;
+    Ok(format!("Removed **{}** from the encounter", combatant.name))
+  }).await?;
+
+  send_ephemeral(ctx, result).await
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "set",
+  name_localized("en-US", "set"),
+  description_localized("en-US", "Changes a combatant's initiative, moving them in the turn order"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/initiative set <name> <initiative>"])
+    .examples_localized("en-US", ["/initiative set Goblin 14", "/initiative set Thalia 'd20 + @dex_mod'"])
+)]
+async fn initiative_set(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "name")]
+  #[description_localized("en-US", "The name of the combatant")]
+  #[max_length = 32]
+  name: String,
+  #[name_localized("en-US", "initiative")]
+  #[description_localized("en-US", "The combatant's new initiative, or a roll for it")]
+  #[max_length = 1000]
+  initiative: String
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let user_id = ctx.author().id;
+  let player = get_dice_player(&core, Some(guild_id), user_id).await?;
+
+  let result = match roll_initiative(&player, &initiative, user_id) {
+    Ok((initiative, roll)) => {
+      let result = operate_tracker(&core, guild_id, ctx.channel_id(), |tracker| {
+        let name = check_can_edit(tracker, &name, user_id)?;
+        tracker.set_initiative(&name, initiative).map_err(error_response)?;
+        Ok(format!("Changed **{name}**'s initiative to **{initiative}**"))
+      }).await?;
+
+      if let (Ok(..), Some((_, entry))) = (&result, roll) {
+        record_roll(&core, guild_id, ctx.channel_id(), entry).await?;
+      };
+
+      result
+    },
+    Err(error) => Err(error)
+  };
+
+  send_ephemeral(ctx, result).await
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "next",
+  name_localized("en-US", "next"),
+  description_localized("en-US", "Moves on to the next combatant's turn"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/initiative next"])
+    .examples_localized("en-US", ["/initiative next"])
+)]
+async fn initiative_next(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let user_id = ctx.author().id;
+  let result = operate_tracker(&core, guild_id, ctx.channel_id(), |tracker| {
+    check_can_advance(tracker, user_id)?;
+    tracker.next_turn();
+    Ok(turn_response(tracker))
+  }).await?;
+
+  send_ephemeral(ctx, result).await
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "previous",
+  name_localized("en-US", "previous"),
+  description_localized("en-US", "Moves back to the previous combatant's turn"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/initiative previous"])
+    .examples_localized("en-US", ["/initiative previous"])
+)]
+async fn initiative_previous(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let user_id = ctx.author().id;
+  let result = operate_tracker(&core, guild_id, ctx.channel_id(), |tracker| {
+    check_can_advance(tracker, user_id)?;
+    match tracker.previous_turn() {
+      true => Ok(turn_response(tracker)),
+      false => Err("It is already the first turn of the encounter".to_owned())
+    }
+  }).await?;
+
+  send_ephemeral(ctx, result).await
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "hp",
+  name_localized("en-US", "hp"),
+  description_localized("en-US", "Damages, heals or sets a combatant's hit points"),
+  custom_data = CommandMetaData::new()
+    .info_localized_concat("en-US", [
+      "Use a value like `-7` to deal damage, `+5` to heal (up to their maximum hit points),",
+      "`30` to set both their current and maximum hit points, or `12/30` to set them separately."
+    ])
+    .usage_localized("en-US", ["/initiative hp <name> <change>"])
+    .examples_localized("en-US", [
+      "/initiative hp Goblin -7",
+      "/initiative hp Thalia +5",
+      "/initiative hp Ogre 59",
+      "/initiative hp Ogre 40/59"
+    ])
+)]
+async fn initiative_hp(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "name")]
+  #[description_localized("en-US", "The name of the combatant")]
+  #[max_length = 32]
+  name: String,
+  #[name_localized("en-US", "change")]
+  #[description_localized("en-US", "The change to make, like -7, +5, 30 or 12/30")]
+  #[max_length = 50]
+  change: String
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let user_id = ctx.author().id;
+
+  let result = match change.parse::<HitPointsChange>() {
+    Ok(change) => operate_tracker(&core, guild_id, ctx.channel_id(), |tracker| {
+      let name = check_can_edit(tracker, &name, user_id)?;
+      let hit_points = tracker.change_hit_points(&name, change).map_err(error_response)?;
+      Ok(format!("**{name}** now has {hit_points} HP"))
+    }).await?,
+    Err(error) => Err(error_response(error))
+  };
+
+  send_ephemeral(ctx, result).await
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "condition",
+  name_localized("en-US", "condition"),
+  description_localized("en-US", "Adds a condition to a combatant, or removes it if they already have it"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/initiative condition <name> <condition>"])
+    .examples_localized("en-US", ["/initiative condition Thalia prone", "/initiative condition Goblin poisoned"])
+)]
+async fn initiative_condition(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "name")]
+  #[description_localized("en-US", "The name of the combatant")]
+  #[max_length = 32]
+  name: String,
+  #[name_localized("en-US", "condition")]
+  #[description_localized("en-US", "The condition to add or remove")]
+  #[max_length = 20]
+  condition: String
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let user_id = ctx.author().id;
+  let result = operate_tracker(&core, guild_id, ctx.channel_id(), |tracker| {
+    let name = check_can_edit(tracker, &name, user_id)?;
+    Ok(match tracker.toggle_condition(&name, &condition).map_err(error_response)? {
+      true => format!("**{name}** is now *{}*", condition.trim().to_lowercase()),
+      false => format!("**{name}** is no longer *{}*", condition.trim().to_lowercase())
+    })
+  }).await?;
+
+  send_ephemeral(ctx, result).await
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "show",
+  name_localized("en-US", "show"),
+  description_localized("en-US", "Displays the encounter in this channel again, below the latest messages"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/initiative show"])
+    .examples_localized("en-US", ["/initiative show"])
+)]
+async fn initiative_show(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let channel_id = ctx.channel_id();
+
+  let tracker = core.operate_persist_guild(guild_id, async |persist_guild| {
+    Ok(persist_guild.initiative_trackers.get(&channel_id).cloned())
+  }).await?;
+  let Some(tracker) = tracker else {
+    return send_ephemeral(ctx, Err(NO_ENCOUNTER.to_owned())).await;
+  };
+
+  let reply = CreateReply::default()
+    .embed(tracker.create_embed())
+    .components(tracker.create_components());
+  let message = ctx.send(reply).await.context("failed to send reply")?
+    .into_message().await.context("failed to get reply message")?;
+
+  let previous_message = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    Ok(persist_guild.initiative_trackers.get_mut(&channel_id)
+      .and_then(|tracker| tracker.message.replace(message.id)))
+  }).await?;
+
+  // the old message's buttons would no longer do anything
+  if let Some(previous_message) = previous_message {
+    channel_id.delete_message(&core, previous_message).await.ok();
+  };
+
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "end",
+  name_localized("en-US", "end"),
+  description_localized("en-US", "Ends the encounter in this channel"),
+  custom_data = CommandMetaData::new()
+    .info_localized("en-US", "Only the encounter's GM, or members who can manage messages, may end it.")
+    .usage_localized("en-US", ["/initiative end"])
+    .examples_localized("en-US", ["/initiative end"])
+)]
+async fn initiative_end(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let channel_id = ctx.channel_id();
+  let user_id = ctx.author().id;
+  let can_manage_messages = ctx.author_member().await
+    .and_then(|member| member.permissions)
+    .is_some_and(|permissions| permissions.manage_messages());
+
+  let result = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    let Some(tracker) = persist_guild.initiative_trackers.get(&channel_id) else {
+      return Ok(Err(NO_ENCOUNTER.to_owned()));
+    };
+
+    if tracker.gm != user_id && !can_manage_messages {
+      return Ok(Err("Only the GM may end this encounter".to_owned()));
+    };
+
+    Ok(Ok(persist_guild.initiative_trackers.remove(&channel_id).unwrap()))
+  }).await?;
+
+  let tracker = match result {
+    Ok(tracker) => tracker,
+    Err(error) => return send_ephemeral(ctx, Err(error)).await
+  };
+
+  if let Some(message_id) = tracker.message {
+    let builder = EditMessage::new().components(Vec::new());
+    channel_id.edit_message(&core, message_id, builder).await
+      .context("failed to edit initiative tracker message").log_error();
+  };
+
+  let response = format!("The encounter has ended after {} rounds", tracker.round());
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+const NO_ENCOUNTER: &str = "There is no encounter in this channel, start one with `/initiative start`";
+
+/// Changes the encounter in a channel, then updates the message displaying it.
+/// The operation may fail with a message to be shown to the user, in which case nothing should be changed.
+async fn operate_tracker<R>(
+  core: &Core, guild_id: GuildId, channel_id: ChannelId,
+  operation: impl FnOnce(&mut InitiativeTracker) -> Result<R, String>
+) -> MelodyResult<Result<R, String>> {
+  let result = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    let Some(tracker) = persist_guild.initiative_trackers.get_mut(&channel_id) else {
+      return Ok(Err(NO_ENCOUNTER.to_owned()));
+    };
+
+    Ok(operation(tracker).map(|output| (output, tracker.clone())))
+  }).await?;
+
+  Ok(match result {
+    Ok((output, tracker)) => {
+      update_initiative_message(core, channel_id, &tracker).await.log_error();
+      Ok(output)
+    },
+    Err(error) => Err(error)
+  })
+}
+
+/// Returns the exact name of a combatant, if it exists and the user may change it.
+fn check_can_edit(tracker: &InitiativeTracker, name: &str, user_id: UserId) -> Result<String, String> {
+  let combatant = tracker.get(name).map_err(error_response)?;
+  match tracker.can_edit(user_id, combatant) {
+    true => Ok(combatant.name.clone()),
+    false => Err(format!("Only the GM or whoever added **{}** may change them", combatant.name))
+  }
+}
+
+fn check_can_advance(tracker: &InitiativeTracker, user_id: UserId) -> Result<(), String> {
+  match tracker.can_advance(user_id) {
+    true => Ok(()),
+    false => Err("Only the GM or the combatant whose turn it is may change turns".to_owned())
+  }
+}
+
+/// Either a fixed initiative, or a roll for it along with the roll's result and its entry for the roll history.
+fn roll_initiative(player: &DicePlayer, notation: &str, user_id: UserId) -> Result<(i64, Option<(ExecutedRoll, RollHistoryEntry)>), String> {
+  if let Ok(initiative) = notation.trim().parse::<i64>() {
+    return Ok((initiative, None));
+  };
+
+  let roll = player.parse_roll(notation).map_err(error_response)?;
+  let seed = RollSeed::generate();
+  let executed_roll = seed.execute(roll.clone()).map_err(error_response)?;
+  let entry = RollHistoryEntry::new(user_id, &roll, &executed_roll, false, seed);
+  Ok((executed_roll.total(), Some((executed_roll, entry))))
+}
+
+fn turn_response(tracker: &InitiativeTracker) -> String {
+  match tracker.current() {
+    Some(current) => format!("Round {}, it is now **{}**'s turn", tracker.round(), current.name),
+    None => "There are no combatants in this encounter".to_owned()
+  }
+}
+
+fn error_response(error: impl fmt::Display) -> String {
+  Blockify(format_args!("Error: {error}")).to_string()
+}
+
+async fn send_ephemeral(ctx: MelodyContext<'_>, result: Result<String, String>) -> MelodyResult {
+  let response = match result {
+    Ok(response) | Err(response) => response
+  };
+
+  ctx.send(CreateReply::default().ephemeral(true).content(response))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
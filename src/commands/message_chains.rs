@@ -0,0 +1,315 @@
+use crate::prelude::*;
+use crate::data::Core;
+use crate::utils::{Blockify, Timestamp, TimestampFormat};
+use super::{MelodyContext, CommandMetaData};
+
+use serenity::model::id::ChannelId;
+
+use std::time::Duration;
+
+
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  subcommands(
+    "message_chains_show",
+    "message_chains_enabled",
+    "message_chains_threshold",
+    "message_chains_chance",
+    "message_chains_cooldown",
+    "message_chains_channel_exclude",
+    "message_chains_channel_include"
+  ),
+  rename = "message-chains",
+  name_localized("en-US", "message-chains"),
+  description_localized("en-US", "Configures how the bot joins in when people repeat the same message"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", [
+      "/message-chains show",
+      "/message-chains enabled <enabled>",
+      "/message-chains threshold <messages>",
+      "/message-chains chance <percent>",
+      "/message-chains cooldown <seconds>",
+      "/message-chains channel-exclude <channel>",
+      "/message-chains channel-include <channel>"
+    ])
+    .examples_localized("en-US", [
+      "/message-chains show",
+      "/message-chains enabled false",
+      "/message-chains threshold 4",
+      "/message-chains chance 50",
+      "/message-chains cooldown 300",
+      "/message-chains channel-exclude #serious-discussion",
+      "/message-chains channel-include #serious-discussion"
+    ])
+)]
+pub async fn message_chains(_ctx: MelodyContext<'_>) -> MelodyResult {
+  Err(MelodyError::COMMAND_PRECONDITION_VIOLATION_ROOT_COMMAND)
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "show",
+  name_localized("en-US", "show"),
+  description_localized("en-US", "Displays the message chain settings for this server, and its longest chain"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/message-chains show"])
+    .examples_localized("en-US", ["/message-chains show"])
+)]
+async fn message_chains_show(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  let settings = core.operate_persist_guild(guild_id, async |persist_guild| {
+    Ok(persist_guild.message_chains.clone())
+  }).await?;
+
+  let excluded_channels = match settings.excluded_channels.is_empty() {
+    true => "(none)".to_owned(),
+    false => settings.excluded_channels.iter().map(|channel_id| channel_id.mention().to_string()).join(", ")
+  };
+  let longest_chain = match &settings.longest_chain {
+    Some(longest_chain) => {
+      let content = match longest_chain.content.is_empty() {
+        true => "a sticker or attachment".to_owned(),
+        false => Blockify(&longest_chain.content).to_string()
+      };
+
+      format!(
+        "{} messages of {content} in {}, {}",
+        longest_chain.len,
+        longest_chain.channel.mention(),
+        Timestamp::new(longest_chain.time, TimestampFormat::Relative)
+      )
+    },
+    None => "(none yet)".to_owned()
+  };
+
+  let response = [
+    format!("Enabled: {}", if settings.enabled { "yes" } else { "no" }),
+    format!("Threshold: {} messages", settings.threshold),
+    format!("Chance: {}%", settings.chance * 100.0),
+    format!("Cooldown: {} seconds", settings.cooldown.as_secs()),
+    format!("Excluded channels: {excluded_channels}"),
+    format!("Longest chain: {longest_chain}")
+  ].join("\n");
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "enabled",
+  name_localized("en-US", "enabled"),
+  description_localized("en-US", "Turns message chains on or off for this server"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/message-chains enabled <enabled>"])
+    .examples_localized("en-US", ["/message-chains enabled false"])
+)]
+async fn message_chains_enabled(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "enabled")]
+  #[description_localized("en-US", "Whether the bot should join in on message chains in this server")]
+  enabled: bool
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.message_chains.enabled = enabled;
+    Ok(())
+  }).await?;
+
+  let response = match enabled {
+    true => "Message chains are now enabled in this server",
+    false => "Message chains are now disabled in this server"
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "threshold",
+  name_localized("en-US", "threshold"),
+  description_localized("en-US", "Sets how many people must repeat a message before the bot may join in"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/message-chains threshold <messages>"])
+    .examples_localized("en-US", ["/message-chains threshold 4"])
+)]
+async fn message_chains_threshold(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "messages")]
+  #[description_localized("en-US", "The number of messages in a chain before the bot may join in")]
+  #[min = 2]
+  #[max = 100]
+  messages: usize
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let messages = messages.clamp(2, 100);
+
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.message_chains.threshold = messages;
+    Ok(())
+  }).await?;
+
+  ctx.reply(format!("The bot may now join in on message chains once they reach {messages} messages"))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "chance",
+  name_localized("en-US", "chance"),
+  description_localized("en-US", "Sets how likely the bot is to join in on each message once a chain is long enough"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/message-chains chance <percent>"])
+    .examples_localized("en-US", ["/message-chains chance 50"])
+)]
+async fn message_chains_chance(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "percent")]
+  #[description_localized("en-US", "The percentage chance of joining in on each message")]
+  #[min = 0]
+  #[max = 100]
+  percent: f64
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let percent = percent.clamp(0.0, 100.0);
+
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.message_chains.chance = percent / 100.0;
+    Ok(())
+  }).await?;
+
+  ctx.reply(format!("The bot will now join in on {percent}% of messages in long enough chains"))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "cooldown",
+  name_localized("en-US", "cooldown"),
+  description_localized("en-US", "Sets how long the bot waits before joining in on another chain in the same channel"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/message-chains cooldown <seconds>"])
+    .examples_localized("en-US", ["/message-chains cooldown 300"])
+)]
+async fn message_chains_cooldown(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "seconds")]
+  #[description_localized("en-US", "The cooldown in seconds, or 0 for no cooldown")]
+  #[max = 86400]
+  seconds: u64
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let seconds = seconds.min(86400);
+
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.message_chains.cooldown = Duration::from_secs(seconds);
+    Ok(())
+  }).await?;
+
+  let response = match seconds {
+    0 => "The bot will no longer wait between joining in on message chains".to_owned(),
+    seconds => format!("The bot will now wait {seconds} seconds between joining in on message chains in the same channel")
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "channel-exclude",
+  name_localized("en-US", "channel-exclude"),
+  description_localized("en-US", "Stops the bot from joining in on message chains in a channel"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/message-chains channel-exclude <channel>"])
+    .examples_localized("en-US", ["/message-chains channel-exclude #serious-discussion"])
+)]
+async fn message_chains_channel_exclude(
+  ctx: MelodyContext<'_>,
+  #[rename = "channel"]
+  #[name_localized("en-US", "channel")]
+  #[description_localized("en-US", "The channel to exclude")]
+  channel_id: ChannelId
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  let added = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    Ok(persist_guild.message_chains.excluded_channels.insert(channel_id))
+  }).await?;
+
+  let response = match added {
+    true => format!("Message chains in {} will now be ignored", channel_id.mention()),
+    false => format!("Message chains in {} are already ignored", channel_id.mention())
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  rename = "channel-include",
+  name_localized("en-US", "channel-include"),
+  description_localized("en-US", "Allows the bot to join in on message chains in a channel again"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/message-chains channel-include <channel>"])
+    .examples_localized("en-US", ["/message-chains channel-include #serious-discussion"])
+)]
+async fn message_chains_channel_include(
+  ctx: MelodyContext<'_>,
+  #[rename = "channel"]
+  #[name_localized("en-US", "channel")]
+  #[description_localized("en-US", "The channel to include")]
+  channel_id: ChannelId
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  let removed = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    Ok(persist_guild.message_chains.excluded_channels.remove(&channel_id))
+  }).await?;
+
+  let response = match removed {
+    true => format!("Message chains in {} will no longer be ignored", channel_id.mention()),
+    false => format!("Message chains in {} are not ignored", channel_id.mention())
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
@@ -1,17 +1,30 @@
 use crate::prelude::*;
 use crate::data::{Core, State};
-use crate::feature::music_player::{MusicPlayer, QueueItem, AttachmentItem, YouTubeItem};
-use crate::utils::youtube;
+use crate::feature::music_player::{
+  MusicPlayer, MusicPlayerSettings, NowPlaying, QueueItem, QueueRemoveResult, VoteSkipResult,
+  AttachmentItem, DirectItem, YouTubeItem, YtDlpItem, channel_listeners
+};
+use crate::feature::roles::{DisplayRole, DisplayUser};
+use crate::utils::lyrics::Lyrics;
+use crate::utils::{is_public_url, youtube};
 use super::{MelodyContext, CommandMetaData};
 
+use poise::reply::CreateReply;
+use serenity::builder::{CreateEmbed, CreateEmbedFooter};
 use serenity::model::id::{ChannelId, GuildId, UserId};
 use serenity::model::channel::Attachment;
+use serenity::model::guild::Role;
 use melody_framework::commands::CommandConditionFunction;
 
 use std::sync::Arc;
+use std::time::Duration;
 
 
 
+const NOT_A_DJ: &str = "Only DJs can do that";
+const QUEUE_LIMIT_REACHED: &str = "You already have as many tracks in the queue as you are allowed";
+
+
 #[poise::command(
   slash_command,
   guild_only,
@@ -24,7 +37,10 @@ use std::sync::Arc;
     "music_player_loop",
     "music_player_skip",
     "music_player_stop",
-    "music_player_kill"
+    "music_player_kill",
+    "music_player_now_playing",
+    "music_player_lyrics",
+    "music_player_settings"
   ),
   category = "music-player",
   rename = "music-player",
@@ -34,11 +50,14 @@ use std::sync::Arc;
     .info_localized_concat("en-US", [
       "Player functionality may be spotty or unreliable.",
       "If the player breaks or stops on a track indefinitely, command the bot to skip, leave the channel, and join the channel again.",
-      "Issuing the stop command will clear the queue, the leave command will not."
+      "Issuing the stop command will clear the queue, the leave command will not.",
+      "If a DJ role has been set, only DJs may stop, kill or clear the queue,",
+      "and everyone else must vote to skip tracks that they did not request."
     ])
     .usage_localized("en-US", [
       "/music-player play youtube <video-url>",
       "/music-player play youtube-playlist <playlist-url>",
+      "/music-player play url <url>",
       "/music-player play attachment <attachment>",
       "/music-player queue show [page]",
       "/music-player queue clear",
@@ -50,11 +69,17 @@ use std::sync::Arc;
       "/music-player loop <true|false>",
       "/music-player skip",
       "/music-player stop",
-      "/music-player kill"
+      "/music-player kill",
+      "/music-player now-playing",
+      "/music-player lyrics [synced]",
+      "/music-player settings dj-role [role]",
+      "/music-player settings vote-skip <percent>",
+      "/music-player settings queue-limit [limit]"
     ])
     .examples_localized("en-US", [
       "/music-player play youtube 'https://www.youtube.com/watch?v=dQw4w9WgXcQ'",
       "/music-player play youtube-playlist 'https://www.youtube.com/playlist?list=OLAK5uy_kZx-hTxk_EfczAhOP3eQT-kJlBsH7NJXs'",
+      "/music-player play url 'https://soundcloud.com/rick-astley-official/never-gonna-give-you-up-4'",
       "/music-player queue show",
       "/music-player queue clear",
       "/music-player queue remove 3",
@@ -65,7 +90,12 @@ use std::sync::Arc;
       "/music-player loop true",
       "/music-player skip",
       "/music-player stop",
-      "/music-player kill"
+      "/music-player kill",
+      "/music-player now-playing",
+      "/music-player lyrics true",
+      "/music-player settings dj-role @DJ",
+      "/music-player settings vote-skip 50",
+      "/music-player settings queue-limit 10"
     ])
     .condition(CommandConditionFunction::new_downcast(|state: Option<&State>| {
       state.is_some_and(|state| state.music_player.is_some())
@@ -81,6 +111,7 @@ pub async fn music_player(_ctx: MelodyContext<'_>) -> MelodyResult {
   subcommands(
     "music_player_play_youtube",
     "music_player_play_youtube_playlist",
+    "music_player_play_url",
     "music_player_play_attachment"
   ),
   category = "music-player",
@@ -91,11 +122,13 @@ pub async fn music_player(_ctx: MelodyContext<'_>) -> MelodyResult {
     .usage_localized("en-US", [
       "/music-player play youtube <video-url>",
       "/music-player play youtube-playlist <playlist-url>",
+      "/music-player play url <url>",
       "/music-player play attachment <attachment>"
     ])
     .examples_localized("en-US", [
       "/music-player play youtube 'https://www.youtube.com/watch?v=dQw4w9WgXcQ'",
-      "/music-player play youtube-playlist 'https://www.youtube.com/playlist?list=OLAK5uy_kZx-hTxk_EfczAhOP3eQT-kJlBsH7NJXs'"
+      "/music-player play youtube-playlist 'https://www.youtube.com/playlist?list=OLAK5uy_kZx-hTxk_EfczAhOP3eQT-kJlBsH7NJXs'",
+      "/music-player play url 'https://soundcloud.com/rick-astley-official/never-gonna-give-you-up-4'"
     ])
 )]
 async fn music_player_play(_ctx: MelodyContext<'_>) -> MelodyResult {
@@ -140,11 +173,15 @@ async fn music_player_play_youtube(
 
           ctx.defer().await.context("failed to defer response")?;
 
-          match music_player.play(&core, guild_id, channel_id, vec![item]).await {
-            Ok(()) => format!("Added video {item_str} to queue"),
-            Err(err) => {
-              error!("failed to connect to channel: {err}");
-              "Failed to connect to channel".to_owned()
+          if queue_capacity(&core, &music_player, guild_id, user_id).await? == Some(0) {
+            QUEUE_LIMIT_REACHED.to_owned()
+          } else {
+            match music_player.play(&core, guild_id, channel_id, user_id, vec![item]).await {
+              Ok(()) => format!("Added video {item_str} to queue"),
+              Err(err) => {
+                error!("failed to connect to channel: {err}");
+                "Failed to connect to channel".to_owned()
+              }
             }
           }
         })
@@ -198,17 +235,28 @@ async fn music_player_play_youtube_playlist(
               let mut items = playlist_info.entries.into_iter()
                 .map(|video_info| QueueItem::YouTube(YouTubeItem { id: video_info.id }))
                 .collect::<Vec<QueueItem>>();
-              let items_count = items.len();
 
               if shuffle {
                 items.shuffle_default();
               };
 
-              match music_player.play(&core, guild_id, channel_id, items).await {
-                Ok(()) => format!("Added playlist of {items_count} videos to queue"),
-                Err(err) => {
-                  error!("failed to connect to channel: {err}");
-                  "Failed to connect to channel".to_owned()
+              let capacity = queue_capacity(&core, &music_player, guild_id, user_id).await?;
+              let truncated = capacity.is_some_and(|capacity| items.len() > capacity);
+              if let Some(capacity) = capacity {
+                items.truncate(capacity);
+              };
+
+              let items_count = items.len();
+              if items_count == 0 && truncated {
+                QUEUE_LIMIT_REACHED.to_owned()
+              } else {
+                match music_player.play(&core, guild_id, channel_id, user_id, items).await {
+                  Ok(()) if truncated => format!("Added {items_count} videos from playlist to queue (you may not queue any more)"),
+                  Ok(()) => format!("Added playlist of {items_count} videos to queue"),
+                  Err(err) => {
+                    error!("failed to connect to channel: {err}");
+                    "Failed to connect to channel".to_owned()
+                  }
                 }
               }
             },
@@ -223,6 +271,86 @@ async fn music_player_play_youtube_playlist(
   }).await
 }
 
+#[poise::command(
+  slash_command,
+  guild_only,
+  category = "music-player",
+  rename = "url",
+  name_localized("en-US", "url"),
+  description_localized("en-US", "Plays audio from a link (SoundCloud, Bandcamp, direct file links, etc.) in your voice channel"),
+  custom_data = CommandMetaData::new()
+    .info_localized_concat("en-US", [
+      "Any site supported by yt-dlp may be used, as well as direct links to audio files."
+    ])
+    .usage_localized("en-US", [
+      "/music-player play url <url>"
+    ])
+    .examples_localized("en-US", [
+      "/music-player play url 'https://soundcloud.com/rick-astley-official/never-gonna-give-you-up-4'"
+    ])
+)]
+async fn music_player_play_url(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "url")]
+  #[description_localized("en-US", "The URL of the track to play")]
+  #[max_length = 1000]
+  url: String
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let user_id = ctx.author().id;
+
+  send_response_result(ctx, {
+    match ensure_in_channel(&core, guild_id, user_id).await {
+      Err(response) => Err(response),
+      Ok((music_player, channel_id)) => match youtube::parse_http_url(&url) {
+        None => Err("Invalid link".to_owned()),
+        Some(url) => Ok({
+          ctx.defer().await.context("failed to defer response")?;
+
+          let item = if !is_public_url(&url).await {
+            Err("That link does not lead to a public address".to_owned())
+          } else if let Some(video_id) = youtube::parse_video_url(url.as_str()) {
+            Ok(QueueItem::YouTube(YouTubeItem { id: video_id }))
+          } else if let Some(item) = DirectItem::parse(&url) {
+            Ok(QueueItem::Direct(item))
+          } else {
+            match music_player.yt_dlp().get_video_info(url.as_str()).await {
+              Ok(video_info) => Ok(QueueItem::YtDlp(YtDlpItem {
+                cache_key: Some(video_info.cache_key()),
+                url: video_info.webpage_url,
+                title: Some(video_info.title)
+              })),
+              Err(err) if err.is_user_error() => Err("That link is not supported".to_owned()),
+              Err(err) => {
+                error!("failed to retrieve track info: {err}");
+                Err("Failed to retrieve track info".to_owned())
+              }
+            }
+          };
+
+          match item {
+            Ok(..) if queue_capacity(&core, &music_player, guild_id, user_id).await? == Some(0) => {
+              QUEUE_LIMIT_REACHED.to_owned()
+            },
+            Ok(item) => {
+              let item_str = item.to_string();
+              match music_player.play(&core, guild_id, channel_id, user_id, vec![item]).await {
+                Ok(()) => format!("Added track {item_str} to queue"),
+                Err(err) => {
+                  error!("failed to connect to channel: {err}");
+                  "Failed to connect to channel".to_owned()
+                }
+              }
+            },
+            Err(response) => response
+          }
+        })
+      }
+    }
+  }).await
+}
+
 #[poise::command(
   slash_command,
   guild_only,
@@ -256,14 +384,17 @@ async fn music_player_play_attachment(
       Ok((music_player, channel_id)) => Ok({
         ctx.defer().await.context("failed to defer response")?;
 
-        match music_player.play(&core, guild_id, channel_id, vec![item]).await {
-          Ok(()) => format!("Added attachment {item_str} to queue"),
-          Err(err) => {
-            error!("failed to connect to channel: {err}");
-            "Failed to connect to channel".to_owned()
+        if queue_capacity(&core, &music_player, guild_id, user_id).await? == Some(0) {
+          QUEUE_LIMIT_REACHED.to_owned()
+        } else {
+          match music_player.play(&core, guild_id, channel_id, user_id, vec![item]).await {
+            Ok(()) => format!("Added attachment {item_str} to queue"),
+            Err(err) => {
+              error!("failed to connect to channel: {err}");
+              "Failed to connect to channel".to_owned()
+            }
           }
         }
-
       })
     }
   }).await
@@ -339,9 +470,13 @@ async fn music_player_queue_show(
         let page_start = page * PER_PAGE;
         let entries = queue_list.into_iter()
           .enumerate().skip(page_start).take(PER_PAGE)
-          .map(|(i, queue_item)| match i == 0 {
-            true => format!("Now Playing {queue_item}"),
-            false => format!("`#{}` {queue_item}", i)
+          .map(|(i, queue_entry)| {
+            let queue_item = queue_entry.item;
+            let requester = DisplayUser::new(queue_entry.requester, &core.cache);
+            match i == 0 {
+              true => format!("Now Playing {queue_item} (added by {requester})"),
+              false => format!("`#{}` {queue_item} (added by {requester})", i)
+            }
           })
           .collect::<Vec<String>>();
         if entries.is_empty() {
@@ -375,7 +510,7 @@ async fn music_player_queue_clear(ctx: MelodyContext<'_>) -> MelodyResult {
   let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
 
   send_response_result(ctx, {
-    match ensure_in_same_channel(&core, guild_id, ctx.author().id).await {
+    match ensure_dj(&core, guild_id, ctx.author().id).await? {
       Err(response) => Err(response),
       Ok((music_player, ..)) => Ok({
         music_player.queue_clear_keep_one(guild_id).await;
@@ -436,14 +571,18 @@ async fn music_player_queue_remove(
   let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
   let index = zusize::new(index.unwrap_or(1))
     .ok_or(MelodyError::COMMAND_PRECONDITION_VIOLATION_ARGUMENTS)?;
+  let user_id = ctx.author().id;
 
   send_response_result(ctx, {
-    match ensure_in_same_channel(&core, guild_id, ctx.author().id).await {
+    match ensure_in_same_channel(&core, guild_id, user_id).await {
       Err(response) => Err(response),
-      Ok((music_player, ..)) => Ok({
-        match music_player.queue_remove(guild_id, index).await {
-          Some(item) => format!("Removed item {item} from position {index} in queue"),
-          None => format!("No item at position {index} in queue")
+      Ok((music_player, channel_id)) => Ok({
+        let settings = get_settings(&core, guild_id).await?;
+        let user_is_dj = is_dj(&core, &settings, guild_id, user_id, channel_id);
+        match music_player.queue_remove(guild_id, index, |entry| user_is_dj || entry.requester == user_id).await {
+          QueueRemoveResult::Removed(entry) => format!("Removed item {} from position {index} in queue", entry.item),
+          QueueRemoveResult::Denied(..) => "Only DJs or the user who added that item can remove it".to_owned(),
+          QueueRemoveResult::NotFound => format!("No item at position {index} in queue")
         }
       })
     }
@@ -599,14 +738,28 @@ async fn music_player_skip(ctx: MelodyContext<'_>) -> MelodyResult {
   let core = Core::from(ctx);
   let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
 
+  let user_id = ctx.author().id;
+
   send_response_result(ctx, {
-    match ensure_in_same_channel(&core, guild_id, ctx.author().id).await {
+    match ensure_in_same_channel(&core, guild_id, user_id).await {
       Err(response) => Err(response),
-      Ok((music_player, ..)) => Ok({
+      Ok((music_player, channel_id)) => Ok({
         ctx.defer().await.context("failed to defer response")?;
-        music_player.skip(&core, guild_id).await;
 
-        "Skipped currently playing track".to_owned()
+        let settings = get_settings(&core, guild_id).await?;
+        let is_requester = music_player.current_requester(guild_id).await == Some(user_id);
+        if is_requester || is_dj(&core, &settings, guild_id, user_id, channel_id) {
+          music_player.skip(&core, guild_id).await;
+
+          "Skipped currently playing track".to_owned()
+        } else {
+          let required = settings.votes_required(channel_listeners(&core, guild_id, channel_id).len());
+          match music_player.vote_skip(&core, guild_id, user_id, required).await {
+            VoteSkipResult::NotPlaying => "Nothing is playing".to_owned(),
+            VoteSkipResult::Voted { votes, required } => format!("Voted to skip the current track ({votes}/{required} votes)"),
+            VoteSkipResult::Skipped => "Vote passed, skipped currently playing track".to_owned()
+          }
+        }
       })
     }
   }).await
@@ -628,7 +781,7 @@ async fn music_player_stop(ctx: MelodyContext<'_>) -> MelodyResult {
   let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
 
   send_response_result(ctx, {
-    match ensure_in_same_channel(&core, guild_id, ctx.author().id).await {
+    match ensure_dj(&core, guild_id, ctx.author().id).await? {
       Err(response) => Err(response),
       Ok((music_player, ..)) => Ok({
         ctx.defer().await.context("failed to defer response")?;
@@ -656,7 +809,7 @@ async fn music_player_kill(ctx: MelodyContext<'_>) -> MelodyResult {
   let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
 
   send_response_result(ctx, {
-    match ensure_in_same_channel(&core, guild_id, ctx.author().id).await {
+    match ensure_dj(&core, guild_id, ctx.author().id).await? {
       Err(response) => Err(response),
       Ok((music_player, channel_id)) => Ok({
         ctx.defer().await.context("failed to defer response")?;
@@ -672,6 +825,250 @@ async fn music_player_kill(ctx: MelodyContext<'_>) -> MelodyResult {
   }).await
 }
 
+#[poise::command(
+  slash_command,
+  guild_only,
+  category = "music-player",
+  rename = "now-playing",
+  name_localized("en-US", "now-playing"),
+  description_localized("en-US", "Shows information about the currently playing track"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/music-player now-playing"])
+    .examples_localized("en-US", ["/music-player now-playing"])
+)]
+async fn music_player_now_playing(ctx: MelodyContext<'_>) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  let music_player = match get_music_player(&core) {
+    Ok(music_player) => music_player,
+    Err(response) => return send_response_result(ctx, Err(response)).await
+  };
+
+  ctx.defer().await.context("failed to defer response")?;
+  let Some(now_playing) = music_player.now_playing(guild_id).await else {
+    return send_response_result(ctx, Err("(Nothing is playing)".to_owned())).await;
+  };
+
+  let lyrics = match now_playing.lyrics_query() {
+    Some(query) => music_player.lyrics_client().find(&query).await.unwrap_or_else(|err| {
+      error!("failed to retrieve lyrics: {err}");
+      None
+    }),
+    None => None
+  };
+
+  let embed = track_info_embed(&core, &now_playing, lyrics.as_ref());
+  ctx.send(CreateReply::default().reply(true).embed(embed)).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  category = "music-player",
+  rename = "lyrics",
+  name_localized("en-US", "lyrics"),
+  description_localized("en-US", "Shows the lyrics of the currently playing track"),
+  custom_data = CommandMetaData::new()
+    .info_localized_concat("en-US", [
+      "In synced mode, each line of the lyrics is posted in this channel as the track reaches it.",
+      "Synced lyrics stop when the track ends, or when synced lyrics are requested again."
+    ])
+    .usage_localized("en-US", ["/music-player lyrics [synced]"])
+    .examples_localized("en-US", ["/music-player lyrics", "/music-player lyrics true"])
+)]
+async fn music_player_lyrics(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "synced")]
+  #[description_localized("en-US", "Whether to post the lyrics line-by-line in time with the track (defaults to false)")]
+  synced: Option<bool>
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let synced = synced.unwrap_or(false);
+
+  let music_player = match get_music_player(&core) {
+    Ok(music_player) => music_player,
+    Err(response) => return send_response_result(ctx, Err(response)).await
+  };
+
+  ctx.defer().await.context("failed to defer response")?;
+  let Some(now_playing) = music_player.now_playing(guild_id).await else {
+    return send_response_result(ctx, Err("(Nothing is playing)".to_owned())).await;
+  };
+
+  let item = &now_playing.entry.item;
+  let Some(query) = now_playing.lyrics_query() else {
+    return send_response_result(ctx, Err(format!("Not enough is known about {item} to find its lyrics"))).await;
+  };
+
+  let response = match music_player.lyrics_client().find(&query).await {
+    Ok(Some(lyrics)) if lyrics.instrumental => Err(format!("{item} is an instrumental track")),
+    Ok(Some(lyrics)) if synced => match lyrics.synced_lines() {
+      None => Err(format!("No synced lyrics were found for {item}")),
+      Some(lines) => match music_player.start_synced_lyrics(&core, guild_id, ctx.channel_id(), lines).await {
+        true => Ok(format!("Posting synced lyrics for `{}` by `{}`", lyrics.track_name, lyrics.artist_name)),
+        false => Err("(Nothing is playing)".to_owned())
+      }
+    },
+    Ok(Some(lyrics)) => match lyrics.plain_text() {
+      None => Err(format!("No lyrics were found for {item}")),
+      Some(plain_text) => {
+        let embed = lyrics_embed(&lyrics, &plain_text);
+        ctx.send(CreateReply::default().reply(true).embed(embed)).await.context("failed to send reply")?;
+        return Ok(());
+      }
+    },
+    Ok(None) => Err(format!("No lyrics were found for {item}")),
+    Err(err) => {
+      error!("failed to retrieve lyrics: {err}");
+      Err("Failed to retrieve lyrics".to_owned())
+    }
+  };
+
+  send_response_result(ctx, response).await
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  subcommands(
+    "music_player_settings_dj_role",
+    "music_player_settings_vote_skip",
+    "music_player_settings_queue_limit"
+  ),
+  category = "music-player",
+  rename = "settings",
+  name_localized("en-US", "settings"),
+  description_localized("en-US", "Configures who may control the music player in this server"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", [
+      "/music-player settings dj-role [role]",
+      "/music-player settings vote-skip <percent>",
+      "/music-player settings queue-limit [limit]"
+    ])
+    .examples_localized("en-US", [
+      "/music-player settings dj-role @DJ",
+      "/music-player settings vote-skip 50",
+      "/music-player settings queue-limit 10"
+    ])
+)]
+async fn music_player_settings(_ctx: MelodyContext<'_>) -> MelodyResult {
+  Err(MelodyError::COMMAND_PRECONDITION_VIOLATION_ROOT_COMMAND)
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  category = "music-player",
+  rename = "dj-role",
+  name_localized("en-US", "dj-role"),
+  description_localized("en-US", "Sets (or unsets) the role allowed to skip, stop and clear the queue without voting"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/music-player settings dj-role [role]"])
+    .examples_localized("en-US", ["/music-player settings dj-role @DJ"])
+)]
+async fn music_player_settings_dj_role(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "role")]
+  #[description_localized("en-US", "The DJ role, omit to let everyone control the music player")]
+  role: Option<Role>
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+  let dj_role = role.map(|role| role.id);
+
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.music_player.dj_role = dj_role;
+    Ok(())
+  }).await?;
+
+  let response = match dj_role {
+    Some(dj_role) => format!("Set the DJ role to {}", DisplayRole::new(dj_role, guild_id, &core.cache)),
+    None => "Unset the DJ role, everyone may now control the music player".to_owned()
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  category = "music-player",
+  rename = "vote-skip",
+  name_localized("en-US", "vote-skip"),
+  description_localized("en-US", "Sets the percentage of listeners who must vote to skip a track"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/music-player settings vote-skip <percent>"])
+    .examples_localized("en-US", ["/music-player settings vote-skip 50"])
+)]
+async fn music_player_settings_vote_skip(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "percent")]
+  #[description_localized("en-US", "The percentage of listeners required to skip a track")]
+  #[min = 0]
+  #[max = 100]
+  percent: u8
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.music_player.vote_skip_ratio = percent.min(100) as f64 / 100.0;
+    Ok(())
+  }).await?;
+
+  let response = format!("Skipping a track now requires votes from {percent}% of listeners");
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  guild_only,
+  category = "music-player",
+  rename = "queue-limit",
+  name_localized("en-US", "queue-limit"),
+  description_localized("en-US", "Sets (or unsets) the maximum number of tracks each user may have in the queue"),
+  default_member_permissions = "MANAGE_GUILD",
+  required_permissions = "MANAGE_GUILD",
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/music-player settings queue-limit [limit]"])
+    .examples_localized("en-US", ["/music-player settings queue-limit 10"])
+)]
+async fn music_player_settings_queue_limit(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "limit")]
+  #[description_localized("en-US", "The maximum number of tracks per user, omit to remove the limit")]
+  #[min = 1]
+  #[max = 65536]
+  limit: Option<usize>
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let guild_id = ctx.guild_id().ok_or(MelodyError::COMMAND_NOT_IN_GUILD)?;
+
+  core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.music_player.queue_limit = limit;
+    Ok(())
+  }).await?;
+
+  let response = match limit {
+    Some(limit) => format!("Users may now only have {limit} tracks in the queue at once (members with the DJ role or the Manage Server permission are exempt)"),
+    None => "Removed the limit on tracks in the queue per user".to_owned()
+  };
+
+  ctx.reply(response).await.context("failed to send reply")?;
+  Ok(())
+}
+
 async fn ensure_in_channel(
   core: &Core, guild_id: GuildId, user_id: UserId
 ) -> Result<(Arc<MusicPlayer>, ChannelId), String> {
@@ -702,6 +1099,129 @@ async fn ensure_in_same_channel(
   }
 }
 
+/// Like [`ensure_in_same_channel`], but also requires that the user is a DJ.
+async fn ensure_dj(
+  core: &Core, guild_id: GuildId, user_id: UserId
+) -> MelodyResult<Result<(Arc<MusicPlayer>, ChannelId), String>> {
+  Ok(match ensure_in_same_channel(core, guild_id, user_id).await {
+    Ok((music_player, channel_id)) => {
+      let settings = get_settings(core, guild_id).await?;
+      match is_dj(core, &settings, guild_id, user_id, channel_id) {
+        true => Ok((music_player, channel_id)),
+        false => Err(NOT_A_DJ.to_owned())
+      }
+    },
+    Err(response) => Err(response)
+  })
+}
+
+/// Whether the user may skip, stop or clear the queue without needing a vote.
+fn is_dj(core: &Core, settings: &MusicPlayerSettings, guild_id: GuildId, user_id: UserId, channel_id: ChannelId) -> bool {
+  // without a DJ role, anyone may control the bot
+  if settings.dj_role.is_none() { return true };
+
+  // a listener who is alone with the bot may always control it
+  has_dj_role(core, settings, guild_id, user_id) || channel_listeners(core, guild_id, channel_id) == [user_id]
+}
+
+/// Whether the user holds the DJ role, or may manage the server.
+fn has_dj_role(core: &Core, settings: &MusicPlayerSettings, guild_id: GuildId, user_id: UserId) -> bool {
+  core.cache.guild(guild_id).and_then(|guild| {
+    let member = guild.members.get(&user_id)?;
+    let has_role = settings.dj_role.is_some_and(|dj_role| member.roles.contains(&dj_role));
+    Some(has_role || guild.member_permissions(member).manage_guild())
+  }).unwrap_or(false)
+}
+
+/// Returns how many more tracks the user may add to the queue, or `None` if they are not limited.
+/// Only members with the DJ role or who may manage the server are exempt from the limit.
+async fn queue_capacity(
+  core: &Core, music_player: &MusicPlayer,
+  guild_id: GuildId, user_id: UserId
+) -> MelodyResult<Option<usize>> {
+  let settings = get_settings(core, guild_id).await?;
+  let Some(queue_limit) = settings.queue_limit else { return Ok(None) };
+  if has_dj_role(core, &settings, guild_id, user_id) { return Ok(None) };
+  let queue_count = music_player.queue_count_for(guild_id, user_id).await;
+  Ok(Some(queue_limit.saturating_sub(queue_count)))
+}
+
+async fn get_settings(core: &Core, guild_id: GuildId) -> MelodyResult<MusicPlayerSettings> {
+  core.operate_persist_guild(guild_id, async |persist_guild| {
+    Ok(persist_guild.music_player.clone())
+  }).await
+}
+
+fn track_info_embed(core: &Core, now_playing: &NowPlaying, lyrics: Option<&Lyrics>) -> CreateEmbed {
+  let video_info = now_playing.video_info.as_ref();
+  let title = video_info.map_or_else(|| now_playing.entry.item.to_string(), |video_info| video_info.title.clone());
+  let artist = video_info.and_then(|video_info| video_info.artist.clone().or_else(|| video_info.uploader.clone()))
+    .or_else(|| lyrics.map(|lyrics| lyrics.artist_name.clone()));
+  let album = video_info.and_then(|video_info| video_info.album.clone())
+    .or_else(|| lyrics.and_then(|lyrics| lyrics.album_name.clone()));
+  let duration = video_info.and_then(|video_info| video_info.duration)
+    .or_else(|| lyrics.and_then(|lyrics| lyrics.duration))
+    .map(Duration::from_secs_f64);
+
+  let position = match (now_playing.position, duration) {
+    (Some(position), Some(duration)) => Some(format!("{} / {}", format_duration(position), format_duration(duration))),
+    (Some(position), None) => Some(format_duration(position)),
+    (None, Some(duration)) => Some(format_duration(duration)),
+    (None, None) => None
+  };
+
+  let lyrics_status = match lyrics {
+    Some(lyrics) if lyrics.instrumental => "Instrumental",
+    Some(lyrics) if lyrics.synced_lyrics.is_some() => "Available (synced)",
+    Some(lyrics) if lyrics.plain_lyrics.is_some() => "Available",
+    Some(..) | None => "Not found"
+  };
+
+  let mut embed = CreateEmbed::default()
+    .title(title)
+    .field("Added by", DisplayUser::new(now_playing.entry.requester, &core.cache).to_string(), true)
+    .field("Lyrics", lyrics_status, true);
+  if let Some(video_info) = video_info {
+    embed = embed.url(&video_info.webpage_url);
+    if let Some(thumbnail) = &video_info.thumbnail {
+      embed = embed.thumbnail(thumbnail);
+    };
+
+    if let Some(extractor) = &video_info.extractor {
+      embed = embed.footer(CreateEmbedFooter::new(format!("Source: {extractor}")));
+    };
+  };
+
+  for (name, value) in [("Artist", artist), ("Album", album), ("Position", position)] {
+    if let Some(value) = value {
+      embed = embed.field(name, value, true);
+    };
+  };
+
+  embed
+}
+
+fn lyrics_embed(lyrics: &Lyrics, plain_text: &str) -> CreateEmbed {
+  const MAX_DESCRIPTION_LEN: usize = 4096;
+
+  let description = match plain_text.char_indices().nth(MAX_DESCRIPTION_LEN - 1) {
+    Some((i, ..)) => format!("{}\u{2026}", &plain_text[..i]),
+    None => plain_text.to_owned()
+  };
+
+  CreateEmbed::default()
+    .title(format!("{} - {}", lyrics.artist_name, lyrics.track_name))
+    .description(description)
+}
+
+fn format_duration(duration: Duration) -> String {
+  let seconds = duration.as_secs();
+  match seconds / 3600 {
+    0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+    hours => format!("{hours}:{:02}:{:02}", seconds / 60 % 60, seconds % 60)
+  }
+}
+
 async fn send_response_result(ctx: MelodyContext<'_>, result: Result<String, String>) -> MelodyResult {
   let response = match result {
     Ok(response) => response,
@@ -0,0 +1,95 @@
+use crate::prelude::*;
+use crate::data::Core;
+use super::{MelodyContext, CommandMetaData};
+
+use poise::reply::CreateReply;
+
+
+
+#[poise::command(
+  slash_command,
+  subcommands(
+    "privacy_logging",
+    "privacy_delete_my_data"
+  ),
+  name_localized("en-US", "privacy"),
+  description_localized("en-US", "Controls what the bot stores about you"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", [
+      "/privacy logging <enabled>",
+      "/privacy delete-my-data"
+    ])
+    .examples_localized("en-US", [
+      "/privacy logging false",
+      "/privacy delete-my-data"
+    ])
+)]
+pub async fn privacy(_ctx: MelodyContext<'_>) -> MelodyResult {
+  Err(MelodyError::COMMAND_PRECONDITION_VIOLATION_ROOT_COMMAND)
+}
+
+#[poise::command(
+  slash_command,
+  rename = "logging",
+  name_localized("en-US", "logging"),
+  description_localized("en-US", "Chooses whether your conversations with the chatbot are logged and learned from"),
+  custom_data = CommandMetaData::new()
+    .usage_localized("en-US", ["/privacy logging <enabled>"])
+    .examples_localized("en-US", ["/privacy logging false"])
+)]
+async fn privacy_logging(
+  ctx: MelodyContext<'_>,
+  #[name_localized("en-US", "enabled")]
+  #[description_localized("en-US", "Whether your conversations with the chatbot may be logged")]
+  enabled: bool
+) -> MelodyResult {
+  let core = Core::from(ctx);
+  let user_id = ctx.author().id;
+
+  core.operate_persist_commit(async |persist| {
+    match enabled {
+      true => persist.chatbot_logging_opt_out.remove(&user_id),
+      false => persist.chatbot_logging_opt_out.insert(user_id)
+    };
+
+    Ok(())
+  }).await?;
+
+  let response = match enabled {
+    true => "Your conversations with the chatbot will now be logged",
+    false => "Your conversations with the chatbot will no longer be logged, use `/privacy delete-my-data` to delete past conversations"
+  };
+
+  ctx.send(CreateReply::default().ephemeral(true).content(response))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
+
+#[poise::command(
+  slash_command,
+  rename = "delete-my-data",
+  name_localized("en-US", "delete-my-data"),
+  description_localized("en-US", "Deletes your chatbot conversations, game stats and other data the bot has stored about you"),
+  custom_data = CommandMetaData::new()
+    .info_localized_concat("en-US", [
+      "Chatbot conversations from before user IDs were recorded in the log cannot be attributed to you, and are not deleted.",
+      "This does not opt you out of future logging, use `/privacy logging` for that."
+    ])
+    .usage_localized("en-US", ["/privacy delete-my-data"])
+    .examples_localized("en-US", ["/privacy delete-my-data"])
+)]
+async fn privacy_delete_my_data(ctx: MelodyContext<'_>) -> MelodyResult {
+  ctx.defer_ephemeral().await.context("failed to defer response")?;
+
+  let core = Core::from(ctx);
+  let deleted = crate::feature::privacy::delete_user_data(&core, ctx.author().id).await?;
+
+  let response = format!(
+    "Deleted {} chatbot log entries, forgot {} of your messages the chatbot learned from, and deleted your connect four data in {} servers",
+    deleted.chatbot_log_entries, deleted.markov_messages, deleted.connect_four_guilds
+  );
+
+  ctx.send(CreateReply::default().ephemeral(true).content(response))
+    .await.context("failed to send reply")?;
+  Ok(())
+}
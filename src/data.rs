@@ -4,10 +4,12 @@ mod config;
 mod persist;
 
 use crate::prelude::*;
-use crate::feature::cleverbot::{CleverBotLoggerWrapper, CleverBotWrapper};
+use crate::feature::cleverbot::{Chatbot, CleverBotLoggerWrapper};
+use crate::feature::emoji_stats::EmojiImages;
 use crate::feature::feed::FeedManager;
 use crate::feature::message_chains::{MessageChains, MessageChainsWrapper};
-use crate::feature::music_player::MusicPlayer;
+use crate::feature::music_player::{AudioCache, MusicPlayer};
+use crate::utils::lyrics::LyricsClient;
 use crate::utils::youtube::YtDlp;
 pub use self::activities::{ActivitiesContainer, Activities};
 pub use self::config::*;
@@ -52,7 +54,8 @@ pub struct State {
   pub persist: PersistContainer,
   pub persist_guilds: PersistGuildsWrapper,
   pub activities: ActivitiesContainer,
-  pub cleverbot: CleverBotWrapper,
+  pub chatbot: Chatbot,
+  pub emoji_images: EmojiImages,
   pub cleverbot_logger: CleverBotLoggerWrapper,
   pub feed: OnceLock<FeedManager>,
   pub message_chains: MessageChainsWrapper,
@@ -69,29 +72,45 @@ impl State {
     activities: ActivitiesContainer,
     http_client: HttpClient
   ) -> MelodyResult<State> {
-    let (cleverbot_delay, yt_dlp_path) = config.operate(async |config| {
+    let (cleverbot_delay, config_chatbot, config_music_player) = config.operate(async |config| {
       info!("YouTube RSS feeds are {}", if config.rss.youtube.is_some() { "enabled" } else { "disabled" });
       info!("Twitter RSS feeds are {}", if config.rss.twitter.is_some() { "enabled" } else { "disabled" });
-      let yt_dlp_path = config.music_player.as_ref().map(|mp| mp.yt_dlp_path.clone());
-      (config.cleverbot_ratelimit, yt_dlp_path)
+      info!("Chatbot backend is {:?}", config.chatbot.backend);
+      (config.cleverbot_ratelimit, config.chatbot.clone(), config.music_player.clone())
     }).await;
 
     let previous_build_id = persist.operate_mut_commit(async |persist| Ok(persist.swap_build_id()))
       .await.context("failed to commit persist-guild state for build id")?;
 
-    let cleverbot = CleverBotWrapper::new(cleverbot_delay);
     let cleverbot_logger = CleverBotLoggerWrapper::create()
       .await.context("failed to create cleverbot logger")?;
+    let chatbot = Chatbot::create(cleverbot_delay, http_client.clone(), &config_chatbot, &cleverbot_logger).await?;
+
+    let emoji_images = EmojiImages::create(http_client.clone()).await?;
 
     let feed = OnceLock::new();
 
     let message_chains = MessageChains::new().into();
 
-    let yt_dlp = yt_dlp_path.map(YtDlp::new);
+    let yt_dlp = config_music_player.as_ref()
+      .map(|config_music_player| YtDlp::new(config_music_player.yt_dlp_path.clone()));
+
+    let music_player = match Option::zip(yt_dlp.clone(), config_music_player) {
+      Some((yt_dlp, config_music_player)) => {
+        let audio_cache = match &config_music_player.cache {
+          Some(config_cache) => Some({
+            AudioCache::create(config_cache).await
+              .context("failed to create audio cache")?
+          }),
+          None => None
+        };
+
+        let lyrics_client = LyricsClient::new(http_client.clone(), config_music_player.lyrics.base_url.clone());
 
-    let music_player = yt_dlp.clone().map(|yt_dlp| {
-      Arc::new(MusicPlayer::new(yt_dlp, http_client.clone()))
-    });
+        Some(Arc::new(MusicPlayer::new(yt_dlp, http_client.clone(), &config_music_player, audio_cache, lyrics_client)))
+      },
+      None => None
+    };
 
     let tasks = Mutex::new(Tasks::default());
 
@@ -101,7 +120,8 @@ impl State {
       persist,
       persist_guilds,
       activities,
-      cleverbot,
+      chatbot,
+      emoji_images,
       cleverbot_logger,
       feed,
       message_chains,
@@ -120,13 +140,19 @@ macro_rules! for_each_some {
 
 #[derive(Debug, Default)]
 pub struct Tasks {
-  pub cycle_activities: Option<JoinHandle<()>>
+  pub cycle_activities: Option<JoinHandle<()>>,
+  pub music_player_inactivity: Option<JoinHandle<()>>,
+  pub chatbot_save: Option<JoinHandle<()>>,
+  pub cleverbot_log_prune: Option<JoinHandle<()>>
 }
 
 impl Tasks {
   pub fn abort(&self) {
     for_each_some!([
-      &self.cycle_activities
+      &self.cycle_activities,
+      &self.music_player_inactivity,
+      &self.chatbot_save,
+      &self.cleverbot_log_prune
     ], task => task.abort());
   }
 }
@@ -1,4 +1,5 @@
 use crate::prelude::*;
+use crate::feature::cleverbot::ChatbotBackendKind;
 
 use serde::de::{Deserialize, Deserializer, Unexpected};
 use serenity::model::gateway::GatewayIntents;
@@ -6,6 +7,7 @@ use serenity::model::colour::Color;
 use singlefile::container_shared_async::StandardContainerSharedAsync;
 use singlefile::manager::standard::StandardManagerOptions;
 use singlefile_formats::data::toml_serde::Toml;
+use url::Url;
 
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -25,6 +27,8 @@ pub struct Config {
   /// Messages will not be sent at a higher interval than this.
   #[serde(default = "default_cleverbot_ratelimit", deserialize_with = "deserialize_duration")]
   pub cleverbot_ratelimit: Duration,
+  #[serde(default)]
+  pub chatbot: ConfigChatbot,
   /// The list of gateway intents the bot should send to the Discord API.
   /// This can either be a list of intent names, or a number representing an intents bitfield.
   /// Defaults to [`GatewayIntents::non_privileged`].
@@ -57,11 +61,171 @@ fn default_emulate_status_modes() -> bool {
   false
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigChatbot {
+  /// The chatbot backend used in DMs and in guilds that have not chosen one.
+  /// One of `cleverbot`, `openai` or `markov`.
+  pub backend: ChatbotBackendKind,
+  /// Whether to reply using the Markov backend when the chosen backend fails.
+  pub markov_fallback: bool,
+  /// Chatbot log entries older than this many days are deleted. Entries are kept forever if this is not set.
+  pub log_retention_days: Option<u32>,
+  /// Limits how often each user may talk to the chatbot.
+  pub user_ratelimit: ConfigChatbotRateLimit,
+  /// Limits how often the chatbot replies in each channel.
+  pub channel_ratelimit: ConfigChatbotRateLimit,
+  /// Conversations that nobody has replied to for this long are forgotten.
+  #[serde(deserialize_with = "deserialize_duration")]
+  pub conversation_expiry: Duration,
+  /// The number of previous messages in a conversation that are sent to CleverBot along with each new message.
+  pub cleverbot_history_size: usize,
+  /// Proxy URLs that requests to CleverBot are sent through, where `direct` means no proxy.
+  /// When requests through one fail, the next healthiest is used. Requests are sent directly if this is empty.
+  pub cleverbot_proxies: Vec<String>,
+  /// User-agents that requests to CleverBot are sent with, rotated whenever a request fails.
+  pub cleverbot_user_agents: Vec<String>,
+  /// Settings for the OpenAI-compatible backend, which is unavailable if this is not set.
+  pub openai: Option<ConfigChatbotOpenAi>
+}
+
+impl Default for ConfigChatbot {
+  fn default() -> Self {
+    ConfigChatbot {
+      backend: ChatbotBackendKind::default(),
+      markov_fallback: true,
+      log_retention_days: None,
+      user_ratelimit: ConfigChatbotRateLimit { burst: zu32::new(3).unwrap(), interval: Duration::from_secs(10) },
+      channel_ratelimit: ConfigChatbotRateLimit { burst: zu32::new(5).unwrap(), interval: Duration::from_secs(6) },
+      conversation_expiry: Duration::from_secs(30 * 60),
+      cleverbot_history_size: 32,
+      cleverbot_proxies: Vec::new(),
+      cleverbot_user_agents: Vec::new(),
+      openai: None
+    }
+  }
+}
+
+/// A token bucket, allowing `burst` messages at once, and then one more message every `interval`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ConfigChatbotRateLimit {
+  pub burst: zu32,
+  #[serde(deserialize_with = "deserialize_duration")]
+  pub interval: Duration
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigChatbotOpenAi {
+  /// The base URL of the API, such as `http://localhost:11434/v1/` for Ollama
+  /// or `http://localhost:8080/v1/` for llama.cpp.
+  pub base_url: Url,
+  /// Sent as a bearer token, if set. Local servers usually do not need this.
+  #[serde(default)]
+  pub api_key: Option<String>,
+  /// The name of the model to request completions from.
+  pub model: String,
+  #[serde(default = "default_openai_system_prompt")]
+  pub system_prompt: String,
+  #[serde(default)]
+  pub max_tokens: Option<u32>,
+  #[serde(default)]
+  pub temperature: Option<f32>,
+  /// The number of previous messages in a conversation that are sent along with each new message.
+  #[serde(default = "default_openai_history_size")]
+  pub history_size: usize,
+  /// Requests taking longer than this are abandoned.
+  #[serde(default = "default_openai_timeout", deserialize_with = "deserialize_duration")]
+  pub timeout: Duration
+}
+
+fn default_openai_system_prompt() -> String {
+  "You are Melody, a friendly Discord bot chatting with users. Keep your replies short and casual.".to_owned()
+}
+
+fn default_openai_history_size() -> usize {
+  16
+}
+
+fn default_openai_timeout() -> Duration {
+  Duration::from_secs(60)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigMusicPlayer {
   /// The path to the `yt-dlp` executable.
   #[serde(alias = "ytdlp_path")]
-  pub yt_dlp_path: PathBuf
+  pub yt_dlp_path: PathBuf,
+  /// The bot will leave a voice channel after nothing has been playing for this long.
+  /// Set to `0` to never leave.
+  #[serde(default = "default_idle_timeout", deserialize_with = "deserialize_timeout")]
+  pub idle_timeout: Option<Duration>,
+  /// The bot will leave a voice channel after everyone else has left it for this long.
+  /// Set to `0` to never leave.
+  #[serde(default = "default_alone_timeout", deserialize_with = "deserialize_timeout")]
+  pub alone_timeout: Option<Duration>,
+  /// How many upcoming queue items should be resolved ahead of time.
+  #[serde(default = "default_prefetch")]
+  pub prefetch: usize,
+  /// Downloaded audio will be kept on disk if this is set.
+  #[serde(default)]
+  pub cache: Option<ConfigMusicPlayerCache>,
+  #[serde(default)]
+  pub lyrics: ConfigMusicPlayerLyrics
+}
+
+fn default_idle_timeout() -> Option<Duration> {
+  Some(Duration::from_secs(300))
+}
+
+fn default_alone_timeout() -> Option<Duration> {
+  Some(Duration::from_secs(60))
+}
+
+fn default_prefetch() -> usize {
+  2
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigMusicPlayerCache {
+  /// The directory that cached audio should be stored in.
+  #[serde(default = "default_cache_path")]
+  pub path: PathBuf,
+  /// The maximum total size of the cache, in megabytes.
+  /// The least recently played audio is removed first once this is exceeded.
+  #[serde(default = "default_cache_max_size")]
+  pub max_size: u64,
+  /// Audio larger than this many megabytes will not be cached.
+  #[serde(default = "default_cache_max_entry_size")]
+  pub max_entry_size: u64
+}
+
+fn default_cache_path() -> PathBuf {
+  PathBuf::from("./data/audio-cache/")
+}
+
+fn default_cache_max_size() -> u64 {
+  1024
+}
+
+fn default_cache_max_entry_size() -> u64 {
+  64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigMusicPlayerLyrics {
+  /// The base URL of an LRCLIB-compatible API that lyrics and track metadata are requested from.
+  ///
+  /// Defaults to `https://lrclib.net/api/`.
+  pub base_url: Url
+}
+
+impl Default for ConfigMusicPlayerLyrics {
+  fn default() -> Self {
+    ConfigMusicPlayerLyrics {
+      base_url: Url::parse("https://lrclib.net/api/").unwrap()
+    }
+  }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -197,6 +361,10 @@ fn deserialize_duration_opt<'de, D: Deserializer<'de>>(deserializer: D) -> Resul
   <Option<f64>>::deserialize(deserializer).map(|opt| opt.map(Duration::from_secs_f64))
 }
 
+fn deserialize_timeout<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+  f64::deserialize(deserializer).map(|secs| (secs > 0.0).then(|| Duration::from_secs_f64(secs)))
+}
+
 fn deserialize_at_least_one<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
 where T: Deserialize<'de>, D: Deserializer<'de> {
   <Vec<T>>::deserialize(deserializer).and_then(|value| match value.is_empty() {
@@ -22,6 +22,8 @@ pub struct Persist {
   pub guild_plugins: HashMap<GuildId, HashSet<String>>,
   /// List of users who have been notified that chatbot messages from Melody are from CleverBot.
   pub cleverbot_notified_users: HashSet<UserId>,
+  /// List of users who have asked for their chatbot conversations not to be logged or learned from.
+  pub chatbot_logging_opt_out: HashSet<UserId>,
   /// List of RSS feeds and their current state.
   #[deprecated]
   pub feeds: HashMap<FeedIdentifier, FeedState>,
@@ -55,6 +57,10 @@ impl Persist {
     self.cleverbot_notified_users.insert(user_id)
   }
 
+  pub fn chatbot_logging_allowed(&self, user_id: UserId) -> bool {
+    !self.chatbot_logging_opt_out.contains(&user_id)
+  }
+
   pub fn get_guild_plugins_mut(&mut self, id: GuildId) -> &mut HashSet<String> {
     self.guild_plugins.entry(id).or_default()
   }
@@ -96,6 +102,7 @@ impl Default for Persist {
       build_id: 0,
       guild_plugins: HashMap::new(),
       cleverbot_notified_users: HashSet::new(),
+      chatbot_logging_opt_out: HashSet::new(),
       #[allow(deprecated)]
       feeds: HashMap::new(),
       feed_states: FeedStates::default()
@@ -134,6 +141,13 @@ impl PersistGuilds {
     wrapper.read().await.guilds.get(&id).cloned()
   }
 
+  /// Every guild that has stored data, and that data.
+  pub async fn get_all(wrapper: &PersistGuildsWrapper) -> Vec<(GuildId, PersistGuildContainer)> {
+    wrapper.read().await.guilds.iter()
+      .map(|(&id, container)| (id, container.clone()))
+      .collect()
+  }
+
   pub async fn get_default(wrapper: &PersistGuildsWrapper, id: GuildId) -> MelodyResult<PersistGuildContainer> {
     use std::collections::hash_map::Entry;
     match wrapper.write().await.guilds.entry(id) {
@@ -158,7 +172,15 @@ pub struct PersistGuild {
   #[serde(alias = "emoji_statistics")]
   pub emoji_stats: crate::feature::emoji_stats::EmojiStats,
   pub join_roles: HashMap<RoleId, JoinRoleFilter>,
-  pub grant_roles: HashMap<RoleId, HashSet<Granter>>
+  pub grant_roles: HashMap<RoleId, HashSet<Granter>>,
+  pub music_player: crate::feature::music_player::MusicPlayerSettings,
+  pub chatbot: crate::feature::cleverbot::ChatbotSettings,
+  pub message_chains: crate::feature::message_chains::MessageChainSettings,
+  pub dice_players: HashMap<UserId, crate::feature::dice_roll::DicePlayer>,
+  pub initiative_trackers: HashMap<ChannelId, crate::feature::dice_roll::InitiativeTracker>,
+  pub roll_history: HashMap<ChannelId, crate::feature::dice_roll::RollHistory>,
+  /// Members with this role are sent a copy of every secret roll.
+  pub roll_gm_role: Option<RoleId>
 }
 
 impl PersistGuild {
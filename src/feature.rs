@@ -1,7 +1,9 @@
+pub mod chart;
 pub mod cleverbot;
 pub mod dice_roll;
 pub mod emoji_stats;
 pub mod feed;
 pub mod message_chains;
 pub mod music_player;
+pub mod privacy;
 pub mod roles;
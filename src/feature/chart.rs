@@ -0,0 +1,355 @@
+//! Charts rendered to PNG images, for statistics like emoji uses, leaderboards and dice distributions.
+use fontdue::{Font, FontSettings};
+use fontdue::layout::{CoordinateSystem, Layout, TextStyle};
+use image::{ImageResult, Rgb, RgbImage, RgbaImage};
+
+use std::sync::LazyLock;
+
+
+
+pub const BACKGROUND: Rgb<u8> = Rgb([0x2b, 0x2d, 0x31]);
+pub const AXIS: Rgb<u8> = Rgb([0x80, 0x84, 0x8e]);
+pub const GRID: Rgb<u8> = Rgb([0x3f, 0x41, 0x47]);
+pub const TEXT: Rgb<u8> = Rgb([0xdb, 0xde, 0xe1]);
+/// Colors for telling apart the bars or lines of a chart, in the order they are used.
+pub const PALETTE: [Rgb<u8>; 6] = [
+  Rgb([0x58, 0x65, 0xf2]),
+  Rgb([0x57, 0xf2, 0x87]),
+  Rgb([0xfe, 0xe7, 0x5c]),
+  Rgb([0xed, 0x42, 0x45]),
+  Rgb([0xeb, 0x45, 0x9e]),
+  Rgb([0x45, 0xdd, 0xc0])
+];
+
+pub fn palette_color(i: usize) -> Rgb<u8> {
+  PALETTE[i % PALETTE.len()]
+}
+
+/// The size that icons (like emoji images) should be scaled to fit within.
+pub const ICON_SIZE: u32 = 24;
+
+const WIDTH: u32 = 640;
+const MARGIN: u32 = 16;
+const TITLE_HEIGHT: u32 = 32;
+const TITLE_SIZE: f32 = 20.0;
+const LABEL_SIZE: f32 = 14.0;
+
+static FONT: LazyLock<Font> = LazyLock::new(|| {
+  Font::from_bytes(include_bytes!("../../libs/melody-chess/assets/Roboto-Bold.ttf").as_slice(), FontSettings::default())
+    .expect("failed to construct static font")
+});
+
+/// A horizontal bar chart, with a labelled row for each bar, like for rankings and leaderboards.
+#[derive(Debug, Clone, Default)]
+pub struct BarChart {
+  pub title: Option<String>,
+  pub bars: Vec<Bar>
+}
+
+#[derive(Debug, Clone)]
+pub struct Bar {
+  pub label: String,
+  /// Drawn before the label, scaled to fit within [`ICON_SIZE`].
+  pub icon: Option<RgbaImage>,
+  pub value: f64
+}
+
+impl BarChart {
+  const ROW_HEIGHT: u32 = 28;
+  const LABEL_WIDTH: u32 = 160;
+  const VALUE_WIDTH: u32 = 64;
+
+  pub fn render(&self) -> ImageResult<Vec<u8>> {
+    let top = MARGIN + if self.title.is_some() { TITLE_HEIGHT } else { 0 };
+    let height = top + Self::ROW_HEIGHT * self.bars.len() as u32 + MARGIN;
+    let mut canvas = Canvas::new(WIDTH, height);
+    if let Some(title) = &self.title {
+      canvas.draw_text(title, MARGIN as f32, (MARGIN + TITLE_HEIGHT / 2) as f32, TITLE_SIZE, TEXT, Align::Left);
+    };
+
+    let label_left = MARGIN + ICON_SIZE + 8;
+    let bar_left = label_left + Self::LABEL_WIDTH + 8;
+    let bar_max_width = WIDTH - MARGIN - Self::VALUE_WIDTH - bar_left;
+    let largest = self.bars.iter().map(|bar| bar.value).fold(0.0, f64::max);
+    for (i, bar) in self.bars.iter().enumerate() {
+      let row_top = top + i as u32 * Self::ROW_HEIGHT;
+      let row_middle = (row_top + Self::ROW_HEIGHT / 2) as f32;
+      if let Some(icon) = &bar.icon {
+        canvas.draw_icon(icon, MARGIN, row_top + (Self::ROW_HEIGHT - ICON_SIZE) / 2);
+      };
+
+      let label = fit_text(&bar.label, LABEL_SIZE, Self::LABEL_WIDTH as f32);
+      canvas.draw_text(&label, label_left as f32, row_middle, LABEL_SIZE, TEXT, Align::Left);
+
+      let bar_width = match largest > 0.0 {
+        true => (bar.value / largest * bar_max_width as f64).round() as u32,
+        false => 0
+      };
+
+      canvas.fill_rect(bar_left, row_top + 6, bar_width.max(2), Self::ROW_HEIGHT - 12, PALETTE[0]);
+      let value_left = (bar_left + bar_width.max(2) + 6) as f32;
+      canvas.draw_text(&format_value(bar.value), value_left, row_middle, LABEL_SIZE, TEXT, Align::Left);
+    };
+
+    canvas.encode_png()
+  }
+}
+
+/// A line chart of one or more series of values at evenly spaced steps, like uses over time.
+#[derive(Debug, Clone, Default)]
+pub struct LineChart {
+  pub title: Option<String>,
+  /// Labels along the horizontal axis, below the step at the given index.
+  pub x_labels: Vec<(usize, String)>,
+  pub series: Vec<Series>
+}
+
+#[derive(Debug, Clone)]
+pub struct Series {
+  pub label: String,
+  /// Drawn in the legend before the label, scaled to fit within [`ICON_SIZE`].
+  pub icon: Option<RgbaImage>,
+  pub values: Vec<f64>
+}
+
+impl LineChart {
+  const HEIGHT: u32 = 360;
+  const LEGEND_HEIGHT: u32 = 28;
+  const Y_LABEL_WIDTH: u32 = 48;
+  const X_LABEL_HEIGHT: u32 = 24;
+  const DIVISIONS: u32 = 4;
+
+  /// The vertical axis is divided into whole steps, as the charted values are expected to be counts.
+  pub fn render(&self) -> ImageResult<Vec<u8>> {
+    let mut canvas = Canvas::new(WIDTH, Self::HEIGHT);
+    let mut top = MARGIN;
+    if let Some(title) = &self.title {
+      canvas.draw_text(title, MARGIN as f32, (top + TITLE_HEIGHT / 2) as f32, TITLE_SIZE, TEXT, Align::Left);
+      top += TITLE_HEIGHT;
+    };
+
+    // the legend is a single row, so series that don't fit are left out of it
+    let mut legend_left = MARGIN;
+    let legend_middle = top + Self::LEGEND_HEIGHT / 2;
+    for (i, series) in self.series.iter().enumerate() {
+      let label_width = text_width(&series.label, LABEL_SIZE).ceil() as u32;
+      let icon_width = if series.icon.is_some() { ICON_SIZE + 4 } else { 0 };
+      let entry_width = 12 + 6 + icon_width + label_width;
+      if legend_left + entry_width > WIDTH - MARGIN { break };
+
+      canvas.fill_rect(legend_left, legend_middle - 6, 12, 12, palette_color(i));
+      legend_left += 12 + 6;
+      if let Some(icon) = &series.icon {
+        canvas.draw_icon(icon, legend_left, legend_middle - ICON_SIZE / 2);
+        legend_left += ICON_SIZE + 4;
+      };
+
+      canvas.draw_text(&series.label, legend_left as f32, legend_middle as f32, LABEL_SIZE, TEXT, Align::Left);
+      legend_left += label_width + 16;
+    };
+
+    top += Self::LEGEND_HEIGHT + 8;
+    let left = MARGIN + Self::Y_LABEL_WIDTH;
+    let right = WIDTH - MARGIN;
+    let bottom = Self::HEIGHT - MARGIN - Self::X_LABEL_HEIGHT;
+    let (plot_width, plot_height) = ((right - left) as f32, (bottom - top) as f32);
+
+    let largest = self.series.iter().flat_map(|series| series.values.iter().copied()).fold(0.0, f64::max);
+    let step = nice_ceiling(largest / Self::DIVISIONS as f64).max(1.0);
+    let ceiling = step * Self::DIVISIONS as f64;
+    for division in 0..=Self::DIVISIONS {
+      let y = bottom - (plot_height * division as f32 / Self::DIVISIONS as f32).round() as u32;
+      let color = if division == 0 { AXIS } else { GRID };
+      canvas.fill_rect(left, y, right - left, 1, color);
+      let label = format_value(step * division as f64);
+      canvas.draw_text(&label, (left - 8) as f32, y as f32, LABEL_SIZE, TEXT, Align::Right);
+    };
+
+    let steps = self.series.iter().map(|series| series.values.len()).max().unwrap_or(0);
+    let step_x = |i: usize| match steps {
+      0 | 1 => left as f32 + plot_width / 2.0,
+      steps => left as f32 + plot_width * i as f32 / (steps - 1) as f32
+    };
+
+    for (i, label) in &self.x_labels {
+      let align = match *i {
+        0 => Align::Left,
+        i if i + 1 >= steps => Align::Right,
+        _ => Align::Center
+      };
+
+      let y = (bottom + Self::X_LABEL_HEIGHT / 2) as f32;
+      canvas.draw_text(label, step_x(*i), y, LABEL_SIZE, TEXT, align);
+    };
+
+    // drawn in reverse, so that the first series end up on top
+    for (i, series) in self.series.iter().enumerate().rev() {
+      let points = series.values.iter().enumerate()
+        .map(|(i, &value)| (step_x(i), bottom as f32 - (value / ceiling) as f32 * plot_height))
+        .collect::<Vec<(f32, f32)>>();
+      match points.as_slice() {
+        [point] => canvas.draw_line(*point, *point, 4.0, palette_color(i)),
+        points => for segment in points.windows(2) {
+          canvas.draw_line(segment[0], segment[1], 2.5, palette_color(i));
+        }
+      };
+    };
+
+    canvas.encode_png()
+  }
+}
+
+/// Where text is drawn relative to the position it is drawn at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+  Left,
+  Center,
+  Right
+}
+
+/// An image being drawn on, with the shapes that charts are made of.
+#[derive(Debug, Clone)]
+pub struct Canvas {
+  image: RgbImage
+}
+
+impl Canvas {
+  pub fn new(width: u32, height: u32) -> Self {
+    Canvas { image: RgbImage::from_pixel(width, height, BACKGROUND) }
+  }
+
+  pub fn width(&self) -> u32 {
+    self.image.width()
+  }
+
+  pub fn height(&self) -> u32 {
+    self.image.height()
+  }
+
+  /// Fills a rectangle, leaving out any part of it outside of the canvas.
+  pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Rgb<u8>) {
+    for py in y..y.saturating_add(height).min(self.image.height()) {
+      for px in x..x.saturating_add(width).min(self.image.width()) {
+        self.image.put_pixel(px, py, color);
+      };
+    };
+  }
+
+  /// Draws an anti-aliased line with rounded ends.
+  pub fn draw_line(&mut self, from: (f32, f32), to: (f32, f32), thickness: f32, color: Rgb<u8>) {
+    let radius = thickness / 2.0;
+    let (min_x, max_x) = (from.0.min(to.0) - radius - 1.0, from.0.max(to.0) + radius + 1.0);
+    let (min_y, max_y) = (from.1.min(to.1) - radius - 1.0, from.1.max(to.1) + radius + 1.0);
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length_squared = dx * dx + dy * dy;
+    for py in (min_y.floor() as i64)..=(max_y.ceil() as i64) {
+      for px in (min_x.floor() as i64)..=(max_x.ceil() as i64) {
+        let (x, y) = (px as f32 + 0.5, py as f32 + 0.5);
+        // the distance from the center of this pixel to the closest point on the line
+        let t = match length_squared > 0.0 {
+          true => (((x - from.0) * dx + (y - from.1) * dy) / length_squared).clamp(0.0, 1.0),
+          false => 0.0
+        };
+
+        let distance = f32::hypot(x - (from.0 + t * dx), y - (from.1 + t * dy));
+        self.blend(px, py, color, (radius + 0.5 - distance).clamp(0.0, 1.0));
+      };
+    };
+  }
+
+  /// Draws a line of text, vertically centered on `y`.
+  pub fn draw_text(&mut self, text: &str, x: f32, y: f32, size: f32, color: Rgb<u8>, align: Align) {
+    let layout = layout_text(text, size);
+    let offset_x = match align {
+      Align::Left => x,
+      Align::Center => x - layout_width(&layout) / 2.0,
+      Align::Right => x - layout_width(&layout)
+    };
+
+    let offset_y = y - layout.height() / 2.0;
+    for glyph in layout.glyphs() {
+      let (metrics, bitmap) = FONT.rasterize_config(glyph.key);
+      let glyph_x = (glyph.x + offset_x).round() as i64;
+      let glyph_y = (glyph.y + offset_y).round() as i64;
+      for sy in 0..metrics.height {
+        for sx in 0..metrics.width {
+          let alpha = bitmap[sx + sy * metrics.width] as f32 / 255.0;
+          self.blend(glyph_x + sx as i64, glyph_y + sy as i64, color, alpha);
+        };
+      };
+    };
+  }
+
+  /// Draws an image centered within a square of [`ICON_SIZE`], with its top left corner at the given position.
+  pub fn draw_icon(&mut self, icon: &RgbaImage, x: u32, y: u32) {
+    let x = x + ICON_SIZE.saturating_sub(icon.width()) / 2;
+    let y = y + ICON_SIZE.saturating_sub(icon.height()) / 2;
+    for (sx, sy, pixel) in icon.enumerate_pixels() {
+      let color = Rgb([pixel[0], pixel[1], pixel[2]]);
+      self.blend((x + sx) as i64, (y + sy) as i64, color, pixel[3] as f32 / 255.0);
+    };
+  }
+
+  pub fn encode_png(&self) -> ImageResult<Vec<u8>> {
+    use image::{ExtendedColorType, ImageEncoder};
+    use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+    let mut buffer = Vec::new();
+    PngEncoder::new_with_quality(&mut buffer, CompressionType::Best, FilterType::Adaptive)
+      .write_image(self.image.as_raw(), self.image.width(), self.image.height(), ExtendedColorType::Rgb8)?;
+    Ok(buffer)
+  }
+
+  fn blend(&mut self, x: i64, y: i64, color: Rgb<u8>, alpha: f32) {
+    if alpha <= 0.0 { return };
+    let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) else { return };
+    if let Some(pixel) = self.image.get_pixel_mut_checked(x, y) {
+      for (channel, source) in pixel.0.iter_mut().zip(color.0) {
+        *channel = (source as f32 * alpha + *channel as f32 * (1.0 - alpha)).round() as u8;
+      };
+    };
+  }
+}
+
+fn layout_text(text: &str, size: f32) -> Layout {
+  let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
+  layout.append(&[&*FONT], &TextStyle::new(text, size, 0));
+  layout
+}
+
+fn layout_width(layout: &Layout) -> f32 {
+  layout.glyphs().iter().map(|glyph| glyph.x + glyph.width as f32).fold(0.0, f32::max)
+}
+
+pub fn text_width(text: &str, size: f32) -> f32 {
+  layout_width(&layout_text(text, size))
+}
+
+/// Shortens text with an ellipsis until it fits within `max_width`.
+pub fn fit_text(text: &str, size: f32, max_width: f32) -> String {
+  if text_width(text, size) <= max_width { return text.to_owned() };
+  let mut text = text.to_owned();
+  while text.pop().is_some() {
+    let shortened = format!("{}…", text.trim_end());
+    if text_width(&shortened, size) <= max_width { return shortened };
+  };
+
+  String::new()
+}
+
+/// Formats a value without a fractional part if it is a whole number.
+pub fn format_value(value: f64) -> String {
+  match value.fract() == 0.0 {
+    true => format!("{value:.0}"),
+    false => format!("{value:.2}")
+  }
+}
+
+/// Rounds a value up to the closest 1, 2, 2.5 or 5 times a power of ten.
+fn nice_ceiling(value: f64) -> f64 {
+  if value <= 0.0 || !value.is_finite() { return 1.0 };
+  let magnitude = 10f64.powf(value.log10().floor());
+  [1.0, 2.0, 2.5, 5.0, 10.0].into_iter()
+    .map(|step| step * magnitude)
+    .find(|&nice| nice >= value)
+    .unwrap_or(10.0 * magnitude)
+}
@@ -1,17 +1,31 @@
+mod conversation;
+mod markov;
+mod openai;
+
+pub use self::conversation::{ConversationKey, ConversationMap, Conversations, ConversationsContainer};
+pub use self::markov::{MarkovBackend, MarkovModel};
+pub use self::openai::{OpenAiBackend, OpenAiError};
+
 use crate::prelude::*;
-use crate::data::Core;
+use crate::data::{ConfigChatbot, Core};
 
 pub use cleverbot::Error as CleverBotError;
 pub use cleverbot_logs::Error as CleverBotLogError;
-use cleverbot::{CleverBotAgent, CleverBotContext};
-use cleverbot_logs::{CleverBotLogger, CleverBotLogEntry};
-use chrono::Utc;
-use melody_ratelimiter::RateLimiter;
+use cleverbot::{CleverBotAgentPool, CleverBotContext};
+use cleverbot_logs::{CleverBotLogger, CleverBotLogEntry, EntryFilter, ExportFormat, LogFiles};
+use fs_err::File;
+use chrono::{DateTime, Utc};
+use melody_ratelimiter::{FairRateLimiter, KeyedRateLimiter};
+use poise::macros::ChoiceParameter;
+use reqwest::Client as HttpClient;
 use serenity::builder::{CreateEmbed, CreateEmbedFooter, CreateMessage};
 use serenity::model::channel::Message;
-use serenity::model::id::ChannelId;
+use serenity::model::id::{ChannelId, MessageId, UserId};
 use tokio::sync::Mutex;
 
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::PathBuf;
 use std::time::Duration;
 use std::sync::Arc;
 
@@ -19,6 +33,11 @@ use std::sync::Arc;
 
 /// Mentions targeting the current user will be replaced with this
 pub const CLEVERBOT_CANONICAL_NAME: &str = "CleverBot";
+pub const CLEVERBOT_LOG_PATH: &str = "./data/cleverbot.log";
+/// Where the cleverbot log is moved to once it reaches [`CLEVERBOT_LOG_SIZE_LIMIT`].
+pub const CLEVERBOT_LOG_ARCHIVE_DIR: &str = "./data/cleverbot-logs/";
+// 8 MiB
+const CLEVERBOT_LOG_SIZE_LIMIT: u64 = 8 * 1024 * 1024;
 
 #[derive(Debug, Clone)]
 pub struct CleverBotLoggerWrapper {
@@ -27,10 +46,14 @@ pub struct CleverBotLoggerWrapper {
 
 impl CleverBotLoggerWrapper {
   pub async fn create() -> Result<Self, CleverBotLogError> {
-    let logger = tokio::task::spawn_blocking(|| {
-      CleverBotLogger::create(format!("./data/cleverbot.log"))
+    let (logger, archive) = tokio::task::spawn_blocking(|| {
+      log_files().create_or_rotate(CLEVERBOT_LOG_SIZE_LIMIT)
     }).await.unwrap()?;
 
+    if let Some(archive) = archive {
+      info!("Rotated cleverbot log to {}", archive.display());
+    };
+
     Ok(CleverBotLoggerWrapper {
       ptr: Arc::new(Mutex::new(logger))
     })
@@ -39,79 +62,434 @@ impl CleverBotLoggerWrapper {
   pub async fn log(
     self,
     channel_id: ChannelId,
+    user_id: UserId,
     message: impl Into<String>,
     response: impl Into<String>
-  ) -> Result<(), CleverBotLogError> {
+  ) -> Result<CleverBotLogEntry, CleverBotLogError> {
     let message = message.into();
     let response = response.into();
     let guard = self.ptr.lock_owned().await;
     tokio::task::spawn_blocking(move || {
-      guard.log(&CleverBotLogEntry {
+      let entry = CleverBotLogEntry {
         thread: channel_id.into(),
+        user: Some(user_id.into()),
         time: Utc::now(),
         message,
         response
-      })
+      };
+
+      guard.log(&entry)?;
+      Ok(entry)
+    }).await.unwrap()
+  }
+
+  /// Reads back every entry that has been logged so far, including rotated logs, oldest first.
+  pub async fn read_entries(&self) -> Result<Vec<CleverBotLogEntry>, CleverBotLogError> {
+    self.read(|entries| entries.collect()).await
+  }
+
+  /// Finds entries matching the filter, returning how many matched and up to `limit` of the most recent matches.
+  pub async fn search(&self, filter: EntryFilter, limit: usize) -> Result<(usize, Vec<CleverBotLogEntry>), CleverBotLogError> {
+    self.read(move |entries| {
+      let mut count = 0;
+      let mut recent = VecDeque::with_capacity(limit);
+      for entry in entries {
+        let entry = entry?;
+        if !filter.matches(&entry) { continue };
+        count += 1;
+        if recent.len() == limit { recent.pop_front(); };
+        if limit > 0 { recent.push_back(entry); };
+      };
+
+      Ok((count, Vec::from(recent)))
+    }).await
+  }
+
+  /// Writes entries matching the filter to a file, returning how many were written.
+  pub async fn export(&self, filter: EntryFilter, format: ExportFormat, path: PathBuf) -> Result<usize, CleverBotLogError> {
+    self.read(move |entries| {
+      if let Some(parent) = path.parent() {
+        fs_err::create_dir_all(parent)?;
+      };
+
+      let file = File::create(&path)?;
+      let entries = entries.filter(|entry| entry.as_ref().map_or(true, |entry| filter.matches(entry)));
+      cleverbot_logs::export_entries(file, format, entries)
+    }).await
+  }
+
+  /// Deletes entries logged before the cutoff, returning how many were deleted.
+  pub async fn prune(&self, cutoff: DateTime<Utc>) -> Result<usize, CleverBotLogError> {
+    self.rewrite(move |log_files| {
+      log_files.retain(|entry| entry.time >= cutoff)
+    }).await
+  }
+
+  /// Deletes every entry for messages sent by the given user, returning the deleted entries.
+  pub async fn purge_user(&self, user_id: UserId) -> Result<Vec<CleverBotLogEntry>, CleverBotLogError> {
+    self.rewrite(move |log_files| {
+      let mut removed = Vec::new();
+      log_files.retain(|entry| match entry.user == Some(user_id.get()) {
+        true => { removed.push(entry.clone()); false },
+        false => true
+      })?;
+
+      Ok(removed)
+    }).await
+  }
+
+  /// Rewrites the log files, then reopens the current log, since rewriting it replaces the file being appended to.
+  async fn rewrite<F, R>(&self, operation: F) -> Result<R, CleverBotLogError>
+  where
+    F: FnOnce(&LogFiles) -> Result<R, CleverBotLogError> + Send + 'static,
+    R: Send + 'static
+  {
+    let mut guard = self.ptr.clone().lock_owned().await;
+    tokio::task::spawn_blocking(move || {
+      let log_files = log_files();
+      let result = operation(&log_files);
+      *guard = CleverBotLogger::create(log_files.current())?;
+      result
+    }).await.unwrap()
+  }
+
+  async fn read<F, R>(&self, operation: F) -> Result<R, CleverBotLogError>
+  where
+    F: FnOnce(cleverbot_logs::LogFilesEntries) -> Result<R, CleverBotLogError> + Send + 'static,
+    R: Send + 'static
+  {
+    // holding the lock ensures that no entry is read while it is only partially written
+    let _guard = self.ptr.lock().await;
+    tokio::task::spawn_blocking(move || {
+      operation(log_files().entries()?)
     }).await.unwrap()
   }
 }
 
-#[derive(Debug, Clone)]
-pub struct CleverBotWrapper {
-  ratelimiter: RateLimiter<CleverBotManager>
+pub fn log_files() -> LogFiles {
+  LogFiles::new(CLEVERBOT_LOG_PATH, CLEVERBOT_LOG_ARCHIVE_DIR)
 }
 
-impl CleverBotWrapper {
-  pub fn new(delay: Duration) -> Self {
-    CleverBotWrapper {
-      ratelimiter: RateLimiter::new(CleverBotManager::new(), delay)
+/// Something that can hold conversations, replying to messages sent in a given conversation.
+#[serenity::async_trait]
+pub trait ChatbotBackend: fmt::Debug + Send + Sync {
+  async fn send(&self, conversation: ConversationKey, message: &str) -> Result<String, ChatbotError>;
+
+  /// Forgets every conversation in a channel, for backends that keep their own conversation state.
+  async fn reset(&self, _channel: ChannelId) {}
+
+  /// Forgets conversations that have not been active since the cutoff, for backends that keep their own conversation state.
+  async fn expire(&self, _cutoff: DateTime<Utc>) {}
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, ChoiceParameter, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatbotBackendKind {
+  /// Scrapes the CleverBot website.
+  #[default]
+  #[name = "CleverBot"]
+  #[name_localized("en-US", "CleverBot")]
+  CleverBot,
+  /// Any OpenAI-compatible chat completions API, such as a local llama.cpp or Ollama server.
+  #[name = "OpenAI-compatible"]
+  #[name_localized("en-US", "OpenAI-compatible")]
+  OpenAi,
+  /// Generates replies offline, based on what it has learned.
+  #[name = "Markov"]
+  #[name_localized("en-US", "Markov")]
+  Markov
+}
+
+impl ChatbotBackendKind {
+  pub fn display_name(self) -> &'static str {
+    match self {
+      Self::CleverBot => "CleverBot",
+      Self::OpenAi => "an AI language model",
+      Self::Markov => "a Markov chain"
     }
   }
+}
 
-  pub async fn send(&self, channel: ChannelId, message: &str) -> Result<String, CleverBotError> {
-    self.ratelimiter.get().await.send(channel, message).await
-  }
+#[derive(Debug, Error)]
+pub enum ChatbotError {
+  #[error(transparent)]
+  CleverBot(#[from] CleverBotError),
+  #[error(transparent)]
+  OpenAi(#[from] OpenAiError),
+  #[error("the markov chain has not learned enough to reply yet")]
+  MarkovEmpty,
+  #[error("the {0:?} backend is not configured")]
+  Unavailable(ChatbotBackendKind)
 }
 
+/// Holds every configured chatbot backend, and sends messages to whichever one is chosen.
 #[derive(Debug)]
-pub struct CleverBotManager {
-  agent: CleverBotAgent,
-  channels: HashMap<ChannelId, CleverBotContext>
+pub struct Chatbot {
+  default_backend: ChatbotBackendKind,
+  markov_fallback: bool,
+  conversation_expiry: Duration,
+  conversations: Arc<ConversationsContainer>,
+  user_ratelimiter: KeyedRateLimiter<UserId>,
+  channel_ratelimiter: KeyedRateLimiter<ChannelId>,
+  cleverbot: CleverBotBackend,
+  openai: Option<OpenAiBackend>,
+  markov: MarkovBackend
 }
 
-impl CleverBotManager {
-  pub fn new() -> Self {
-    CleverBotManager {
-      agent: CleverBotAgent::new(),
-      channels: HashMap::new()
+impl Chatbot {
+  pub async fn create(
+    cleverbot_delay: Duration,
+    http_client: HttpClient,
+    config: &ConfigChatbot,
+    cleverbot_logger: &CleverBotLoggerWrapper
+  ) -> MelodyResult<Self> {
+    let conversations = Arc::new(Conversations::create().await?);
+    Ok(Chatbot {
+      default_backend: config.backend,
+      markov_fallback: config.markov_fallback,
+      conversation_expiry: config.conversation_expiry,
+      conversations: conversations.clone(),
+      user_ratelimiter: KeyedRateLimiter::new(config.user_ratelimit.burst.get(), config.user_ratelimit.interval),
+      channel_ratelimiter: KeyedRateLimiter::new(config.channel_ratelimit.burst.get(), config.channel_ratelimit.interval),
+      cleverbot: CleverBotBackend::new(cleverbot_delay, conversations.clone(), config)
+        .context("failed to create cleverbot agents")?,
+      openai: config.openai.clone().map(|config_openai| OpenAiBackend::new(http_client, conversations.clone(), config_openai)),
+      markov: MarkovBackend::create(cleverbot_logger).await?
+    })
+  }
+
+  /// The backend used when a guild hasn't chosen one.
+  pub fn default_backend(&self) -> ChatbotBackendKind {
+    self.default_backend
+  }
+
+  pub fn backend(&self, kind: ChatbotBackendKind) -> Option<&dyn ChatbotBackend> {
+    match kind {
+      ChatbotBackendKind::CleverBot => Some(&self.cleverbot),
+      ChatbotBackendKind::OpenAi => self.openai.as_ref().map(|openai| openai as &dyn ChatbotBackend),
+      ChatbotBackendKind::Markov => Some(&self.markov)
+    }
+  }
+
+  pub fn is_available(&self, kind: ChatbotBackendKind) -> bool {
+    self.backend(kind).is_some()
+  }
+
+  /// Uses up one of the user's and the channel's messages, or returns how long until they may talk to the chatbot again.
+  pub fn try_acquire(&self, user_id: UserId, channel_id: ChannelId) -> Result<(), Duration> {
+    // don't use up the user's message if the channel is what's limited
+    self.user_ratelimiter.check(&user_id)?;
+    self.channel_ratelimiter.try_acquire(channel_id)?;
+    self.user_ratelimiter.try_acquire(user_id)
+  }
+
+  pub fn markov(&self) -> &MarkovBackend {
+    &self.markov
+  }
+
+  /// Finds the conversation that a message continues, given the message it replies to (if that was one of the bot's messages).
+  pub async fn conversation(&self, channel: ChannelId, replied_to: Option<MessageId>) -> ConversationKey {
+    self.conversations.operate(async |conversations| conversations.resolve(channel, replied_to)).await
+  }
+
+  /// Remembers which conversation one of the bot's replies belongs to, so that replying to it continues that conversation.
+  pub async fn record_reply(&self, reply: MessageId, conversation: ConversationKey) {
+    self.conversations.operate_mut(async |conversations| conversations.record_reply(reply, conversation)).await;
+  }
+
+  /// Forgets every conversation in a channel, for every backend.
+  pub async fn reset(&self, channel: ChannelId) {
+    self.conversations.operate_mut(async |conversations| conversations.reset_channel(channel)).await;
+    if let Some(openai) = &self.openai {
+      openai.reset(channel).await;
+    };
+  }
+
+  /// Forgets conversations that have been inactive for longer than the configured expiry time.
+  pub async fn expire(&self) {
+    let cutoff = Utc::now() - self.conversation_expiry;
+    self.conversations.operate_mut(async |conversations| conversations.expire(cutoff)).await;
+    if let Some(openai) = &self.openai {
+      openai.expire(cutoff).await;
+    };
+  }
+
+  /// Writes the markov model and any ongoing conversations to disk.
+  pub async fn save(&self) -> MelodyResult {
+    self.markov.save().await?;
+    self.conversations.commit().await.context("failed to commit data/conversations.bin")?;
+    trace!("Saved data/conversations.bin");
+    Ok(())
+  }
+
+  /// Sends a message to the given backend, falling back to the Markov backend if it fails (and that is enabled).
+  /// Returns the backend that produced the reply, along with the reply.
+  pub async fn send(&self, kind: ChatbotBackendKind, conversation: ConversationKey, message: &str) -> Result<(ChatbotBackendKind, String), ChatbotError> {
+    let backend = self.backend(kind).ok_or(ChatbotError::Unavailable(kind))?;
+    match backend.send(conversation, message).await {
+      Ok(reply) => Ok((kind, reply)),
+      Err(error) if self.markov_fallback && kind != ChatbotBackendKind::Markov => {
+        warn!("Chatbot backend ({kind:?}) failed, falling back to markov: {error}");
+        // report the original error if the markov chain can't reply either
+        let reply = self.markov.send(conversation, message).await.map_err(|_| error)?;
+        Ok((ChatbotBackendKind::Markov, reply))
+      },
+      Err(error) => Err(error)
     }
   }
+}
 
-  pub async fn send(&mut self, channel: ChannelId, message: &str) -> Result<String, CleverBotError> {
-    self.channels.entry(channel).or_default().send(&mut self.agent, message).await
+#[derive(Debug, Clone)]
+pub struct CleverBotBackend {
+  /// Channels take turns, so that one busy channel can't hold up every other one.
+  ratelimiter: FairRateLimiter<ChannelId, CleverBotAgentPool>,
+  /// Conversation contexts are kept here so that they can be saved.
+  conversations: Arc<ConversationsContainer>,
+  history_size: usize
+}
+
+impl CleverBotBackend {
+  pub fn new(delay: Duration, conversations: Arc<ConversationsContainer>, config: &ConfigChatbot) -> Result<Self, CleverBotError> {
+    let agents = CleverBotAgentPool::new(config.cleverbot_proxies.clone(), config.cleverbot_user_agents.clone())?;
+    Ok(CleverBotBackend {
+      ratelimiter: FairRateLimiter::new(agents, delay),
+      conversations,
+      history_size: config.cleverbot_history_size
+    })
   }
 }
 
-pub async fn send_reply(core: &Core, message: &Message, content: impl Into<String>) -> MelodyResult {
-  // whether or not to notify the user that this message is from cleverbot
+#[serenity::async_trait]
+impl ChatbotBackend for CleverBotBackend {
+  async fn send(&self, conversation: ConversationKey, message: &str) -> Result<String, ChatbotError> {
+    // holding the time-slice keeps replies within a channel in order
+    let mut agents = self.ratelimiter.get(conversation.channel).await;
+    let mut context = self.conversations.operate_mut(async |conversations| {
+      conversations.cleverbot.get_or_insert_with(conversation, CleverBotContext::new).clone()
+    }).await;
+
+    // the configured size may have changed since the context was created
+    context.history_size_limit = self.history_size;
+    let reply = match agents.send(&mut context, message).await {
+      Ok(reply) => reply,
+      Err(error) => {
+        let health = agents.health()
+          .map(|egress| format!("{} ({:.2})", egress.name, egress.health))
+          .join(", ");
+        warn!("CleverBot requests failed through every proxy, health: {health}");
+        return Err(error.into());
+      }
+    };
+    // the conversation may have been reset while waiting for the reply, which shouldn't be undone
+    self.conversations.operate_mut(async |conversations| {
+      conversations.cleverbot.replace(conversation, context);
+    }).await;
+
+    Ok(reply)
+  }
+}
+
+/// Decides whether the chatbot should reply to a message, and if so, which backend should reply.
+/// Messages outside of guilds are only replied to when they mention the bot.
+pub async fn get_reply_backend(core: &Core, message: &Message, mentioned: bool) -> MelodyResult<Option<ChatbotBackendKind>> {
+  let chatbot = &core.state.chatbot;
+  let (should_reply, backend) = match message.guild_id {
+    Some(guild_id) => core.operate_persist_guild(guild_id, async |persist_guild| {
+      let should_reply = persist_guild.chatbot.should_reply(message.channel_id, mentioned)
+        // never reply to other bots unprompted, they may well reply back
+        && (mentioned || !message.author.bot);
+      Ok((should_reply, persist_guild.chatbot.backend))
+    }).await?,
+    None => (mentioned, None)
+  };
+
+  // a guild may have chosen a backend that has since been removed from the config
+  let backend = backend.filter(|&backend| chatbot.is_available(backend)).unwrap_or(chatbot.default_backend());
+  Ok(should_reply.then_some(backend))
+}
+
+/// Replies to a message with the chatbot's response, returning the reply that was sent.
+pub async fn send_reply(core: &Core, message: &Message, backend: ChatbotBackendKind, content: impl Into<String>) -> MelodyResult<Message> {
+  // whether or not to notify the user that this message is from a chatbot
   let notify = core.operate_persist_commit(async |persist| {
     Ok(persist.cleverbot_notify(message.author.id))
   }).await?;
 
   let message_builder = CreateMessage::new()
     .allowed_mentions(Default::default())
-    .embeds(if notify { vec![cleverbot_note_embed()] } else { Vec::new() })
+    .embeds(if notify { vec![chatbot_note_embed(backend)] } else { Vec::new() })
     .reference_message(message)
     .content(content);
   message.channel_id.send_message(&core, message_builder)
-    .await.context("failed to send cleverbot reply")?;
-
-  Ok(())
+    .await.context("failed to send cleverbot reply")
 }
 
-fn cleverbot_note_embed() -> CreateEmbed {
+fn chatbot_note_embed(backend: ChatbotBackendKind) -> CreateEmbed {
   CreateEmbed::default()
     .title("Please note")
-    .description("Melody's chatbot responses are from CleverBot. If you send messages too quickly, you'll be ratelimited.")
+    .description(format!("Melody's chatbot responses are from {}. If you send messages too quickly, you'll be ratelimited.", backend.display_name()))
     .footer(CreateEmbedFooter::new("You're seeing this because it's your first time using this feature."))
 }
+
+/// Per-guild chatbot settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatbotSettings {
+  /// Overrides the backend chosen in the config.
+  pub backend: Option<ChatbotBackendKind>,
+  /// Whether the Markov backend may learn from messages sent in this guild.
+  pub markov_learning: bool,
+  /// Whether the chatbot replies in this guild at all.
+  pub enabled: bool,
+  /// Whether `channels` lists the only channels the chatbot replies in, or the channels it never replies in.
+  pub channel_list_mode: ChannelListMode,
+  pub channels: HashSet<ChannelId>,
+  /// When set, the chatbot only replies to messages that mention it or reply to it.
+  pub mention_only: bool,
+  /// The chance (from 0 to 1) of replying to any other message, when `mention_only` is not set.
+  pub reply_chance: f64
+}
+
+impl ChatbotSettings {
+  pub fn allows_channel(&self, channel_id: ChannelId) -> bool {
+    match self.channel_list_mode {
+      ChannelListMode::Deny => !self.channels.contains(&channel_id),
+      ChannelListMode::Allow => self.channels.contains(&channel_id)
+    }
+  }
+
+  pub fn should_reply(&self, channel_id: ChannelId, mentioned: bool) -> bool {
+    if !self.enabled || !self.allows_channel(channel_id) { return false };
+    mentioned || (!self.mention_only && rand::random_bool(self.reply_chance.clamp(0.0, 1.0)))
+  }
+}
+
+impl Default for ChatbotSettings {
+  fn default() -> Self {
+    ChatbotSettings {
+      backend: None,
+      markov_learning: false,
+      enabled: true,
+      channel_list_mode: ChannelListMode::Deny,
+      channels: HashSet::new(),
+      mention_only: true,
+      reply_chance: 0.0
+    }
+  }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, ChoiceParameter, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelListMode {
+  /// The chatbot replies everywhere except the listed channels.
+  #[default]
+  #[name = "Deny listed channels"]
+  #[name_localized("en-US", "Deny listed channels")]
+  Deny,
+  /// The chatbot replies only in the listed channels.
+  #[name = "Allow only listed channels"]
+  #[name_localized("en-US", "Allow only listed channels")]
+  Allow
+}
@@ -0,0 +1,132 @@
+use crate::prelude::*;
+use super::openai::ChatMessage;
+
+use chrono::{DateTime, Utc};
+use cleverbot::CleverBotContext;
+use serenity::model::id::{ChannelId, MessageId};
+use singlefile::container_shared_async::StandardContainerSharedAsync;
+use singlefile::manager::standard::StandardManagerOptions;
+use singlefile_formats::data::cbor_serde::Cbor;
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+const OPTIONS: StandardManagerOptions = StandardManagerOptions::LOCKED_WRITABLE;
+
+pub type ConversationsContainer = StandardContainerSharedAsync<Conversations, Cbor>;
+
+/// Identifies a conversation with the chatbot.
+///
+/// Discord threads have their own channel ID, so each thread is a separate conversation.
+/// Within a channel, replying to one of the bot's messages continues the conversation that message was part of,
+/// and mentioning the bot without replying continues the channel's main conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ConversationKey {
+  pub channel: ChannelId,
+  /// The message that started a reply chain, or `None` for the channel's main conversation.
+  pub root: Option<MessageId>
+}
+
+impl ConversationKey {
+  pub const fn channel(channel: ChannelId) -> Self {
+    ConversationKey { channel, root: None }
+  }
+}
+
+/// Conversations that persist across restarts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Conversations {
+  pub cleverbot: ConversationMap<CleverBotContext>,
+  pub openai: ConversationMap<VecDeque<ChatMessage>>,
+  /// The conversation that each of the bot's replies belongs to, and when it was sent.
+  pub replies: HashMap<MessageId, (ConversationKey, DateTime<Utc>)>
+}
+
+impl Conversations {
+  pub async fn create() -> MelodyResult<ConversationsContainer> {
+    let path = PathBuf::from(format!("./data/conversations.bin"));
+    let container = ConversationsContainer::create_or_default(path, Cbor, OPTIONS)
+      .await.context("failed to load data/conversations.bin")?;
+    trace!("Loaded data/conversations.bin");
+    Ok(container)
+  }
+
+  /// Finds the conversation that a message continues, given the message it replies to (if that was one of the bot's messages).
+  pub fn resolve(&self, channel: ChannelId, replied_to: Option<MessageId>) -> ConversationKey {
+    match replied_to {
+      Some(replied_to) => self.replies.get(&replied_to).map_or(
+        ConversationKey { channel, root: Some(replied_to) },
+        |&(conversation, ..)| conversation
+      ),
+      None => ConversationKey::channel(channel)
+    }
+  }
+
+  pub fn record_reply(&mut self, reply: MessageId, conversation: ConversationKey) {
+    self.replies.insert(reply, (conversation, Utc::now()));
+  }
+
+  pub fn reset_channel(&mut self, channel: ChannelId) {
+    self.cleverbot.reset_channel(channel);
+    self.openai.reset_channel(channel);
+    self.replies.retain(|_, (conversation, ..)| conversation.channel != channel);
+  }
+
+  pub fn expire(&mut self, cutoff: DateTime<Utc>) {
+    self.cleverbot.expire(cutoff);
+    self.openai.expire(cutoff);
+    self.replies.retain(|_, &mut (.., time)| time >= cutoff);
+  }
+}
+
+/// Some state for each conversation, along with when each conversation was last active.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ConversationMap<C> {
+  conversations: HashMap<ConversationKey, Conversation<C>>
+}
+
+impl<C> ConversationMap<C> {
+  pub fn new() -> Self {
+    ConversationMap { conversations: HashMap::new() }
+  }
+
+  /// Gets the state of a conversation, marking it as active.
+  pub fn get_or_insert_with(&mut self, conversation: ConversationKey, f: impl FnOnce() -> C) -> &mut C {
+    let entry = self.conversations.entry(conversation)
+      .or_insert_with(|| Conversation { state: f(), last_active: Utc::now() });
+    entry.last_active = Utc::now();
+    &mut entry.state
+  }
+
+  /// Replaces the state of a conversation, unless it has been forgotten since its state was taken,
+  /// returning whether it was replaced.
+  pub fn replace(&mut self, conversation: ConversationKey, state: C) -> bool {
+    let Some(entry) = self.conversations.get_mut(&conversation) else { return false };
+    *entry = Conversation { state, last_active: Utc::now() };
+    true
+  }
+
+  /// Forgets every conversation in a channel.
+  pub fn reset_channel(&mut self, channel: ChannelId) {
+    self.conversations.retain(|conversation, _| conversation.channel != channel);
+  }
+
+  /// Forgets conversations that have not been active since the cutoff.
+  pub fn expire(&mut self, cutoff: DateTime<Utc>) {
+    self.conversations.retain(|_, conversation| conversation.last_active >= cutoff);
+  }
+}
+
+impl<C> Default for ConversationMap<C> {
+  fn default() -> Self {
+    ConversationMap::new()
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Conversation<C> {
+  state: C,
+  last_active: DateTime<Utc>
+}
@@ -0,0 +1,255 @@
+use crate::prelude::*;
+use super::{ChatbotBackend, ChatbotError, CleverBotLoggerWrapper, ConversationKey};
+
+use chrono::{DateTime, Utc};
+use cleverbot_logs::CleverBotLogEntry;
+use melody_markov::MarkovChain;
+use serenity::model::id::UserId;
+use singlefile::container_shared_async::StandardContainerSharedAsync;
+use singlefile::manager::standard::StandardManagerOptions;
+use singlefile_formats::data::cbor_serde::Cbor;
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const OPTIONS: StandardManagerOptions = StandardManagerOptions::LOCKED_WRITABLE;
+
+pub type MarkovModelContainer = StandardContainerSharedAsync<MarkovModel, Cbor>;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarkovModel {
+  pub chain: MarkovChain,
+  /// The time of the latest cleverbot log entry that the chain has been trained on,
+  /// so that only newer entries need to be trained on at startup.
+  /// Entries are logged in order, so this stays correct when older entries are rotated, pruned or purged.
+  pub trained_until: Option<DateTime<Utc>>,
+  /// Messages learned from in guilds that have opted in, by the user who sent them,
+  /// since those are not in the cleverbot log and would otherwise be impossible to forget.
+  pub learned_messages: HashMap<UserId, Vec<String>>
+}
+
+impl MarkovModel {
+  /// Whether the given cleverbot log entry is one that the chain has been trained on.
+  pub fn has_trained(&self, entry: &CleverBotLogEntry) -> bool {
+    self.trained_until.is_some_and(|trained_until| entry.time <= trained_until)
+  }
+
+  pub fn train_entry(&mut self, entry: &CleverBotLogEntry) {
+    self.chain.train(&entry.message);
+    self.chain.train(&entry.response);
+    self.trained_until = self.trained_until.max(Some(entry.time));
+  }
+
+  pub fn learn_message(&mut self, user_id: UserId, message: &str) {
+    self.chain.train(message);
+    self.learned_messages.entry(user_id).or_default().push(message.to_owned());
+  }
+
+  /// Untrains the chain on every message learned from the given user, returning how many there were.
+  pub fn forget_user(&mut self, user_id: UserId) -> usize {
+    let messages = self.learned_messages.remove(&user_id).unwrap_or_default();
+    for message in &messages {
+      self.chain.untrain(message);
+    };
+
+    messages.len()
+  }
+
+  /// Untrains the chain on removed cleverbot log entries, skipping any that it was never trained on.
+  pub fn forget_entries(&mut self, entries: &[CleverBotLogEntry]) {
+    for entry in entries {
+      if !self.has_trained(entry) { continue };
+      self.chain.untrain(&entry.message);
+      self.chain.untrain(&entry.response);
+    };
+  }
+
+  /// Trains the chain on entries newer than any it has been trained on, returning how many there were.
+  pub fn train_new_entries<'a>(&mut self, entries: impl IntoIterator<Item = &'a CleverBotLogEntry>) -> usize {
+    let mut trained = 0;
+    for entry in entries {
+      if self.has_trained(entry) { continue };
+      self.train_entry(entry);
+      trained += 1;
+    };
+
+    trained
+  }
+}
+
+/// A chatbot backend that needs no network access, replying with text generated
+/// from past chatbot conversations and messages in guilds that have opted in.
+#[derive(Debug)]
+pub struct MarkovBackend {
+  container: MarkovModelContainer,
+  /// Whether the model has changed since it was last saved.
+  dirty: AtomicBool
+}
+
+impl MarkovBackend {
+  const MAX_WORDS: usize = 40;
+
+  pub async fn create(cleverbot_logger: &CleverBotLoggerWrapper) -> MelodyResult<Self> {
+    let path = PathBuf::from(format!("./data/markov.bin"));
+    let container = MarkovModelContainer::create_or_default(path, Cbor, OPTIONS)
+      .await.context("failed to load data/markov.bin")?;
+    trace!("Loaded data/markov.bin");
+
+    let markov_backend = MarkovBackend { container, dirty: AtomicBool::new(false) };
+    markov_backend.train_from_logs(cleverbot_logger).await?;
+    Ok(markov_backend)
+  }
+
+  /// Trains the chain on any entries in the cleverbot log that it hasn't seen yet.
+  async fn train_from_logs(&self, cleverbot_logger: &CleverBotLoggerWrapper) -> MelodyResult {
+    let entries = cleverbot_logger.read_entries().await
+      .context("failed to read cleverbot log")?;
+    let trained = self.container.operate_mut(async |model| {
+      model.train_new_entries(&entries)
+    }).await;
+
+    if trained > 0 {
+      info!("Trained markov chain on {trained} new cleverbot log entries");
+      self.dirty.store(true, Ordering::Relaxed);
+    };
+
+    Ok(())
+  }
+
+  /// Learns from a message and the reply it got, once it has been written to the cleverbot log.
+  pub async fn learn_exchange(&self, entry: &CleverBotLogEntry) {
+    self.container.operate_mut(async |model| model.train_entry(entry)).await;
+
+    self.dirty.store(true, Ordering::Relaxed);
+  }
+
+  /// Forgets exchanges that have been removed from the cleverbot log.
+  pub async fn forget_exchanges(&self, entries: &[CleverBotLogEntry]) {
+    self.container.operate_mut(async |model| model.forget_entries(entries)).await;
+    self.dirty.store(true, Ordering::Relaxed);
+  }
+
+  /// Learns from a message observed in a guild that has opted in.
+  pub async fn learn(&self, user_id: UserId, message: &str) {
+    self.container.operate_mut(async |model| model.learn_message(user_id, message)).await;
+    self.dirty.store(true, Ordering::Relaxed);
+  }
+
+  /// Forgets every message learned from the given user in guilds that have opted in, returning how many there were.
+  pub async fn forget_user(&self, user_id: UserId) -> usize {
+    let forgotten = self.container.operate_mut(async |model| model.forget_user(user_id)).await;
+    if forgotten > 0 { self.dirty.store(true, Ordering::Relaxed) };
+    forgotten
+  }
+
+  /// Generates text continuing on from the prompt, if there is one.
+  pub async fn generate(&self, prompt: Option<&str>) -> Option<String> {
+    self.container.operate(async |model| match prompt {
+      Some(prompt) => model.chain.generate_seeded(&mut rand::rng(), prompt, Self::MAX_WORDS),
+      None => model.chain.generate(&mut rand::rng(), Self::MAX_WORDS)
+    }).await
+  }
+
+  /// Writes the model to disk if it has changed since it was last saved.
+  pub async fn save(&self) -> MelodyResult {
+    if self.dirty.swap(false, Ordering::Relaxed) {
+      self.container.commit().await.context("failed to commit data/markov.bin")?;
+      trace!("Saved data/markov.bin");
+    };
+
+    Ok(())
+  }
+}
+
+#[serenity::async_trait]
+impl ChatbotBackend for MarkovBackend {
+  async fn send(&self, _conversation: ConversationKey, message: &str) -> Result<String, ChatbotError> {
+    self.generate(Some(message)).await.ok_or(ChatbotError::MarkovEmpty)
+  }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn entry(time: i64, message: &str, response: &str) -> CleverBotLogEntry {
+    CleverBotLogEntry {
+      thread: 0,
+      user: None,
+      time: DateTime::from_timestamp_millis(time).unwrap(),
+      message: message.to_owned(),
+      response: response.to_owned()
+    }
+  }
+
+  #[test]
+  fn resumes_after_the_last_trained_entry() {
+    let entries = [
+      entry(1, "hello there", "general kenobi"),
+      entry(2, "how are you", "fine thanks"),
+      entry(3, "what is new", "not much")
+    ];
+
+    let mut model = MarkovModel::default();
+    assert_eq!(model.train_new_entries(&entries[..2]), 2);
+    assert_eq!(model.train_new_entries(&entries), 1);
+
+    let mut expected = MarkovModel::default();
+    for entry in &entries {
+      expected.train_entry(entry);
+    };
+
+    assert_eq!(model.chain, expected.chain);
+    assert_eq!(model.trained_until, expected.trained_until);
+  }
+
+  #[test]
+  fn resumes_after_older_entries_are_removed() {
+    let entries = [
+      entry(1, "hello there", "general kenobi"),
+      entry(2, "how are you", "fine thanks"),
+      entry(3, "what is new", "not much")
+    ];
+
+    let mut model = MarkovModel::default();
+    model.train_new_entries(&entries[..2]);
+    // the first entry was pruned, so the log has shifted
+    assert_eq!(model.train_new_entries(&entries[1..]), 1);
+    assert_eq!(model.train_new_entries(&entries[2..]), 0);
+  }
+
+  #[test]
+  fn forgets_only_trained_entries() {
+    let entries = [
+      entry(1, "hello there", "general kenobi"),
+      entry(2, "hello there", "general kenobi")
+    ];
+
+    let mut model = MarkovModel::default();
+    model.train_new_entries(&entries[..1]);
+    let trained = model.clone();
+
+    // the second entry was purged before the chain was trained on it
+    model.forget_entries(&entries[1..]);
+    assert_eq!(model.chain, trained.chain);
+
+    model.forget_entries(&entries);
+    assert!(model.chain.is_empty());
+  }
+
+  #[test]
+  fn forgets_learned_messages_by_user() {
+    let mut model = MarkovModel::default();
+    model.learn_message(UserId::new(1), "the quick brown fox");
+    let learned = model.chain.clone();
+    model.learn_message(UserId::new(2), "the lazy dog");
+    model.learn_message(UserId::new(2), "the quick red fox");
+
+    assert_eq!(model.forget_user(UserId::new(2)), 2);
+    assert_eq!(model.chain, learned);
+    assert_eq!(model.forget_user(UserId::new(2)), 0);
+  }
+}
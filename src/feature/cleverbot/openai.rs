@@ -0,0 +1,160 @@
+use crate::prelude::*;
+use crate::data::ConfigChatbotOpenAi;
+use super::{ChatbotBackend, ChatbotError, ConversationKey, ConversationMap, ConversationsContainer};
+
+use chrono::{DateTime, Utc};
+use reqwest::{Client as HttpClient, StatusCode};
+use serenity::model::id::ChannelId;
+use singlefile_formats::data::json_serde::original as serde_json;
+use tokio::sync::Mutex;
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+
+
+/// A chatbot backend for any server implementing OpenAI's chat completions API,
+/// which includes local model servers like llama.cpp and Ollama.
+#[derive(Debug)]
+pub struct OpenAiBackend {
+  http_client: HttpClient,
+  config: ConfigChatbotOpenAi,
+  /// Conversation histories are kept here so that they can be saved.
+  conversations: Arc<ConversationsContainer>,
+  /// Held while replying, so that replies within a conversation stay in order.
+  turns: Mutex<ConversationMap<Arc<Mutex<()>>>>
+}
+
+impl OpenAiBackend {
+  pub fn new(http_client: HttpClient, conversations: Arc<ConversationsContainer>, config: ConfigChatbotOpenAi) -> Self {
+    OpenAiBackend { http_client, config, conversations, turns: Mutex::new(ConversationMap::new()) }
+  }
+
+  async fn complete(&self, messages: Vec<ChatMessage>) -> Result<String, OpenAiError> {
+    let url = self.config.base_url.join("chat/completions").map_err(OpenAiError::Url)?;
+    let request = ChatCompletionRequest {
+      model: &self.config.model,
+      messages,
+      max_tokens: self.config.max_tokens,
+      temperature: self.config.temperature
+    };
+
+    let body = serde_json::to_vec(&request).map_err(OpenAiError::Json)?;
+    let mut request_builder = self.http_client.post(url)
+      .header("content-type", "application/json")
+      .timeout(self.config.timeout)
+      .body(body);
+    if let Some(api_key) = &self.config.api_key {
+      request_builder = request_builder.bearer_auth(api_key);
+    };
+
+    let response = request_builder.send().await?;
+    let status = response.status();
+    let response_body = response.bytes().await?;
+    if !status.is_success() {
+      return Err(OpenAiError::Response(status, String::from_utf8_lossy(&response_body).into_owned()));
+    };
+
+    let response = serde_json::from_slice::<ChatCompletionResponse>(&response_body).map_err(OpenAiError::Json)?;
+    response.choices.into_iter().next()
+      .map(|choice| choice.message.content.trim().to_owned())
+      .filter(|content| !content.is_empty())
+      .ok_or(OpenAiError::EmptyResponse)
+  }
+}
+
+#[serenity::async_trait]
+impl ChatbotBackend for OpenAiBackend {
+  async fn send(&self, conversation: ConversationKey, message: &str) -> Result<String, ChatbotError> {
+    let turn = self.turns.lock().await.get_or_insert_with(conversation, Default::default).clone();
+    let _turn = turn.lock().await;
+    let mut history = self.conversations.operate_mut(async |conversations| {
+      conversations.openai.get_or_insert_with(conversation, VecDeque::new).clone()
+    }).await;
+
+    let messages = std::iter::once(ChatMessage::system(&self.config.system_prompt))
+      .chain(history.iter().cloned())
+      .chain(std::iter::once(ChatMessage::user(message)))
+      .collect::<Vec<ChatMessage>>();
+    let reply = self.complete(messages).await?;
+
+    history.push_back(ChatMessage::user(message));
+    history.push_back(ChatMessage::assistant(&reply));
+    while history.len() > self.config.history_size {
+      history.pop_front();
+    };
+
+    // the conversation may have been reset while waiting for the reply, which shouldn't be undone
+    self.conversations.operate_mut(async |conversations| {
+      conversations.openai.replace(conversation, history);
+    }).await;
+
+    Ok(reply)
+  }
+
+  async fn reset(&self, channel: ChannelId) {
+    self.turns.lock().await.reset_channel(channel);
+  }
+
+  async fn expire(&self, cutoff: DateTime<Utc>) {
+    self.turns.lock().await.expire(cutoff);
+  }
+}
+
+#[derive(Debug, Error)]
+pub enum OpenAiError {
+  #[error(transparent)]
+  Http(#[from] reqwest::Error),
+  #[error(transparent)]
+  Url(url::ParseError),
+  #[error(transparent)]
+  Json(serde_json::Error),
+  #[error("response error: {0}: {1}")]
+  Response(StatusCode, String),
+  #[error("response contained no reply")]
+  EmptyResponse
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+  role: String,
+  content: String
+}
+
+impl ChatMessage {
+  fn new(role: &str, content: &str) -> Self {
+    ChatMessage { role: role.to_owned(), content: content.to_owned() }
+  }
+
+  fn system(content: &str) -> Self {
+    Self::new("system", content)
+  }
+
+  fn user(content: &str) -> Self {
+    Self::new("user", content)
+  }
+
+  fn assistant(content: &str) -> Self {
+    Self::new("assistant", content)
+  }
+}
+
+#[derive(Debug, Serialize)]
+struct ChatCompletionRequest<'a> {
+  model: &'a str,
+  messages: Vec<ChatMessage>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  max_tokens: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  temperature: Option<f32>
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionResponse {
+  choices: Vec<ChatCompletionChoice>
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChoice {
+  message: ChatMessage
+}
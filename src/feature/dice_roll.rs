@@ -1,232 +1,448 @@
-use chumsky::prelude::*;
-use chumsky::error::SimpleReason;
-use chumsky::text::whitespace;
+mod distribution;
+mod histogram;
+mod history;
+mod initiative;
+mod parser;
+mod player;
+
+pub use self::distribution::{Distribution, DistributionError, RollDistribution};
+pub use self::histogram::render_histogram;
+pub use self::history::{ParseRollSeedError, RollCommitment, RollHistory, RollHistoryEntry, RollSeed};
+pub use self::initiative::{
+  Combatant, HitPoints, HitPointsChange, InitiativeError, InitiativeTracker,
+  handle_initiative_component, update_initiative_message
+};
+pub use self::player::{DicePlayer, DicePlayerError};
+
+use chumsky::Parser;
+use chumsky::error::{Simple, SimpleReason};
 use rand::Rng;
 
 use std::fmt;
 use std::str::FromStr;
-use std::ops::Range;
-
-/*
-ROLL: (ROLL_COIN | ROLL_DICE)
-ROLL_COIN: 'coin' (','? TARGET_COIN)?
-TARGET_COIN: ('heads' | 'tails' | '1' | '2')
-ROLL_DICE: (SYNTAX1 | SYNTAX2) (','? TARGET_DICE)?
-TARGET_DICE: COMPARISON INT
-COMPARISON: ('>=' | '>' | '<=' | '<' | '==' | '!=')
-MODIFIER: ('-' | '+') INT
-MODE1: ('min' | 'minimum' | 'max' | 'maximum') INT?
-MODE2: ('adv' | 'advantage' | 'dis' | 'disadvantage')
-SYNTAX1: INT 'd' INT MODIFIER? MODE1?
-SYNTAX2: 'd' INT MODIFIER? MODE2
-INT: DIGIT+
-DIGIT: (ascii digits)
-*/
 
-#[derive(Debug, Clone)]
-pub struct ExecutedRoll {
-  pub roll_results: Vec<u32>,
-  pub roll: Roll
-}
+/// The longest that a variable or macro name may be.
+pub const MAX_NAME_LEN: usize = 32;
 
-impl fmt::Display for ExecutedRoll {
-  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    let roll_results_total;
 
-    if let Ok([roll_result]) = <[u32; 1]>::try_from(&self.roll_results[..]) {
-      roll_results_total = roll_result as i64 + self.roll.modifier as i64;
-      match self.roll.modifier {
-        0 => write!(f, "**{roll_result}**")?,
-        m => write!(f, "({roll_result}){} = **{roll_results_total}**", DisplayModifier(m))?
-      };
-    } else {
-      write!(f, "[")?;
-      let mut values = Vec::with_capacity(self.roll_results.len());
-      for (i, &roll_result) in self.roll_results.iter().enumerate() {
-        if i != 0 { write!(f, ", ")? };
-        let value = roll_result as i64 + self.roll.modifier as i64;
-        match self.roll.modifier {
-          0 => write!(f, "{roll_result}")?,
-          m => write!(f, "({roll_result}){} = {value}", DisplayModifier(m))?
-        };
+/// Either a coin flip, or an arithmetic expression of dice and numbers, optionally compared against a target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Roll {
+  /// A coin flip, with an optional call of heads (`true`) or tails (`false`).
+  Coin(Option<bool>),
+  Dice(Expr, Option<RollTarget>)
+}
 
-        values.push(value);
-      };
-      write!(f, "]")?;
+impl Roll {
+  /// Replaces every variable in this roll with its value.
+  pub fn resolve(mut self, mut lookup: impl FnMut(&str) -> Option<i64>) -> Result<Self, ResolveRollError> {
+    if let Roll::Dice(expr, _) = &mut self {
+      expr.resolve(&mut lookup)?;
+    };
 
-      if let Some(mode @ Mode(mode_type, n)) = self.roll.mode {
-        debug_assert!(n >= 1);
-        match mode_type {
-          ModeType::Max => values.sort_unstable_by(|a, b| Ord::cmp(b, a)),
-          ModeType::Min => values.sort_unstable_by(|a, b| Ord::cmp(a, b))
-        };
+    Ok(self)
+  }
 
-        let values = &values[0..(n as usize)];
-        roll_results_total = values.into_iter().sum::<i64>();
-        write!(f, " {mode} = **{roll_results_total}**")?;
-      } else {
-        roll_results_total = values.into_iter().sum::<i64>();
-        write!(f, " = **{roll_results_total}**")?;
-      };
-    };
+  pub fn execute(self, rng: &mut (impl Rng + ?Sized)) -> Result<ExecutedRoll, RollError> {
+    match self {
+      Roll::Coin(call) => Ok(ExecutedRoll::Coin { heads: rng.random_bool(0.5), call }),
+      Roll::Dice(expr, target) => {
+        let mut roller = Roller { rng, dice_rolled: 0 };
+        let result = expr.evaluate(&mut roller)?;
+        Ok(ExecutedRoll::Dice { result, target })
+      }
+    }
+  }
+}
 
-    if let Some(RollTarget { total, comparison }) = self.roll.target {
-      let outcome = if comparison.exec(roll_results_total, total) { "SUCCESS" } else { "FAILURE" };
-      write!(f, " {comparison} {total}, **{outcome}**")?;
-    };
+impl fmt::Display for Roll {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Roll::Coin(None) => write!(f, "coin"),
+      Roll::Coin(Some(call)) => write!(f, "coin {}", if *call { "heads" } else { "tails" }),
+      Roll::Dice(expr, None) => write!(f, "{expr}"),
+      Roll::Dice(expr, Some(RollTarget { total, comparison })) => write!(f, "{expr} {} {total}", comparison.to_ascii_str())
+    }
+  }
+}
 
-    Ok(())
+impl FromStr for Roll {
+  type Err = ParseRollError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match self::parser::roll().parse(s.to_ascii_lowercase()) {
+      Ok(roll) => Ok(roll),
+      Err(mut err) => {
+        err.truncate(8);
+        Err(ParseRollError(err))
+      }
+    }
   }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct RollTarget {
   pub total: i64,
   pub comparison: Comparison
 }
 
-impl RollTarget {
-  pub const COIN_HEADS: RollTarget = RollTarget { total: 1, comparison: Comparison::EqualTo };
-  pub const COIN_TAILS: RollTarget = RollTarget { total: 2, comparison: Comparison::EqualTo };
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+  Number(i64),
+  Dice(Dice),
+  /// A named value, such as `@str_mod`, which must be resolved before the expression can be evaluated.
+  Variable(String),
+  Neg(Box<Expr>),
+  Binary(Box<Expr>, BinaryOp, Box<Expr>),
+  /// An expression in parentheses, which are kept so that it renders the way it was written.
+  Group(Box<Expr>)
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct Roll {
-  pub dice: u8,
-  pub sides: u32,
-  pub modifier: i16,
-  pub mode: Option<Mode>,
-  pub target: Option<RollTarget>
-}
+impl Expr {
+  /// Every dice term in this expression, from left to right.
+  pub fn dice_terms(&self) -> Vec<&Dice> {
+    match self {
+      Expr::Number(..) | Expr::Variable(..) => Vec::new(),
+      Expr::Dice(dice) => vec![dice],
+      Expr::Neg(expr) | Expr::Group(expr) => expr.dice_terms(),
+      Expr::Binary(lhs, _, rhs) => {
+        let mut dice_terms = lhs.dice_terms();
+        dice_terms.extend(rhs.dice_terms());
+        dice_terms
+      }
+    }
+  }
 
-impl Roll {
-  pub const fn new_coin(target: Option<bool>) -> Self {
-    let target = match target {
-      Some(true) => Some(RollTarget::COIN_HEADS),
-      Some(false) => Some(RollTarget::COIN_TAILS),
-      None => None
+  fn resolve(&mut self, lookup: &mut dyn FnMut(&str) -> Option<i64>) -> Result<(), ResolveRollError> {
+    match self {
+      Expr::Number(..) | Expr::Dice(..) => (),
+      Expr::Variable(name) => match lookup(name) {
+        Some(value) => *self = Expr::signed_number(value),
+        None => return Err(ResolveRollError(std::mem::take(name)))
+      },
+      Expr::Neg(expr) | Expr::Group(expr) => expr.resolve(lookup)?,
+      Expr::Binary(lhs, op, rhs) => {
+        lhs.resolve(lookup)?;
+        let is_variable = matches!(**rhs, Expr::Variable(..));
+        rhs.resolve(lookup)?;
+
+        // adding a negative variable reads as `d20 - 1` rather than `d20 + -1`
+        if let (true, BinaryOp::Add | BinaryOp::Sub, Expr::Neg(value)) = (is_variable, *op, &**rhs) {
+          *op = if *op == BinaryOp::Add { BinaryOp::Sub } else { BinaryOp::Add };
+          *rhs = value.clone();
+        };
+      }
     };
 
-    Roll { dice: 1, sides: 2, modifier: 0, mode: None, target }
+    Ok(())
   }
 
-  pub fn execute(self, rng: &mut (impl Rng + ?Sized)) -> ExecutedRoll {
-    let roll_results = (0..self.dice).map(|_| rng.random_range(1..=self.sides)).collect();
-    ExecutedRoll { roll_results, roll: self.normalize() }
+  /// A number, negated rather than negative, so that it is displayed the same way as it would be written.
+  fn signed_number(value: i64) -> Self {
+    match value.checked_neg() {
+      Some(negated) if value < 0 => Expr::Neg(Box::new(Expr::Number(negated))),
+      _ => Expr::Number(value)
+    }
   }
 
-  fn normalize(mut self) -> Self {
-    debug_assert!(self.dice >= 1);
-    debug_assert!(self.sides >= 2);
-    if self.dice == 1 {
-      self.mode = None;
-    };
+  fn dice_terms_mut(&mut self) -> Box<dyn Iterator<Item = &mut Dice> + '_> {
+    match self {
+      Expr::Number(..) | Expr::Variable(..) => Box::new(std::iter::empty()),
+      Expr::Dice(dice) => Box::new(std::iter::once(dice)),
+      Expr::Neg(expr) | Expr::Group(expr) => expr.dice_terms_mut(),
+      Expr::Binary(lhs, _, rhs) => Box::new(lhs.dice_terms_mut().chain(rhs.dice_terms_mut()))
+    }
+  }
 
-    self
+  fn evaluate<R: Rng + ?Sized>(&self, roller: &mut Roller<'_, R>) -> Result<Evaluated, RollError> {
+    Ok(match self {
+      Expr::Number(number) => Evaluated::Number(*number),
+      Expr::Dice(dice) => Evaluated::Dice(dice.clone(), roller.roll(dice)?),
+      Expr::Variable(..) => return Err(RollError::UnresolvedVariable),
+      Expr::Neg(expr) => {
+        let expr = expr.evaluate(roller)?;
+        let value = expr.value().checked_neg().ok_or(RollError::Overflow)?;
+        Evaluated::Neg(Box::new(expr), value)
+      },
+      Expr::Binary(lhs, op, rhs) => {
+        let lhs = lhs.evaluate(roller)?;
+        let rhs = rhs.evaluate(roller)?;
+        let value = op.apply(lhs.value(), rhs.value())?;
+        Evaluated::Binary(Box::new(lhs), *op, Box::new(rhs), value)
+      },
+      Expr::Group(expr) => Evaluated::Group(Box::new(expr.evaluate(roller)?))
+    })
   }
 }
 
-impl fmt::Display for Roll {
+impl fmt::Display for Expr {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Expr::Number(number) => write!(f, "{number}"),
+      Expr::Dice(dice) => write!(f, "{dice}"),
+      Expr::Variable(name) => write!(f, "@{name}"),
+      Expr::Neg(expr) => write!(f, "-{expr}"),
+      Expr::Binary(lhs, op, rhs) => write!(f, "{lhs} {op} {rhs}"),
+      Expr::Group(expr) => write!(f, "({expr})")
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+  Add,
+  Sub,
+  Mul,
+  /// Division, rounding down.
+  Div
+}
+
+impl BinaryOp {
+  fn apply(self, lhs: i64, rhs: i64) -> Result<i64, RollError> {
+    match self {
+      BinaryOp::Add => lhs.checked_add(rhs).ok_or(RollError::Overflow),
+      BinaryOp::Sub => lhs.checked_sub(rhs).ok_or(RollError::Overflow),
+      BinaryOp::Mul => lhs.checked_mul(rhs).ok_or(RollError::Overflow),
+      BinaryOp::Div if rhs == 0 => Err(RollError::DivisionByZero),
+      BinaryOp::Div => {
+        let quotient = lhs.checked_div(rhs).ok_or(RollError::Overflow)?;
+        let rounds_up = lhs % rhs != 0 && (lhs < 0) != (rhs < 0);
+        Ok(if rounds_up { quotient - 1 } else { quotient })
+      }
+    }
+  }
+}
+
+impl fmt::Display for BinaryOp {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    if self.dice > 1 { write!(f, "{}", self.dice)? };
-    write!(f, "d{}{}", self.sides, DisplayModifier(self.modifier))?;
-    match self.mode {
-      Some(Mode(mt, 1)) if self.dice == 2 => match mt {
-        ModeType::Max => write!(f, " adv")?,
-        ModeType::Min => write!(f, " dis")?
+    f.write_str(match self {
+      BinaryOp::Add => "+",
+      BinaryOp::Sub => "-",
+      BinaryOp::Mul => "*",
+      BinaryOp::Div => "/"
+    })
+  }
+}
+
+/// A group of identical dice, such as `4d6kh3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dice {
+  pub count: u32,
+  pub sides: Sides,
+  pub reroll: Option<Reroll>,
+  pub explode: Option<Explode>,
+  pub keep: Option<Keep>,
+  /// When set, the dice count how many of them meet this condition, instead of adding up.
+  pub success: Option<Condition>
+}
+
+impl Dice {
+  pub const MAX_COUNT: u32 = 1000;
+  /// The most times a single die may be rerolled or explode.
+  pub const MAX_REPEATS: usize = 100;
+
+  pub const fn new(count: u32, sides: Sides) -> Self {
+    Dice { count, sides, reroll: None, explode: None, keep: None, success: None }
+  }
+
+  fn apply_modifier(&mut self, modifier: DiceModifier) -> Result<(), String> {
+    let (min, max) = (self.sides.min(), self.sides.max());
+    match modifier {
+      DiceModifier::Reroll(_) if self.reroll.is_some() => return Err("dice may only be rerolled once".to_owned()),
+      DiceModifier::Reroll(reroll) if reroll.condition.matches_all(min, max) => return Err("dice can't be rerolled on every side".to_owned()),
+      DiceModifier::Reroll(reroll) => self.reroll = Some(reroll),
+      DiceModifier::Explode(_) if self.explode.is_some() => return Err("dice may only explode once".to_owned()),
+      DiceModifier::Explode(explode) if explode.condition(self.sides).matches_all(min, max) => return Err("dice can't explode on every side".to_owned()),
+      DiceModifier::Explode(Explode { condition }) => {
+        // exploding on the highest side is the default, so it is written the shorter way
+        let condition = condition.filter(|&condition| condition != Explode::default_condition(self.sides));
+        self.explode = Some(Explode { condition });
       },
-      Some(m) => write!(f, " {m}")?,
-      None => ()
+      DiceModifier::Keep(_) if self.keep.is_some() => return Err("dice may only be kept or dropped once".to_owned()),
+      DiceModifier::Keep(keep) => self.keep = Some(keep)
     };
 
     Ok(())
   }
 }
 
-type Roll1 = ((((u8, u32), i16), Option<Mode>), Option<RollTarget>);
-type Roll2 = (((u32, i16), ModeType), Option<RollTarget>);
+impl fmt::Display for Dice {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.count != 1 { write!(f, "{}", self.count)? };
+    write!(f, "d{}", self.sides)?;
+    if let Some(Reroll { condition, once }) = self.reroll {
+      write!(f, "{}{condition}", if once { "ro" } else { "r" })?;
+    };
+
+    if let Some(explode) = self.explode {
+      // a success comparison directly after `!` would be read as the explosion condition
+      let ambiguous = self.keep.is_none() && self.success.is_some();
+      match explode.condition {
+        Some(condition) => write!(f, "!{condition}")?,
+        None if ambiguous => write!(f, "!{}", explode.condition(self.sides))?,
+        None => f.write_str("!")?
+      };
+    };
+
+    if let Some(Keep { kind, count }) = self.keep {
+      write!(f, "{kind}{count}")?;
+    };
+
+    if let Some(Condition { comparison, value }) = self.success {
+      write!(f, "{}{value}", comparison.to_ascii_str())?;
+    };
 
-impl From<Roll1> for Roll {
-  fn from(((((dice, sides), modifier), mode), target): Roll1) -> Roll {
-    Roll { dice, sides, modifier, mode, target }
+    Ok(())
   }
 }
 
-impl From<Roll2> for Roll {
-  fn from((((sides, modifier), mode_type), target): Roll2) -> Roll {
-    let mode = Mode(mode_type, 1);
-    Roll { dice: 2, sides, modifier, mode: Some(mode), target }
-  }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sides {
+  Number(u32),
+  /// `d%`, which is the same as `d100`.
+  Percentile,
+  /// `dF`, where each die is -1, 0 or +1.
+  Fate
 }
 
-impl FromStr for Roll {
-  type Err = ParseRollError;
+impl Sides {
+  pub const fn min(self) -> i64 {
+    match self {
+      Sides::Number(..) | Sides::Percentile => 1,
+      Sides::Fate => -1
+    }
+  }
 
-  fn from_str(s: &str) -> Result<Self, Self::Err> {
-    match roll().parse(s.to_ascii_lowercase()) {
-      Ok(roll) => Ok(roll.normalize()),
-      Err(mut err) => {
-        err.truncate(8);
-        Err(ParseRollError(err))
-      }
+  pub const fn max(self) -> i64 {
+    match self {
+      Sides::Number(sides) => sides as i64,
+      Sides::Percentile => 100,
+      Sides::Fate => 1
     }
   }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct Mode(pub ModeType, pub u8);
-
-impl fmt::Display for Mode {
+impl fmt::Display for Sides {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    f.write_str(match self.0 {
-      ModeType::Max => "max",
-      ModeType::Min => "min"
-    })?;
+    match self {
+      Sides::Number(sides) => write!(f, "{sides}"),
+      Sides::Percentile => write!(f, "%"),
+      Sides::Fate => write!(f, "F")
+    }
+  }
+}
 
-    if self.1 > 1 {
-      write!(f, " {}", self.1)?;
-    };
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum DiceModifier {
+  Reroll(Reroll),
+  Explode(Explode),
+  Keep(Keep)
+}
 
-    Ok(())
-  }
+/// Rerolls dice meeting a condition, either until they don't, or only once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Reroll {
+  pub condition: Condition,
+  pub once: bool
+}
+
+/// Rolls another die for each die meeting a condition, or rolling the highest side if there is no condition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Explode {
+  pub condition: Option<Condition>
 }
 
-impl From<(ModeType, u8)> for Mode {
-  fn from((mode_type, n): (ModeType, u8)) -> Self {
-    Mode(mode_type, n)
+impl Explode {
+  pub fn condition(self, sides: Sides) -> Condition {
+    self.condition.unwrap_or(Self::default_condition(sides))
+  }
+
+  const fn default_condition(sides: Sides) -> Condition {
+    Condition { comparison: Comparison::EqualTo, value: sides.max() }
   }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum ModeType {
-  Min,
-  Max
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Keep {
+  pub kind: KeepKind,
+  pub count: u32
 }
 
-#[derive(Debug, Clone)]
-pub struct ParseRollError(Vec<Simple<char>>);
+impl Keep {
+  /// Whether each of the given dice values is kept.
+  fn apply(self, values: &[i64]) -> Vec<bool> {
+    let mut order = (0..values.len()).collect::<Vec<usize>>();
+    order.sort_by_key(|&i| values[i]);
+    if let KeepKind::KeepHighest | KeepKind::DropLowest = self.kind {
+      order.reverse();
+    };
 
-impl fmt::Display for ParseRollError {
+    let count = (self.count as usize).min(values.len());
+    let keep_first = match self.kind {
+      KeepKind::KeepHighest | KeepKind::KeepLowest => count,
+      KeepKind::DropHighest | KeepKind::DropLowest => values.len() - count
+    };
+
+    let mut kept = vec![false; values.len()];
+    for &i in &order[..keep_first] {
+      kept[i] = true;
+    };
+
+    kept
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeepKind {
+  KeepHighest,
+  KeepLowest,
+  DropHighest,
+  DropLowest
+}
+
+impl fmt::Display for KeepKind {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    for (i, err) in self.0.iter().enumerate() {
-      if i != 0 { writeln!(f)? };
+    f.write_str(match self {
+      KeepKind::KeepHighest => "kh",
+      KeepKind::KeepLowest => "kl",
+      KeepKind::DropHighest => "dh",
+      KeepKind::DropLowest => "dl"
+    })
+  }
+}
 
-      if let SimpleReason::Custom(msg) = err.reason() {
-        write!(f, "{msg}")?;
-      } else {
-        write!(f, "{err}")?;
-      };
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Condition {
+  pub comparison: Comparison,
+  pub value: i64
+}
 
-      if let Some(label) = err.label() {
-        write!(f, " ({label})")?;
-      };
-    };
+impl Condition {
+  pub const fn matches(self, value: i64) -> bool {
+    self.comparison.exec(value, self.value)
+  }
 
-    Ok(())
+  /// Whether every value from `min` to `max` meets this condition.
+  const fn matches_all(self, min: i64, max: i64) -> bool {
+    match self.comparison {
+      Comparison::GreaterThanEqualTo => self.value <= min,
+      Comparison::GreaterThan => self.value < min,
+      Comparison::LessThanEqualTo => self.value >= max,
+      Comparison::LessThan => self.value > max,
+      Comparison::EqualTo => min == max && self.value == min,
+      Comparison::NotEqualTo => self.value < min || self.value > max
+    }
   }
 }
 
-impl std::error::Error for ParseRollError {}
+impl fmt::Display for Condition {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.comparison {
+      Comparison::EqualTo => write!(f, "{}", self.value),
+      comparison => write!(f, "{}{}", comparison.to_ascii_str(), self.value)
+    }
+  }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Comparison {
@@ -260,6 +476,18 @@ impl Comparison {
       Self::NotEqualTo => "\u{2260}"
     }
   }
+
+  /// The way this comparison is written in dice notation.
+  pub const fn to_ascii_str(self) -> &'static str {
+    match self {
+      Self::GreaterThanEqualTo => ">=",
+      Self::GreaterThan => ">",
+      Self::LessThanEqualTo => "<=",
+      Self::LessThan => "<",
+      Self::EqualTo => "=",
+      Self::NotEqualTo => "!="
+    }
+  }
 }
 
 impl AsRef<str> for Comparison {
@@ -274,132 +502,583 @@ impl fmt::Display for Comparison {
   }
 }
 
-macro_rules! spaced {
-  ($expr:expr $(,$exprn:expr)*) => {
-    $expr$(.then_ignore(whitespace()).then($exprn))*
-  };
+struct Roller<'r, R: ?Sized> {
+  rng: &'r mut R,
+  dice_rolled: usize
 }
 
-fn or_not_with<P, T>(p: P, default: T) -> impl Parser<char, T, Error = Simple<char>> + Copy
-where P: Parser<char, T, Error = Simple<char>> + Copy, T: Copy {
-  p.or_not().map(move |v| v.unwrap_or(default))
+impl<'r, R: Rng + ?Sized> Roller<'r, R> {
+  /// The most dice that may be rolled for a single roll, including rerolls and explosions.
+  const MAX_DICE_ROLLED: usize = 10000;
+
+  fn roll_die(&mut self, sides: Sides) -> Result<i64, RollError> {
+    self.dice_rolled += 1;
+    if self.dice_rolled > Self::MAX_DICE_ROLLED {
+      return Err(RollError::TooManyDice);
+    };
+
+    Ok(self.rng.random_range(sides.min()..=sides.max()))
+  }
+
+  fn roll(&mut self, dice: &Dice) -> Result<DiceResult, RollError> {
+    let mut rolls = Vec::with_capacity(dice.count as usize);
+    for _ in 0..dice.count {
+      let mut value = self.roll_die(dice.sides)?;
+      if let Some(Reroll { condition, once }) = dice.reroll {
+        let mut rerolls = 0;
+        while condition.matches(value) && rerolls < if once { 1 } else { Dice::MAX_REPEATS } {
+          rolls.push(DieRoll::new(value, DieState::Rerolled));
+          value = self.roll_die(dice.sides)?;
+          rerolls += 1;
+        };
+      };
+
+      rolls.push(DieRoll::new(value, DieState::Kept));
+      if let Some(explode) = dice.explode {
+        let condition = explode.condition(dice.sides);
+        let mut explosions = 0;
+        while condition.matches(value) && explosions < Dice::MAX_REPEATS {
+          rolls.last_mut().unwrap().exploded = true;
+          value = self.roll_die(dice.sides)?;
+          rolls.push(DieRoll::new(value, DieState::Kept));
+          explosions += 1;
+        };
+      };
+    };
+
+    if let Some(keep) = dice.keep {
+      let (indices, values): (Vec<usize>, Vec<i64>) = rolls.iter().enumerate()
+        .filter(|(_, roll)| roll.state == DieState::Kept)
+        .map(|(i, roll)| (i, roll.value))
+        .unzip();
+      for (i, kept) in indices.into_iter().zip(keep.apply(&values)) {
+        if !kept { rolls[i].state = DieState::Dropped };
+      };
+    };
+
+    let kept = rolls.iter().filter(|roll| roll.state == DieState::Kept);
+    let value = match dice.success {
+      Some(success) => kept.filter(|roll| success.matches(roll.value)).count() as i64,
+      None => kept.map(|roll| roll.value).sum::<i64>()
+    };
+
+    Ok(DiceResult { rolls, value })
+  }
 }
 
-fn roll() -> impl Parser<char, Roll, Error = Simple<char>> {
-  roll_coin().or(roll_dice()).padded().then_ignore(end())
+/// An expression that has been evaluated, along with the value of each part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Evaluated {
+  Number(i64),
+  Dice(Dice, DiceResult),
+  Neg(Box<Evaluated>, i64),
+  Binary(Box<Evaluated>, BinaryOp, Box<Evaluated>, i64),
+  Group(Box<Evaluated>)
 }
 
-fn roll_coin() -> impl Parser<char, Roll, Error = Simple<char>> {
-  let target = just(',').then(whitespace()).or_not()
-    .ignore_then(target_coin()).or_not();
+impl Evaluated {
+  pub fn value(&self) -> i64 {
+    match self {
+      Evaluated::Number(value) => *value,
+      Evaluated::Dice(_, result) => result.value,
+      Evaluated::Neg(_, value) => *value,
+      Evaluated::Binary(_, _, _, value) => *value,
+      Evaluated::Group(evaluated) => evaluated.value()
+    }
+  }
+
+  /// Displays the expression with the results of each dice term alongside it, like `2d6 [3, 5] + 2`.
+  pub fn display_rolls(&self) -> impl fmt::Display + '_ {
+    DisplayEvaluated { evaluated: self, rolls: true }
+  }
 
-  just("coin").then(whitespace()).ignore_then(target)
-    .map(Roll::new_coin).labelled("coin syntax")
+  /// Displays the expression with each dice term replaced by its value, like `8 + 2`.
+  pub fn display_values(&self) -> impl fmt::Display + '_ {
+    DisplayEvaluated { evaluated: self, rolls: false }
+  }
 }
 
-fn target_coin() -> impl Parser<char, bool, Error = Simple<char>> {
-  choice([
-    just("heads").to(true),
-    just("tails").to(false),
-    just("1").to(true),
-    just("2").to(false)
-  ])
+struct DisplayEvaluated<'a> {
+  evaluated: &'a Evaluated,
+  rolls: bool
 }
 
-fn roll_dice() -> impl Parser<char, Roll, Error = Simple<char>> {
-  let dice = or_not_with(dice(), 1);
-  let sides = just('d').then(whitespace()).ignore_then(sides());
-  let modifier = or_not_with(modifier(), 0);
-  let target = just(',').then(whitespace()).or_not()
-    .ignore_then(target_dice()).or_not()
-    .boxed();
+impl<'a> DisplayEvaluated<'a> {
+  fn with(&self, evaluated: &'a Evaluated) -> Self {
+    DisplayEvaluated { evaluated, rolls: self.rolls }
+  }
+}
 
-  let syntax1 = spaced!(dice, sides, modifier, mode1().or_not(), target.clone())
-    .map(Roll::from).labelled("dice syntax 1");
-  let syntax2 = spaced!(sides, modifier, mode2(), target)
-    .map(Roll::from).labelled("dice syntax 2");
-  choice((syntax2, syntax1))
+impl fmt::Display for DisplayEvaluated<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.evaluated {
+      Evaluated::Number(value) => write!(f, "{value}"),
+      Evaluated::Dice(dice, result) if self.rolls => write!(f, "{dice} {}", result.display_rolls(dice)),
+      Evaluated::Dice(_, result) => write!(f, "{}", result.value),
+      Evaluated::Neg(evaluated, _) => write!(f, "-{}", self.with(evaluated)),
+      Evaluated::Binary(lhs, op, rhs, _) => write!(f, "{} {op} {}", self.with(lhs), self.with(rhs)),
+      Evaluated::Group(evaluated) => write!(f, "({})", self.with(evaluated))
+    }
+  }
 }
 
-fn target_dice() -> impl Parser<char, RollTarget, Error = Simple<char>> {
-  spaced!(comparison(), int::<i64>()).map(|(comparison, total)| RollTarget { total, comparison })
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiceResult {
+  /// Every die that was rolled, in order, including those that were rerolled or dropped.
+  pub rolls: Vec<DieRoll>,
+  pub value: i64
 }
 
-fn comparison() -> impl Parser<char, Comparison, Error = Simple<char>> {
-  choice([
-    just("\u{2265}").to(Comparison::GreaterThanEqualTo),
-    just(">=").to(Comparison::GreaterThanEqualTo),
-    just(">").to(Comparison::GreaterThan),
-    just("\u{2264}").to(Comparison::LessThanEqualTo),
-    just("<=").to(Comparison::LessThanEqualTo),
-    just("<").to(Comparison::LessThan),
-    just("\u{2260}").to(Comparison::NotEqualTo),
-    just("!=").to(Comparison::NotEqualTo),
-    just("==").to(Comparison::EqualTo),
-    just("=").to(Comparison::EqualTo),
-  ])
+impl DiceResult {
+  /// Only this many dice are listed for each dice term.
+  const MAX_DISPLAYED: usize = 50;
+
+  fn display_rolls<'a>(&'a self, dice: &'a Dice) -> impl fmt::Display + 'a {
+    DisplayRolls { result: self, dice }
+  }
 }
 
-fn mode1() -> impl Parser<char, Mode, Error = Simple<char>> {
-  let min = choice((just("minimum"), just("min"))).to(ModeType::Min);
-  let max = choice((just("maximum"), just("max"))).to(ModeType::Max);
-  let i = or_not_with(int::<u8>().validate(valid_int(1)), 1);
-  spaced!(choice((min, max)), i).map(Mode::from).labelled("mode: syntax 1")
+struct DisplayRolls<'a> {
+  result: &'a DiceResult,
+  dice: &'a Dice
 }
 
-fn mode2() -> impl Parser<char, ModeType, Error = Simple<char>> {
-  let dis = choice((just("disadvantage"), just("dis"))).to(ModeType::Min);
-  let adv = choice((just("advantage"), just("adv"))).to(ModeType::Max);
-  choice((dis, adv)).labelled("mode: syntax 2")
+impl fmt::Display for DisplayRolls<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("[")?;
+    for (i, roll) in self.result.rolls.iter().take(DiceResult::MAX_DISPLAYED).enumerate() {
+      if i != 0 { f.write_str(", ")? };
+      let value = match self.dice.sides {
+        Sides::Fate => match roll.value { 1.. => "+".to_owned(), 0 => "0".to_owned(), ..0 => "-".to_owned() },
+        _ => roll.value.to_string()
+      };
+
+      let exploded = if roll.exploded { "!" } else { "" };
+      let success = self.dice.success.is_some_and(|success| success.matches(roll.value));
+      match roll.state {
+        DieState::Kept if success => write!(f, "**{value}**{exploded}")?,
+        DieState::Kept => write!(f, "{value}{exploded}")?,
+        DieState::Rerolled | DieState::Dropped => write!(f, "~~{value}~~{exploded}")?
+      };
+    };
+
+    if let Some(remaining) = self.result.rolls.len().checked_sub(DiceResult::MAX_DISPLAYED).filter(|&n| n > 0) {
+      write!(f, ", ... {remaining} more")?;
+    };
+
+    f.write_str("]")?;
+    if self.dice.success.is_some() {
+      let successes = self.result.value;
+      write!(f, " ({successes} {})", if successes == 1 { "success" } else { "successes" })?;
+    };
+
+    Ok(())
+  }
 }
 
-fn dice() -> impl Parser<char, u8, Error = Simple<char>> + Copy {
-  int::<u8>().validate(valid_int(1)).labelled("dice count")
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DieRoll {
+  pub value: i64,
+  pub state: DieState,
+  /// Whether this die caused another die to be rolled.
+  pub exploded: bool
 }
 
-fn sides() -> impl Parser<char, u32, Error = Simple<char>> + Copy {
-  int::<u32>().validate(valid_int(2)).labelled("sides count")
+impl DieRoll {
+  const fn new(value: i64, state: DieState) -> Self {
+    DieRoll { value, state, exploded: false }
+  }
 }
 
-fn modifier() -> impl Parser<char, i16, Error = Simple<char>> + Copy {
-  let sign = choice((just('-'), just('+'))).then_ignore(whitespace());
-  from_str(sign.chain(digits()).collect::<String>()).labelled("modifier")
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DieState {
+  Kept,
+  /// This die was replaced by rolling it again.
+  Rerolled,
+  /// This die was removed by a keep or drop modifier.
+  Dropped
 }
 
-fn int<T: FromStr>() -> impl Parser<char, T, Error = Simple<char>> + Copy
-where <T as FromStr>::Err: ToString {
-  from_str(digits().collect::<String>()).labelled("int")
+#[derive(Debug, Clone)]
+pub enum ExecutedRoll {
+  Coin { heads: bool, call: Option<bool> },
+  Dice { result: Evaluated, target: Option<RollTarget> }
 }
 
-fn digits() -> impl Parser<char, Vec<char>, Error = Simple<char>> + Copy {
-  filter(char::is_ascii_digit).repeated().at_least(1).labelled("digits")
+impl ExecutedRoll {
+  pub fn total(&self) -> i64 {
+    match self {
+      ExecutedRoll::Coin { heads, .. } => if *heads { 1 } else { 2 },
+      ExecutedRoll::Dice { result, .. } => result.value()
+    }
+  }
+
+  /// Whether the roll met its target, if it had one.
+  pub fn success(&self) -> Option<bool> {
+    match self {
+      ExecutedRoll::Coin { heads, call } => call.map(|call| call == *heads),
+      ExecutedRoll::Dice { result, target } => target.map(|target| target.comparison.exec(result.value(), target.total))
+    }
+  }
 }
 
-fn from_str<T: FromStr, P, S>(p: P) -> impl Parser<char, T, Error = Simple<char>> + Copy
-where <T as FromStr>::Err: ToString, S: AsRef<str>, P: Parser<char, S, Error = Simple<char>> + Copy {
-  p.try_map(|s, span| s.as_ref().parse::<T>().map_err(|err| Simple::custom(span, err)))
+impl fmt::Display for ExecutedRoll {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ExecutedRoll::Coin { heads, call } => {
+        write!(f, "**{}**", if *heads { "Heads" } else { "Tails" })?;
+        if let Some(call) = call {
+          write!(f, ", called {}", if *call { "heads" } else { "tails" })?;
+        };
+      },
+      ExecutedRoll::Dice { result, target } => {
+        let rolls = result.display_rolls().to_string();
+        let values = result.display_values().to_string();
+        let total = result.value();
+
+        f.write_str(&rolls)?;
+        if values != rolls && values != total.to_string() {
+          write!(f, " = {values}")?;
+        };
+
+        write!(f, " = **{total}**")?;
+        if let Some(RollTarget { total: target_total, comparison }) = target {
+          write!(f, " {comparison} {target_total}")?;
+        };
+      }
+    };
+
+    if let Some(success) = self.success() {
+      write!(f, ", **{}**", if success { "SUCCESS" } else { "FAILURE" })?;
+    };
+
+    Ok(())
+  }
 }
 
-fn valid_int<I: Copy + Ord + fmt::Display>(min: I)
--> impl Fn(I, Range<usize>, &mut dyn FnMut(Simple<char>)) -> I + Copy {
-  move |v, span, emit| {
-    if v < min { emit(Simple::custom(span, format!("int may not be less than {min}"))) };
-    v
+#[derive(Debug, Clone)]
+pub struct ParseRollError(Vec<Simple<char>>);
+
+impl fmt::Display for ParseRollError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for (i, err) in self.0.iter().enumerate() {
+      if i != 0 { writeln!(f)? };
+
+      if let SimpleReason::Custom(msg) = err.reason() {
+        write!(f, "{msg}")?;
+      } else {
+        write!(f, "{err}")?;
+      };
+
+      if let Some(label) = err.label() {
+        write!(f, " ({label})")?;
+      };
+    };
+
+    Ok(())
   }
 }
 
+impl std::error::Error for ParseRollError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("there is no variable named @{0}")]
+pub struct ResolveRollError(pub String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum RollError {
+  #[error("the result was too large")]
+  Overflow,
+  #[error("attempted to divide by zero")]
+  DivisionByZero,
+  #[error("too many dice were rolled")]
+  TooManyDice,
+  #[error("the roll contains variables that haven't been filled in")]
+  UnresolvedVariable
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
 
+  use itertools::Itertools;
+  use proptest::prelude::*;
+  use rand::SeedableRng;
+  use rand::rngs::StdRng;
 
-#[derive(Debug, Clone, Copy)]
-struct DisplayModifier(i16);
+  use std::collections::HashMap;
 
-impl fmt::Display for DisplayModifier {
-  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    let modifier = (self.0 as i32).abs() as u16;
-    if self.0.is_positive() {
-      write!(f, " + {modifier}")
+  fn sides() -> impl Strategy<Value = Sides> {
+    prop_oneof![(2u32..=100).prop_map(Sides::Number), Just(Sides::Percentile), Just(Sides::Fate)]
+  }
+
+  fn comparison() -> impl Strategy<Value = Comparison> {
+    prop_oneof![
+      Just(Comparison::GreaterThanEqualTo),
+      Just(Comparison::GreaterThan),
+      Just(Comparison::LessThanEqualTo),
+      Just(Comparison::LessThan),
+      Just(Comparison::EqualTo),
+      Just(Comparison::NotEqualTo)
+    ]
+  }
+
+  fn keep_kind() -> impl Strategy<Value = KeepKind> {
+    prop_oneof![
+      Just(KeepKind::KeepHighest),
+      Just(KeepKind::KeepLowest),
+      Just(KeepKind::DropHighest),
+      Just(KeepKind::DropLowest)
+    ]
+  }
+
+  /// Dice with any modifiers, except that they never explode when `explode` is false.
+  fn dice(explode: bool) -> impl Strategy<Value = Dice> {
+    (1u32..=10, sides()).prop_flat_map(move |(count, sides)| {
+      let reroll = (any::<bool>(), any::<bool>()).prop_map(move |(reroll, once)| {
+        // the smallest non-negative side, which can be written without a minus sign
+        let condition = Condition { comparison: Comparison::EqualTo, value: sides.min().max(0) };
+        reroll.then_some(Reroll { condition, once })
+      });
+      let explode = (any::<bool>(), any::<bool>()).prop_map(move |(exploding, condition)| {
+        let condition = condition.then_some(Condition { comparison: Comparison::GreaterThanEqualTo, value: sides.max() });
+        (explode && exploding).then_some(Explode { condition })
+      });
+      let keep = proptest::option::of((keep_kind(), 1..=count).prop_map(|(kind, count)| Keep { kind, count }));
+      let success = proptest::option::of((comparison(), 0..=sides.max()).prop_map(|(comparison, value)| Condition { comparison, value }));
+      (reroll, explode, keep, success).prop_map(move |(reroll, explode, keep, success)| {
+        Dice { count, sides, reroll, explode, keep, success }
+      })
+    })
+  }
+
+  fn group(expr: Expr) -> Expr {
+    match expr {
+      Expr::Binary(..) => Expr::Group(Box::new(expr)),
+      expr => expr
+    }
+  }
+
+  /// Expressions that are written the way they would be parsed, with binary operations grouped.
+  fn expr(explode: bool, division: bool) -> impl Strategy<Value = Expr> {
+    let op = if division {
+      prop_oneof![Just(BinaryOp::Add), Just(BinaryOp::Sub), Just(BinaryOp::Mul), Just(BinaryOp::Div)].boxed()
     } else {
-      write!(f, " - {modifier}")
+      prop_oneof![Just(BinaryOp::Add), Just(BinaryOp::Sub), Just(BinaryOp::Mul)].boxed()
+    };
+
+    let leaf = prop_oneof![(0i64..1000).prop_map(Expr::Number), dice(explode).prop_map(Expr::Dice)];
+    leaf.prop_recursive(4, 16, 2, move |inner| prop_oneof![
+      inner.clone().prop_map(|expr| Expr::Neg(Box::new(group(expr)))),
+      inner.clone().prop_map(|expr| Expr::Group(Box::new(expr))),
+      (inner.clone(), op.clone(), inner).prop_map(|(lhs, op, rhs)| {
+        Expr::Binary(Box::new(group(lhs)), op, Box::new(group(rhs)))
+      })
+    ])
+  }
+
+  fn kept_count(dice: &Dice) -> i64 {
+    let count = dice.count as i64;
+    match dice.keep {
+      Some(Keep { kind: KeepKind::KeepHighest | KeepKind::KeepLowest, count: keep }) => count.min(keep as i64),
+      Some(Keep { kind: KeepKind::DropHighest | KeepKind::DropLowest, count: drop }) => count - count.min(drop as i64),
+      None => count
+    }
+  }
+
+  /// The smallest and largest values an expression without explosions or division can have.
+  fn bounds(expr: &Expr) -> (i64, i64) {
+    match expr {
+      Expr::Number(number) => (*number, *number),
+      Expr::Dice(dice) => {
+        let kept = kept_count(dice);
+        match dice.success {
+          Some(..) => (0, kept),
+          None => (kept * dice.sides.min(), kept * dice.sides.max())
+        }
+      },
+      Expr::Neg(expr) => {
+        let (min, max) = bounds(expr);
+        (-max, -min)
+      },
+      Expr::Group(expr) => bounds(expr),
+      Expr::Binary(lhs, op, rhs) => {
+        let ((a, b), (c, d)) = (bounds(lhs), bounds(rhs));
+        match op {
+          BinaryOp::Add => (a + c, b + d),
+          BinaryOp::Sub => (a - d, b - c),
+          BinaryOp::Mul => {
+            let products = [a * c, a * d, b * c, b * d];
+            (*products.iter().min().unwrap(), *products.iter().max().unwrap())
+          },
+          BinaryOp::Div => unreachable!()
+        }
+      },
+      Expr::Variable(..) => unreachable!()
     }
   }
+
+  fn execute(roll: &str, seed: u64) -> ExecutedRoll {
+    let roll = roll.parse::<Roll>().unwrap_or_else(|err| panic!("failed to parse {roll:?}: {err}"));
+    roll.execute(&mut StdRng::seed_from_u64(seed)).unwrap()
+  }
+
+  fn dice_result(executed: &ExecutedRoll) -> &DiceResult {
+    match executed {
+      ExecutedRoll::Dice { result: Evaluated::Dice(_, result), .. } => result,
+      executed => panic!("expected a single dice term, found {executed:?}")
+    }
+  }
+
+  proptest! {
+    #[test]
+    fn display_roundtrips(expr in expr(true, true), target in proptest::option::of((comparison(), 0i64..100))) {
+      let target = target.map(|(comparison, total)| RollTarget { total, comparison });
+      let roll = Roll::Dice(expr, target);
+      let written = roll.to_string();
+      prop_assert_eq!(written.parse::<Roll>().map_err(|err| err.to_string()), Ok(roll), "{}", written);
+    }
+
+    #[test]
+    fn totals_are_within_bounds(expr in expr(false, false), seed in any::<u64>()) {
+      let (min, max) = bounds(&expr);
+      let executed = Roll::Dice(expr, None).execute(&mut StdRng::seed_from_u64(seed)).unwrap();
+      prop_assert!((min..=max).contains(&executed.total()), "{} not in {}..={}", executed.total(), min, max);
+    }
+
+    #[test]
+    fn keeps_the_right_number_of_dice(count in 1u32..=20, sides in 2u32..=20, kind in keep_kind(), keep in 1u32..=25, seed in any::<u64>()) {
+      let executed = execute(&format!("{count}d{sides}{kind}{keep}"), seed);
+      let result = dice_result(&executed);
+      let kept = result.rolls.iter().filter(|roll| roll.state == DieState::Kept).collect::<Vec<_>>();
+      prop_assert_eq!(result.rolls.len(), count as usize);
+      prop_assert_eq!(kept.len() as i64, kept_count(&Dice { keep: Some(Keep { kind, count: keep }), ..Dice::new(count, Sides::Number(sides)) }));
+      prop_assert_eq!(kept.iter().map(|roll| roll.value).sum::<i64>(), result.value);
+
+      let dropped = result.rolls.iter().filter(|roll| roll.state == DieState::Dropped);
+      for (kept, dropped) in kept.iter().cartesian_product(dropped) {
+        match kind {
+          KeepKind::KeepHighest | KeepKind::DropLowest => prop_assert!(kept.value >= dropped.value),
+          KeepKind::KeepLowest | KeepKind::DropHighest => prop_assert!(kept.value <= dropped.value)
+        };
+      };
+    }
+
+    #[test]
+    fn successes_never_exceed_dice(count in 1u32..=50, sides in 2u32..=20, comparison in comparison(), value in 0i64..=20, seed in any::<u64>()) {
+      let executed = execute(&format!("{count}d{sides}{}{value}", comparison.to_ascii_str()), seed);
+      let result = dice_result(&executed);
+      let successes = result.rolls.iter().filter(|roll| Condition { comparison, value }.matches(roll.value)).count();
+      prop_assert!((0..=count as i64).contains(&result.value));
+      prop_assert_eq!(result.value, successes as i64);
+    }
+
+    #[test]
+    fn fate_dice_are_within_count(count in 1u32..=100, seed in any::<u64>()) {
+      let executed = execute(&format!("{count}dF"), seed);
+      let result = dice_result(&executed);
+      prop_assert!(result.rolls.iter().all(|roll| (-1..=1).contains(&roll.value)));
+      prop_assert!((-(count as i64)..=count as i64).contains(&result.value));
+    }
+
+    #[test]
+    fn rerolled_dice_avoid_the_condition(count in 1u32..=20, sides in 2u32..=20, below in 1i64..20, seed in any::<u64>()) {
+      // rerolling on at most half of the sides keeps it from running into the reroll limit
+      let below = below.min(sides as i64 / 2);
+      let executed = execute(&format!("{count}d{sides}r<={below}"), seed);
+      let result = dice_result(&executed);
+      for roll in &result.rolls {
+        match roll.state {
+          DieState::Kept => prop_assert!(roll.value > below),
+          DieState::Rerolled => prop_assert!(roll.value <= below),
+          DieState::Dropped => prop_assert!(false, "nothing should be dropped")
+        };
+      };
+    }
+
+    #[test]
+    fn exploded_dice_roll_again(count in 1u32..=20, sides in 2u32..=20, seed in any::<u64>()) {
+      let executed = execute(&format!("{count}d{sides}!"), seed);
+      let result = dice_result(&executed);
+      let exploded = result.rolls.iter().filter(|roll| roll.exploded).count();
+      prop_assert_eq!(result.rolls.len(), count as usize + exploded);
+      prop_assert!(result.rolls.iter().all(|roll| roll.exploded == (roll.value == sides as i64)));
+    }
+
+    #[test]
+    fn rolls_are_deterministic(expr in expr(true, true), seed in any::<u64>()) {
+      let roll = Roll::Dice(expr, None);
+      let first = roll.clone().execute(&mut StdRng::seed_from_u64(seed)).map(|executed| executed.to_string());
+      let second = roll.execute(&mut StdRng::seed_from_u64(seed)).map(|executed| executed.to_string());
+      prop_assert_eq!(first, second);
+    }
+  }
+
+  #[test]
+  fn parses_notation() {
+    let cases = [
+      ("2d6 + 1d4 + 3", "2d6 + d4 + 3"),
+      ("4d6kh3", "4d6kh3"),
+      ("4d6k", "4d6kh1"),
+      ("d6!", "d6!"),
+      ("d6!6", "d6!"),
+      ("d6!>=5", "d6!>=5"),
+      ("d6!=3", "d6!=3"),
+      ("d6! = 3", "d6! = 3"),
+      ("10d10>=7", "10d10>=7"),
+      ("4df", "4dF"),
+      ("d%", "d%"),
+      ("(1d8+2)*2", "(d8 + 2) * 2"),
+      ("2d20kh1 >= 15", "2d20kh1 >= 15"),
+      ("d20 adv", "2d20kh1"),
+      ("4d6 max 3, > 12", "4d6kh3 > 12"),
+      ("1d20 + 5 \u{2265} 15", "d20 + 5 >= 15"),
+      ("coin heads", "coin heads"),
+      ("5000 + 1", "5000 + 1"),
+      ("1d20 + @STR_Mod", "d20 + @str_mod")
+    ];
+
+    for (input, expected) in cases {
+      let roll = input.parse::<Roll>().unwrap_or_else(|err| panic!("failed to parse {input:?}: {err}"));
+      assert_eq!(roll.to_string(), expected, "parsing {input:?}");
+    };
+  }
+
+  #[test]
+  fn rejects_invalid_notation() {
+    for input in ["d1", "0d6", "1001d6", "d6r<=6", "d6!>=1", "d6khkh", "d6r1r2", "d6 + d8 adv", "2d20 adv", "d6 +"] {
+      assert!(input.parse::<Roll>().is_err(), "{input:?} should not parse");
+    };
+  }
+
+  #[test]
+  fn reports_evaluation_errors() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut execute = |roll: &str| roll.parse::<Roll>().unwrap().execute(&mut rng).map(|_| ());
+    assert_eq!(execute("d6 / 0"), Err(RollError::DivisionByZero));
+    assert_eq!(execute("9223372036854775807 + 1"), Err(RollError::Overflow));
+    assert_eq!(execute("1000d6 + 1000d6 + 1000d6 + 1000d6 + 1000d6 + 1000d6 + 1000d6 + 1000d6 + 1000d6 + 1000d6 + 1d6"), Err(RollError::TooManyDice));
+    assert_eq!(execute("-7 / 2"), Ok(()));
+  }
+
+  #[test]
+  fn resolves_variables() {
+    let variables = HashMap::from([("str_mod", -1), ("prof", 2)]);
+    let lookup = |name: &str| variables.get(name).copied();
+    let roll = "d20 + @str_mod + @prof".parse::<Roll>().unwrap();
+    assert_eq!(roll.clone().execute(&mut StdRng::seed_from_u64(0)).unwrap_err(), RollError::UnresolvedVariable);
+    assert_eq!(roll.resolve(lookup).unwrap().to_string(), "d20 - 1 + 2");
+    let roll = "@str_mod + d20 - @str_mod".parse::<Roll>().unwrap().resolve(lookup).unwrap();
+    assert_eq!(roll.to_string(), "-1 + d20 + 1");
+    let roll = "d20 + @dex_mod".parse::<Roll>().unwrap();
+    assert_eq!(roll.resolve(lookup), Err(ResolveRollError("dex_mod".to_owned())));
+  }
+
+  #[test]
+  fn divides_rounding_down() {
+    assert_eq!(BinaryOp::Div.apply(7, 2), Ok(3));
+    assert_eq!(BinaryOp::Div.apply(-7, 2), Ok(-4));
+    assert_eq!(BinaryOp::Div.apply(7, -2), Ok(-4));
+    assert_eq!(BinaryOp::Div.apply(-8, 2), Ok(-4));
+  }
 }
@@ -0,0 +1,511 @@
+use super::*;
+
+use std::collections::BTreeMap;
+
+
+
+/// The exact chance of each outcome of a roll, along with the chance of it meeting its target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollDistribution {
+  pub outcomes: Distribution,
+  pub success_chance: Option<f64>
+}
+
+impl Roll {
+  /// Calculates the exact probability distribution of this roll.
+  ///
+  /// Dice that explode or are rerolled until they stop are only followed for as long as it is not vanishingly unlikely,
+  /// so the probabilities of some very large or very unlikely outcomes may be left out.
+  pub fn distribution(&self) -> Result<RollDistribution, DistributionError> {
+    match self {
+      Roll::Coin(call) => {
+        let outcomes = Distribution::uniform(1, 2)?;
+        let success_chance = call.map(|_| 0.5);
+        Ok(RollDistribution { outcomes, success_chance })
+      },
+      Roll::Dice(expr, target) => {
+        let outcomes = Calculator::default().expr(expr)?;
+        let success_chance = target.map(|target| outcomes.chance(|value| target.comparison.exec(value, target.total)));
+        Ok(RollDistribution { outcomes, success_chance })
+      }
+    }
+  }
+}
+
+/// The probability of each value in a range of values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+  min: i64,
+  /// The probability of each value, starting with `min`.
+  probabilities: Vec<f64>
+}
+
+impl Distribution {
+  /// Distributions with more possible outcomes than this are not calculated.
+  pub const MAX_OUTCOMES: usize = 1_000_000;
+
+  pub fn constant(value: i64) -> Self {
+    Distribution { min: value, probabilities: vec![1.0] }
+  }
+
+  pub fn uniform(min: i64, max: i64) -> Result<Self, DistributionError> {
+    let mut distribution = Distribution::zeroed(min, max)?;
+    let len = distribution.probabilities.len();
+    distribution.probabilities.fill(1.0 / len as f64);
+    Ok(distribution)
+  }
+
+  fn from_map(map: BTreeMap<i64, f64>) -> Result<Self, DistributionError> {
+    let (&min, &max) = map.first_key_value().zip(map.last_key_value())
+      .map(|((min, _), (max, _))| (min, max))
+      .expect("distribution has no outcomes");
+    let mut distribution = Distribution::zeroed(min, max)?;
+    for (value, probability) in map {
+      distribution.probabilities[(value - min) as usize] += probability;
+    };
+
+    Ok(distribution)
+  }
+
+  fn zeroed(min: i64, max: i64) -> Result<Self, DistributionError> {
+    let len = max.checked_sub(min).and_then(|span| usize::try_from(span).ok())
+      .and_then(|span| span.checked_add(1))
+      .filter(|&len| len <= Self::MAX_OUTCOMES)
+      .ok_or(DistributionError::TooComplex)?;
+    Ok(Distribution { min, probabilities: vec![0.0; len] })
+  }
+
+  /// The smallest outcome with a chance of happening that is too large to round down to zero.
+  pub fn min(&self) -> i64 {
+    self.outcomes().next().map_or(self.min, |(value, _)| value)
+  }
+
+  /// The largest outcome with a chance of happening that is too large to round down to zero.
+  pub fn max(&self) -> i64 {
+    self.outcomes().next_back().map_or(self.min, |(value, _)| value)
+  }
+
+  /// Every possible outcome, in order, along with its probability.
+  pub fn outcomes(&self) -> impl DoubleEndedIterator<Item = (i64, f64)> + '_ {
+    self.probabilities.iter().enumerate()
+      .filter(|&(_, &probability)| probability > 0.0)
+      .map(|(i, &probability)| (self.min + i as i64, probability))
+  }
+
+  /// The chance of an outcome meeting a condition.
+  pub fn chance(&self, mut condition: impl FnMut(i64) -> bool) -> f64 {
+    self.outcomes().filter(|&(value, _)| condition(value)).map(|(_, probability)| probability).sum()
+  }
+
+  pub fn mean(&self) -> f64 {
+    self.outcomes().map(|(value, probability)| value as f64 * probability).sum()
+  }
+
+  pub fn variance(&self) -> f64 {
+    let mean = self.mean();
+    self.outcomes().map(|(value, probability)| (value as f64 - mean).powi(2) * probability).sum()
+  }
+
+  pub fn std_dev(&self) -> f64 {
+    self.variance().sqrt()
+  }
+
+  fn max_value(&self) -> i64 {
+    self.min + self.probabilities.len() as i64 - 1
+  }
+
+  fn shifted(&self, by: i64) -> Result<Self, DistributionError> {
+    let min = self.min.checked_add(by).ok_or(RollError::Overflow)?;
+    min.checked_add(self.probabilities.len() as i64).ok_or(RollError::Overflow)?;
+    Ok(Distribution { min, probabilities: self.probabilities.clone() })
+  }
+
+  fn negated(&self) -> Result<Self, DistributionError> {
+    let min = self.max_value().checked_neg().ok_or(RollError::Overflow)?;
+    let probabilities = self.probabilities.iter().rev().copied().collect();
+    Ok(Distribution { min, probabilities })
+  }
+
+  /// Adds the probabilities of another distribution, each multiplied by `weight`, to this one.
+  fn accumulate(&mut self, other: &Distribution, weight: f64) -> Result<(), DistributionError> {
+    let min = self.min.min(other.min);
+    let max = self.max_value().max(other.max_value());
+    if min != self.min || max != self.max_value() {
+      let mut expanded = Distribution::zeroed(min, max)?;
+      let offset = (self.min - min) as usize;
+      expanded.probabilities[offset..offset + self.probabilities.len()].copy_from_slice(&self.probabilities);
+      *self = expanded;
+    };
+
+    let offset = (other.min - self.min) as usize;
+    for (i, probability) in other.probabilities.iter().enumerate() {
+      self.probabilities[offset + i] += probability * weight;
+    };
+
+    Ok(())
+  }
+
+  /// Removes outcomes that cannot happen from either end of the distribution.
+  fn trimmed(mut self) -> Self {
+    let start = self.probabilities.iter().position(|&probability| probability > 0.0).unwrap_or(0);
+    let end = self.probabilities.iter().rposition(|&probability| probability > 0.0).map_or(1, |end| end + 1);
+    self.probabilities.truncate(end);
+    self.probabilities.drain(..start);
+    self.min += start as i64;
+    self
+  }
+}
+
+impl Default for Distribution {
+  fn default() -> Self {
+    Distribution::constant(0)
+  }
+}
+
+/// Combines distributions while keeping track of how much work has been done,
+/// so that overly complex rolls give up instead of taking forever.
+#[derive(Debug, Default)]
+struct Calculator {
+  work: usize
+}
+
+impl Calculator {
+  const MAX_WORK: usize = 50_000_000;
+  /// Explosions and rerolls are followed until the chance of continuing falls below this.
+  const CUTOFF: f64 = 1e-12;
+
+  fn charge(&mut self, work: usize) -> Result<(), DistributionError> {
+    self.work = self.work.saturating_add(work);
+    if self.work > Self::MAX_WORK {
+      return Err(DistributionError::TooComplex);
+    };
+
+    Ok(())
+  }
+
+  fn expr(&mut self, expr: &Expr) -> Result<Distribution, DistributionError> {
+    match expr {
+      Expr::Number(number) => Ok(Distribution::constant(*number)),
+      Expr::Dice(dice) => self.dice(dice),
+      Expr::Variable(..) => Err(RollError::UnresolvedVariable.into()),
+      Expr::Neg(expr) => self.expr(expr)?.negated(),
+      Expr::Group(expr) => self.expr(expr),
+      Expr::Binary(lhs, op, rhs) => {
+        let lhs = self.expr(lhs)?;
+        let rhs = self.expr(rhs)?;
+        match op {
+          BinaryOp::Add => self.add(&lhs, &rhs),
+          BinaryOp::Sub => self.add(&lhs, &rhs.negated()?),
+          BinaryOp::Mul | BinaryOp::Div => self.combine(&lhs, &rhs, |lhs, rhs| op.apply(lhs, rhs))
+        }
+      }
+    }
+  }
+
+  /// Checked against the budget before anything is allocated, since a single die can have billions of sides.
+  fn uniform(&mut self, min: i64, max: i64) -> Result<Distribution, DistributionError> {
+    let len = usize::try_from(max.abs_diff(min)).unwrap_or(usize::MAX).saturating_add(1);
+    self.charge(len)?;
+    Distribution::uniform(min, max)
+  }
+
+  /// The distribution of the sum of two independent outcomes.
+  fn add(&mut self, lhs: &Distribution, rhs: &Distribution) -> Result<Distribution, DistributionError> {
+    self.charge(lhs.probabilities.len() * rhs.probabilities.len())?;
+    let min = lhs.min.checked_add(rhs.min).ok_or(RollError::Overflow)?;
+    let max = lhs.max_value().checked_add(rhs.max_value()).ok_or(RollError::Overflow)?;
+    let mut distribution = Distribution::zeroed(min, max)?;
+    for (i, &p) in lhs.probabilities.iter().enumerate() {
+      if p == 0.0 { continue };
+      for (j, &q) in rhs.probabilities.iter().enumerate() {
+        distribution.probabilities[i + j] += p * q;
+      };
+    };
+
+    Ok(distribution)
+  }
+
+  fn combine(
+    &mut self, lhs: &Distribution, rhs: &Distribution,
+    op: impl Fn(i64, i64) -> Result<i64, RollError>
+  ) -> Result<Distribution, DistributionError> {
+    self.charge(lhs.probabilities.len() * rhs.probabilities.len())?;
+    let mut map = BTreeMap::new();
+    for (x, p) in lhs.outcomes() {
+      for (y, q) in rhs.outcomes() {
+        *map.entry(op(x, y)?).or_insert(0.0) += p * q;
+      };
+    };
+
+    Distribution::from_map(map)
+  }
+
+  /// The distribution of the sum of `count` independent outcomes.
+  fn repeat(&mut self, distribution: &Distribution, mut count: u32) -> Result<Distribution, DistributionError> {
+    let mut total = Distribution::constant(0);
+    let mut base = distribution.clone();
+    while count > 0 {
+      if count & 1 == 1 { total = self.add(&total, &base)? };
+      count >>= 1;
+      if count > 0 { base = self.add(&base, &base)? };
+    };
+
+    Ok(total)
+  }
+
+  fn dice(&mut self, dice: &Dice) -> Result<Distribution, DistributionError> {
+    let faces = self.uniform(dice.sides.min(), dice.sides.max())?;
+    let first = match dice.reroll {
+      Some(reroll) => reroll_face(&faces, reroll),
+      None => faces.clone()
+    };
+
+    // the value each die contributes to the total, which is 1 or 0 when counting successes
+    let contribution = |value: i64| match dice.success {
+      Some(success) => success.matches(value) as i64,
+      None => value
+    };
+
+    match (dice.explode, dice.keep) {
+      (Some(..), Some(..)) => Err(DistributionError::ExplodingKeep),
+      (Some(explode), None) => {
+        let condition = explode.condition(dice.sides);
+        let chain = self.explosion_chain(&faces, condition, contribution)?;
+        let mut die = Distribution::zeroed(0, 0)?;
+        for (value, probability) in first.outcomes() {
+          let outcome = match condition.matches(value) {
+            true => chain.shifted(contribution(value))?,
+            false => Distribution::constant(contribution(value))
+          };
+
+          die.accumulate(&outcome, probability)?;
+        };
+
+        self.repeat(&die.trimmed(), dice.count)
+      },
+      (None, Some(keep)) => self.keep(&first, dice.count, keep, contribution),
+      (None, None) => {
+        let mut die = Distribution::zeroed(0, 0)?;
+        for (value, probability) in first.outcomes() {
+          die.accumulate(&Distribution::constant(contribution(value)), probability)?;
+        };
+
+        self.repeat(&die.trimmed(), dice.count)
+      }
+    }
+  }
+
+  /// The distribution of everything added by a die exploding, including the dice it causes to explode in turn.
+  fn explosion_chain(
+    &mut self, faces: &Distribution, condition: Condition,
+    contribution: impl Fn(i64) -> i64
+  ) -> Result<Distribution, DistributionError> {
+    let explode_chance = faces.chance(|value| condition.matches(value));
+    let depth = (1..Dice::MAX_REPEATS)
+      .find(|&depth| explode_chance.powi(depth as i32) < Self::CUTOFF)
+      .unwrap_or(Dice::MAX_REPEATS);
+
+    // built from the last explosion that could happen back to the first
+    let mut chain = Distribution::zeroed(0, 0)?;
+    for (value, probability) in faces.outcomes() {
+      chain.accumulate(&Distribution::constant(contribution(value)), probability)?;
+    };
+
+    for _ in 1..depth {
+      let mut next = Distribution::zeroed(0, 0)?;
+      for (value, probability) in faces.outcomes() {
+        let outcome = match condition.matches(value) {
+          true => chain.shifted(contribution(value))?,
+          false => Distribution::constant(contribution(value))
+        };
+
+        self.charge(outcome.probabilities.len())?;
+        next.accumulate(&outcome, probability)?;
+      };
+
+      chain = next.trimmed();
+    };
+
+    Ok(chain.trimmed())
+  }
+
+  /// The distribution of the total of the dice that are kept out of `count` dice.
+  ///
+  /// Faces are visited from the most preferred (highest when keeping highest) to the least,
+  /// deciding how many of the remaining dice landed on each face; the first dice to be placed are the ones kept.
+  fn keep(
+    &mut self, faces: &Distribution, count: u32, keep: Keep,
+    contribution: impl Fn(i64) -> i64
+  ) -> Result<Distribution, DistributionError> {
+    let count = count as usize;
+    let keep_count = (keep.count as usize).min(count);
+    let kept = match keep.kind {
+      KeepKind::KeepHighest | KeepKind::KeepLowest => keep_count,
+      KeepKind::DropHighest | KeepKind::DropLowest => count - keep_count
+    };
+
+    let mut faces = faces.outcomes().collect::<Vec<(i64, f64)>>();
+    if let KeepKind::KeepHighest | KeepKind::DropLowest = keep.kind {
+      faces.reverse();
+    };
+
+    // `placed[m]` is the distribution of the total so far, given that `m` dice have been placed
+    let mut placed = vec![None; count + 1];
+    placed[0] = Some(Distribution::constant(0));
+    let mut remaining_chance = 1.0;
+    let last = faces.len() - 1;
+    for (i, (value, probability)) in faces.into_iter().enumerate() {
+      // every die that hasn't been placed yet lands on the last face
+      let chance = if i == last { 1.0 } else { (probability / remaining_chance).min(1.0) };
+      remaining_chance -= probability;
+
+      let mut next = vec![None::<Distribution>; count + 1];
+      for (m, distribution) in placed.iter().enumerate() {
+        let Some(distribution) = distribution else { continue };
+        let remaining = count - m;
+        for (t, weight) in binomial(remaining, chance).into_iter().enumerate() {
+          if weight == 0.0 { continue };
+          self.charge(distribution.probabilities.len())?;
+          let newly_kept = kept.saturating_sub(m).min(t) as i64;
+          let outcome = distribution.shifted(newly_kept * contribution(value))?;
+          match &mut next[m + t] {
+            Some(next) => next.accumulate(&outcome, weight)?,
+            next @ None => {
+              let mut first = Distribution::zeroed(outcome.min, outcome.max_value())?;
+              first.accumulate(&outcome, weight)?;
+              *next = Some(first);
+            }
+          };
+        };
+      };
+
+      placed = next;
+    };
+
+    Ok(placed[count].take().unwrap_or_default().trimmed())
+  }
+}
+
+/// The chance of a single die landing on each face, after it is rerolled.
+fn reroll_face(faces: &Distribution, reroll: Reroll) -> Distribution {
+  let reroll_chance = faces.chance(|value| reroll.condition.matches(value));
+  let mut distribution = faces.clone();
+  for (i, probability) in distribution.probabilities.iter_mut().enumerate() {
+    let rerolled = reroll.condition.matches(faces.min + i as i64);
+    *probability = match (reroll.once, rerolled) {
+      // landing on this face either the first time, or after being rerolled
+      (true, false) => *probability + reroll_chance * *probability,
+      (true, true) => reroll_chance * *probability,
+      // rerolling until it stops leaves only the faces that aren't rerolled, all equally likely
+      (false, false) => *probability / (1.0 - reroll_chance),
+      (false, true) => 0.0
+    };
+  };
+
+  distribution
+}
+
+/// The chance of each number of successes out of `n` attempts that each succeed with chance `p`.
+fn binomial(n: usize, p: f64) -> Vec<f64> {
+  if p >= 1.0 {
+    let mut weights = vec![0.0; n + 1];
+    weights[n] = 1.0;
+    return weights;
+  };
+
+  // calculated with logarithms, since the individual factors over- or underflow with many dice
+  let ln_factorials = (0..=n).scan(0.0, |ln_factorial, k| {
+    if k > 0 { *ln_factorial += (k as f64).ln() };
+    Some(*ln_factorial)
+  }).collect::<Vec<f64>>();
+
+  (0..=n).map(|k| {
+    let ln_choose = ln_factorials[n] - ln_factorials[k] - ln_factorials[n - k];
+    (ln_choose + k as f64 * p.ln() + (n - k) as f64 * (1.0 - p).ln()).exp()
+  }).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum DistributionError {
+  #[error(transparent)]
+  Roll(#[from] RollError),
+  #[error("this roll has too many possible outcomes to calculate")]
+  TooComplex,
+  #[error("the odds of dice that both explode and are kept or dropped cannot be calculated")]
+  ExplodingKeep
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn distribution(roll: &str) -> RollDistribution {
+    roll.parse::<Roll>().unwrap().distribution().unwrap()
+  }
+
+  fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "expected {expected}, found {actual}");
+  }
+
+  #[test]
+  fn sums_dice() {
+    let outcomes = distribution("2d6").outcomes;
+    assert_eq!((outcomes.min(), outcomes.max()), (2, 12));
+    assert_close(outcomes.mean(), 7.0);
+    assert_close(outcomes.variance(), 35.0 / 6.0);
+    assert_close(outcomes.chance(|value| value == 7), 6.0 / 36.0);
+    assert_close(outcomes.outcomes().map(|(_, probability)| probability).sum(), 1.0);
+  }
+
+  #[test]
+  fn keeps_dice() {
+    // the well-known average of rolling ability scores
+    assert_close(distribution("4d6kh3").outcomes.mean(), 15869.0 / 1296.0);
+    assert_close(distribution("4d6dl1").outcomes.mean(), 15869.0 / 1296.0);
+    assert_close(distribution("2d20kl1").outcomes.chance(|value| value == 20), 1.0 / 400.0);
+    assert_close(distribution("1000d6kh1").outcomes.chance(|value| value == 6), 1.0 - (5.0f64 / 6.0).powi(1000));
+  }
+
+  #[test]
+  fn counts_target_successes() {
+    assert_close(distribution("d20 adv >= 15").success_chance.unwrap(), 1.0 - (14.0f64 / 20.0).powi(2));
+    assert_close(distribution("d20 dis >= 15").success_chance.unwrap(), (6.0f64 / 20.0).powi(2));
+    assert_close(distribution("3d6 max 2 > 11").success_chance.unwrap(), distribution("3d6kh2 > 11").success_chance.unwrap());
+    assert_close(distribution("coin heads").success_chance.unwrap(), 0.5);
+  }
+
+  #[test]
+  fn counts_dice_successes() {
+    let outcomes = distribution("10d10>=7").outcomes;
+    assert_eq!((outcomes.min(), outcomes.max()), (0, 10));
+    assert_close(outcomes.mean(), 4.0);
+  }
+
+  #[test]
+  fn handles_special_dice() {
+    let outcomes = distribution("4dF").outcomes;
+    assert_eq!((outcomes.min(), outcomes.max()), (-4, 4));
+    assert_close(outcomes.mean(), 0.0);
+    assert_close(distribution("d%").outcomes.mean(), 50.5);
+    assert_close(distribution("d6r1").outcomes.mean(), 4.0);
+    assert_close(distribution("d6ro1").outcomes.mean(), (20.0 + 3.5) / 6.0);
+    assert_close(distribution("d6!").outcomes.mean(), 3.5 * 6.0 / 5.0);
+  }
+
+  #[test]
+  fn combines_terms() {
+    let outcomes = distribution("(1d8 + 2) * 2").outcomes;
+    assert_eq!((outcomes.min(), outcomes.max()), (6, 20));
+    assert_close(outcomes.chance(|value| value % 2 == 1), 0.0);
+    assert_close(distribution("d6 - d6").outcomes.mean(), 0.0);
+    assert_close(distribution("d4 / 2").outcomes.mean(), 1.0);
+    assert_eq!("d6 / (d2 - 1)".parse::<Roll>().unwrap().distribution(), Err(DistributionError::Roll(RollError::DivisionByZero)));
+    assert_eq!("1000d6! + 0".parse::<Roll>().unwrap().distribution(), Err(DistributionError::TooComplex));
+  }
+
+  #[test]
+  fn rejects_huge_dice() {
+    assert_eq!("d4294967295".parse::<Roll>().unwrap().distribution(), Err(DistributionError::TooComplex));
+    assert_eq!(Distribution::uniform(i64::MIN, i64::MAX), Err(DistributionError::TooComplex));
+  }
+}
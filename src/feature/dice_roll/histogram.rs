@@ -0,0 +1,93 @@
+use super::*;
+use crate::feature::chart::{Align, Canvas, AXIS, TEXT};
+
+use image::{ImageResult, Rgb};
+
+
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 320;
+const MARGIN: u32 = 16;
+/// Outcomes are grouped together into at most this many bars.
+const MAX_BARS: usize = 150;
+/// Outcomes this unlikely at either end are left off, so that long tails (from exploding dice) don't flatten the chart.
+const TAIL: f64 = 1e-4;
+
+const LABEL_SIZE: f32 = 12.0;
+
+const NEUTRAL: Rgb<u8> = Rgb([0x58, 0x65, 0xf2]);
+const SUCCESS: Rgb<u8> = Rgb([0x57, 0xf2, 0x87]);
+const FAILURE: Rgb<u8> = Rgb([0xed, 0x42, 0x45]);
+const MEAN: Rgb<u8> = Rgb([0xfe, 0xe7, 0x5c]);
+
+/// Draws a bar chart of the chance of each outcome, encoded as a PNG.
+///
+/// When there is a target, outcomes meeting it are colored differently from those that don't.
+pub fn render_histogram(distribution: &Distribution, target: Option<RollTarget>) -> ImageResult<Vec<u8>> {
+  let (min, max) = visible_range(distribution);
+  let span = (max - min) as usize + 1;
+  let outcomes_per_bar = span.div_ceil(MAX_BARS);
+  let bar_count = span.div_ceil(outcomes_per_bar);
+
+  let mut bars = vec![(0.0, 0.0); bar_count];
+  for (value, probability) in distribution.outcomes().filter(|&(value, _)| (min..=max).contains(&value)) {
+    let (total, successes) = &mut bars[(value - min) as usize / outcomes_per_bar];
+    *total += probability;
+    if target.is_some_and(|target| target.comparison.exec(value, target.total)) {
+      *successes += probability;
+    };
+  };
+
+  let tallest = bars.iter().map(|&(total, _)| total).fold(0.0, f64::max);
+  let chart_width = WIDTH - MARGIN * 2;
+  let chart_height = HEIGHT - MARGIN * 3;
+  let bar_width = chart_width / bar_count as u32;
+  let gap = if bar_width >= 4 { 1 } else { 0 };
+  let left = MARGIN + (chart_width - bar_width * bar_count as u32) / 2;
+  let bottom = HEIGHT - MARGIN * 2;
+
+  let mut canvas = Canvas::new(WIDTH, HEIGHT);
+  for (i, &(total, successes)) in bars.iter().enumerate() {
+    let height = (total / tallest * chart_height as f64).round() as u32;
+    let color = match target {
+      Some(..) if successes * 2.0 >= total => SUCCESS,
+      Some(..) => FAILURE,
+      None => NEUTRAL
+    };
+
+    let x = left + i as u32 * bar_width;
+    canvas.fill_rect(x + gap, bottom - height, bar_width - gap * 2, height, color);
+  };
+
+  // a marker along the axis, below where the average outcome is
+  let mean_offset = (distribution.mean() - min as f64) / outcomes_per_bar as f64;
+  let mean_x = left + ((mean_offset + 0.5) * bar_width as f64).max(0.0).round() as u32;
+  canvas.fill_rect(left, bottom, bar_width * bar_count as u32, 2, AXIS);
+  canvas.fill_rect(mean_x.saturating_sub(1).min(WIDTH - MARGIN - 3), bottom, 3, MARGIN / 2, MEAN);
+
+  // the lowest and highest outcomes shown, below either end of the axis
+  let label_y = (HEIGHT - MARGIN) as f32;
+  let right = left + bar_width * bar_count as u32;
+  canvas.draw_text(&min.to_string(), left as f32, label_y, LABEL_SIZE, TEXT, Align::Left);
+  if max != min {
+    canvas.draw_text(&max.to_string(), right as f32, label_y, LABEL_SIZE, TEXT, Align::Right);
+  };
+
+  canvas.encode_png()
+}
+
+fn visible_range(distribution: &Distribution) -> (i64, i64) {
+  let min = first_visible(distribution.outcomes()).unwrap_or(distribution.min());
+  let max = first_visible(distribution.outcomes().rev()).unwrap_or(distribution.max());
+  (min, max.max(min))
+}
+
+fn first_visible(outcomes: impl Iterator<Item = (i64, f64)>) -> Option<i64> {
+  let mut cumulative = 0.0;
+  for (value, probability) in outcomes {
+    cumulative += probability;
+    if cumulative > TAIL { return Some(value) };
+  };
+
+  None
+}
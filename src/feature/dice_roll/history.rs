@@ -0,0 +1,173 @@
+use super::*;
+
+use chrono::{DateTime, Utc};
+use melody_random::{SecureRng, SecureRngSeed, SecureThreadRng};
+use rand::{RngCore, SeedableRng};
+use serenity::model::id::UserId;
+use sha2::{Digest, Sha256};
+
+use std::collections::VecDeque;
+
+
+
+/// The seed a roll was made with, which reproduces the roll exactly when it is made again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RollSeed(SecureRngSeed);
+
+impl RollSeed {
+  pub fn generate() -> Self {
+    let mut seed = SecureRngSeed::default();
+    SecureThreadRng::new().fill_bytes(&mut seed);
+    RollSeed(seed)
+  }
+
+  pub fn execute(&self, roll: Roll) -> Result<ExecutedRoll, RollError> {
+    roll.execute(&mut SecureRng::from_seed(self.0))
+  }
+
+  /// A SHA-256 hash of this seed, the roll's notation and whoever made it, which can be shared in the seed's place
+  /// to prove later that the roll was made with this seed, without revealing the seed (and the roll's result) right away.
+  /// The notation should be the roll's canonical form, as given by its `Display` implementation.
+  pub fn commitment(&self, roll: &str, user: UserId) -> RollCommitment {
+    let digest = Sha256::new()
+      .chain_update(self.0)
+      .chain_update(roll.as_bytes())
+      .chain_update(user.get().to_be_bytes())
+      .finalize();
+    let mut commitment = [0; 32];
+    commitment.copy_from_slice(&digest);
+    RollCommitment(commitment)
+  }
+}
+
+impl fmt::Display for RollSeed {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write_hex(f, &self.0)
+  }
+}
+
+impl FromStr for RollSeed {
+  type Err = ParseRollSeedError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let s = s.trim();
+    let mut seed = SecureRngSeed::default();
+    if s.len() != seed.len() * 2 || !s.is_ascii() { return Err(ParseRollSeedError) };
+    for (byte, digits) in seed.iter_mut().zip(s.as_bytes().chunks(2)) {
+      let digits = std::str::from_utf8(digits).map_err(|_| ParseRollSeedError)?;
+      *byte = u8::from_str_radix(digits, 16).map_err(|_| ParseRollSeedError)?;
+    };
+
+    Ok(RollSeed(seed))
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RollCommitment([u8; 32]);
+
+impl fmt::Display for RollCommitment {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write_hex(f, &self.0)
+  }
+}
+
+fn write_hex(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+  bytes.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+}
+
+#[derive(Debug, Clone, Copy, Error)]
+#[error("a seed must be 64 hexadecimal digits")]
+pub struct ParseRollSeedError;
+
+/// A record of a roll that was made in a channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RollHistoryEntry {
+  pub user: UserId,
+  pub time: DateTime<Utc>,
+  /// The roll that was made, with its variables filled in, so that it can be made again.
+  pub roll: String,
+  pub total: i64,
+  pub success: Option<bool>,
+  /// Secret rolls only show their seed's commitment, until their seed is revealed by whoever made them.
+  pub secret: bool,
+  pub seed: RollSeed
+}
+
+impl RollHistoryEntry {
+  pub fn new(user: UserId, roll: &Roll, executed_roll: &ExecutedRoll, secret: bool, seed: RollSeed) -> Self {
+    RollHistoryEntry {
+      user,
+      time: Utc::now(),
+      roll: roll.to_string(),
+      total: executed_roll.total(),
+      success: executed_roll.success(),
+      secret,
+      seed
+    }
+  }
+
+  pub fn commitment(&self) -> RollCommitment {
+    self.seed.commitment(&self.roll, self.user)
+  }
+}
+
+/// The most recent rolls made in a channel, from oldest to newest.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RollHistory {
+  entries: VecDeque<RollHistoryEntry>
+}
+
+impl RollHistory {
+  pub const MAX_ENTRIES: usize = 50;
+
+  pub fn push(&mut self, entry: RollHistoryEntry) {
+    if self.entries.len() >= Self::MAX_ENTRIES {
+      self.entries.pop_front();
+    };
+
+    self.entries.push_back(entry);
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// Iterates over the rolls in this history, from newest to oldest.
+  pub fn iter(&self) -> impl Iterator<Item = &RollHistoryEntry> {
+    self.entries.iter().rev()
+  }
+
+  /// Removes every roll made by a user.
+  pub fn remove_user(&mut self, user: UserId) {
+    self.entries.retain(|entry| entry.user != user);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn seeds_reproduce_rolls() {
+    let seed = RollSeed::generate();
+    assert_eq!(seed.to_string().parse::<RollSeed>().unwrap(), seed);
+    assert!("not a seed".parse::<RollSeed>().is_err());
+
+    let roll = "4d6kh3 + 10d20!".parse::<Roll>().unwrap();
+    let first = seed.execute(roll.clone()).unwrap().to_string();
+    let second = seed.execute(roll).unwrap().to_string();
+    assert_eq!(first, second);
+  }
+
+  #[test]
+  fn commitments_bind_the_roll_and_user() {
+    let seed = RollSeed::generate();
+    let roll = "1d20".parse::<Roll>().unwrap().to_string();
+    let other_roll = "1d20 + 5".parse::<Roll>().unwrap().to_string();
+    let commitment = seed.commitment(&roll, UserId::new(1));
+    assert_eq!(commitment, seed.commitment(&roll, UserId::new(1)));
+    assert_ne!(commitment, seed.commitment(&other_roll, UserId::new(1)));
+    assert_ne!(commitment, seed.commitment(&roll, UserId::new(2)));
+  }
+}
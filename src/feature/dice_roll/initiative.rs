@@ -0,0 +1,442 @@
+use crate::prelude::*;
+use crate::data::Core;
+
+use serenity::builder::{
+  CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter,
+  CreateInteractionResponse, CreateInteractionResponseMessage, EditMessage
+};
+use serenity::model::application::{ButtonStyle, ComponentInteraction};
+use serenity::model::id::{ChannelId, MessageId, UserId};
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+
+
+const BUTTON_PREVIOUS: &str = "initiative-previous";
+const BUTTON_NEXT: &str = "initiative-next";
+
+/// A combat encounter being tracked in a channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InitiativeTracker {
+  /// The user who started the encounter, who may change any of its combatants.
+  pub gm: UserId,
+  /// The message displaying this tracker, which is edited whenever it changes.
+  pub message: Option<MessageId>,
+  /// Combatants, from highest to lowest initiative.
+  combatants: Vec<Combatant>,
+  /// The index of the combatant whose turn it is.
+  turn: usize,
+  round: u32
+}
+
+impl InitiativeTracker {
+  // these keep the tracker's embed description within discord's limit of 4096 characters
+  pub const MAX_COMBATANTS: usize = 20;
+  pub const MAX_NAME_LEN: usize = 32;
+  pub const MAX_CONDITIONS: usize = 4;
+  pub const MAX_CONDITION_LEN: usize = 20;
+
+  pub fn new(gm: UserId) -> Self {
+    InitiativeTracker { gm, message: None, combatants: Vec::new(), turn: 0, round: 1 }
+  }
+
+  pub fn combatants(&self) -> &[Combatant] {
+    &self.combatants
+  }
+
+  /// The combatant whose turn it is, if there are any.
+  pub fn current(&self) -> Option<&Combatant> {
+    self.combatants.get(self.turn)
+  }
+
+  pub fn round(&self) -> u32 {
+    self.round
+  }
+
+  /// Whether a user may change a combatant, or pass its turn.
+  pub fn can_edit(&self, user: UserId, combatant: &Combatant) -> bool {
+    user == self.gm || user == combatant.owner
+  }
+
+  /// Whether a user may move to the next or previous turn.
+  pub fn can_advance(&self, user: UserId) -> bool {
+    user == self.gm || self.current().is_some_and(|current| current.owner == user)
+  }
+
+  pub fn get(&self, name: &str) -> Result<&Combatant, InitiativeError> {
+    self.position(name).map(|i| &self.combatants[i])
+  }
+
+  pub fn add(&mut self, combatant: Combatant) -> Result<(), InitiativeError> {
+    let name_len = combatant.name.chars().count();
+    if name_len == 0 || name_len > Self::MAX_NAME_LEN {
+      return Err(InitiativeError::InvalidName);
+    } else if self.position(&combatant.name).is_ok() {
+      return Err(InitiativeError::DuplicateName(combatant.name));
+    } else if self.combatants.len() >= Self::MAX_COMBATANTS {
+      return Err(InitiativeError::TooManyCombatants);
+    };
+
+    self.combatants.push(combatant);
+    self.sort();
+    Ok(())
+  }
+
+  /// Removes a combatant, passing the turn on to the next one if it was theirs.
+  pub fn remove(&mut self, name: &str) -> Result<Combatant, InitiativeError> {
+    let i = self.position(name)?;
+    let combatant = self.combatants.remove(i);
+    self.removed(i);
+    Ok(combatant)
+  }
+
+  /// Removes every combatant belonging to a user, returning how many there were.
+  pub fn remove_user(&mut self, user: UserId) -> usize {
+    let mut removed = 0;
+    while let Some(i) = self.combatants.iter().position(|combatant| combatant.owner == user) {
+      self.combatants.remove(i);
+      self.removed(i);
+      removed += 1;
+    };
+
+    removed
+  }
+
+  fn removed(&mut self, i: usize) {
+    if i < self.turn {
+      self.turn -= 1;
+    } else if self.turn >= self.combatants.len() && self.turn != 0 {
+      self.turn = 0;
+      self.round += 1;
+    };
+  }
+
+  /// Changes a combatant's initiative, moving them to their new place in the order.
+  pub fn set_initiative(&mut self, name: &str, initiative: i64) -> Result<(), InitiativeError> {
+    let i = self.position(name)?;
+    self.combatants[i].initiative = initiative;
+    self.sort();
+    Ok(())
+  }
+
+  /// Puts the combatants in order of initiative, keeping the turn with the same combatant.
+  /// Combatants with the same initiative stay in the order they were added.
+  fn sort(&mut self) {
+    let current = self.current().map(|current| current.name.clone());
+    self.combatants.sort_by_key(|combatant| std::cmp::Reverse(combatant.initiative));
+    if let Some(current) = current {
+      self.turn = self.position(&current).unwrap_or(0);
+    };
+  }
+
+  /// Moves on to the next combatant's turn, starting a new round after the last one.
+  pub fn next_turn(&mut self) {
+    if self.combatants.is_empty() { return };
+    self.turn += 1;
+    if self.turn >= self.combatants.len() {
+      self.turn = 0;
+      self.round += 1;
+    };
+  }
+
+  /// Moves back to the previous combatant's turn, returning false if it is already the first turn of the encounter.
+  pub fn previous_turn(&mut self) -> bool {
+    if self.turn > 0 {
+      self.turn -= 1;
+    } else if self.round > 1 && !self.combatants.is_empty() {
+      self.turn = self.combatants.len() - 1;
+      self.round -= 1;
+    } else {
+      return false;
+    };
+
+    true
+  }
+
+  pub fn change_hit_points(&mut self, name: &str, change: HitPointsChange) -> Result<HitPoints, InitiativeError> {
+    let combatant = self.get_mut(name)?;
+    let hit_points = match (change, combatant.hit_points) {
+      (HitPointsChange::Set(hit_points), _) => hit_points,
+      (HitPointsChange::Heal(amount), Some(HitPoints { current, max })) => {
+        HitPoints { current: current.saturating_add(amount).min(max).max(current), max }
+      },
+      (HitPointsChange::Damage(amount), Some(HitPoints { current, max })) => {
+        HitPoints { current: current.saturating_sub(amount), max }
+      },
+      (_, None) => return Err(InitiativeError::NoHitPoints(combatant.name.clone()))
+    };
+
+    combatant.hit_points = Some(hit_points);
+    Ok(hit_points)
+  }
+
+  /// Adds a condition to a combatant, or removes it if they already have it.
+  /// Returns whether the condition was added.
+  pub fn toggle_condition(&mut self, name: &str, condition: &str) -> Result<bool, InitiativeError> {
+    let condition = condition.trim().to_lowercase();
+    let condition_len = condition.chars().count();
+    if condition_len == 0 || condition_len > Self::MAX_CONDITION_LEN {
+      return Err(InitiativeError::InvalidCondition);
+    };
+
+    let combatant = self.get_mut(name)?;
+    if combatant.conditions.remove(&condition) {
+      Ok(false)
+    } else if combatant.conditions.len() >= Self::MAX_CONDITIONS {
+      Err(InitiativeError::TooManyConditions)
+    } else {
+      combatant.conditions.insert(condition);
+      Ok(true)
+    }
+  }
+
+  fn get_mut(&mut self, name: &str) -> Result<&mut Combatant, InitiativeError> {
+    self.position(name).map(|i| &mut self.combatants[i])
+  }
+
+  /// Finds a combatant by name, ignoring case.
+  fn position(&self, name: &str) -> Result<usize, InitiativeError> {
+    let name = name.trim();
+    self.combatants.iter()
+      .position(|combatant| combatant.name.to_lowercase() == name.to_lowercase())
+      .ok_or_else(|| InitiativeError::UnknownCombatant(name.to_owned()))
+  }
+
+  pub fn create_embed(&self) -> CreateEmbed {
+    let mut description = format!("Run by {}\n\n", self.gm.mention());
+    if self.combatants.is_empty() {
+      description.push_str("*No combatants yet, add some with `/initiative add`*");
+    };
+
+    for (i, combatant) in self.combatants.iter().enumerate() {
+      let marker = if i == self.turn { "▶" } else { "▫️" };
+      description.push_str(&format!("{marker} {combatant}\n"));
+    };
+
+    let title = format!("Initiative — Round {}", self.round);
+    let embed = CreateEmbed::default().title(title).description(description);
+    match self.current() {
+      Some(current) => embed.footer(CreateEmbedFooter::new(format!("It is {}'s turn", current.name))),
+      None => embed
+    }
+  }
+
+  pub fn create_components(&self) -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+      CreateButton::new(BUTTON_PREVIOUS).label("Previous").style(ButtonStyle::Secondary),
+      CreateButton::new(BUTTON_NEXT).label("Next").style(ButtonStyle::Primary)
+    ])]
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Combatant {
+  pub name: String,
+  /// The user who added this combatant, who may change it and pass its turn.
+  pub owner: UserId,
+  pub initiative: i64,
+  pub hit_points: Option<HitPoints>,
+  pub conditions: BTreeSet<String>
+}
+
+impl Combatant {
+  pub fn new(name: impl Into<String>, owner: UserId, initiative: i64, hit_points: Option<HitPoints>) -> Self {
+    Combatant { name: name.into().trim().to_owned(), owner, initiative, hit_points, conditions: BTreeSet::new() }
+  }
+}
+
+impl fmt::Display for Combatant {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "`{:>3}` **{}**", self.initiative, self.name)?;
+    if let Some(hit_points) = self.hit_points {
+      write!(f, " — {hit_points} HP")?;
+      if hit_points.current <= 0 {
+        f.write_str(" 💀")?;
+      };
+    };
+
+    if !self.conditions.is_empty() {
+      write!(f, " — *{}*", self.conditions.iter().join(", "))?;
+    };
+
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HitPoints {
+  pub current: i64,
+  pub max: i64
+}
+
+impl fmt::Display for HitPoints {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}/{}", self.current, self.max)
+  }
+}
+
+/// A change to a combatant's hit points, written as `-7` for damage, `+5` for healing,
+/// `30` to set their current and maximum hit points, or `12/30` to set them separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitPointsChange {
+  Set(HitPoints),
+  Heal(i64),
+  Damage(i64)
+}
+
+impl FromStr for HitPointsChange {
+  type Err = InitiativeError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = || InitiativeError::InvalidHitPointsChange(s.to_owned());
+    let parse = |s: &str| s.trim().parse::<i64>().map_err(|_| invalid());
+    let s = s.trim();
+    if let Some(amount) = s.strip_prefix('+') {
+      Ok(HitPointsChange::Heal(parse(amount)?.max(0)))
+    } else if let Some(amount) = s.strip_prefix('-') {
+      Ok(HitPointsChange::Damage(parse(amount)?.max(0)))
+    } else if let Some((current, max)) = s.split_once('/') {
+      Ok(HitPointsChange::Set(HitPoints { current: parse(current)?, max: parse(max)? }))
+    } else {
+      let value = parse(s)?;
+      Ok(HitPointsChange::Set(HitPoints { current: value, max: value }))
+    }
+  }
+}
+
+#[derive(Debug, Error)]
+pub enum InitiativeError {
+  #[error("combatant names must be between 1 and {} characters long", InitiativeTracker::MAX_NAME_LEN)]
+  InvalidName,
+  #[error("there is already a combatant named {0:?}")]
+  DuplicateName(String),
+  #[error("there is no combatant named {0:?}")]
+  UnknownCombatant(String),
+  #[error("there may be at most {} combatants in an encounter", InitiativeTracker::MAX_COMBATANTS)]
+  TooManyCombatants,
+  #[error("{0:?} has no hit points to change, set them first with a value like `30`")]
+  NoHitPoints(String),
+  #[error("{0:?} is not a valid hit point change, use a value like `-7`, `+5`, `30` or `12/30`")]
+  InvalidHitPointsChange(String),
+  #[error("conditions must be between 1 and {} characters long", InitiativeTracker::MAX_CONDITION_LEN)]
+  InvalidCondition,
+  #[error("a combatant may have at most {} conditions", InitiativeTracker::MAX_CONDITIONS)]
+  TooManyConditions
+}
+
+/// Edits the message displaying a channel's tracker to match its current state.
+pub async fn update_initiative_message(core: &Core, channel_id: ChannelId, tracker: &InitiativeTracker) -> MelodyResult {
+  let Some(message_id) = tracker.message else { return Ok(()) };
+  let builder = EditMessage::new()
+    .embed(tracker.create_embed())
+    .components(tracker.create_components());
+  channel_id.edit_message(core, message_id, builder).await
+    .context("failed to edit initiative tracker message")?;
+  Ok(())
+}
+
+/// Handles the previous and next buttons on initiative tracker messages, ignoring any other components.
+pub async fn handle_initiative_component(core: &Core, component: &ComponentInteraction) -> MelodyResult {
+  let custom_id = component.data.custom_id.as_str();
+  if custom_id != BUTTON_PREVIOUS && custom_id != BUTTON_NEXT { return Ok(()) };
+  let Some(guild_id) = component.guild_id else { return Ok(()) };
+
+  let (channel_id, message_id, user_id) = (component.channel_id, component.message.id, component.user.id);
+  let response = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    let Some(tracker) = persist_guild.initiative_trackers.get_mut(&channel_id)
+      .filter(|tracker| tracker.message == Some(message_id)) else {
+      // this message belongs to an encounter that has ended or been shown again elsewhere
+      let message = CreateInteractionResponseMessage::new().components(Vec::new());
+      return Ok(CreateInteractionResponse::UpdateMessage(message));
+    };
+
+    if !tracker.can_advance(user_id) {
+      let message = CreateInteractionResponseMessage::new().ephemeral(true)
+        .content("Only the GM or the combatant whose turn it is may change turns");
+      return Ok(CreateInteractionResponse::Message(message));
+    };
+
+    if custom_id == BUTTON_NEXT {
+      tracker.next_turn();
+    } else {
+      tracker.previous_turn();
+    };
+
+    let message = CreateInteractionResponseMessage::new()
+      .embed(tracker.create_embed())
+      .components(tracker.create_components());
+    Ok(CreateInteractionResponse::UpdateMessage(message))
+  }).await?;
+
+  component.create_response(core, response).await
+    .context("failed to respond to initiative tracker button")?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tracker(combatants: &[(&str, i64)]) -> InitiativeTracker {
+    let mut tracker = InitiativeTracker::new(UserId::new(1));
+    for &(name, initiative) in combatants {
+      tracker.add(Combatant::new(name, UserId::new(2), initiative, None)).unwrap();
+    };
+
+    tracker
+  }
+
+  fn names(tracker: &InitiativeTracker) -> Vec<&str> {
+    tracker.combatants().iter().map(|combatant| combatant.name.as_str()).collect()
+  }
+
+  #[test]
+  fn sorts_by_initiative() {
+    let tracker = tracker(&[("Goblin", 12), ("Fighter", 18), ("Wizard", 12), ("Ogre", 3)]);
+    assert_eq!(names(&tracker), ["Fighter", "Goblin", "Wizard", "Ogre"]);
+  }
+
+  #[test]
+  fn keeps_turn_when_order_changes() {
+    let mut tracker = tracker(&[("Fighter", 18), ("Goblin", 12), ("Ogre", 3)]);
+    tracker.next_turn();
+    assert_eq!(tracker.current().unwrap().name, "Goblin");
+
+    tracker.add(Combatant::new("Rogue", UserId::new(2), 20, None)).unwrap();
+    assert_eq!(tracker.current().unwrap().name, "Goblin");
+    tracker.set_initiative("goblin", 1).unwrap();
+    assert_eq!(tracker.current().unwrap().name, "Goblin");
+    tracker.remove("Fighter").unwrap();
+    assert_eq!(tracker.current().unwrap().name, "Goblin");
+
+    // removing the last combatant during their turn passes it to the first combatant of the next round
+    tracker.remove("Goblin").unwrap();
+    assert_eq!(tracker.current().unwrap().name, "Rogue");
+    assert_eq!(tracker.round(), 2);
+  }
+
+  #[test]
+  fn advances_turns_and_rounds() {
+    let mut tracker = tracker(&[("Fighter", 18), ("Goblin", 12)]);
+    assert!(!tracker.previous_turn());
+    tracker.next_turn();
+    tracker.next_turn();
+    assert_eq!((tracker.round(), tracker.current().unwrap().name.as_str()), (2, "Fighter"));
+    assert!(tracker.previous_turn());
+    assert_eq!((tracker.round(), tracker.current().unwrap().name.as_str()), (1, "Goblin"));
+  }
+
+  #[test]
+  fn changes_hit_points() {
+    let mut tracker = tracker(&[("Goblin", 12)]);
+    let change = |s: &str| s.parse::<HitPointsChange>().unwrap();
+    assert!(tracker.change_hit_points("Goblin", change("-3")).is_err());
+    assert_eq!(tracker.change_hit_points("Goblin", change("7")).unwrap(), HitPoints { current: 7, max: 7 });
+    assert_eq!(tracker.change_hit_points("Goblin", change("-10")).unwrap(), HitPoints { current: -3, max: 7 });
+    assert_eq!(tracker.change_hit_points("Goblin", change("+20")).unwrap(), HitPoints { current: 7, max: 7 });
+    assert_eq!(tracker.change_hit_points("Goblin", change("2/9")).unwrap(), HitPoints { current: 2, max: 9 });
+    assert!("heal".parse::<HitPointsChange>().is_err());
+  }
+}
@@ -0,0 +1,275 @@
+use super::*;
+
+use chumsky::prelude::*;
+use chumsky::text::whitespace;
+
+use std::ops::Range;
+use std::str::FromStr;
+
+/*
+ROLL: (ROLL_COIN | ROLL_DICE)
+ROLL_COIN: 'coin' (','? TARGET_COIN)?
+TARGET_COIN: ('heads' | 'tails' | '1' | '2')
+ROLL_DICE: EXPR LEGACY_MODE? (','? TARGET_DICE)?
+TARGET_DICE: COMPARISON INT
+EXPR: PRODUCT (('+' | '-') PRODUCT)*
+PRODUCT: UNARY (('*' | '/') UNARY)*
+UNARY: '-'* ATOM
+ATOM: (DICE | INT | VARIABLE | '(' EXPR ')')
+VARIABLE: '@' NAME
+DICE: INT? 'd' SIDES DICE_MODIFIER* SUCCESS?
+SIDES: (INT | '%' | 'f')
+DICE_MODIFIER: (REROLL | EXPLODE | KEEP)
+REROLL: ('ro' | 'r') CONDITION
+EXPLODE: '!' CONDITION?
+KEEP: ('kh' | 'kl' | 'k' | 'dh' | 'dl') INT?
+SUCCESS: COMPARISON INT
+CONDITION: COMPARISON? INT
+COMPARISON: ('>=' | '>' | '<=' | '<' | '==' | '=' | '!=')
+LEGACY_MODE: (('min' | 'minimum' | 'max' | 'maximum') INT? | 'adv' | 'advantage' | 'dis' | 'disadvantage')
+INT: DIGIT+
+NAME: (ascii letters | DIGIT | '_')+
+DIGIT: (ascii digits)
+*/
+
+macro_rules! spaced {
+  ($expr:expr $(,$exprn:expr)*) => {
+    $expr$(.then_ignore(whitespace()).then($exprn))*
+  };
+}
+
+pub(super) fn roll() -> impl Parser<char, Roll, Error = Simple<char>> {
+  roll_coin().or(roll_dice()).padded().then_ignore(end())
+}
+
+fn roll_coin() -> impl Parser<char, Roll, Error = Simple<char>> {
+  let target = just(',').then(whitespace()).or_not()
+    .ignore_then(target_coin()).or_not();
+
+  just("coin").then(whitespace()).ignore_then(target)
+    .map(Roll::Coin).labelled("coin syntax")
+}
+
+fn target_coin() -> impl Parser<char, bool, Error = Simple<char>> {
+  choice([
+    just("heads").to(true),
+    just("tails").to(false),
+    just("1").to(true),
+    just("2").to(false)
+  ])
+}
+
+fn roll_dice() -> impl Parser<char, Roll, Error = Simple<char>> {
+  let target = just(',').then(whitespace()).or_not()
+    .ignore_then(target_dice());
+
+  spaced!(expr(), legacy_mode().or_not(), target.or_not())
+    .try_map(|((expr, legacy_mode), target), span| {
+      let expr = match legacy_mode {
+        Some(legacy_mode) => legacy_mode.apply(expr).map_err(|msg| Simple::custom(span, msg))?,
+        None => expr
+      };
+
+      Ok(Roll::Dice(expr, target))
+    })
+    .labelled("dice syntax")
+}
+
+fn target_dice() -> impl Parser<char, RollTarget, Error = Simple<char>> {
+  spaced!(comparison(), int::<i64>()).map(|(comparison, total)| RollTarget { total, comparison })
+}
+
+fn expr() -> impl Parser<char, Expr, Error = Simple<char>> {
+  recursive(|expr| {
+    let group = expr
+      .delimited_by(just('(').then(whitespace()), whitespace().then(just(')')))
+      .map(|expr| Expr::Group(Box::new(expr)))
+      .labelled("parentheses");
+    let atom = choice((dice().map(Expr::Dice), int::<i64>().map(Expr::Number), variable().map(Expr::Variable), group)).boxed();
+
+    let unary = just('-').then(whitespace()).repeated()
+      .then(atom)
+      .foldr(|_, expr| Expr::Neg(Box::new(expr)))
+      .boxed();
+
+    let product_op = choice((just('*').to(BinaryOp::Mul), just('/').to(BinaryOp::Div)));
+    let product = unary.clone()
+      .then(whitespace().ignore_then(product_op).then_ignore(whitespace()).then(unary).repeated())
+      .foldl(|lhs, (op, rhs)| Expr::Binary(Box::new(lhs), op, Box::new(rhs)))
+      .boxed();
+
+    let sum_op = choice((just('+').to(BinaryOp::Add), just('-').to(BinaryOp::Sub)));
+    product.clone()
+      .then(whitespace().ignore_then(sum_op).then_ignore(whitespace()).then(product).repeated())
+      .foldl(|lhs, (op, rhs)| Expr::Binary(Box::new(lhs), op, Box::new(rhs)))
+  })
+}
+
+fn variable() -> impl Parser<char, String, Error = Simple<char>> {
+  just('@').ignore_then(name()).labelled("variable")
+}
+
+/// The name of a variable or macro.
+pub(super) fn name() -> impl Parser<char, String, Error = Simple<char>> + Copy {
+  filter(|c: &char| c.is_ascii_alphanumeric() || *c == '_').repeated().at_least(1).collect::<String>()
+    .validate(|name, span, emit| {
+      if name.len() > MAX_NAME_LEN {
+        emit(Simple::custom(span, format!("names may not be longer than {MAX_NAME_LEN} characters")));
+      };
+
+      name
+    })
+    .labelled("name")
+}
+
+fn dice() -> impl Parser<char, Dice, Error = Simple<char>> {
+  let count = or_not_with(dice_count(), 1);
+  let sides = just('d').ignore_then(sides());
+  count.then(sides)
+    .then(dice_modifier().repeated())
+    .then(comparison().then(int::<i64>()).or_not())
+    .try_map(|(((count, sides), modifiers), success), span| {
+      let mut dice = Dice::new(count, sides);
+      for modifier in modifiers {
+        dice.apply_modifier(modifier).map_err(|msg| Simple::custom(span.clone(), msg))?;
+      };
+
+      dice.success = success.map(|(comparison, value)| Condition { comparison, value });
+      Ok(dice)
+    })
+    .labelled("dice")
+}
+
+fn dice_count() -> impl Parser<char, u32, Error = Simple<char>> + Copy {
+  int::<u32>().validate(valid_int(1, Dice::MAX_COUNT)).labelled("dice count")
+}
+
+fn sides() -> impl Parser<char, Sides, Error = Simple<char>> {
+  choice((
+    just('%').to(Sides::Percentile),
+    just('f').to(Sides::Fate),
+    int::<u32>().validate(valid_int(2, u32::MAX)).map(Sides::Number)
+  )).labelled("sides count")
+}
+
+fn dice_modifier() -> impl Parser<char, DiceModifier, Error = Simple<char>> {
+  let reroll = choice((just("ro").to(true), just("r").to(false)))
+    .then(condition())
+    .map(|(once, condition)| DiceModifier::Reroll(Reroll { condition, once }));
+  // `!=` after a dice term is a success comparison, not an explosion
+  let explode = just('!').ignore_then(just('=').or_not().rewind())
+    .try_map(|eq, span| match eq {
+      Some(..) => Err(Simple::custom(span, "expected an explosion")),
+      None => Ok(())
+    })
+    .ignore_then(condition().or_not())
+    .map(|condition| DiceModifier::Explode(Explode { condition }));
+  let keep_kind = choice((
+    just("kh").to(KeepKind::KeepHighest),
+    just("kl").to(KeepKind::KeepLowest),
+    just("k").to(KeepKind::KeepHighest),
+    just("dh").to(KeepKind::DropHighest),
+    just("dl").to(KeepKind::DropLowest)
+  ));
+  let keep = keep_kind.then(or_not_with(int::<u32>().validate(valid_int(1, Dice::MAX_COUNT)), 1))
+    .map(|(kind, count)| DiceModifier::Keep(Keep { kind, count }));
+
+  choice((reroll, explode, keep)).labelled("dice modifier")
+}
+
+fn condition() -> impl Parser<char, Condition, Error = Simple<char>> {
+  or_not_with(comparison(), Comparison::EqualTo).then(int::<i64>())
+    .map(|(comparison, value)| Condition { comparison, value })
+    .labelled("condition")
+}
+
+fn comparison() -> impl Parser<char, Comparison, Error = Simple<char>> + Copy {
+  choice([
+    just("\u{2265}").to(Comparison::GreaterThanEqualTo),
+    just(">=").to(Comparison::GreaterThanEqualTo),
+    just(">").to(Comparison::GreaterThan),
+    just("\u{2264}").to(Comparison::LessThanEqualTo),
+    just("<=").to(Comparison::LessThanEqualTo),
+    just("<").to(Comparison::LessThan),
+    just("\u{2260}").to(Comparison::NotEqualTo),
+    just("!=").to(Comparison::NotEqualTo),
+    just("==").to(Comparison::EqualTo),
+    just("=").to(Comparison::EqualTo),
+  ])
+}
+
+fn legacy_mode() -> impl Parser<char, LegacyMode, Error = Simple<char>> {
+  let min = choice((just("minimum"), just("min"))).to(KeepKind::KeepLowest);
+  let max = choice((just("maximum"), just("max"))).to(KeepKind::KeepHighest);
+  let count = or_not_with(int::<u32>().validate(valid_int(1, Dice::MAX_COUNT)), 1);
+  let mode1 = spaced!(choice((min, max)), count)
+    .map(|(kind, count)| LegacyMode::Keep(Keep { kind, count }));
+
+  let dis = choice((just("disadvantage"), just("dis"))).to(KeepKind::KeepLowest);
+  let adv = choice((just("advantage"), just("adv"))).to(KeepKind::KeepHighest);
+  let mode2 = choice((dis, adv)).map(LegacyMode::Advantage);
+
+  choice((mode1, mode2)).labelled("mode")
+}
+
+fn or_not_with<P, T>(p: P, default: T) -> impl Parser<char, T, Error = Simple<char>> + Copy
+where P: Parser<char, T, Error = Simple<char>> + Copy, T: Copy {
+  p.or_not().map(move |v| v.unwrap_or(default))
+}
+
+fn int<T: FromStr>() -> impl Parser<char, T, Error = Simple<char>> + Copy
+where <T as FromStr>::Err: ToString {
+  from_str(digits().collect::<String>()).labelled("int")
+}
+
+fn digits() -> impl Parser<char, Vec<char>, Error = Simple<char>> + Copy {
+  filter(char::is_ascii_digit).repeated().at_least(1).labelled("digits")
+}
+
+fn from_str<T: FromStr, P, S>(p: P) -> impl Parser<char, T, Error = Simple<char>> + Copy
+where <T as FromStr>::Err: ToString, S: AsRef<str>, P: Parser<char, S, Error = Simple<char>> + Copy {
+  p.try_map(|s, span| s.as_ref().parse::<T>().map_err(|err| Simple::custom(span, err)))
+}
+
+fn valid_int<I: Copy + Ord + fmt::Display>(min: I, max: I)
+-> impl Fn(I, Range<usize>, &mut dyn FnMut(Simple<char>)) -> I + Copy {
+  move |v, span, emit| {
+    if v < min { emit(Simple::custom(span.clone(), format!("int may not be less than {min}"))) };
+    if v > max { emit(Simple::custom(span, format!("int may not be more than {max}"))) };
+    v
+  }
+}
+
+/// The older `min`/`max`/`adv`/`dis` syntax, which applies to the only dice term in an expression.
+#[derive(Debug, Clone, Copy)]
+enum LegacyMode {
+  Keep(Keep),
+  /// Rolls a single die twice, keeping one of them.
+  Advantage(KeepKind)
+}
+
+impl LegacyMode {
+  fn apply(self, mut expr: Expr) -> Result<Expr, String> {
+    let mut dice_terms = expr.dice_terms_mut();
+    let dice = match (dice_terms.next(), dice_terms.next()) {
+      (Some(dice), None) => dice,
+      _ => return Err("min, max, advantage and disadvantage may only be used with a single dice term".to_owned())
+    };
+
+    let keep = match self {
+      LegacyMode::Keep(keep) => keep,
+      LegacyMode::Advantage(kind) => {
+        if dice.count != 1 {
+          return Err("advantage and disadvantage may only be used when rolling a single die".to_owned());
+        };
+
+        dice.count = 2;
+        Keep { kind, count: 1 }
+      }
+    };
+
+    dice.apply_modifier(DiceModifier::Keep(keep))?;
+    drop(dice_terms);
+    Ok(expr)
+  }
+}
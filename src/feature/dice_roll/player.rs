@@ -0,0 +1,124 @@
+use super::*;
+
+use singlefile_formats::data::json_serde::original as serde_json;
+
+use std::collections::BTreeMap;
+
+
+
+/// A user's saved roll macros and character sheet in a guild.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DicePlayer {
+  /// Saved roll notations, by name.
+  pub macros: BTreeMap<String, String>,
+  /// The values that variables such as `@str_mod` are replaced with.
+  pub sheet: BTreeMap<String, i64>
+}
+
+impl DicePlayer {
+  pub const MAX_MACROS: usize = 50;
+  pub const MAX_VARIABLES: usize = 100;
+
+  pub fn is_empty(&self) -> bool {
+    self.macros.is_empty() && self.sheet.is_empty()
+  }
+
+  /// Parses a roll, filling in its variables from this player's character sheet.
+  pub fn parse_roll(&self, notation: &str) -> Result<Roll, DicePlayerError> {
+    let roll = notation.parse::<Roll>()?;
+    Ok(roll.resolve(|name| self.sheet.get(name).copied())?)
+  }
+
+  /// Parses one of this player's saved macros, filling in its variables.
+  pub fn parse_macro(&self, name: &str) -> Result<Roll, DicePlayerError> {
+    let name = normalize_name(name)?;
+    let notation = self.macros.get(&name).ok_or(DicePlayerError::UnknownMacro(name))?;
+    self.parse_roll(notation)
+  }
+
+  /// Saves a macro, returning the notation it replaced, if there was one.
+  pub fn save_macro(&mut self, name: &str, notation: &str) -> Result<Option<String>, DicePlayerError> {
+    let name = normalize_name(name)?;
+    notation.parse::<Roll>()?;
+    if !self.macros.contains_key(&name) && self.macros.len() >= Self::MAX_MACROS {
+      return Err(DicePlayerError::TooManyMacros);
+    };
+
+    Ok(self.macros.insert(name, notation.trim().to_owned()))
+  }
+
+  pub fn delete_macro(&mut self, name: &str) -> Result<Option<String>, DicePlayerError> {
+    Ok(self.macros.remove(&normalize_name(name)?))
+  }
+
+  /// Sets a variable on the character sheet, returning the value it replaced, if there was one.
+  pub fn set_variable(&mut self, name: &str, value: i64) -> Result<Option<i64>, DicePlayerError> {
+    let name = normalize_name(name)?;
+    if !self.sheet.contains_key(&name) && self.sheet.len() >= Self::MAX_VARIABLES {
+      return Err(DicePlayerError::TooManyVariables);
+    };
+
+    Ok(self.sheet.insert(name, value))
+  }
+
+  pub fn unset_variable(&mut self, name: &str) -> Result<Option<i64>, DicePlayerError> {
+    Ok(self.sheet.remove(&normalize_name(name)?))
+  }
+
+  pub fn to_json(&self) -> String {
+    serde_json::to_string_pretty(self).expect("failed to serialize dice player")
+  }
+
+  /// Reads macros and a character sheet that were exported with [`DicePlayer::to_json`], checking that they are valid.
+  pub fn from_json(json: &[u8]) -> Result<Self, DicePlayerError> {
+    let imported = serde_json::from_slice::<DicePlayer>(json)?;
+    let mut player = DicePlayer::default();
+    player.import(imported)?;
+    Ok(player)
+  }
+
+  /// Adds the macros and variables of another player to this one, replacing any that have the same name.
+  /// Nothing is changed if any of them are invalid.
+  pub fn import(&mut self, other: DicePlayer) -> Result<(), DicePlayerError> {
+    let mut player = self.clone();
+    for (name, notation) in other.macros {
+      player.save_macro(&name, &notation)?;
+    };
+
+    for (name, value) in other.sheet {
+      player.set_variable(&name, value)?;
+    };
+
+    *self = player;
+    Ok(())
+  }
+}
+
+/// Checks that a macro or variable name is valid, returning it in lowercase and without a leading `@`.
+fn normalize_name(name: &str) -> Result<String, DicePlayerError> {
+  let name = name.trim();
+  let name = name.strip_prefix('@').unwrap_or(name).to_ascii_lowercase();
+  match super::parser::name().then_ignore(chumsky::primitive::end()).parse(name.as_str()) {
+    Ok(..) => Ok(name),
+    Err(..) => Err(DicePlayerError::InvalidName(name))
+  }
+}
+
+#[derive(Debug, Error)]
+pub enum DicePlayerError {
+  #[error("{0:?} is not a valid name, names may only contain letters, numbers and underscores, and be at most {MAX_NAME_LEN} characters long")]
+  InvalidName(String),
+  #[error("there is no macro named {0:?}")]
+  UnknownMacro(String),
+  #[error("there may be at most {} macros", DicePlayer::MAX_MACROS)]
+  TooManyMacros,
+  #[error("there may be at most {} variables on a character sheet", DicePlayer::MAX_VARIABLES)]
+  TooManyVariables,
+  #[error(transparent)]
+  Parse(#[from] ParseRollError),
+  #[error(transparent)]
+  Resolve(#[from] ResolveRollError),
+  #[error("invalid JSON: {0}")]
+  Json(#[from] serde_json::Error)
+}
@@ -1,79 +1,896 @@
+mod backfill;
+mod images;
+
 use crate::prelude::*;
+use self::backfill::{message_id_at, message_time, ChannelBackfill, EmojiBackfill};
+pub use self::backfill::{backfill_emoji_stats, EmojiBackfillError, EmojiBackfillProgress};
+pub use self::images::EmojiImages;
+
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, TimeDelta, Utc};
+use emojis::SkinTone;
+use indexmap::{IndexMap, IndexSet};
+use poise::macros::ChoiceParameter;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{Error as DeError, Unexpected, Visitor};
+use serenity::model::channel::{Message, ReactionType};
+use serenity::model::id::{ChannelId, EmojiId, MessageId, StickerId, UserId};
 
-use ahash::AHasher;
-use chrono::{DateTime, Utc};
-use serenity::model::id::{EmojiId, UserId};
-use serenity::model::guild::Emoji;
+use std::collections::BTreeMap;
+use std::fmt;
 
-use std::hash::{Hasher, Hash};
 
 
+/// Uses are kept by day for this many days, after which they are compacted into lifetime totals.
+pub const RETENTION_DAYS: u64 = 180;
 
-#[derive(Debug, Clone, Deserialize, Serialize, Default)]
-#[serde(transparent)]
+#[derive(Debug, Clone, Serialize, Default)]
 pub struct EmojiStats {
-  uses: HashMap<EmojiId, usize>,
-  #[serde(skip, default)]
-  last_interaction: Option<EmojiInteraction>,
+  /// Uses from before the retention window, without the day they happened on.
+  compacted: EmojiCounts,
+  /// Uses within the retention window, by the day they happened on.
+  days: BTreeMap<NaiveDate, EmojiCounts>,
+  /// When uses started being counted as they happen, messages from before then are left to backfills.
+  counting_since: Option<DateTime<Utc>>,
+  backfill: EmojiBackfill,
+  #[serde(skip)]
+  recent: RecentInteractions
 }
 
 impl EmojiStats {
-  pub fn increment_emoji_uses(&mut self, emoji_id: EmojiId, user_id: UserId) {
-    if self.try_interact(emoji_id, user_id, true) {
-      increment(self.uses.entry(emoji_id).or_default());
+  /// Counts the uses of emojis in a message, unless the message has been counted already.
+  pub fn add_message(&mut self, message_id: MessageId, user_id: UserId, emojis: impl IntoIterator<Item = EmojiKey>) {
+    self.add_message_at(message_id, user_id, emojis, Utc::now());
+  }
+
+  /// Counts a reaction to a message, unless it is already being counted.
+  pub fn add_reaction(&mut self, channel_id: ChannelId, message_id: MessageId, user_id: UserId, emoji: EmojiKey) {
+    self.add_reaction_at(channel_id, message_id, user_id, emoji, Utc::now());
+  }
+
+  /// Takes back a reaction to a message, if it was being counted.
+  pub fn remove_reaction(&mut self, channel_id: ChannelId, message_id: MessageId, user_id: UserId, emoji: EmojiKey) {
+    self.remove_reaction_at(channel_id, message_id, user_id, emoji);
+  }
+
+  fn add_message_at(&mut self, message_id: MessageId, user_id: UserId, emojis: impl IntoIterator<Item = EmojiKey>, now: DateTime<Utc>) {
+    let emojis = emojis.into_iter().collect::<Vec<EmojiKey>>();
+    if emojis.is_empty() || !self.recent.insert_message(message_id) { return };
+    // starting from the message's own time leaves it out of backfills, since it's counted here
+    self.counting_since.get_or_insert(message_time(message_id));
+    for emoji in emojis {
+      if self.recent.try_use(user_id, &emoji, now) {
+        self.increment_emoji_uses(emoji, user_id, EmojiSource::Message, now);
+      };
+    };
+  }
+
+  fn add_reaction_at(&mut self, channel_id: ChannelId, message_id: MessageId, user_id: UserId, emoji: EmojiKey, now: DateTime<Utc>) {
+    let key = (message_id, user_id, emoji.clone());
+    let counted = match self.recent.reactions.get(&key) {
+      // this reaction is already on the message, and the event is a duplicate
+      Some(ReactionState::Counted | ReactionState::Uncounted) => return,
+      // re-adding a reaction that was taken back counts it again, regardless of recent uses
+      Some(ReactionState::Removed) => true,
+      None => self.recent.try_use(user_id, &emoji, now)
+    };
+
+    if counted {
+      // reactions have no ID of their own, but they are noted below so that backfills don't count them again
+      self.counting_since.get_or_insert(now);
+      self.increment_emoji_uses(emoji.clone(), user_id, EmojiSource::Reaction, now);
+      self.note_live_reaction(channel_id, message_id, emoji, 1);
+    };
+
+    let state = if counted { ReactionState::Counted } else { ReactionState::Uncounted };
+    self.recent.set_reaction(key, Some(state));
+  }
+
+  fn remove_reaction_at(&mut self, channel_id: ChannelId, message_id: MessageId, user_id: UserId, emoji: EmojiKey) {
+    let key = (message_id, user_id, emoji.clone());
+    match self.recent.reactions.get(&key) {
+      Some(ReactionState::Removed) => (),
+      // the reaction was never counted, so adding it again should be treated like a new reaction
+      Some(ReactionState::Uncounted) => self.recent.set_reaction(key, None),
+      // reactions from before they were being tracked are assumed to have been counted
+      Some(ReactionState::Counted) | None => {
+        self.decrement_emoji_uses(&emoji, user_id, EmojiSource::Reaction);
+        self.recent.set_reaction(key, Some(ReactionState::Removed));
+        self.note_live_reaction(channel_id, message_id, emoji, -1);
+      }
+    };
+  }
+
+  fn increment_emoji_uses(&mut self, emoji: EmojiKey, user_id: UserId, source: EmojiSource, now: DateTime<Utc>) {
+    let today = now.date_naive();
+    self.compact(today);
+    self.days.entry(today).or_default().add(emoji, Some(user_id), source, 1);
+  }
+
+  /// Takes back the most recent use of an emoji by a user.
+  fn decrement_emoji_uses(&mut self, emoji: &EmojiKey, user_id: UserId, source: EmojiSource) {
+    let removed = self.days.values_mut().rev()
+      .chain(std::iter::once(&mut self.compacted))
+      .any(|counts| counts.remove(emoji, Some(user_id), source));
+    if !removed {
+      // uses recorded before users were counted can't be attributed to anyone
+      self.compacted.remove(emoji, None, source);
+    };
+  }
+
+  /// Starts counting uses from channel history, from before uses started being counted as they happen.
+  pub fn start_backfill(&mut self, now: DateTime<Utc>) -> Result<(), EmojiBackfillError> {
+    if self.backfill.running { return Err(EmojiBackfillError::AlreadyRunning) };
+    if self.counting_since.is_none() {
+      if !self.is_empty() { return Err(EmojiBackfillError::UnknownStart) };
+      self.counting_since = Some(now);
+    };
+
+    self.backfill.running = true;
+    Ok(())
+  }
+
+  pub fn finish_backfill(&mut self) {
+    self.backfill.running = false;
+  }
+
+  /// The message before which a channel's history is still to be backfilled, or `None` once all of it has been.
+  pub fn backfill_position(&self, channel_id: ChannelId) -> Option<MessageId> {
+    let counting_since = self.counting_since?;
+    match self.backfill.channels.get(&channel_id) {
+      Some(ChannelBackfill::Before(message_id)) => Some(*message_id),
+      Some(ChannelBackfill::Done) => None,
+      None => Some(message_id_at(counting_since))
+    }
+  }
+
+  /// Counts the uses in a page of a channel's history, which should be the messages right before its
+  /// [`EmojiStats::backfill_position`], then moves the channel's position past them. Returns how many uses were counted.
+  /// Reactions can't be attributed to anyone, and are counted on the day their message was sent.
+  pub fn add_backfilled_messages(&mut self, channel_id: ChannelId, messages: &[Message], finished: bool, today: NaiveDate) -> usize {
+    let mut counted = 0;
+    let mut channel_live_reactions = self.backfill.live_reactions.remove(&channel_id).unwrap_or_default();
+    for message in messages {
+      let day = message_time(message.id).date_naive();
+      if !message.author.bot {
+        for emoji in message_emojis(message) {
+          self.add_backfilled(emoji, Some(message.author.id), EmojiSource::Message, 1, day, today);
+          counted += 1;
+        };
+      };
+
+      let mut live_reactions = channel_live_reactions.remove(&message.id).unwrap_or_default();
+      for reaction in &message.reactions {
+        let Some(emoji) = EmojiKey::from_reaction(&reaction.reaction_type) else { continue };
+        // reactions that were already counted as they happened aren't counted again
+        let live = live_reactions.remove(&emoji).unwrap_or(0);
+        let count = (reaction.count as i64).saturating_sub(live).max(0) as usize;
+        if count > 0 {
+          self.add_backfilled(emoji, None, EmojiSource::Reaction, count, day, today);
+          counted += count;
+        };
+      };
+    };
+
+    let position = match messages.iter().map(|message| message.id).min() {
+      Some(oldest) if !finished => ChannelBackfill::Before(oldest),
+      _ => ChannelBackfill::Done
     };
+
+    // reactions noted on messages that have been passed without being found were on messages that have since been deleted
+    if let ChannelBackfill::Before(oldest) = position {
+      channel_live_reactions.retain(|&message_id, _| message_id < oldest);
+      if !channel_live_reactions.is_empty() {
+        self.backfill.live_reactions.insert(channel_id, channel_live_reactions);
+      };
+    };
+
+    self.backfill.channels.insert(channel_id, position);
+    counted
   }
 
-  pub fn decrement_emoji_uses(&mut self, emoji_id: EmojiId, user_id: UserId) {
-    if self.try_interact(emoji_id, user_id, false) {
-      self.uses.get_mut(&emoji_id).map(decrement);
+  fn add_backfilled(&mut self, emoji: EmojiKey, user_id: Option<UserId>, source: EmojiSource, count: usize, day: NaiveDate, today: NaiveDate) {
+    let counts = match day < today - Days::new(RETENTION_DAYS) {
+      true => &mut self.compacted,
+      false => self.days.entry(day).or_default()
     };
+
+    counts.add(emoji, user_id, source, count);
   }
 
-  pub fn get_emoji_uses<'a, F>(&self, mut f: F) -> Vec<(Emoji, usize)>
-  where F: FnMut(EmojiId) -> Option<&'a Emoji> {
-    let mut uses = self.uses.iter()
-      .filter_map(|(&id, &c)| (c > 0).then_some((id, c)))
-      .filter_map(|(id, c)| f(id).map(|emoji| (emoji.clone(), c)))
-      .collect::<Vec<(Emoji, usize)>>();
-    uses.sort_unstable_by(|a, b| Ord::cmp(&a.1, &b.1).reverse());
+  /// Notes a reaction counted or taken back as it happened on a message that is still to be backfilled,
+  /// which the backfill would otherwise find on the message and count again.
+  fn note_live_reaction(&mut self, channel_id: ChannelId, message_id: MessageId, emoji: EmojiKey, change: i64) {
+    if self.backfill_position(channel_id).is_none_or(|before| message_id >= before) { return };
+    let channel_live_reactions = self.backfill.live_reactions.entry(channel_id).or_default();
+    let reactions = channel_live_reactions.entry(message_id).or_default();
+    let count = reactions.entry(emoji.clone()).or_default();
+    *count += change;
+    if *count == 0 { reactions.remove(&emoji); };
+    if reactions.is_empty() { channel_live_reactions.remove(&message_id); };
+    if channel_live_reactions.is_empty() { self.backfill.live_reactions.remove(&channel_id); };
+  }
+
+  fn is_empty(&self) -> bool {
+    self.compacted.totals.is_empty() && self.days.is_empty()
+  }
+
+  /// Counts the uses of each emoji, optionally only since a day, only by a user, and only from one source.
+  /// Days before the retention window can't be told apart, so `since` should be within it.
+  pub fn get_emoji_uses(
+    &self,
+    since: Option<NaiveDate>,
+    user_id: Option<UserId>,
+    source: Option<EmojiSource>
+  ) -> HashMap<EmojiKey, usize> {
+    let mut uses = HashMap::new();
+    if since.is_none() {
+      self.compacted.count_into(user_id, source, &mut uses);
+    };
+
+    let days = self.days.range(since.unwrap_or(NaiveDate::MIN)..);
+    for (_, counts) in days {
+      counts.count_into(user_id, source, &mut uses);
+    };
+
+    uses.retain(|_, &mut count| count > 0);
+
     uses
   }
 
-  fn try_interact(&mut self, emoji_id: EmojiId, user_id: UserId, state: bool) -> bool {
-    let now = Utc::now();
-    let hash = hash_interaction(emoji_id, user_id, state);
-    match self.last_interaction.replace(EmojiInteraction { hash, time: now }) {
-      Some(interaction) => interaction.is_valid(hash, now),
-      None => true
+  /// Counts the uses of each emoji on each day from `since` until today, like [`EmojiStats::get_emoji_uses`].
+  /// Days without any uses are included, so that the days are evenly spaced.
+  pub fn get_daily_emoji_uses(
+    &self,
+    since: NaiveDate,
+    user_id: Option<UserId>,
+    source: Option<EmojiSource>
+  ) -> Vec<(NaiveDate, HashMap<EmojiKey, usize>)> {
+    let today = Utc::now().date_naive();
+    since.iter_days().take_while(|&day| day <= today)
+      .map(|day| {
+        let mut uses = HashMap::new();
+        if let Some(counts) = self.days.get(&day) {
+          counts.count_into(user_id, source, &mut uses);
+        };
+
+        (day, uses)
+      })
+      .collect()
+  }
+
+  /// Folds every day from before the retention window into the lifetime totals.
+  pub fn compact(&mut self, today: NaiveDate) {
+    let cutoff = today - Days::new(RETENTION_DAYS);
+    while let Some(entry) = self.days.first_entry().filter(|entry| *entry.key() < cutoff) {
+      self.compacted.merge(entry.remove());
+    };
+  }
+
+  /// Forgets anything that could be associated with the given user.
+  /// Their uses are still counted towards each emoji's totals, but no longer attributed to them.
+  pub fn forget_user(&mut self, user_id: UserId) {
+    self.compacted.users.remove(&user_id);
+    for counts in self.days.values_mut() {
+      counts.users.remove(&user_id);
+    };
+
+    self.recent.forget_user(user_id);
+  }
+}
+
+impl<'de> Deserialize<'de> for EmojiStats {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum EmojiStatsRepr {
+      /// Before uses were counted by day and by user, only the total uses of each emoji were kept.
+      Legacy(HashMap<EmojiId, usize>),
+      Current {
+        #[serde(default)]
+        compacted: EmojiCounts,
+        #[serde(default)]
+        days: BTreeMap<NaiveDate, EmojiCounts>,
+        #[serde(default)]
+        counting_since: Option<DateTime<Utc>>,
+        #[serde(default)]
+        backfill: EmojiBackfill
+      }
     }
+
+    Ok(match EmojiStatsRepr::deserialize(deserializer)? {
+      EmojiStatsRepr::Legacy(totals) => {
+        let totals = totals.into_iter()
+          .map(|(emoji_id, count)| (EmojiKey::Custom(emoji_id), UseCounts { unsorted: count, ..UseCounts::default() }))
+          .collect();
+        let compacted = EmojiCounts { totals, users: HashMap::new() };
+        EmojiStats { compacted, ..EmojiStats::default() }
+      },
+      EmojiStatsRepr::Current { compacted, days, counting_since, backfill } => {
+        // uses were counted by day for a while before it was recorded when counting started
+        let counting_since = counting_since.or_else(|| {
+          let first_day = days.keys().next().filter(|_| compacted.totals.is_empty())?;
+          Some(first_day.and_time(NaiveTime::MIN).and_utc())
+        });
+
+        EmojiStats { compacted, days, counting_since, backfill, ..EmojiStats::default() }
+      }
+    })
   }
 }
 
-#[derive(Debug, Clone, Copy)]
-struct EmojiInteraction {
-  hash: u64,
-  time: DateTime<Utc>
+/// Something whose uses are counted, either a custom emoji, a Unicode emoji or a sticker.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EmojiKey {
+  Custom(EmojiId),
+  /// A fully qualified Unicode emoji, without any skin tone.
+  Unicode(String),
+  Sticker(StickerId)
 }
 
-impl EmojiInteraction {
-  fn is_valid(self, hash: u64, now: DateTime<Utc>) -> bool {
-    self.hash != hash || now.signed_duration_since(self.time).num_seconds() > 5
+impl EmojiKey {
+  /// Skin tones are counted as uses of the same emoji.
+  pub fn unicode(emoji: &emojis::Emoji) -> Self {
+    let emoji = emoji.with_skin_tone(SkinTone::Default).unwrap_or(emoji);
+    EmojiKey::Unicode(emoji.as_str().to_owned())
+  }
+
+  pub fn from_reaction(reaction: &ReactionType) -> Option<Self> {
+    match reaction {
+      ReactionType::Custom { id, .. } => Some(EmojiKey::Custom(*id)),
+      ReactionType::Unicode(emoji) => emojis::get(emoji).map(EmojiKey::unicode),
+      _ => None
+    }
+  }
+
+  pub fn kind(&self) -> EmojiKind {
+    match self {
+      EmojiKey::Custom(..) => EmojiKind::Custom,
+      EmojiKey::Unicode(..) => EmojiKind::Unicode,
+      EmojiKey::Sticker(..) => EmojiKind::Sticker
+    }
+  }
+}
+
+/// Custom emojis are stored as just their ID, like they were before other kinds were counted.
+impl Serialize for EmojiKey {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    match self {
+      EmojiKey::Custom(emoji_id) => serializer.collect_str(emoji_id),
+      EmojiKey::Unicode(emoji) => serializer.collect_str(&format_args!("u:{emoji}")),
+      EmojiKey::Sticker(sticker_id) => serializer.collect_str(&format_args!("s:{sticker_id}"))
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for EmojiKey {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    struct EmojiKeyVisitor;
+
+    impl<'de> Visitor<'de> for EmojiKeyVisitor {
+      type Value = EmojiKey;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an emoji ID, a Unicode emoji or a sticker ID")
+      }
+
+      fn visit_u64<E: DeError>(self, v: u64) -> Result<Self::Value, E> {
+        match v {
+          0 => Err(E::invalid_value(Unexpected::Unsigned(v), &self)),
+          v => Ok(EmojiKey::Custom(EmojiId::new(v)))
+        }
+      }
+
+      fn visit_str<E: DeError>(self, v: &str) -> Result<Self::Value, E> {
+        let parse_id = |id: &str| id.parse::<u64>().ok().filter(|&id| id != 0)
+          .ok_or_else(|| E::invalid_value(Unexpected::Str(v), &self));
+        if let Some(emoji) = v.strip_prefix("u:") {
+          Ok(EmojiKey::Unicode(emoji.to_owned()))
+        } else if let Some(sticker_id) = v.strip_prefix("s:") {
+          parse_id(sticker_id).map(StickerId::new).map(EmojiKey::Sticker)
+        } else {
+          parse_id(v).map(EmojiId::new).map(EmojiKey::Custom)
+        }
+      }
+    }
+
+    deserializer.deserialize_any(EmojiKeyVisitor)
   }
 }
 
-fn hash_interaction(emoji_id: EmojiId, user_id: UserId, state: bool) -> u64 {
-  let mut hasher = AHasher::default();
-  emoji_id.hash(&mut hasher);
-  user_id.hash(&mut hasher);
-  state.hash(&mut hasher);
-  hasher.finish()
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ChoiceParameter)]
+pub enum EmojiKind {
+  #[name = "Custom emojis"]
+  #[name_localized("en-US", "Custom emojis")]
+  Custom,
+  #[name = "Unicode emojis"]
+  #[name_localized("en-US", "Unicode emojis")]
+  Unicode,
+  #[name = "Stickers"]
+  #[name_localized("en-US", "Stickers")]
+  Sticker
+}
+
+/// Where an emoji was used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ChoiceParameter)]
+pub enum EmojiSource {
+  #[name = "Messages"]
+  #[name_localized("en-US", "Messages")]
+  Message,
+  #[name = "Reactions"]
+  #[name_localized("en-US", "Reactions")]
+  Reaction
 }
 
-fn increment(i: &mut usize) {
-  *i = i.saturating_add(1);
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct EmojiCounts {
+  /// Uses of each emoji, including those that aren't attributed to a user.
+  totals: HashMap<EmojiKey, UseCounts>,
+  users: HashMap<UserId, HashMap<EmojiKey, UseCounts>>
 }
 
-fn decrement(i: &mut usize) {
-  *i = i.saturating_sub(1);
+impl EmojiCounts {
+  fn add(&mut self, emoji: EmojiKey, user_id: Option<UserId>, source: EmojiSource, count: usize) {
+    if let Some(user_id) = user_id {
+      add(self.users.entry(user_id).or_default(), emoji.clone(), source, count);
+    };
+
+    add(&mut self.totals, emoji, source, count);
+  }
+
+  /// Takes back one use of an emoji, returning false if there were none to take back.
+  /// Without a user, only uses that aren't attributed to anyone are taken back.
+  fn remove(&mut self, emoji: &EmojiKey, user_id: Option<UserId>, source: EmojiSource) -> bool {
+    match user_id {
+      Some(user_id) => {
+        let Some(user_uses) = self.users.get_mut(&user_id) else { return false };
+        if !subtract(user_uses, emoji, source) { return false };
+        if user_uses.is_empty() { self.users.remove(&user_id); };
+      },
+      None => {
+        let attributed = self.users.values()
+          .filter_map(|user_uses| user_uses.get(emoji))
+          .map(|counts| counts.available(source))
+          .sum::<usize>();
+        let total = self.totals.get(emoji).map_or(0, |counts| counts.available(source));
+        if total <= attributed { return false };
+      }
+    };
+
+    subtract(&mut self.totals, emoji, source);
+    true
+  }
+
+  fn merge(&mut self, other: EmojiCounts) {
+    for (emoji, counts) in other.totals {
+      self.totals.entry(emoji).or_default().merge(counts);
+    };
+
+    for (user_id, user_uses) in other.users {
+      let entry = self.users.entry(user_id).or_default();
+      for (emoji, counts) in user_uses {
+        entry.entry(emoji).or_default().merge(counts);
+      };
+    };
+  }
+
+  fn count_into(&self, user_id: Option<UserId>, source: Option<EmojiSource>, uses: &mut HashMap<EmojiKey, usize>) {
+    let counts = match user_id {
+      Some(user_id) => match self.users.get(&user_id) {
+        Some(user_uses) => user_uses,
+        None => return
+      },
+      None => &self.totals
+    };
+
+    for (emoji, counts) in counts {
+      let entry = uses.entry(emoji.clone()).or_default();
+      *entry = entry.saturating_add(counts.get(source));
+    };
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+struct UseCounts {
+  messages: usize,
+  reactions: usize,
+  /// Uses from before messages and reactions were counted separately.
+  #[serde(skip_serializing_if = "is_zero")]
+  unsorted: usize
+}
+
+impl UseCounts {
+  /// Uses that can't be told apart only count when not filtering by source.
+  fn get(self, source: Option<EmojiSource>) -> usize {
+    match source {
+      Some(EmojiSource::Message) => self.messages,
+      Some(EmojiSource::Reaction) => self.reactions,
+      None => self.messages.saturating_add(self.reactions).saturating_add(self.unsorted)
+    }
+  }
+
+  /// How many uses from a source could be taken back, including those that can't be told apart.
+  fn available(self, source: EmojiSource) -> usize {
+    self.get(Some(source)).saturating_add(self.unsorted)
+  }
+
+  fn get_mut(&mut self, source: EmojiSource) -> &mut usize {
+    match source {
+      EmojiSource::Message => &mut self.messages,
+      EmojiSource::Reaction => &mut self.reactions
+    }
+  }
+
+  fn merge(&mut self, other: UseCounts) {
+    self.messages = self.messages.saturating_add(other.messages);
+    self.reactions = self.reactions.saturating_add(other.reactions);
+    self.unsorted = self.unsorted.saturating_add(other.unsorted);
+  }
+
+  fn is_empty(self) -> bool {
+    self == UseCounts::default()
+  }
+}
+
+impl<'de> Deserialize<'de> for UseCounts {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum UseCountsRepr {
+      /// Before messages and reactions were counted separately, only the total uses were kept.
+      Legacy(usize),
+      Current {
+        #[serde(default)]
+        messages: usize,
+        #[serde(default)]
+        reactions: usize,
+        #[serde(default)]
+        unsorted: usize
+      }
+    }
+
+    Ok(match UseCountsRepr::deserialize(deserializer)? {
+      UseCountsRepr::Legacy(unsorted) => UseCounts { unsorted, ..UseCounts::default() },
+      UseCountsRepr::Current { messages, reactions, unsorted } => UseCounts { messages, reactions, unsorted }
+    })
+  }
+}
+
+/// Recent interactions with emojis, for telling apart new uses from spam and duplicate events.
+/// These are only kept in memory, and only the most recent ones are kept.
+#[derive(Debug, Clone, Default)]
+struct RecentInteractions {
+  /// When each user last had a use of each emoji counted, from oldest to newest.
+  uses: IndexMap<(UserId, EmojiKey), DateTime<Utc>>,
+  /// The state of each reaction to a message, from least to most recently changed.
+  reactions: IndexMap<(MessageId, UserId, EmojiKey), ReactionState>,
+  /// Messages whose emojis have been counted, from oldest to newest.
+  messages: IndexSet<MessageId>
+}
+
+impl RecentInteractions {
+  /// Another use of the same emoji by the same user within this long is only counted once.
+  const USE_WINDOW: TimeDelta = TimeDelta::seconds(5);
+  const MAX_USES: usize = 1024;
+  const MAX_REACTIONS: usize = 4096;
+  const MAX_MESSAGES: usize = 1024;
+
+  /// Returns whether a use of an emoji by a user should be counted, which is the case
+  /// unless another use of it by them was counted within [`Self::USE_WINDOW`].
+  fn try_use(&mut self, user_id: UserId, emoji: &EmojiKey, now: DateTime<Utc>) -> bool {
+    while let Some((_, &time)) = self.uses.first() {
+      if now - time < Self::USE_WINDOW { break };
+      self.uses.shift_remove_index(0);
+    };
+
+    let key = (user_id, emoji.clone());
+    if self.uses.get(&key).is_some_and(|&time| now - time < Self::USE_WINDOW) {
+      return false;
+    };
+
+    self.uses.shift_remove(&key);
+    self.uses.insert(key, now);
+    if self.uses.len() > Self::MAX_USES {
+      self.uses.shift_remove_index(0);
+    };
+
+    true
+  }
+
+  /// Returns false if the message has already been counted.
+  fn insert_message(&mut self, message_id: MessageId) -> bool {
+    if !self.messages.insert(message_id) { return false };
+    if self.messages.len() > Self::MAX_MESSAGES {
+      self.messages.shift_remove_index(0);
+    };
+
+    true
+  }
+
+  fn set_reaction(&mut self, key: (MessageId, UserId, EmojiKey), state: Option<ReactionState>) {
+    self.reactions.shift_remove(&key);
+    if let Some(state) = state {
+      self.reactions.insert(key, state);
+      if self.reactions.len() > Self::MAX_REACTIONS {
+        self.reactions.shift_remove_index(0);
+      };
+    };
+  }
+
+  fn forget_user(&mut self, user_id: UserId) {
+    self.uses.retain(|(user, _), _| *user != user_id);
+    self.reactions.retain(|(_, user, _), _| *user != user_id);
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReactionState {
+  /// The reaction is on the message, and is counted as a use.
+  Counted,
+  /// The reaction is on the message, but isn't counted, because the user had just used the emoji.
+  Uncounted,
+  /// The reaction was counted, but has since been taken back.
+  Removed
+}
+
+fn add(counts: &mut HashMap<EmojiKey, UseCounts>, emoji: EmojiKey, source: EmojiSource, count: usize) {
+  let entry = counts.entry(emoji).or_default().get_mut(source);
+  *entry = entry.saturating_add(count);
+}
+
+/// Subtracts one use from a source, or one that can't be told apart, removing the counts once they are empty.
+/// Returns false if there was no use to subtract.
+fn subtract(counts: &mut HashMap<EmojiKey, UseCounts>, emoji: &EmojiKey, source: EmojiSource) -> bool {
+  let Some(entry) = counts.get_mut(emoji) else { return false };
+  if *entry.get_mut(source) > 0 {
+    *entry.get_mut(source) -= 1;
+  } else if entry.unsorted > 0 {
+    entry.unsorted -= 1;
+  } else {
+    return false;
+  };
+
+  if entry.is_empty() { counts.remove(emoji); };
+  true
+}
+
+fn is_zero(count: &usize) -> bool {
+  *count == 0
+}
+
+/// The emojis counted for a message, which are one of those in its content, picked at random, and its stickers.
+pub fn message_emojis(message: &Message) -> Vec<EmojiKey> {
+  let emojis = crate::utils::parse_emojis(&message.content).into_iter().map(EmojiKey::Custom)
+    .chain(crate::utils::parse_unicode_emojis(&message.content).into_iter().map(EmojiKey::unicode))
+    .collect::<Vec<EmojiKey>>();
+  // don't be greedy
+  emojis.choose_default().cloned().into_iter()
+    .chain(message.sticker_items.iter().map(|sticker| EmojiKey::Sticker(sticker.id)))
+    .collect()
+}
+
+/// Parses how far back to count emoji uses, like `30d` or `4w`, returning the first day to count.
+pub fn parse_since(since: &str, today: NaiveDate) -> Option<NaiveDate> {
+  let since = since.trim().to_ascii_lowercase();
+  let (number, unit) = since.find(|c: char| !c.is_ascii_digit())
+    .map_or((since.as_str(), "d"), |i| since.split_at(i));
+  let days = match unit.trim() {
+    "d" | "day" | "days" => number.parse::<u64>().ok()?,
+    "w" | "week" | "weeks" => number.parse::<u64>().ok()?.checked_mul(7)?,
+    _ => return None
+  };
+
+  (1..=RETENTION_DAYS).contains(&days).then(|| today - Days::new(days - 1))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ALICE: UserId = UserId::new(1);
+  const BOB: UserId = UserId::new(2);
+  const CHANNEL: ChannelId = ChannelId::new(1);
+
+  fn emoji(id: u64) -> EmojiKey {
+    EmojiKey::Custom(EmojiId::new(id))
+  }
+
+  fn message(id: u64) -> MessageId {
+    MessageId::new(id)
+  }
+
+  fn uses(stats: &EmojiStats, emoji: &EmojiKey, user_id: Option<UserId>) -> usize {
+    stats.get_emoji_uses(None, user_id, None).get(emoji).copied().unwrap_or(0)
+  }
+
+  fn live_reaction(stats: &EmojiStats, message_id: MessageId, emoji: &EmojiKey) -> Option<i64> {
+    stats.backfill.live_reactions.get(&CHANNEL)?.get(&message_id)?.get(emoji).copied()
+  }
+
+  fn seconds(start: DateTime<Utc>, seconds: i64) -> DateTime<Utc> {
+    start + TimeDelta::seconds(seconds)
+  }
+
+  #[test]
+  fn alternating_users_are_each_limited() {
+    let (mut stats, start) = (EmojiStats::default(), Utc::now());
+    for (i, user_id) in [ALICE, BOB, ALICE, BOB, ALICE, BOB].into_iter().enumerate() {
+      stats.add_reaction_at(CHANNEL, message(i as u64 + 1), user_id, emoji(1), seconds(start, i as i64 / 2));
+    };
+
+    assert_eq!(uses(&stats, &emoji(1), Some(ALICE)), 1);
+    assert_eq!(uses(&stats, &emoji(1), Some(BOB)), 1);
+    assert_eq!(uses(&stats, &emoji(1), None), 2);
+
+    stats.add_reaction_at(CHANNEL, message(10), ALICE, emoji(1), seconds(start, 6));
+    assert_eq!(uses(&stats, &emoji(1), Some(ALICE)), 2);
+  }
+
+  #[test]
+  fn users_do_not_block_each_other() {
+    let (mut stats, start) = (EmojiStats::default(), Utc::now());
+    stats.add_reaction_at(CHANNEL, message(1), ALICE, emoji(1), start);
+    stats.add_reaction_at(CHANNEL, message(2), ALICE, emoji(2), start);
+    stats.add_reaction_at(CHANNEL, message(3), ALICE, emoji(3), start);
+    stats.add_reaction_at(CHANNEL, message(1), BOB, emoji(1), start);
+    stats.add_message_at(message(4), BOB, [emoji(2)], start);
+
+    assert_eq!(uses(&stats, &emoji(1), None), 2);
+    assert_eq!(uses(&stats, &emoji(2), None), 2);
+    assert_eq!(uses(&stats, &emoji(3), None), 1);
+  }
+
+  #[test]
+  fn remove_and_readd_follows_the_reaction() {
+    let (mut stats, start) = (EmojiStats::default(), Utc::now());
+    for i in 0..5 {
+      stats.add_reaction_at(CHANNEL, message(1), ALICE, emoji(1), seconds(start, i));
+      assert_eq!(uses(&stats, &emoji(1), None), 1);
+      stats.remove_reaction_at(CHANNEL, message(1), ALICE, emoji(1));
+      assert_eq!(uses(&stats, &emoji(1), None), 0);
+    };
+
+    // duplicate events change nothing
+    stats.add_reaction_at(CHANNEL, message(1), ALICE, emoji(1), start);
+    stats.add_reaction_at(CHANNEL, message(1), ALICE, emoji(1), start);
+    assert_eq!(uses(&stats, &emoji(1), None), 1);
+    stats.remove_reaction_at(CHANNEL, message(1), ALICE, emoji(1));
+    stats.remove_reaction_at(CHANNEL, message(1), ALICE, emoji(1));
+    assert_eq!(uses(&stats, &emoji(1), None), 0);
+  }
+
+  #[test]
+  fn uncounted_reactions_are_not_taken_back() {
+    let (mut stats, start) = (EmojiStats::default(), Utc::now());
+    stats.add_reaction_at(CHANNEL, message(1), ALICE, emoji(1), start);
+    stats.add_reaction_at(CHANNEL, message(2), ALICE, emoji(1), seconds(start, 1));
+    assert_eq!(uses(&stats, &emoji(1), None), 1);
+
+    stats.remove_reaction_at(CHANNEL, message(2), ALICE, emoji(1));
+    assert_eq!(uses(&stats, &emoji(1), None), 1);
+    stats.remove_reaction_at(CHANNEL, message(1), ALICE, emoji(1));
+    assert_eq!(uses(&stats, &emoji(1), None), 0);
+
+    // removing an uncounted reaction lets it be counted when it is added again later
+    stats.add_reaction_at(CHANNEL, message(2), ALICE, emoji(1), seconds(start, 10));
+    assert_eq!(uses(&stats, &emoji(1), None), 1);
+  }
+
+  #[test]
+  fn untracked_reactions_are_taken_back() {
+    let (mut stats, start) = (EmojiStats::default(), Utc::now());
+    stats.add_reaction_at(CHANNEL, message(1), ALICE, emoji(1), start);
+    stats.recent = RecentInteractions::default();
+
+    stats.remove_reaction_at(CHANNEL, message(1), ALICE, emoji(1));
+    assert_eq!(uses(&stats, &emoji(1), None), 0);
+    stats.add_reaction_at(CHANNEL, message(1), ALICE, emoji(1), seconds(start, 1));
+    assert_eq!(uses(&stats, &emoji(1), None), 1);
+  }
+
+  #[test]
+  fn messages_are_counted_once() {
+    let (mut stats, start) = (EmojiStats::default(), Utc::now());
+    stats.add_message_at(message(1), ALICE, [emoji(1), emoji(2)], start);
+    stats.add_message_at(message(1), ALICE, [emoji(1), emoji(2)], seconds(start, 10));
+    assert_eq!(uses(&stats, &emoji(1), None), 1);
+    assert_eq!(uses(&stats, &emoji(2), None), 1);
+
+    // a reaction right after using the emoji in a message is spam all the same
+    stats.add_reaction_at(CHANNEL, message(2), ALICE, emoji(1), seconds(start, 1));
+    stats.add_message_at(message(3), ALICE, [emoji(1)], seconds(start, 12));
+    assert_eq!(uses(&stats, &emoji(1), None), 2);
+    assert_eq!(stats.get_emoji_uses(None, None, Some(EmojiSource::Reaction)).len(), 0);
+  }
+
+  #[test]
+  fn recent_interactions_are_bounded() {
+    let (mut stats, start) = (EmojiStats::default(), Utc::now());
+    for i in 0..(RecentInteractions::MAX_REACTIONS as u64 + 10) {
+      stats.add_reaction_at(CHANNEL, message(i + 1), UserId::new(i + 1), emoji(1), start);
+    };
+
+    assert_eq!(stats.recent.reactions.len(), RecentInteractions::MAX_REACTIONS);
+    assert_eq!(stats.recent.uses.len(), RecentInteractions::MAX_USES);
+
+    // once the window has passed, old uses are dropped as new ones come in
+    stats.add_reaction_at(CHANNEL, message(1), ALICE, emoji(2), seconds(start, 6));
+    assert_eq!(stats.recent.uses.len(), 1);
+  }
+
+  #[test]
+  fn backfill_starts_where_counting_started() {
+    let start = Utc::now();
+    let mut stats = EmojiStats::default();
+    stats.start_backfill(start).unwrap();
+    assert_eq!(stats.backfill_position(CHANNEL), Some(message_id_at(start)));
+    assert!(matches!(stats.start_backfill(start), Err(EmojiBackfillError::AlreadyRunning)));
+
+    stats.add_backfilled_messages(CHANNEL, &[], true, start.date_naive());
+    assert_eq!(stats.backfill_position(CHANNEL), None);
+
+    // there's no telling which messages were counted before days were kept
+    let mut legacy = serde_json::from_str::<EmojiStats>(r#"{"1": 3}"#).unwrap();
+    assert!(matches!(legacy.start_backfill(start), Err(EmojiBackfillError::UnknownStart)));
+
+    let days = serde_json::from_str::<EmojiStats>(r#"{"days": {"2025-03-01": {"totals": {"1": 1}}}}"#).unwrap();
+    let first_day = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+    assert_eq!(days.counting_since, Some(first_day.and_time(NaiveTime::MIN).and_utc()));
+  }
+
+  #[test]
+  fn live_reactions_are_noted_until_backfilled() {
+    let start = Utc::now();
+    let mut stats = EmojiStats::default();
+    stats.start_backfill(start).unwrap();
+    let old_message = MessageId::new(message_id_at(start).get() - 1);
+
+    stats.add_reaction_at(CHANNEL, old_message, ALICE, emoji(1), seconds(start, 1));
+    stats.add_reaction_at(CHANNEL, message_id_at(seconds(start, 1)), BOB, emoji(1), seconds(start, 1));
+    assert_eq!(live_reaction(&stats, old_message, &emoji(1)), Some(1));
+    assert_eq!(stats.backfill.live_reactions[&CHANNEL].len(), 1);
+
+    stats.remove_reaction_at(CHANNEL, old_message, ALICE, emoji(1));
+    assert!(stats.backfill.live_reactions.is_empty());
+
+    // once a channel has been backfilled, its reactions no longer need noting
+    stats.add_backfilled_messages(CHANNEL, &[], true, start.date_naive());
+    stats.add_reaction_at(CHANNEL, old_message, ALICE, emoji(1), seconds(start, 10));
+    assert!(stats.backfill.live_reactions.is_empty());
+  }
+
+  #[test]
+  fn live_reactions_are_kept_until_passed() {
+    let start = Utc::now();
+    let mut stats = EmojiStats::default();
+    stats.start_backfill(start).unwrap();
+    let before = message_id_at(start).get();
+
+    // even reactions on the oldest messages are kept, since those are the last to be backfilled
+    for i in 1..=5000 {
+      stats.add_reaction_at(CHANNEL, MessageId::new(before - i), UserId::new(i), emoji(1), seconds(start, i as i64));
+    };
+
+    assert_eq!(stats.backfill.live_reactions[&CHANNEL].len(), 5000);
+
+    // a page that reaches back past messages without finding them means that they were deleted
+    let mut message = Message::default();
+    message.id = MessageId::new(before - 4000);
+    stats.add_backfilled_messages(CHANNEL, &[message], false, start.date_naive());
+    assert_eq!(stats.backfill.live_reactions[&CHANNEL].len(), 1000);
+    assert_eq!(live_reaction(&stats, MessageId::new(before - 4001), &emoji(1)), Some(1));
+  }
+
+  #[test]
+  fn messages_counted_live_are_not_backfilled() {
+    let sent = Utc::now();
+    let first_message = MessageId::new(message_id_at(sent).get() + 42);
+    let mut stats = EmojiStats::default();
+    stats.add_message_at(first_message, ALICE, [emoji(1)], seconds(sent, 2));
+    stats.start_backfill(seconds(sent, 3)).unwrap();
+
+    let position = stats.backfill_position(CHANNEL).unwrap();
+    assert!(first_message >= position);
+    assert!(MessageId::new(first_message.get() - 43) < position);
+  }
 }
@@ -0,0 +1,204 @@
+use super::*;
+use crate::data::Core;
+
+use reqwest::StatusCode;
+use serenity::builder::GetMessages;
+use serenity::http::{LightMethod, Request, Route};
+use serenity::model::channel::{ChannelType, ThreadsData};
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::model::timestamp::Timestamp;
+
+use std::time::{Duration, Instant};
+
+
+
+/// The most messages Discord returns in a single page of channel history.
+const PAGE_SIZE: u8 = 100;
+/// Channel history is shared with every other request made, so the backfill takes its time.
+const PAGE_DELAY: Duration = Duration::from_millis(500);
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+/// The first second of 2015, from which Discord's snowflakes count milliseconds.
+const DISCORD_EPOCH: i64 = 1420070400000;
+
+/// Progress of counting uses from the history of a guild's channels, from before uses were counted as they happened.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(super) struct EmojiBackfill {
+  pub(super) channels: HashMap<ChannelId, ChannelBackfill>,
+  /// Net reactions counted as they happened on messages that are still to be backfilled, by channel,
+  /// which are already among the reactions a backfill will find on those messages.
+  /// These are kept until the backfill passes their message, however long that takes.
+  pub(super) live_reactions: HashMap<ChannelId, BTreeMap<MessageId, HashMap<EmojiKey, i64>>>,
+  #[serde(skip)]
+  pub(super) running: bool
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum ChannelBackfill {
+  /// Messages before this one are still to be backfilled.
+  Before(MessageId),
+  Done
+}
+
+#[derive(Debug, Error)]
+pub enum EmojiBackfillError {
+  #[error("Emoji stats are already being backfilled in this server")]
+  AlreadyRunning,
+  #[error("Emoji stats in this server were counted before it was recorded when counting started, so they can't be backfilled without counting messages twice")]
+  UnknownStart
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EmojiBackfillProgress {
+  pub channels_done: usize,
+  pub channels_total: usize,
+  /// Channels that the bot can't read the history of, which are left to be backfilled another time.
+  pub channels_skipped: usize,
+  pub messages: usize,
+  pub uses: usize
+}
+
+/// Counts uses from the history of every channel in a guild, including threads and forum posts,
+/// from before uses were counted as they happened.
+/// Progress is committed after every page, so a backfill that is interrupted picks up where it left off when run again.
+pub async fn backfill_emoji_stats(
+  core: &Core,
+  guild_id: GuildId,
+  mut report: impl AsyncFnMut(&EmojiBackfillProgress)
+) -> MelodyResult<Result<EmojiBackfillProgress, EmojiBackfillError>> {
+  let started = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    Ok(persist_guild.emoji_stats.start_backfill(Utc::now()))
+  }).await?;
+  if let Err(error) = started { return Ok(Err(error)) };
+
+  let result = backfill_channels(core, guild_id, &mut report).await;
+  let finished = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+    persist_guild.emoji_stats.finish_backfill();
+    Ok(())
+  }).await;
+
+  let progress = result?;
+  finished?;
+  Ok(Ok(progress))
+}
+
+async fn backfill_channels(
+  core: &Core,
+  guild_id: GuildId,
+  report: &mut impl AsyncFnMut(&EmojiBackfillProgress)
+) -> MelodyResult<EmojiBackfillProgress> {
+  let channels = list_channels(core, guild_id).await?;
+  let mut progress = EmojiBackfillProgress { channels_total: channels.len(), ..EmojiBackfillProgress::default() };
+  let mut last_report = Instant::now();
+  for channel_id in channels {
+    loop {
+      let position = core.operate_persist_guild(guild_id, async |persist_guild| {
+        Ok(persist_guild.emoji_stats.backfill_position(channel_id))
+      }).await?;
+      let Some(before) = position else { break };
+
+      let builder = GetMessages::new().before(before).limit(PAGE_SIZE);
+      let messages = match channel_id.messages(core, builder).await {
+        Ok(messages) => messages,
+        Err(error) if is_inaccessible(&error) => {
+          info!("Skipping emoji stats backfill of channel {channel_id}: {error}");
+          progress.channels_skipped += 1;
+          break;
+        },
+        Err(error) => return Err(error).context("failed to get channel messages")
+      };
+
+      let finished = messages.len() < PAGE_SIZE as usize;
+      let uses = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+        Ok(persist_guild.emoji_stats.add_backfilled_messages(channel_id, &messages, finished, Utc::now().date_naive()))
+      }).await?;
+
+      progress.messages += messages.len();
+      progress.uses += uses;
+      if last_report.elapsed() >= REPORT_INTERVAL {
+        report(&progress).await;
+        last_report = Instant::now();
+      };
+
+      if finished { break };
+      // serenity waits out rate limits on its own, this only keeps from running into them
+      tokio::time::sleep(PAGE_DELAY).await;
+    };
+
+    progress.channels_done += 1;
+  };
+
+  Ok(progress)
+}
+
+/// Every channel in a guild that has a message history, followed by every thread, active or archived.
+/// Private threads are only included while they are active, since listing archived ones needs more permissions.
+async fn list_channels(core: &Core, guild_id: GuildId) -> MelodyResult<Vec<ChannelId>> {
+  let (mut channels, thread_parents) = core.cache.guild(guild_id).map(|guild| {
+    let channels = guild.channels.values()
+      .filter(|channel| matches!(channel.kind, ChannelType::Text | ChannelType::News | ChannelType::Voice | ChannelType::Stage))
+      .sorted_by_key(|channel| (channel.position, channel.id))
+      .map(|channel| channel.id)
+      .chain(guild.threads.iter().map(|thread| thread.id))
+      .collect::<Vec<ChannelId>>();
+    // forum posts are threads in a forum channel
+    let thread_parents = guild.channels.values()
+      .filter(|channel| matches!(channel.kind, ChannelType::Text | ChannelType::News | ChannelType::Forum))
+      .sorted_by_key(|channel| (channel.position, channel.id))
+      .map(|channel| channel.id)
+      .collect::<Vec<ChannelId>>();
+    (channels, thread_parents)
+  }).ok_or(MelodyError::command_cache_failure("guild"))?;
+
+  for parent_id in thread_parents {
+    let mut before = None;
+    loop {
+      let threads = match archived_public_threads(core, parent_id, before).await {
+        Ok(threads) => threads,
+        Err(error) if is_inaccessible(&error) => {
+          info!("Skipping archived threads of channel {parent_id} in emoji stats backfill: {error}");
+          break;
+        },
+        Err(error) => return Err(error).context("failed to get archived threads")
+      };
+
+      channels.extend(threads.threads.iter().map(|thread| thread.id));
+      before = threads.threads.last()
+        .and_then(|thread| thread.thread_metadata)
+        .and_then(|thread_metadata| thread_metadata.archive_timestamp);
+      if !threads.has_more || before.is_none() { break };
+      tokio::time::sleep(PAGE_DELAY).await;
+    };
+  };
+
+  // a thread may have been archived between listing active threads and archived ones
+  Ok(channels.into_iter().unique().collect())
+}
+
+/// Lists a page of a channel's archived public threads, most recently archived first.
+/// serenity sends `before` as a number, where Discord expects a timestamp, so this makes the request itself.
+async fn archived_public_threads(core: &Core, channel_id: ChannelId, before: Option<Timestamp>) -> serenity::Result<ThreadsData> {
+  let mut params = vec![("limit", PAGE_SIZE.to_string())];
+  if let Some(before) = before {
+    params.push(("before", before.to_string()));
+  };
+
+  let request = Request::new(Route::ChannelArchivedPublicThreads { channel_id }, LightMethod::Get);
+  core.http.fire(request.params(Some(params))).await
+}
+
+/// Whether the bot is missing access to a channel, which is left to be backfilled another time.
+fn is_inaccessible(error: &serenity::Error) -> bool {
+  matches!(error, serenity::Error::Http(error) if matches!(error.status_code(), Some(StatusCode::FORBIDDEN | StatusCode::NOT_FOUND)))
+}
+
+/// The earliest possible ID of a message sent at the given time, which is after every message sent before it.
+pub(super) fn message_id_at(time: DateTime<Utc>) -> MessageId {
+  let milliseconds = (time.timestamp_millis() - DISCORD_EPOCH).max(1) as u64;
+  MessageId::new(milliseconds << 22)
+}
+
+pub(super) fn message_time(message_id: MessageId) -> DateTime<Utc> {
+  let milliseconds = (message_id.get() >> 22) as i64 + DISCORD_EPOCH;
+  DateTime::from_timestamp_millis(milliseconds).unwrap_or_default()
+}
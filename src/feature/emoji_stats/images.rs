@@ -0,0 +1,107 @@
+use super::*;
+
+use image::{ImageError, ImageFormat, RgbaImage};
+use image::imageops::FilterType;
+use reqwest::Client as HttpClient;
+use tokio::sync::Mutex;
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+
+
+const PART_EXTENSION: &str = "part";
+
+/// Images of custom emojis, Unicode emojis and stickers for charts, downloaded once and then kept on disk.
+#[derive(Debug)]
+pub struct EmojiImages {
+  path: PathBuf,
+  http_client: HttpClient,
+  /// Emojis whose images couldn't be downloaded or decoded, like Lottie stickers, so they aren't retried.
+  unavailable: Mutex<HashSet<EmojiKey>>
+}
+
+impl EmojiImages {
+  pub async fn create(http_client: HttpClient) -> MelodyResult<Self> {
+    let path = PathBuf::from("./data/emoji-images");
+    fs_err::tokio::create_dir_all(&path).await
+      .context(format!("failed to create {}", path.display()))?;
+    Ok(EmojiImages { path, http_client, unavailable: Mutex::new(HashSet::new()) })
+  }
+
+  /// Gets the image of an emoji, scaled to fit within `size` pixels, downloading it if it hasn't been yet.
+  pub async fn get(&self, emoji: &EmojiKey, size: u32) -> Option<RgbaImage> {
+    if self.unavailable.lock().await.contains(emoji) { return None };
+    let result = self.load(emoji).await.and_then(|bytes| {
+      let image = image::load_from_memory_with_format(&bytes, ImageFormat::Png)?;
+      Ok(image.resize(size, size, FilterType::Triangle).into_rgba8())
+    });
+
+    match result {
+      Ok(image) => Some(image),
+      Err(error) => {
+        warn!("Unable to get image for emoji {emoji:?}: {error}");
+        self.unavailable.lock().await.insert(emoji.clone());
+        None
+      }
+    }
+  }
+
+  async fn load(&self, emoji: &EmojiKey) -> Result<Vec<u8>, EmojiImageError> {
+    let path = self.entry_path(emoji);
+    match fs_err::tokio::read(&path).await {
+      Ok(bytes) => return Ok(bytes),
+      Err(error) if error.kind() == ErrorKind::NotFound => (),
+      Err(error) => return Err(error.into())
+    };
+
+    let bytes = self.http_client.get(image_url(emoji))
+      .send().await?
+      .error_for_status()?
+      .bytes().await?;
+
+    let part_path = path.with_extension(PART_EXTENSION);
+    fs_err::tokio::write(&part_path, &bytes).await?;
+    fs_err::tokio::rename(&part_path, &path).await?;
+    trace!("Downloaded {} bytes of emoji image to {}", bytes.len(), path.display());
+    Ok(bytes.to_vec())
+  }
+
+  fn entry_path(&self, emoji: &EmojiKey) -> PathBuf {
+    let file_name = match emoji {
+      EmojiKey::Custom(emoji_id) => format!("emoji-{emoji_id}.png"),
+      EmojiKey::Unicode(emoji) => format!("unicode-{}.png", twemoji_code(emoji)),
+      EmojiKey::Sticker(sticker_id) => format!("sticker-{sticker_id}.png")
+    };
+
+    Path::join(&self.path, file_name)
+  }
+}
+
+fn image_url(emoji: &EmojiKey) -> String {
+  match emoji {
+    EmojiKey::Custom(emoji_id) => format!("https://cdn.discordapp.com/emojis/{emoji_id}.png?size=64"),
+    EmojiKey::Unicode(emoji) => format!("https://cdn.jsdelivr.net/gh/jdecked/twemoji@latest/assets/72x72/{}.png", twemoji_code(emoji)),
+    EmojiKey::Sticker(sticker_id) => format!("https://media.discordapp.net/stickers/{sticker_id}.png?size=160")
+  }
+}
+
+/// The codepoints of an emoji in hexadecimal, joined by dashes, like Twemoji names its images.
+/// Variation selectors are left out, unless the emoji is a sequence joined by zero width joiners.
+fn twemoji_code(emoji: &str) -> String {
+  let joined = emoji.contains('\u{200d}');
+  emoji.chars()
+    .filter(|&c| joined || c != '\u{fe0f}')
+    .map(|c| format!("{:x}", c as u32))
+    .join("-")
+}
+
+#[derive(Debug, Error)]
+enum EmojiImageError {
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+  #[error(transparent)]
+  Http(#[from] reqwest::Error),
+  #[error(transparent)]
+  Image(#[from] ImageError)
+}
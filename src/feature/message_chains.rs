@@ -1,12 +1,18 @@
 use crate::prelude::*;
+use crate::data::Core;
+use crate::utils::LazyRegex;
 
 use ahash::AHasher;
-use serenity::model::id::{ChannelId, UserId};
+use chrono::{DateTime, Utc};
+use serenity::builder::{CreateAttachment, CreateMessage};
+use serenity::model::id::{ChannelId, EmojiId, StickerId, UserId};
 use serenity::model::channel::Message;
 use tokio::sync::Mutex;
+use tokio::time::Instant;
 
 use std::hash::{Hasher, Hash};
 use std::sync::Arc;
+use std::time::Duration;
 
 
 
@@ -20,32 +26,38 @@ impl From<MessageChains> for MessageChainsWrapper {
 
 #[derive(Debug, Clone, Default)]
 pub struct MessageChains {
-  chains: HashMap<ChannelId, MessageChain>
+  chains: HashMap<ChannelId, MessageChain>,
+  /// When the bot last joined in on a chain in each channel.
+  last_contributed: HashMap<ChannelId, Instant>
 }
 
 impl MessageChains {
-  const ACTIVATION_THRESHOLD: usize = 3;
-  const ACTIVATION_CHANCE: f64 = 0.375;
-
   pub fn new() -> MessageChains {
     Self::default()
   }
 
-  pub fn should_contribute(&mut self, message: &Message) -> bool {
-    let chain_len = self.observe_message(message);
-    if chain_len >= Self::ACTIVATION_THRESHOLD && rand::random_bool(Self::ACTIVATION_CHANCE) {
+  /// Observes a message, deciding whether the bot should join in on the chain it continues.
+  /// Returns `None` if message chains are disabled where the message was sent.
+  pub fn observe(&mut self, message: &Message, settings: &MessageChainSettings) -> Option<MessageChainObservation> {
+    if !settings.allows_channel(message.channel_id) { return None };
+
+    let len = self.observe_message(message);
+    let cooling_down = self.last_contributed.get(&message.channel_id)
+      .is_some_and(|&last_contributed| last_contributed.elapsed() < settings.cooldown);
+    let contribute = len >= settings.threshold && !cooling_down
+      && rand::random_bool(settings.chance.clamp(0.0, 1.0));
+    if contribute {
       self.reset_message_chain(message.channel_id);
-      true
-    } else {
-      false
-    }
+      self.last_contributed.insert(message.channel_id, Instant::now());
+    };
+
+    Some(MessageChainObservation { len, contribute })
   }
 
   fn observe_message(&mut self, message: &Message) -> usize {
-    let message_content = message.content.trim();
-    if message_content.is_empty() { return 0 };
+    let Some(signature) = MessageSignature::new(message) else { return 0 };
     let previous = self.chains.get(&message.channel_id).copied();
-    let chain = MessageChain::new(message_content, message.author.id, previous);
+    let chain = MessageChain::new(&signature, message.author.id, previous);
     self.chains.insert(message.channel_id, chain);
     chain.len
   }
@@ -55,6 +67,14 @@ impl MessageChains {
   }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageChainObservation {
+  /// The length of the chain that the message is part of, or 0 if it can't be part of a chain.
+  pub len: usize,
+  /// Whether the bot should join in on the chain.
+  pub contribute: bool
+}
+
 #[derive(Debug, Clone, Copy)]
 struct MessageChain {
   hash: u64,
@@ -63,8 +83,8 @@ struct MessageChain {
 }
 
 impl MessageChain {
-  fn new(content: &str, user: UserId, previous: Option<Self>) -> Self {
-    let hash = get_content_hash(content);
+  fn new(signature: &MessageSignature, user: UserId, previous: Option<Self>) -> Self {
+    let hash = signature.content_hash();
     let len = match previous {
       Some(prev) if prev.continues(hash, user) => prev.len + 1,
       Some(..) | None => 1
@@ -79,8 +99,125 @@ impl MessageChain {
   }
 }
 
-fn get_content_hash(content: &str) -> u64 {
-  let mut hasher = AHasher::default();
-  content.trim().to_lowercase().hash(&mut hasher);
-  hasher.finish()
+/// What makes two messages the same, for the purposes of a message chain.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum MessageSignature {
+  Stickers(Vec<StickerId>),
+  /// Custom emojis are compared by ID, so that renamed or animated variants still match.
+  Emojis(Vec<EmojiId>),
+  /// Attachments are compared by file name and size, since each upload gets a new URL.
+  Text { content: String, attachments: Vec<(String, u32)> }
+}
+
+impl MessageSignature {
+  fn new(message: &Message) -> Option<Self> {
+    let content = message.content.trim();
+    if !message.sticker_items.is_empty() {
+      let stickers = message.sticker_items.iter().map(|sticker| sticker.id).collect();
+      return Some(MessageSignature::Stickers(stickers));
+    };
+
+    if message.attachments.is_empty() && is_emoji_only(content) {
+      return Some(MessageSignature::Emojis(crate::utils::parse_emojis(content)));
+    };
+
+    let attachments = message.attachments.iter()
+      .map(|attachment| (attachment.filename.clone(), attachment.size))
+      .collect::<Vec<(String, u32)>>();
+    if content.is_empty() && attachments.is_empty() { return None };
+    Some(MessageSignature::Text { content: content.to_lowercase(), attachments })
+  }
+
+  fn content_hash(&self) -> u64 {
+    let mut hasher = AHasher::default();
+    self.hash(&mut hasher);
+    hasher.finish()
+  }
+}
+
+fn is_emoji_only(content: &str) -> bool {
+  static RX: LazyRegex = LazyRegex::new(r"<a?:[\d\w]+:\d+>");
+  RX.is_match(content) && RX.replace_all(content, "").trim().is_empty()
+}
+
+/// Joins in on a message chain by repeating the message, including its stickers and attachments.
+pub async fn contribute(core: &Core, message: &Message) -> MelodyResult {
+  let mut attachments = Vec::with_capacity(message.attachments.len());
+  for attachment in message.attachments.iter() {
+    let attachment = CreateAttachment::url(&core.http, &attachment.url)
+      .await.context("failed to download attachment")?;
+    attachments.push(attachment);
+  };
+
+  let message_builder = CreateMessage::new()
+    .content(&message.content)
+    .sticker_ids(message.sticker_items.iter().map(|sticker| sticker.id))
+    .add_files(attachments);
+  message.channel_id.send_message(&core, message_builder)
+    .await.context("failed to send message")?;
+
+  Ok(())
+}
+
+/// Per-guild message chain settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MessageChainSettings {
+  /// Whether the bot joins in on message chains in this guild at all.
+  pub enabled: bool,
+  /// How many people must send the same message before the bot may join in.
+  pub threshold: usize,
+  /// The chance (from 0 to 1) of joining in on each message once the threshold is reached.
+  pub chance: f64,
+  /// The minimum time between joining in on chains in the same channel.
+  pub cooldown: Duration,
+  /// Channels where message chains are ignored.
+  pub excluded_channels: HashSet<ChannelId>,
+  /// The longest message chain seen in this guild.
+  pub longest_chain: Option<LongestMessageChain>
+}
+
+impl MessageChainSettings {
+  pub fn allows_channel(&self, channel_id: ChannelId) -> bool {
+    self.enabled && !self.excluded_channels.contains(&channel_id)
+  }
+
+  /// Whether a chain of this length would be the longest one so far.
+  pub fn is_longest_chain(&self, len: usize) -> bool {
+    len >= 2 && self.longest_chain.as_ref().is_none_or(|longest_chain| len > longest_chain.len)
+  }
+
+  /// Records the chain that a message is part of, if it is the longest one so far.
+  pub fn record_chain(&mut self, message: &Message, len: usize) {
+    if !self.is_longest_chain(len) { return };
+    self.longest_chain = Some(LongestMessageChain {
+      len,
+      channel: message.channel_id,
+      content: message.content.clone(),
+      time: Utc::now()
+    });
+  }
+}
+
+impl Default for MessageChainSettings {
+  fn default() -> Self {
+    MessageChainSettings {
+      enabled: true,
+      threshold: 3,
+      chance: 0.375,
+      cooldown: Duration::ZERO,
+      excluded_channels: HashSet::new(),
+      longest_chain: None
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LongestMessageChain {
+  pub len: usize,
+  pub channel: ChannelId,
+  /// The text of the repeated message, which may be empty for stickers and images.
+  pub content: String,
+  /// When the chain reached this length.
+  pub time: DateTime<Utc>
 }
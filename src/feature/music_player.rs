@@ -1,32 +1,114 @@
+mod cache;
+mod lyrics;
+
+pub use self::cache::{AudioCache, AudioCacheError};
+
 use crate::prelude::*;
-use crate::data::Core;
-use crate::utils::youtube::{self, YtDlpSource, YtDlp};
+use crate::data::{ConfigMusicPlayer, Core};
+use crate::utils::lyrics::{LyricLine, LyricsClient, LyricsQuery};
+use crate::utils::youtube::{self, VideoInfo, VideoInfoStore, YtDlpSource, YtDlp};
 
+use melody_timer::{TimerReceiverInstant, TimerSenderInstant};
 use reqwest::Client as HttpClient;
-use serenity::model::id::{AttachmentId, ChannelId, GuildId};
+use serenity::model::id::{AttachmentId, ChannelId, GuildId, RoleId, UserId};
 use songbird::{Call, Songbird, SongbirdKey};
 use songbird::events::{Event, EventContext, EventHandler, TrackEvent};
 use songbird::tracks::{TrackHandle, PlayMode};
-use songbird::input::{Input, HttpRequest};
+use songbird::input::{File as FileInput, Input, HttpRequest};
 use songbird::error::JoinError;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use url::Url;
 
 use std::collections::vec_deque::VecDeque;
 use std::fmt;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 
 
 #[derive(Debug)]
 pub struct MusicPlayer {
   yt_dlp: YtDlp,
-  http_client: HttpClient,
+  prefetcher: Prefetcher,
+  lyrics_client: LyricsClient,
+  inactivity_timer: InactivityTimer,
+  inactivity_receiver: Mutex<Option<TimerReceiverInstant<(GuildId, Inactivity)>>>,
   guilds: Mutex<HashMap<GuildId, Arc<Mutex<QueueBundle>>>>
 }
 
 impl MusicPlayer {
-  pub fn new(yt_dlp: YtDlp, http_client: HttpClient) -> Self {
-    MusicPlayer { yt_dlp, http_client, guilds: Mutex::new(HashMap::new()) }
+  pub fn new(
+    yt_dlp: YtDlp, http_client: HttpClient, config: &ConfigMusicPlayer,
+    audio_cache: Option<AudioCache>, lyrics_client: LyricsClient
+  ) -> Self {
+    let (sender, receiver) = melody_timer::timer();
+    let inactivity_timer = InactivityTimer {
+      idle_timeout: config.idle_timeout,
+      alone_timeout: config.alone_timeout,
+      sender
+    };
+
+    let prefetcher = Prefetcher {
+      count: config.prefetch,
+      yt_dlp: yt_dlp.clone(),
+      http_client,
+      video_info_store: VideoInfoStore::new(),
+      audio_cache: audio_cache.map(Arc::new),
+      in_flight: Arc::new(std::sync::Mutex::new(HashSet::new()))
+    };
+
+    MusicPlayer {
+      yt_dlp, prefetcher, lyrics_client, inactivity_timer,
+      inactivity_receiver: Mutex::new(Some(receiver)),
+      guilds: Mutex::new(HashMap::new())
+    }
+  }
+
+  /// Receives inactivity timeouts and disconnects from voice sessions that have been idle or alone for too long.
+  /// Only the first call to this function will do anything, subsequent calls will return immediately.
+  pub async fn inactivity_task(self: Arc<Self>, core: Core) {
+    let Some(mut receiver) = self.inactivity_receiver.lock().await.take() else { return };
+    while let Some((guild_id, inactivity)) = receiver.next().await {
+      self.check_inactivity(&core, guild_id, inactivity).await.log_error();
+    };
+  }
+
+  async fn check_inactivity(&self, core: &Core, guild_id: GuildId, inactivity: Inactivity) -> MelodyResult {
+    let Some(queue_bundle) = self.guilds.lock().await.get(&guild_id).cloned() else { return Ok(()) };
+    let expired = queue_bundle.lock().await.take_expired_deadline(inactivity, Instant::now());
+    if !expired { return Ok(()) };
+
+    let Some(channel_id) = self.current_channel(core, guild_id).await else { return Ok(()) };
+    info!("Leaving voice channel ({channel_id}) in guild ({guild_id}) due to inactivity ({inactivity:?})");
+    let notice = match inactivity {
+      Inactivity::Idle => "Leaving the voice channel since nothing has been played for a while",
+      Inactivity::Alone => "Leaving the voice channel since everyone else has left"
+    };
+
+    channel_id.say(core, notice).await
+      .context("failed to send inactivity notice").log_error();
+    if let Err(err) = self.leave(core, guild_id).await {
+      error!("failed to leave channel: {err}");
+    };
+
+    Ok(())
+  }
+
+  /// Starts or cancels the timer for leaving a voice channel that the bot has been left alone in.
+  /// Should be called whenever a voice state in the given guild changes.
+  pub async fn update_alone(&self, core: &Core, guild_id: GuildId) {
+    let Some(channel_id) = self.current_channel(core, guild_id).await else { return };
+    let is_alone = channel_listeners(core, guild_id, channel_id).is_empty();
+
+    let queue_bundle = self.get_guild_queue_bundle(guild_id).await;
+    let mut queue_bundle = queue_bundle.lock().await;
+    match is_alone {
+      true => self.inactivity_timer.schedule(guild_id, Inactivity::Alone, &mut queue_bundle),
+      false => queue_bundle.alone_deadline = None
+    };
   }
 
   pub async fn join(&self, core: &Core, guild_id: GuildId, channel_id: ChannelId) -> Result<(), JoinError> {
@@ -40,21 +122,31 @@ impl MusicPlayer {
     let songbird = core.get::<SongbirdKey>().await;
     let queue_bundle = self.get_guild_queue_bundle(guild_id).await;
     songbird.remove(guild_id).await?;
-    queue_bundle.lock().await.track.take();
+    let mut queue_bundle = queue_bundle.lock().await;
+    queue_bundle.track.take();
+    queue_bundle.clear_deadlines();
+    queue_bundle.stop_lyrics();
     Ok(())
   }
 
-  pub async fn play(&self, core: &Core, guild_id: GuildId, channel_id: ChannelId, items: Vec<QueueItem>) -> Result<(), JoinError> {
+  pub async fn play(
+    &self, core: &Core, guild_id: GuildId, channel_id: ChannelId,
+    requester: UserId, items: Vec<QueueItem>
+  ) -> Result<(), JoinError> {
     let songbird = core.get::<SongbirdKey>().await;
     let session = self.join_and_deafen(&songbird, guild_id, channel_id).await?;
-    session.start_playing_or_append(items).await;
+    let entries = items.into_iter().map(|item| QueueEntry { item, requester }).collect();
+    session.start_playing_or_append(entries).await;
     Ok(())
   }
 
   pub async fn kill(&self, core: &Core, guild_id: GuildId) -> Result<(), JoinError> {
     let songbird = core.get::<SongbirdKey>().await;
     songbird.remove(guild_id).await?;
-    self.remove_guild_queue_bundle(guild_id).await;
+    if let Some(queue_bundle) = self.remove_guild_queue_bundle(guild_id).await {
+      queue_bundle.lock().await.stop_lyrics();
+    };
+
     Ok(())
   }
 
@@ -74,6 +166,75 @@ impl MusicPlayer {
     };
   }
 
+  /// Registers a vote to skip the current track, skipping it if at least `required` votes have been cast.
+  pub async fn vote_skip(&self, core: &Core, guild_id: GuildId, user_id: UserId, required: usize) -> VoteSkipResult {
+    let songbird = core.get::<SongbirdKey>().await;
+    let Some(session) = self.current_session(&songbird, guild_id).await else { return VoteSkipResult::NotPlaying };
+    let mut queue_bundle = session.queue_bundle.lock().await;
+    if queue_bundle.queue.get_current().is_none() { return VoteSkipResult::NotPlaying };
+
+    queue_bundle.skip_votes.insert(user_id);
+    let votes = queue_bundle.skip_votes.len();
+    if votes >= required {
+      queue_bundle.queue.advance();
+      std::mem::drop(queue_bundle);
+      session.start_playing().await;
+      VoteSkipResult::Skipped
+    } else {
+      VoteSkipResult::Voted { votes, required }
+    }
+  }
+
+  /// Gets the currently playing track, along with its info if it was resolved through yt-dlp.
+  pub async fn now_playing(&self, guild_id: GuildId) -> Option<NowPlaying> {
+    let queue_bundle = self.get_guild_queue_bundle(guild_id).await;
+    let (entry, track) = {
+      let queue_bundle = queue_bundle.lock().await;
+      let entry = queue_bundle.queue.get_current()?.clone();
+      (entry, queue_bundle.track.clone()?)
+    };
+
+    let position = track.get_info().await.ok().map(|state| state.position);
+    let video_info = match entry.item.yt_dlp_url() {
+      Some(url) => match self.prefetcher.video_info_store.resolve(&self.yt_dlp, &url).await {
+        Ok(video_info) => Some(video_info),
+        Err(err) => {
+          error!("failed to retrieve track info: {err}");
+          None
+        }
+      },
+      None => None
+    };
+
+    Some(NowPlaying { entry, position, video_info })
+  }
+
+  /// Starts posting synced lyrics for the current track in the given channel,
+  /// replacing any synced lyrics that were already being posted in this guild.
+  /// Returns false if nothing is playing.
+  pub async fn start_synced_lyrics(&self, core: &Core, guild_id: GuildId, channel_id: ChannelId, lines: Vec<LyricLine>) -> bool {
+    let queue_bundle = self.get_guild_queue_bundle(guild_id).await;
+    let mut queue_bundle = queue_bundle.lock().await;
+    let Some(track) = queue_bundle.track.clone() else { return false };
+    queue_bundle.stop_lyrics();
+    queue_bundle.lyrics_task = Some(tokio::spawn({
+      lyrics::post_synced_lyrics(core.clone(), channel_id, track, lines)
+    }));
+
+    true
+  }
+
+  pub async fn current_requester(&self, guild_id: GuildId) -> Option<UserId> {
+    self.queue_manipulate(guild_id, |queue| queue.get_current().map(|entry| entry.requester)).await
+  }
+
+  /// Counts the number of tracks in the queue that were requested by the given user.
+  pub async fn queue_count_for(&self, guild_id: GuildId, user_id: UserId) -> usize {
+    self.queue_manipulate(guild_id, |queue| {
+      queue.contents.iter().filter(|entry| entry.requester == user_id).count()
+    }).await
+  }
+
   pub async fn current_channel(&self, core: &Core, guild_id: GuildId) -> Option<ChannelId> {
     let songbird = core.get::<SongbirdKey>().await;
     match songbird.get(guild_id) {
@@ -90,11 +251,18 @@ impl MusicPlayer {
 
   pub async fn set_pause(&self, guild_id: GuildId, state: bool) {
     let queue_bundle = self.get_guild_queue_bundle(guild_id).await;
-    if let Some(track) = &queue_bundle.lock().await.track {
+    let mut queue_bundle = queue_bundle.lock().await;
+    if let Some(track) = &queue_bundle.track {
       let result = if state { track.pause() } else { track.play() };
       if let Err(err) = result {
         error!("failed to pause/unpause track: {err}");
       };
+
+      // a paused player counts as idle
+      match state {
+        true => self.inactivity_timer.schedule(guild_id, Inactivity::Idle, &mut queue_bundle),
+        false => queue_bundle.idle_deadline = None
+      };
     };
   }
 
@@ -106,11 +274,19 @@ impl MusicPlayer {
     self.queue_manipulate(guild_id, |queue| queue.shuffle()).await
   }
 
-  pub async fn queue_remove(&self, guild_id: GuildId, index: zusize) -> Option<QueueItem> {
-    self.queue_manipulate(guild_id, |queue| queue.remove(index)).await
+  /// Removes an item from the queue, only if `predicate` returns true for it.
+  pub async fn queue_remove<F>(&self, guild_id: GuildId, index: zusize, predicate: F) -> QueueRemoveResult
+  where F: FnOnce(&QueueEntry) -> bool {
+    self.queue_manipulate(guild_id, |queue| {
+      let Some(entry) = queue.contents.get(index.get()) else { return QueueRemoveResult::NotFound };
+      match predicate(entry) {
+        true => QueueRemoveResult::Removed(queue.remove(index).expect("infallible")),
+        false => QueueRemoveResult::Denied(entry.clone())
+      }
+    }).await
   }
 
-  pub async fn queue_list(&self, guild_id: GuildId) -> (Vec<QueueItem>, bool) {
+  pub async fn queue_list(&self, guild_id: GuildId) -> (Vec<QueueEntry>, bool) {
     self.queue_manipulate(guild_id, |queue| (queue.to_vec(), queue.looped)).await
   }
 
@@ -125,8 +301,9 @@ impl MusicPlayer {
     let call = songbird.get(guild_id)?;
     let queue_bundle = self.get_guild_queue_bundle(guild_id).await;
     Some(Session {
-      yt_dlp: self.yt_dlp.clone(),
-      http_client: self.http_client.clone(),
+      guild_id,
+      prefetcher: self.prefetcher.clone(),
+      inactivity_timer: self.inactivity_timer.clone(),
       call, queue_bundle
     })
   }
@@ -135,8 +312,9 @@ impl MusicPlayer {
     let call = join_and_deafen(&songbird, guild_id, channel_id).await?;
     let queue_bundle = self.get_guild_queue_bundle(guild_id).await;
     Ok(Session {
-      yt_dlp: self.yt_dlp.clone(),
-      http_client: self.http_client.clone(),
+      guild_id,
+      prefetcher: self.prefetcher.clone(),
+      inactivity_timer: self.inactivity_timer.clone(),
       call, queue_bundle
     })
   }
@@ -152,6 +330,79 @@ impl MusicPlayer {
   pub fn yt_dlp(&self) -> &YtDlp {
     &self.yt_dlp
   }
+
+  pub fn lyrics_client(&self) -> &LyricsClient {
+    &self.lyrics_client
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct NowPlaying {
+  pub entry: QueueEntry,
+  /// How far into the track playback is.
+  pub position: Option<Duration>,
+  pub video_info: Option<VideoInfo>
+}
+
+impl NowPlaying {
+  /// The query to look up lyrics for this track with, if enough is known about the track.
+  pub fn lyrics_query(&self) -> Option<LyricsQuery> {
+    if let Some(video_info) = &self.video_info {
+      return Some(LyricsQuery::from_video_info(video_info));
+    };
+
+    match &self.entry.item {
+      QueueItem::Attachment(item) => Some(LyricsQuery {
+        track: item.filename.rsplit_once('.').map_or(item.filename.as_str(), |(stem, _)| stem).replace('_', " "),
+        artist: None,
+        album: None,
+        duration: None
+      }),
+      _ => None
+    }
+  }
+}
+
+/// Music player settings that may be configured per-guild.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MusicPlayerSettings {
+  /// Members with this role may skip, stop and clear the queue without voting.
+  /// When no DJ role is set, every member is treated as a DJ.
+  pub dj_role: Option<RoleId>,
+  /// The share of listeners (between 0 and 1) who must vote to skip a track.
+  pub vote_skip_ratio: f64,
+  /// The maximum number of tracks a single non-DJ member may have in the queue at once.
+  pub queue_limit: Option<usize>
+}
+
+impl MusicPlayerSettings {
+  /// The number of votes needed to skip a track with the given number of listeners.
+  pub fn votes_required(&self, listeners: usize) -> usize {
+    (listeners as f64 * self.vote_skip_ratio.clamp(0.0, 1.0)).ceil().max(1.0) as usize
+  }
+}
+
+impl Default for MusicPlayerSettings {
+  fn default() -> Self {
+    MusicPlayerSettings {
+      dj_role: None,
+      vote_skip_ratio: 0.5,
+      queue_limit: None
+    }
+  }
+}
+
+/// Lists the users in the given voice channel, excluding bots.
+pub fn channel_listeners(core: &Core, guild_id: GuildId, channel_id: ChannelId) -> Vec<UserId> {
+  let me = core.current_user_id();
+  let Some(guild) = core.cache.guild(guild_id) else { return Vec::new() };
+  guild.voice_states.values()
+    .filter(|voice_state| voice_state.channel_id == Some(channel_id) && voice_state.user_id != me)
+    .filter(|voice_state| !voice_state.member.as_ref().is_some_and(|member| member.user.bot))
+    .filter(|voice_state| !core.cache.user(voice_state.user_id).is_some_and(|user| user.bot))
+    .map(|voice_state| voice_state.user_id)
+    .collect()
 }
 
 async fn join_and_deafen(
@@ -176,8 +427,9 @@ async fn join_and_deafen(
 
 #[derive(Debug, Clone)]
 struct Session {
-  yt_dlp: YtDlp,
-  http_client: HttpClient,
+  guild_id: GuildId,
+  prefetcher: Prefetcher,
+  inactivity_timer: InactivityTimer,
   queue_bundle: Arc<Mutex<QueueBundle>>,
   call: Arc<Mutex<Call>>
 }
@@ -188,31 +440,42 @@ impl Session {
     let mut call = self.call.lock().await;
     call.stop();
     queue_bundle.track = None;
+    self.inactivity_timer.schedule(self.guild_id, Inactivity::Idle, &mut queue_bundle);
   }
 
   /// Start playing the first item in the queue, stopping any other tracks that might be playing, internally.
   async fn start_playing(&self) {
     let mut queue_bundle = self.queue_bundle.lock().await;
     let mut call = self.call.lock().await;
-    if let Some(current_item) = queue_bundle.queue.get_current() {
-      let input = current_item.to_input(self.http_client.clone(), self.yt_dlp.clone());
+    queue_bundle.skip_votes.clear();
+    if let Some(current_entry) = queue_bundle.queue.get_current() {
+      let cached = self.prefetcher.cached_path(&current_entry.item).await;
+      let input = self.prefetcher.to_input(&current_entry.item, cached);
       let track_handle = self.play(&mut call, input);
       queue_bundle.track = Some(track_handle);
+      queue_bundle.idle_deadline = None;
+      self.prefetcher.prefetch(&queue_bundle.queue);
     } else {
       queue_bundle.track = None;
+      self.inactivity_timer.schedule(self.guild_id, Inactivity::Idle, &mut queue_bundle);
     };
   }
 
   /// Adds the item to the end of the queue if a track is playing, otherwise causes it to start playing.
-  async fn start_playing_or_append(&self, items: Vec<QueueItem>) {
+  async fn start_playing_or_append(&self, entries: Vec<QueueEntry>) {
     let mut queue_bundle = self.queue_bundle.lock().await;
     let mut call = self.call.lock().await;
-    queue_bundle.queue.append(items);
-    if let (None, Some(current_item)) = (&queue_bundle.track, queue_bundle.queue.get_current()) {
-      let input = current_item.to_input(self.http_client.clone(), self.yt_dlp.clone());
+    queue_bundle.queue.append(entries);
+    if let (None, Some(current_entry)) = (&queue_bundle.track, queue_bundle.queue.get_current()) {
+      let cached = self.prefetcher.cached_path(&current_entry.item).await;
+      let input = self.prefetcher.to_input(&current_entry.item, cached);
       let track_handle = self.play(&mut call, input);
       queue_bundle.track = Some(track_handle);
+      queue_bundle.idle_deadline = None;
+      queue_bundle.skip_votes.clear();
     };
+
+    self.prefetcher.prefetch(&queue_bundle.queue);
   }
 
   fn play(&self, call: &mut Call, input: Input) -> TrackHandle {
@@ -223,6 +486,78 @@ impl Session {
   }
 }
 
+/// Resolves upcoming queue items ahead of time, so that there is no gap between tracks
+/// while yt-dlp runs, and downloads them into the audio cache when it is enabled.
+#[derive(Debug, Clone)]
+struct Prefetcher {
+  /// The number of upcoming items to prefetch.
+  count: usize,
+  yt_dlp: YtDlp,
+  http_client: HttpClient,
+  video_info_store: VideoInfoStore,
+  audio_cache: Option<Arc<AudioCache>>,
+  /// URLs that are currently being prefetched.
+  in_flight: Arc<std::sync::Mutex<HashSet<String>>>
+}
+
+impl Prefetcher {
+  /// Finds the item's audio in the audio cache, if it is there.
+  /// This only briefly locks the cache's in-memory index, so it is fine to do while holding the queue.
+  async fn cached_path(&self, item: &QueueItem) -> Option<PathBuf> {
+    let audio_cache = self.audio_cache.as_deref()?;
+    let cache_key = item.cache_key()?;
+    audio_cache.get(&cache_key).await
+  }
+
+  /// Creates an input for the given item, preferring its cached audio if it was found.
+  fn to_input(&self, item: &QueueItem, cached: Option<PathBuf>) -> Input {
+    if let Some(path) = cached {
+      trace!("Playing {item} from audio cache");
+      return FileInput::new(path).into();
+    };
+
+    item.to_input(self.http_client.clone(), self.yt_dlp.clone(), &self.video_info_store)
+  }
+
+  /// Starts prefetching the items that come after the current one in the queue.
+  fn prefetch(&self, queue: &Queue) {
+    for entry in queue.contents.iter().skip(1).take(self.count) {
+      let Some(url) = entry.item.yt_dlp_url() else { continue };
+      if !self.in_flight.lock().unwrap().insert(url.clone()) { continue };
+
+      let prefetcher = self.clone();
+      let cache_key = entry.item.cache_key();
+      tokio::spawn(async move {
+        prefetcher.prefetch_item(&url, cache_key.as_deref()).await;
+        prefetcher.in_flight.lock().unwrap().remove(&url);
+      });
+    };
+  }
+
+  async fn prefetch_item(&self, url: &str, cache_key: Option<&str>) {
+    let audio_cache = Option::zip(self.audio_cache.as_deref(), cache_key);
+    if let Some((audio_cache, cache_key)) = audio_cache {
+      if audio_cache.contains(cache_key).await { return };
+    };
+
+    let video_info = match self.video_info_store.resolve(&self.yt_dlp, url).await {
+      Ok(video_info) => video_info,
+      Err(err) => {
+        warn!("failed to prefetch track info for {url}: {err}");
+        return;
+      }
+    };
+
+    if let Some((audio_cache, cache_key)) = audio_cache {
+      match audio_cache.download(cache_key, &self.http_client, &video_info).await {
+        Ok(()) => trace!("Cached audio for {url}"),
+        Err(AudioCacheError::TooLarge) => trace!("Audio for {url} is too large to be cached"),
+        Err(err) => warn!("failed to cache audio for {url}: {err}")
+      };
+    };
+  }
+}
+
 #[derive(Debug, Clone)]
 struct OnTrackEnd(Session);
 
@@ -258,16 +593,78 @@ impl EventHandler for OnTrackError {
   }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Inactivity {
+  /// Nothing is playing, either because the queue is empty or the player is paused.
+  Idle,
+  /// Nobody else is in the voice channel.
+  Alone
+}
+
+#[derive(Debug, Clone)]
+struct InactivityTimer {
+  idle_timeout: Option<Duration>,
+  alone_timeout: Option<Duration>,
+  sender: TimerSenderInstant<(GuildId, Inactivity)>
+}
+
+impl InactivityTimer {
+  /// Sets a deadline for the given inactivity, unless one has already been set.
+  fn schedule(&self, guild_id: GuildId, inactivity: Inactivity, queue_bundle: &mut QueueBundle) {
+    let (timeout, deadline) = match inactivity {
+      Inactivity::Idle => (self.idle_timeout, &mut queue_bundle.idle_deadline),
+      Inactivity::Alone => (self.alone_timeout, &mut queue_bundle.alone_deadline)
+    };
+
+    if let (Some(timeout), None) = (timeout, *deadline) {
+      let instant = Instant::now() + timeout;
+      *deadline = Some(instant);
+      // the receiver is only dropped when the bot is shutting down
+      let _ = self.sender.send((guild_id, inactivity), instant);
+    };
+  }
+}
+
 #[derive(Debug, Default)]
 struct QueueBundle {
   queue: Queue,
-  track: Option<TrackHandle>
+  track: Option<TrackHandle>,
+  /// Users who have voted to skip the current track.
+  skip_votes: HashSet<UserId>,
+  idle_deadline: Option<Instant>,
+  alone_deadline: Option<Instant>,
+  /// The task posting synced lyrics for the current track, if any.
+  lyrics_task: Option<JoinHandle<()>>
+}
+
+impl QueueBundle {
+  /// Clears the deadline for the given inactivity and returns true if it has passed.
+  /// Timers cannot be cancelled, so deadlines that have since been cleared or replaced are ignored here.
+  fn take_expired_deadline(&mut self, inactivity: Inactivity, now: Instant) -> bool {
+    let deadline = match inactivity {
+      Inactivity::Idle => &mut self.idle_deadline,
+      Inactivity::Alone => &mut self.alone_deadline
+    };
+
+    deadline.take_if(|deadline| *deadline <= now).is_some()
+  }
+
+  fn clear_deadlines(&mut self) {
+    self.idle_deadline = None;
+    self.alone_deadline = None;
+  }
+
+  fn stop_lyrics(&mut self) {
+    if let Some(lyrics_task) = self.lyrics_task.take() {
+      lyrics_task.abort();
+    };
+  }
 }
 
 #[derive(Debug, Clone)]
 struct Queue {
   /// Position 0 in the queue is special and cannot be cleared, as it is the currently playing track.
-  contents: VecDeque<QueueItem>,
+  contents: VecDeque<QueueEntry>,
   looped: bool
 }
 
@@ -295,19 +692,19 @@ impl Queue {
     };
   }
 
-  fn append(&mut self, items: impl IntoIterator<Item = QueueItem>) {
-    self.contents.extend(items);
+  fn append(&mut self, entries: impl IntoIterator<Item = QueueEntry>) {
+    self.contents.extend(entries);
   }
 
-  fn remove(&mut self, index: zusize) -> Option<QueueItem> {
+  fn remove(&mut self, index: zusize) -> Option<QueueEntry> {
     self.contents.remove(index.get())
   }
 
-  fn get_current(&self) -> Option<&QueueItem> {
+  fn get_current(&self) -> Option<&QueueEntry> {
     self.contents.front()
   }
 
-  fn to_vec(&self) -> Vec<QueueItem> {
+  fn to_vec(&self) -> Vec<QueueEntry> {
     self.contents.iter().cloned().collect()
   }
 }
@@ -321,25 +718,70 @@ impl Default for Queue {
   }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoteSkipResult {
+  NotPlaying,
+  Voted { votes: usize, required: usize },
+  Skipped
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueRemoveResult {
+  Removed(QueueEntry),
+  Denied(QueueEntry),
+  NotFound
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueEntry {
+  pub item: QueueItem,
+  /// The user who added this item to the queue.
+  pub requester: UserId
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum QueueItem {
   YouTube(YouTubeItem),
+  YtDlp(YtDlpItem),
+  Direct(DirectItem),
   Attachment(AttachmentItem)
 }
 
 impl QueueItem {
-  fn to_input(&self, http_client: HttpClient, yt_dlp: YtDlp) -> Input {
+  fn to_input(&self, http_client: HttpClient, yt_dlp: YtDlp, video_info_store: &VideoInfoStore) -> Input {
     match self {
-      QueueItem::YouTube(item) => item.to_input(http_client, yt_dlp).into(),
+      QueueItem::YouTube(item) => item.to_input(http_client, yt_dlp).with_video_info_store(video_info_store.clone()).into(),
+      QueueItem::YtDlp(item) => item.to_input(http_client, yt_dlp).with_video_info_store(video_info_store.clone()).into(),
+      QueueItem::Direct(item) => item.to_input(http_client).into(),
       QueueItem::Attachment(item) => item.to_input(http_client).into()
     }
   }
+
+  /// The URL that yt-dlp should resolve for this item, if it goes through yt-dlp at all.
+  fn yt_dlp_url(&self) -> Option<String> {
+    match self {
+      QueueItem::YouTube(item) => Some(youtube::display_video_url(&item.id).to_string()),
+      QueueItem::YtDlp(item) => Some(item.url.clone()),
+      QueueItem::Direct(..) | QueueItem::Attachment(..) => None
+    }
+  }
+
+  /// The key that this item's audio is stored under in the [`AudioCache`].
+  fn cache_key(&self) -> Option<String> {
+    match self {
+      QueueItem::YouTube(item) => Some(youtube::cache_key("youtube", &item.id)),
+      QueueItem::YtDlp(item) => item.cache_key.clone(),
+      QueueItem::Direct(..) | QueueItem::Attachment(..) => None
+    }
+  }
 }
 
 impl fmt::Display for QueueItem {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
       QueueItem::YouTube(item) => fmt::Display::fmt(item, f),
+      QueueItem::YtDlp(item) => fmt::Display::fmt(item, f),
+      QueueItem::Direct(item) => fmt::Display::fmt(item, f),
       QueueItem::Attachment(item) => fmt::Display::fmt(item, f)
     }
   }
@@ -352,7 +794,7 @@ pub struct YouTubeItem {
 
 impl YouTubeItem {
   fn to_input(&self, http_client: HttpClient, yt_dlp: YtDlp) -> YtDlpSource {
-    YtDlpSource::new(yt_dlp, self.id.clone(), http_client)
+    YtDlpSource::new(yt_dlp, youtube::display_video_url(&self.id).to_string(), http_client)
   }
 }
 
@@ -362,6 +804,58 @@ impl fmt::Display for YouTubeItem {
   }
 }
 
+/// A track from any site that yt-dlp has an extractor for (SoundCloud, Bandcamp, etc.).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YtDlpItem {
+  pub url: String,
+  pub title: Option<String>,
+  /// See [`VideoInfo::cache_key`][youtube::VideoInfo::cache_key].
+  pub cache_key: Option<String>
+}
+
+impl YtDlpItem {
+  fn to_input(&self, http_client: HttpClient, yt_dlp: YtDlp) -> YtDlpSource {
+    YtDlpSource::new(yt_dlp, self.url.clone(), http_client)
+  }
+}
+
+impl fmt::Display for YtDlpItem {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match &self.title {
+      Some(title) => write!(f, "`{title}` (<{}>)", self.url),
+      None => write!(f, "<{}>", self.url)
+    }
+  }
+}
+
+/// A direct link to an audio file, which is streamed without going through yt-dlp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectItem {
+  pub url: String
+}
+
+impl DirectItem {
+  const AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "aac", "alac", "flac", "wav", "ogg", "oga", "opus", "webm", "mp4"];
+
+  /// Returns a [`DirectItem`] if the URL's path ends in a known audio file extension.
+  pub fn parse(url: &Url) -> Option<Self> {
+    let (_, extension) = url.path().rsplit_once('.')?;
+    let extension = extension.to_ascii_lowercase();
+    Self::AUDIO_EXTENSIONS.contains(&extension.as_str())
+      .then(|| DirectItem { url: url.to_string() })
+  }
+
+  fn to_input(&self, http_client: HttpClient) -> HttpRequest {
+    HttpRequest::new(http_client, self.url.clone())
+  }
+}
+
+impl fmt::Display for DirectItem {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "<{}>", self.url)
+  }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AttachmentItem {
   pub id: AttachmentId,
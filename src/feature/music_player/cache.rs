@@ -0,0 +1,206 @@
+use crate::prelude::*;
+use crate::data::ConfigMusicPlayerCache;
+use crate::utils::youtube::VideoInfo;
+
+use reqwest::Client as HttpClient;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+
+
+const PART_EXTENSION: &str = "part";
+
+/// A size-bounded on-disk cache of downloaded audio, keyed by video ID.
+/// When the cache grows too large, the least recently used entries are evicted first.
+#[derive(Debug)]
+pub struct AudioCache {
+  path: PathBuf,
+  max_size: u64,
+  max_entry_size: u64,
+  state: Mutex<AudioCacheState>
+}
+
+#[derive(Debug, Default)]
+struct AudioCacheState {
+  entries: HashMap<String, AudioCacheEntry>,
+  /// Keys that are currently being downloaded.
+  pending: HashSet<String>,
+  total_size: u64
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AudioCacheEntry {
+  size: u64,
+  last_used: SystemTime
+}
+
+impl AudioCache {
+  pub async fn create(config: &ConfigMusicPlayerCache) -> std::io::Result<Self> {
+    let path = config.path.clone();
+    fs_err::tokio::create_dir_all(&path).await?;
+
+    let mut state = AudioCacheState::default();
+    let mut read_dir = fs_err::tokio::read_dir(&path).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+      let metadata = entry.metadata().await?;
+      if !metadata.is_file() { continue };
+
+      let entry_path = entry.path();
+      if entry_path.extension().is_some_and(|extension| extension == PART_EXTENSION) {
+        // incomplete downloads left over from a previous run
+        fs_err::tokio::remove_file(&entry_path).await
+          .context("failed to remove incomplete audio cache entry").log_error();
+        continue;
+      };
+
+      let Some(key) = entry_path.file_name().and_then(|file_name| file_name.to_str()) else { continue };
+      let last_used = metadata.modified().unwrap_or_else(|_| SystemTime::now());
+      state.total_size += metadata.len();
+      state.entries.insert(key.to_owned(), AudioCacheEntry { size: metadata.len(), last_used });
+    };
+
+    let audio_cache = AudioCache {
+      path,
+      max_size: config.max_size.saturating_mul(MEGABYTE),
+      max_entry_size: config.max_entry_size.saturating_mul(MEGABYTE),
+      state: Mutex::new(state)
+    };
+
+    let evicted = audio_cache.state.lock().await.evict(audio_cache.max_size, None);
+    audio_cache.remove_files(evicted).await;
+
+    Ok(audio_cache)
+  }
+
+  pub async fn contains(&self, key: &str) -> bool {
+    let state = self.state.lock().await;
+    state.entries.contains_key(key) || state.pending.contains(key)
+  }
+
+  /// Returns the path of the cached audio for the given key, marking it as recently used.
+  /// The file's modification time is updated in the background, which is where `last_used` is read from on startup.
+  pub async fn get(&self, key: &str) -> Option<PathBuf> {
+    let mut state = self.state.lock().await;
+    let entry = state.entries.get_mut(key)?;
+    let now = SystemTime::now();
+    entry.last_used = now;
+
+    let path = self.entry_path(key);
+    let touch_path = path.clone();
+    tokio::task::spawn_blocking(move || {
+      fs_err::File::open(&touch_path).and_then(|file| file.file().set_modified(now))
+        .context("failed to update audio cache entry modification time").log_error();
+    });
+
+    Some(path)
+  }
+
+  /// Downloads the audio stream of the given video into the cache, unless it is already present.
+  pub async fn download(&self, key: &str, http_client: &HttpClient, video_info: &VideoInfo) -> Result<(), AudioCacheError> {
+    if video_info.filesize.is_some_and(|filesize| filesize > self.max_entry_size) {
+      return Err(AudioCacheError::TooLarge);
+    };
+
+    {
+      let mut state = self.state.lock().await;
+      if state.entries.contains_key(key) || !state.pending.insert(key.to_owned()) {
+        return Ok(());
+      };
+    };
+
+    let result = self.download_file(key, http_client, video_info).await;
+
+    let evicted = {
+      let mut state = self.state.lock().await;
+      state.pending.remove(key);
+      match result {
+        Ok(size) => {
+          state.total_size += size;
+          state.entries.insert(key.to_owned(), AudioCacheEntry { size, last_used: SystemTime::now() });
+          state.evict(self.max_size, Some(key))
+        },
+        Err(err) => return Err(err)
+      }
+    };
+
+    self.remove_files(evicted).await;
+    Ok(())
+  }
+
+  async fn download_file(&self, key: &str, http_client: &HttpClient, video_info: &VideoInfo) -> Result<u64, AudioCacheError> {
+    let part_path = self.entry_path(key).with_extension(PART_EXTENSION);
+    let mut response = http_client.get(&video_info.url)
+      .headers(video_info.headers())
+      .send().await?
+      .error_for_status()?;
+
+    let mut file = fs_err::tokio::File::create(&part_path).await?;
+    let mut size = 0;
+    while let Some(chunk) = response.chunk().await? {
+      size += chunk.len() as u64;
+      if size > self.max_entry_size {
+        std::mem::drop(file);
+        fs_err::tokio::remove_file(&part_path).await?;
+        return Err(AudioCacheError::TooLarge);
+      };
+
+      file.write_all(&chunk).await?;
+    };
+
+    file.flush().await?;
+    std::mem::drop(file);
+    fs_err::tokio::rename(&part_path, self.entry_path(key)).await?;
+    trace!("Downloaded {size} bytes of audio to cache entry {key}");
+    Ok(size)
+  }
+
+  async fn remove_files(&self, keys: Vec<String>) {
+    for key in keys {
+      trace!("Evicting audio cache entry {key}");
+      fs_err::tokio::remove_file(self.entry_path(&key)).await
+        .context("failed to remove audio cache entry").log_error();
+    };
+  }
+
+  fn entry_path(&self, key: &str) -> PathBuf {
+    Path::join(&self.path, key)
+  }
+}
+
+impl AudioCacheState {
+  /// Removes the least recently used entries until the total size is within `max_size`,
+  /// returning the keys of the removed entries.
+  fn evict(&mut self, max_size: u64, keep: Option<&str>) -> Vec<String> {
+    let mut candidates = self.entries.iter()
+      .filter(|&(key, _)| Some(key.as_str()) != keep)
+      .map(|(key, entry)| (entry.last_used, key.clone()))
+      .collect::<Vec<(SystemTime, String)>>();
+    candidates.sort_unstable();
+
+    let mut evicted = Vec::new();
+    for (_, key) in candidates {
+      if self.total_size <= max_size { break };
+      if let Some(entry) = self.entries.remove(&key) {
+        self.total_size -= entry.size;
+        evicted.push(key);
+      };
+    };
+
+    evicted
+  }
+}
+
+const MEGABYTE: u64 = 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum AudioCacheError {
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+  #[error(transparent)]
+  Http(#[from] reqwest::Error),
+  #[error("audio is too large to be cached")]
+  TooLarge
+}
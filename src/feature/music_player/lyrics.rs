@@ -0,0 +1,54 @@
+use crate::prelude::*;
+use crate::data::Core;
+use crate::utils::lyrics::LyricLine;
+
+use serenity::builder::CreateMessage;
+use serenity::model::id::ChannelId;
+use songbird::tracks::{PlayMode, TrackHandle};
+
+use std::time::Duration;
+
+
+
+/// The longest the task will sleep before checking the track position again,
+/// so that seeking or pausing doesn't throw the lyrics out of sync for long.
+const MAX_SLEEP: Duration = Duration::from_secs(2);
+/// Lines that were due further back than this are skipped rather than posted late.
+const MAX_LATENESS: Duration = Duration::from_secs(1);
+
+/// Posts each line of the lyrics in the given channel as the track reaches it,
+/// returning once every line has been posted or the track has ended.
+pub async fn post_synced_lyrics(core: Core, channel_id: ChannelId, track: TrackHandle, lines: Vec<LyricLine>) {
+  let mut lines = lines.into_iter()
+    .filter(|line| !line.text.is_empty())
+    .peekable();
+
+  while let Some(line) = lines.peek() {
+    let Ok(state) = track.get_info().await else { break };
+    if state.position > line.time + MAX_LATENESS {
+      // the lyrics were requested partway through, or the track was seeked past this line
+      lines.next();
+      continue;
+    };
+
+    let wait = match state.playing {
+      PlayMode::Play => line.time.saturating_sub(state.position),
+      PlayMode::Pause => MAX_SLEEP,
+      _ => break
+    };
+
+    if wait.is_zero() {
+      let message = CreateMessage::new()
+        .allowed_mentions(Default::default())
+        .content(format!("\u{266A} {}", line.text));
+      if let Err(err) = channel_id.send_message(&core, message).await {
+        error!("failed to post synced lyrics: {err}");
+        break;
+      };
+
+      lines.next();
+    } else {
+      tokio::time::sleep(wait.min(MAX_SLEEP)).await;
+    };
+  };
+}
@@ -0,0 +1,54 @@
+use crate::prelude::*;
+use crate::data::{Core, PersistGuilds};
+
+use serenity::model::id::UserId;
+
+
+
+/// What was deleted by [`delete_user_data`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeletedUserData {
+  pub chatbot_log_entries: usize,
+  /// The number of messages the markov chain learned from in guilds that opted in.
+  pub markov_messages: usize,
+  /// The number of guilds that had connect four stats, challenges or games for the user.
+  pub connect_four_guilds: usize
+}
+
+/// Deletes everything stored about a user: their chatbot log entries, what the markov chain learned from those and from their messages in guilds that opted in,
+/// emoji stats, connect four stats and games, roll macros, character sheets and roll history, initiative combatants and encounters, and whether they have seen the chatbot notice.
+pub async fn delete_user_data(core: &Core, user_id: UserId) -> MelodyResult<DeletedUserData> {
+  let removed_entries = core.state.cleverbot_logger.purge_user(user_id)
+    .await.context("failed to purge user from cleverbot log")?;
+  core.state.chatbot.markov().forget_exchanges(&removed_entries).await;
+  let markov_messages = core.state.chatbot.markov().forget_user(user_id).await;
+  core.state.chatbot.markov().save().await?;
+
+  let mut connect_four_guilds = 0;
+  for (guild_id, ..) in PersistGuilds::get_all(&core.state.persist_guilds).await {
+    let removed = core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+      persist_guild.emoji_stats.forget_user(user_id);
+      persist_guild.dice_players.remove(&user_id);
+      persist_guild.initiative_trackers.retain(|_, tracker| tracker.gm != user_id);
+      for tracker in persist_guild.initiative_trackers.values_mut() {
+        tracker.remove_user(user_id);
+      };
+
+      for roll_history in persist_guild.roll_history.values_mut() {
+        roll_history.remove_user(user_id);
+      };
+
+      persist_guild.roll_history.retain(|_, roll_history| !roll_history.is_empty());
+      Ok(persist_guild.connect_four.remove_player(user_id))
+    }).await?;
+    if removed { connect_four_guilds += 1 };
+  };
+
+  core.operate_persist_commit(async |persist| {
+    persist.cleverbot_notified_users.remove(&user_id);
+    Ok(())
+  }).await?;
+
+  info!("Deleted data for user {user_id}: {} chatbot log entries, {markov_messages} markov messages, connect four data in {connect_four_guilds} guilds", removed_entries.len());
+  Ok(DeletedUserData { chatbot_log_entries: removed_entries.len(), markov_messages, connect_four_guilds })
+}
@@ -3,6 +3,7 @@ mod input;
 
 use crate::prelude::*;
 use crate::data::*;
+use crate::feature::emoji_stats::{message_emojis, EmojiKey};
 pub use self::input::InputAgent;
 
 use melody_flag::Flag;
@@ -11,11 +12,13 @@ use reqwest::Client as HttpClient;
 use serenity::cache::Cache;
 use serenity::client::Client;
 use serenity::gateway::ShardManager;
-use serenity::model::channel::{Reaction, ReactionType, Message};
+use serenity::model::application::Interaction;
+use serenity::model::channel::{Reaction, Message};
 use serenity::model::gateway::Ready;
 use serenity::model::guild::{Guild, UnavailableGuild};
 use serenity::model::guild::Member;
 use serenity::model::id::{GuildId, UserId, RoleId};
+use serenity::model::voice::VoiceState;
 use songbird::{SerenityInit, Config as SongbirdConfig};
 use term_stratum::StratumEvent;
 use tokio::sync::mpsc::UnboundedReceiver as MpscReceiver;
@@ -72,6 +75,7 @@ pub async fn launch(event_receiver: MpscReceiver<StratumEvent>) -> MelodyResult
   client.start().await.context("failed to start client")?;
 
   core.abort().await;
+  core.state.chatbot.save().await.log_error();
   events_task.abort();
   client.data.write().await.clear();
 
@@ -115,6 +119,23 @@ impl MelodyHandler<State, MelodyError> for Handler {
       tasks.cycle_activities.get_or_insert_with(|| {
         tokio::spawn(cycle_activity_task(core.clone()))
       });
+
+      // Spawn the task for leaving inactive voice channels unless it's already been spawned
+      if let Some(music_player) = core.state.music_player.clone() {
+        tasks.music_player_inactivity.get_or_insert_with(|| {
+          tokio::spawn(music_player.inactivity_task(core.clone()))
+        });
+      };
+
+      // Spawn the task for deleting old chatbot log entries unless it's already been spawned
+      tasks.cleverbot_log_prune.get_or_insert_with(|| {
+        tokio::spawn(cleverbot_log_prune_task(core.clone()))
+      });
+
+      // Spawn the task for expiring and saving chatbot state unless it's already been spawned
+      tasks.chatbot_save.get_or_insert_with(|| {
+        tokio::spawn(chatbot_save_task(core.clone()))
+      });
     }).await;
   }
 
@@ -148,43 +169,92 @@ impl MelodyHandler<State, MelodyError> for Handler {
     let core = Core::from(ctx);
 
     let me = core.current_user_id();
-    if message.author.id == me || message.content.is_empty() { return };
+    if message.author.id == me { return };
 
     if !message.author.bot {
       if let Some(guild_id) = message.guild_id {
-        let emojis = crate::utils::parse_emojis(&message.content);
-        core.operate_persist_guild_commit(guild_id, async |persist_guild| {
-          // don't be greedy
-          if let Some(&emoji) = emojis.choose_default() {
-            persist_guild.emoji_stats.increment_emoji_uses(emoji, message.author.id);
-          };
-          Ok(())
-        }).await.log_error();
+        let emojis = message_emojis(&message);
+        if !emojis.is_empty() {
+          core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+            persist_guild.emoji_stats.add_message(message.id, message.author.id, emojis);
+            Ok(())
+          }).await.log_error();
+        };
+
+        observe_message_chain(&core, guild_id, &message).await.log_error();
       };
+    };
+
+    // everything past this point only deals with text
+    if message.content.is_empty() { return };
 
-      if core.state.message_chains.operate_mut(async |message_chains| message_chains.should_contribute(&message)).await {
-        message.channel_id.say(&core, &message.content).await.context("failed to send message").log_error();
+    if !message.author.bot {
+      if let Some(guild_id) = message.guild_id {
+        let markov_learning = core.operate_persist_guild(guild_id, async |persist_guild| {
+          Ok(persist_guild.chatbot.markov_learning)
+        }).await.log_error().unwrap_or(false);
+        let logging_allowed = core.operate_persist(async |persist| {
+          persist.chatbot_logging_allowed(message.author.id)
+        }).await;
+        if markov_learning && logging_allowed {
+          let content = clean_message_for_cleverbot(&core, &message.content, me);
+          core.state.chatbot.markov().learn(message.author.id, &content).await;
+        };
       };
     };
 
-    if is_mentioning_user(&message, me) {
+    let mentioned = is_mentioning_user(&message, me);
+    let backend = crate::feature::cleverbot::get_reply_backend(&core, &message, mentioned).await.log_error().flatten();
+    if let Some(backend) = backend {
+      if let Err(wait) = core.state.chatbot.try_acquire(message.author.id, message.channel_id) {
+        info!("Chatbot ratelimited for user {} in channel {}", message.author.id, message.channel_id);
+        if mentioned {
+          let response = format!("You're talking to the chatbot too quickly, please wait {:.1} seconds", wait.as_secs_f64());
+          message.reply(&core, response).await
+            .context("failed to send chatbot ratelimit message")
+            .log_error();
+        };
+
+        return;
+      };
+
       let content = clean_message_for_cleverbot(&core, &message.content, me);
 
-      info!("Sending message to cleverbot: {content:?}");
+      // replying to one of the bot's messages continues the conversation that message was part of
+      let replied_to = message.referenced_message.as_ref()
+        .filter(|referenced_message| referenced_message.author.id == me)
+        .map(|referenced_message| referenced_message.id);
+      let conversation = core.state.chatbot.conversation(message.channel_id, replied_to).await;
+
+      info!("Sending message to chatbot ({backend:?}): {content:?}");
       let typing = message.channel_id.start_typing(&core.http);
-      match core.state.cleverbot.send(message.channel_id, &content).await {
-        Ok(reply) => {
-          info!("Recieved reply from cleverbot: {reply:?}");
+      match core.state.chatbot.send(backend, conversation, &content).await {
+        Ok((backend, reply)) => {
+          info!("Recieved reply from chatbot ({backend:?}): {reply:?}");
           let reply = crate::utils::message_content_human_readable(&core, &reply);
-          crate::feature::cleverbot::send_reply(&core, &message, &reply).await.log_error();
-          core.state.cleverbot_logger.clone()
-            .log(message.channel_id, content, reply).await.log_error();
+          if let Some(reply_message) = crate::feature::cleverbot::send_reply(&core, &message, backend, &reply).await.log_error() {
+            core.state.chatbot.record_reply(reply_message.id, conversation).await;
+          };
+          let logging_allowed = core.operate_persist(async |persist| {
+            persist.chatbot_logging_allowed(message.author.id)
+          }).await;
+          if logging_allowed {
+            // only learn what was logged, so that it can be found and forgotten again
+            let entry = core.state.cleverbot_logger.clone()
+              .log(message.channel_id, message.author.id, content, reply).await.log_error();
+            if let Some(entry) = entry {
+              core.state.chatbot.markov().learn_exchange(&entry).await;
+            };
+          };
         },
         Err(error) => {
-          error!("Unable to get reply from cleverbot: {error}");
-          message.reply(&core, "There was an error getting a reply from cleverbot").await
-            .context("failed to send cleverbot failure message")
-            .log_error();
+          error!("Unable to get reply from chatbot ({backend:?}): {error}");
+          // stay quiet if nobody asked for a reply
+          if mentioned {
+            message.reply(&core, "There was an error getting a reply from the chatbot").await
+              .context("failed to send cleverbot failure message")
+              .log_error();
+          };
         }
       };
 
@@ -201,20 +271,42 @@ impl MelodyHandler<State, MelodyError> for Handler {
   async fn reaction_add(&self, ctx: MelodyHandlerContext<'_>, reaction: Reaction) {
     let core = Core::from(ctx);
 
-    if let (Some(guild_id), Some(user_id), ReactionType::Custom { id: emoji_id, .. }) = (reaction.guild_id, reaction.user_id, reaction.emoji) {
+    let emoji = EmojiKey::from_reaction(&reaction.emoji);
+    if let (Some(guild_id), Some(user_id), Some(emoji)) = (reaction.guild_id, reaction.user_id, emoji) {
       core.operate_persist_guild_commit(guild_id, async |persist_guild| {
-        persist_guild.emoji_stats.increment_emoji_uses(emoji_id, user_id);
+        persist_guild.emoji_stats.add_reaction(reaction.channel_id, reaction.message_id, user_id, emoji);
         Ok(())
       }).await.log_error();
     };
   }
 
+  async fn interaction_create(&self, ctx: MelodyHandlerContext<'_>, interaction: Interaction) {
+    let core = Core::from(ctx);
+
+    // commands are dispatched by the framework, only message components are handled here
+    if let Interaction::Component(component) = interaction {
+      crate::feature::dice_roll::handle_initiative_component(&core, &component).await.log_error();
+    };
+  }
+
+  async fn voice_state_update(&self, ctx: MelodyHandlerContext<'_>, old: Option<VoiceState>, new: VoiceState) {
+    let core = Core::from(ctx);
+
+    if let (Some(music_player), Some(guild_id)) = (&core.state.music_player, new.guild_id) {
+      // only channel changes can affect whether the bot has been left alone
+      if old.as_ref().map_or(true, |old| old.channel_id != new.channel_id) {
+        music_player.update_alone(&core, guild_id).await;
+      };
+    };
+  }
+
   async fn reaction_remove(&self, ctx: MelodyHandlerContext<'_>, reaction: Reaction) {
     let core = Core::from(ctx);
 
-    if let (Some(guild_id), Some(user_id), ReactionType::Custom { id: emoji_id, .. }) = (reaction.guild_id, reaction.user_id, reaction.emoji) {
+    let emoji = EmojiKey::from_reaction(&reaction.emoji);
+    if let (Some(guild_id), Some(user_id), Some(emoji)) = (reaction.guild_id, reaction.user_id, emoji) {
       core.operate_persist_guild_commit(guild_id, async |persist_guild| {
-        persist_guild.emoji_stats.decrement_emoji_uses(emoji_id, user_id);
+        persist_guild.emoji_stats.remove_reaction(reaction.channel_id, reaction.message_id, user_id, emoji);
         Ok(())
       }).await.log_error();
     };
@@ -233,6 +325,30 @@ impl MelodyHandlerFull<State, MelodyError> for Handler {
   }
 }
 
+/// Keeps track of message chains in a guild, joining in on them and recording the longest.
+async fn observe_message_chain(core: &Core, guild_id: GuildId, message: &Message) -> MelodyResult {
+  let settings = core.operate_persist_guild(guild_id, async |persist_guild| {
+    Ok(persist_guild.message_chains.clone())
+  }).await?;
+
+  let Some(observation) = core.state.message_chains.operate_mut(async |message_chains| {
+    message_chains.observe(message, &settings)
+  }).await else { return Ok(()) };
+
+  if observation.contribute {
+    crate::feature::message_chains::contribute(core, message).await?;
+  };
+
+  if settings.is_longest_chain(observation.len) {
+    core.operate_persist_guild_commit(guild_id, async |persist_guild| {
+      persist_guild.message_chains.record_chain(message, observation.len);
+      Ok(())
+    }).await?;
+  };
+
+  Ok(())
+}
+
 /// Gets the remaining content of a message if it either replies to the
 /// given user, or begins with a mention of the given user.
 fn is_mentioning_user(message: &Message, who: UserId) -> bool {
@@ -320,6 +436,38 @@ async fn cycle_activity_task(core: Core) {
   };
 }
 
+async fn chatbot_save_task(core: Core) {
+  const CHATBOT_SAVE_TIME: Duration = Duration::from_secs(300);
+
+  let mut interval = tokio::time::interval(CHATBOT_SAVE_TIME);
+  interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+  loop {
+    interval.tick().await;
+    core.state.chatbot.expire().await;
+    core.state.chatbot.save().await.log_error();
+  };
+}
+
+async fn cleverbot_log_prune_task(core: Core) {
+  const PRUNE_TIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+  let Some(retention_days) = core.operate_config(async |config| config.chatbot.log_retention_days).await else { return };
+
+  let mut interval = tokio::time::interval(PRUNE_TIME);
+  interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+  loop {
+    interval.tick().await;
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(retention_days.into());
+    let Some(removed) = core.state.cleverbot_logger.prune(cutoff).await
+      .context("failed to prune cleverbot log").log_error() else { continue };
+    if removed > 0 {
+      info!("Pruned {removed} cleverbot log entries older than {retention_days} days");
+    };
+  };
+}
+
 fn framework_error_friendly_name(framework_error: MelodyFrameworkError) -> String {
   match framework_error {
     MelodyFrameworkError::Command(..) => {
@@ -1,11 +1,15 @@
 use crate::prelude::*;
 use crate::data::Core;
 
+use chrono::{DateTime, NaiveDate, Utc};
+use cleverbot_logs::{EntryFilter, ExportFormat};
 use futures::future::BoxFuture;
 use log::Level;
 use melody_commander::{Command, Commands, Parsed, resolve_args};
 use serenity::model::id::GuildId;
 
+use std::path::PathBuf;
+
 macro_rules! command_function {
   ($function:ident(..)) => (
     |agent, remaining_args| Box::pin(async move {
@@ -140,6 +144,25 @@ const COMMANDS: Commands<CommandFunction, CommandHelp> = &[
       }
     ]
   },
+  command!{
+    name: "cleverbot-logs",
+    description: "Command group for reading the chatbot conversation log",
+    usage: "cleverbot-logs <search|export>",
+    group: [
+      command!{
+        name: "search",
+        description: "Shows the most recent log entries matching the given filters (thread=<u64>, since=<date>, until=<date>, contains=<text>)",
+        usage: "cleverbot-logs search [filter]...",
+        target: command_cleverbot_logs_search(..)
+      },
+      command!{
+        name: "export",
+        description: "Exports log entries matching the given filters (see 'search') to a JSONL or CSV file",
+        usage: "cleverbot-logs export <format: jsonl|csv> <path: string> [filter]...",
+        target: command_cleverbot_logs_export(..)
+      }
+    ]
+  },
   command!{
     name: "update-yt-dlp",
     description: "Updates the version of yt-dlp used by the bot",
@@ -256,6 +279,47 @@ impl InputAgent {
     Ok(())
   }
 
+  async fn command_cleverbot_logs_search(&mut self, args: Box<[String]>) -> MelodyResult {
+    const LIMIT: usize = 10;
+
+    let filter = match parse_entry_filter(&args) {
+      Ok(filter) => filter,
+      Err(message) => {
+        self.output.error(message);
+        return Ok(());
+      }
+    };
+
+    let (count, entries) = self.core.state.cleverbot_logger.search(filter, LIMIT)
+      .await.context("failed to read cleverbot log")?;
+    self.output.info(format!("Found {count} matching entries, showing the last {}", entries.len()));
+    for entry in entries {
+      self.output.info(format!(
+        "[{}] (thread {}) {:?} -> {:?}",
+        entry.time.format("%Y-%m-%d %H:%M:%S"), entry.thread, entry.message, entry.response
+      ));
+    };
+
+    Ok(())
+  }
+
+  async fn command_cleverbot_logs_export(&mut self, args: Box<[String]>) -> MelodyResult {
+    let (format, path) = resolve_args::<(Parsed<ExportFormat>, Parsed<PathBuf>)>(&args)?;
+    let filter = match parse_entry_filter(args.get(2..).unwrap_or_default()) {
+      Ok(filter) => filter,
+      Err(message) => {
+        self.output.error(message);
+        return Ok(());
+      }
+    };
+
+    let count = self.core.state.cleverbot_logger.export(filter, format, path.clone())
+      .await.context("failed to export cleverbot log")?;
+    self.output.info(format!("Exported {count} entries to {}", path.display()));
+
+    Ok(())
+  }
+
   async fn command_reload_activities(&mut self) -> MelodyResult {
     self.core.reload_activities().await?;
     self.output.info("Reloaded data/activities.json");
@@ -386,6 +450,29 @@ impl InputAgentOutput {
   }
 }
 
+/// Parses filters of the form `key=value`, where the key is one of `thread`, `since`, `until` or `contains`.
+fn parse_entry_filter(args: &[String]) -> Result<EntryFilter, String> {
+  args.iter().try_fold(EntryFilter::new(), |filter, arg| {
+    let (key, value) = arg.split_once('=')
+      .ok_or_else(|| format!("Invalid filter {arg:?}, expected key=value"))?;
+    match key {
+      "thread" => value.parse::<u64>().map(|thread| filter.thread(thread))
+        .map_err(|_| format!("Invalid thread id {value:?}")),
+      "since" => parse_date(value).map(|since| filter.since(since))
+        .ok_or_else(|| format!("Invalid date {value:?}, expected YYYY-MM-DD or an RFC 3339 timestamp")),
+      "until" => parse_date(value).map(|until| filter.until(until))
+        .ok_or_else(|| format!("Invalid date {value:?}, expected YYYY-MM-DD or an RFC 3339 timestamp")),
+      "contains" => Ok(filter.contains(value)),
+      _ => Err(format!("Unknown filter {key:?}, expected one of thread, since, until or contains"))
+    }
+  })
+}
+
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+  DateTime::parse_from_rfc3339(value).map(|datetime| datetime.to_utc()).ok()
+    .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0).map(|datetime| datetime.and_utc()))
+}
+
 async fn get_or_insert_with_async<T>(option: &mut Option<T>, f: impl AsyncFnOnce() -> T) -> &mut T {
   // stupid hack because borrow checker is stupid
   if option.is_some() {
@@ -17,12 +17,15 @@ extern crate cleverbot;
 extern crate cleverbot_logs;
 extern crate const_random;
 extern crate defy;
+extern crate emojis;
 extern crate feed_machine;
 extern crate fern;
 extern crate float_ord;
+extern crate fontdue;
 extern crate fs_err;
 extern crate futures;
 extern crate ids;
+extern crate image;
 extern crate itertools;
 #[macro_use]
 extern crate log;
@@ -30,8 +33,10 @@ extern crate melody_commander;
 extern crate melody_connect_four;
 extern crate melody_flag;
 extern crate melody_framework;
+extern crate melody_markov;
 extern crate melody_random;
 extern crate melody_ratelimiter;
+extern crate melody_timer;
 extern crate poise;
 extern crate rand;
 extern crate regex;
@@ -39,6 +44,7 @@ extern crate reqwest;
 #[macro_use]
 extern crate serde;
 extern crate serenity;
+extern crate sha2;
 extern crate singlefile;
 extern crate singlefile_formats;
 extern crate songbird;
@@ -48,6 +54,7 @@ extern crate term_stratum;
 extern crate thiserror;
 extern crate tokio;
 extern crate tracing;
+extern crate unicode_segmentation;
 extern crate uord;
 extern crate url;
 extern crate yggdrasil;
@@ -1,3 +1,4 @@
+pub mod lyrics;
 pub mod youtube;
 
 use crate::prelude::*;
@@ -12,10 +13,12 @@ use serenity::cache::Cache;
 use serenity::model::id::{EmojiId, RoleId, UserId};
 use serenity::utils::{ContentSafeOptions, content_safe};
 use tokio::sync::{Mutex, RwLock};
-use url::Url;
+use unicode_segmentation::UnicodeSegmentation;
+use url::{Host, Url};
 
 use std::borrow::Cow;
 use std::fmt;
+use std::net::IpAddr;
 use std::ops::Deref;
 use std::sync::{Arc, OnceLock};
 
@@ -119,6 +122,49 @@ pub fn parse_emojis(message: &str) -> Vec<EmojiId> {
     .collect::<Vec<EmojiId>>()
 }
 
+/// Finds the Unicode emojis in a message, one for each grapheme, so that emojis made of
+/// several codepoints (like flags, keycaps and skin tones) are each found as a whole.
+pub fn parse_unicode_emojis(message: &str) -> Vec<&'static emojis::Emoji> {
+  message.graphemes(true)
+    .filter(|grapheme| !grapheme.is_ascii())
+    .filter_map(emojis::get)
+    .collect::<Vec<&'static emojis::Emoji>>()
+}
+
+/// Whether every address a URL's host resolves to is public, so that users can't have the bot
+/// make requests to itself or to its own network, like to a cloud provider's metadata service.
+pub async fn is_public_url(url: &Url) -> bool {
+  let port = url.port_or_known_default().unwrap_or(80);
+  match url.host() {
+    Some(Host::Ipv4(ip)) => is_public_ip(IpAddr::V4(ip)),
+    Some(Host::Ipv6(ip)) => is_public_ip(IpAddr::V6(ip)),
+    Some(Host::Domain(domain)) => match tokio::net::lookup_host((domain, port)).await {
+      Ok(addrs) => {
+        let addrs = addrs.map(|addr| addr.ip()).collect::<Vec<IpAddr>>();
+        !addrs.is_empty() && addrs.into_iter().all(is_public_ip)
+      },
+      Err(..) => false
+    },
+    None => false
+  }
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+  match ip {
+    IpAddr::V4(ip) => {
+      // 100.64.0.0/10 is shared address space for carrier-grade NAT
+      let is_shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0b1100_0000) == 64;
+      !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified()
+        || ip.is_broadcast() || ip.is_documentation() || ip.is_multicast() || is_shared)
+    },
+    IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+      Some(ip) => is_public_ip(IpAddr::V4(ip)),
+      None => !(ip.is_loopback() || ip.is_unspecified() || ip.is_unique_local()
+        || ip.is_unicast_link_local() || ip.is_multicast())
+    }
+  }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Blockify<S>(pub S);
 
@@ -306,3 +352,19 @@ impl<T> OperateMut<T> for RateLimiter<T> {
     operation(&mut *guard).await
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn internal_addresses_are_not_public() {
+    for ip in ["127.0.0.1", "10.1.2.3", "192.168.0.1", "172.16.5.5", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+      assert!(!is_public_ip(ip.parse().unwrap()), "{ip}");
+    };
+
+    for ip in ["1.1.1.1", "93.184.216.34", "2606:4700:4700::1111"] {
+      assert!(is_public_ip(ip.parse().unwrap()), "{ip}");
+    };
+  }
+}
@@ -0,0 +1,319 @@
+//! A client for LRCLIB-compatible lyrics providers.
+//!
+//! See <https://lrclib.net/docs> for the API that this expects.
+
+use crate::prelude::*;
+use crate::utils::youtube::VideoInfo;
+
+use reqwest::{Client as HttpClient, StatusCode};
+use reqwest::header::{HeaderValue, USER_AGENT};
+use serde::de::DeserializeOwned;
+use singlefile_formats::data::json_serde::original as serde_json;
+use url::Url;
+
+use std::time::Duration;
+
+
+
+const DEFAULT_USER_AGENT: &str = concat!("Melody/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone)]
+pub struct LyricsClient {
+  http_client: HttpClient,
+  base_url: Url
+}
+
+impl LyricsClient {
+  pub fn new(http_client: HttpClient, base_url: Url) -> Self {
+    LyricsClient { http_client, base_url }
+  }
+
+  /// Finds the lyrics that best match the query, returning `None` if the provider has none.
+  ///
+  /// An exact lookup is tried first, falling back to a search with a cleaned-up title,
+  /// since video titles often include things like `(Official Video)`.
+  pub async fn find(&self, query: &LyricsQuery) -> Result<Option<Lyrics>, LyricsError> {
+    if let Some(artist) = &query.artist {
+      let mut params = vec![("track_name", query.track.clone()), ("artist_name", artist.clone())];
+      if let Some(album) = &query.album {
+        params.push(("album_name", album.clone()));
+      };
+
+      if let Some(duration) = query.duration {
+        params.push(("duration", duration.as_secs().to_string()));
+      };
+
+      if let Some(lyrics) = self.request::<Lyrics>("get", &params).await? {
+        return Ok(Some(lyrics));
+      };
+    };
+
+    let search_query = match &query.artist {
+      Some(artist) => format!("{artist} {}", clean_title(&query.track)),
+      None => clean_title(&query.track)
+    };
+
+    let results = self.request::<Vec<Lyrics>>("search", &[("q", search_query)]).await?;
+    Ok(results.and_then(|results| pick_closest(results, query.duration)))
+  }
+
+  async fn request<T: DeserializeOwned>(&self, endpoint: &str, params: &[(&str, String)]) -> Result<Option<T>, LyricsError> {
+    let url = self.base_url.join(endpoint).map_err(LyricsError::Url)?;
+    let response = self.http_client.get(url)
+      .header(USER_AGENT, HeaderValue::from_static(DEFAULT_USER_AGENT))
+      .query(params)
+      .send().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+      return Ok(None);
+    };
+
+    let body = response.error_for_status()?.bytes().await?;
+    serde_json::from_slice::<T>(&body).map(Some).map_err(LyricsError::Json)
+  }
+}
+
+#[derive(Debug, Error)]
+pub enum LyricsError {
+  #[error(transparent)]
+  Http(#[from] reqwest::Error),
+  #[error(transparent)]
+  Url(url::ParseError),
+  #[error(transparent)]
+  Json(serde_json::Error)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LyricsQuery {
+  pub track: String,
+  pub artist: Option<String>,
+  pub album: Option<String>,
+  pub duration: Option<Duration>
+}
+
+impl LyricsQuery {
+  pub fn from_video_info(video_info: &VideoInfo) -> Self {
+    LyricsQuery {
+      track: video_info.track.clone().unwrap_or_else(|| video_info.title.clone()),
+      artist: video_info.artist.clone()
+        .or_else(|| video_info.channel.clone())
+        .or_else(|| video_info.uploader.clone())
+        .map(|artist| artist.trim_end_matches(" - Topic").to_owned()),
+      album: video_info.album.clone(),
+      duration: video_info.duration.map(Duration::from_secs_f64)
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lyrics {
+  pub id: u64,
+  pub track_name: String,
+  pub artist_name: String,
+  pub album_name: Option<String>,
+  pub duration: Option<f64>,
+  #[serde(default)]
+  pub instrumental: bool,
+  pub plain_lyrics: Option<String>,
+  pub synced_lyrics: Option<String>
+}
+
+impl Lyrics {
+  /// Parses the synced lyrics, if there are any.
+  pub fn synced_lines(&self) -> Option<Vec<LyricLine>> {
+    self.synced_lyrics.as_deref()
+      .map(parse_lrc)
+      .filter(|lines| !lines.is_empty())
+  }
+
+  /// Plain lyrics, falling back to synced lyrics with their timestamps removed.
+  pub fn plain_text(&self) -> Option<String> {
+    self.plain_lyrics.clone()
+      .filter(|plain_lyrics| !plain_lyrics.trim().is_empty())
+      .or_else(|| self.synced_lines().map(|lines| lines.into_iter().map(|line| line.text).join("\n")))
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LyricLine {
+  pub time: Duration,
+  pub text: String
+}
+
+/// Parses LRC formatted lyrics, where each line is prefixed with one or more `[mm:ss.xx]` timestamps.
+/// Lines without timestamps, such as `[ar: Artist]` metadata tags, are ignored.
+pub fn parse_lrc(lrc: &str) -> Vec<LyricLine> {
+  let mut lines = Vec::new();
+  for line in lrc.lines() {
+    let mut rest = line.trim();
+    let mut times = Vec::new();
+    while let Some((tag, after)) = rest.strip_prefix('[').and_then(|s| s.split_once(']')) {
+      let Some(time) = parse_lrc_timestamp(tag) else { break };
+      times.push(time);
+      rest = after;
+    };
+
+    let text = rest.trim();
+    lines.extend(times.into_iter().map(|time| LyricLine { time, text: text.to_owned() }));
+  };
+
+  lines.sort_by_key(|line| line.time);
+  lines
+}
+
+fn parse_lrc_timestamp(tag: &str) -> Option<Duration> {
+  let (minutes, seconds) = tag.trim().split_once(':')?;
+  let minutes = minutes.parse::<u64>().ok()?;
+  let seconds = seconds.parse::<f64>().ok().filter(|seconds| seconds.is_finite() && *seconds >= 0.0)?;
+  Some(Duration::from_secs(minutes * 60) + Duration::from_secs_f64(seconds))
+}
+
+/// Removes bracketed parts of a title, like `(Official Music Video)` or `[HD]`.
+fn clean_title(title: &str) -> String {
+  let mut depth = 0usize;
+  let cleaned = title.chars()
+    .filter(|&ch| match ch {
+      '(' | '[' => { depth += 1; false },
+      ')' | ']' => { depth = depth.saturating_sub(1); false },
+      _ => depth == 0
+    })
+    .collect::<String>();
+  cleaned.split_whitespace().join(" ")
+}
+
+/// Picks the result with the duration closest to the given one, or the first result if no duration is known.
+fn pick_closest(results: Vec<Lyrics>, duration: Option<Duration>) -> Option<Lyrics> {
+  match duration {
+    Some(duration) => results.into_iter().min_by(|a, b| {
+      let difference = |lyrics: &Lyrics| lyrics.duration.map_or(f64::INFINITY, |d| (d - duration.as_secs_f64()).abs());
+      difference(a).total_cmp(&difference(b))
+    }),
+    None => results.into_iter().next()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+  use tokio::net::TcpListener;
+
+  const RECORD: &str = r#"{
+    "id": 3396226,
+    "trackName": "I Want to Live",
+    "artistName": "Borislav Slavov",
+    "albumName": "Baldur's Gate 3 (Original Game Soundtrack)",
+    "duration": 233,
+    "instrumental": false,
+    "plainLyrics": "I feel your breath upon my neck\nThe clock won't stop",
+    "syncedLyrics": "[00:17.12] I feel your breath upon my neck\n[00:20.41] The clock won't stop"
+  }"#;
+
+  /// Starts a stand-in for the lyrics provider on a random local port, which responds
+  /// to each request with whatever `route` returns for the request's path and query.
+  async fn stand_in_server(route: fn(&str) -> (u16, String)) -> Url {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+      loop {
+        let Ok((mut stream, _)) = listener.accept().await else { break };
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+          match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => request.extend_from_slice(&buffer[..n])
+          };
+        };
+
+        let request = String::from_utf8_lossy(&request);
+        let target = request.split_whitespace().nth(1).unwrap_or("/");
+        let (status, body) = route(target);
+        let response = format!(
+          "HTTP/1.1 {status} Status\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+          body.len()
+        );
+
+        let _ = stream.write_all(response.as_bytes()).await;
+      };
+    });
+
+    Url::parse(&format!("http://{address}/api/")).unwrap()
+  }
+
+  fn query() -> LyricsQuery {
+    LyricsQuery {
+      track: "I Want to Live (Official Audio)".to_owned(),
+      artist: Some("Borislav Slavov".to_owned()),
+      album: None,
+      duration: Some(Duration::from_secs(233))
+    }
+  }
+
+  #[tokio::test]
+  async fn find_exact() {
+    let base_url = stand_in_server(|target| match target.starts_with("/api/get?") {
+      true => (200, RECORD.to_owned()),
+      false => (500, String::new())
+    }).await;
+
+    let client = LyricsClient::new(HttpClient::new(), base_url);
+    let lyrics = client.find(&query()).await.unwrap().unwrap();
+    assert_eq!(lyrics.id, 3396226);
+    assert_eq!(lyrics.synced_lines().unwrap(), vec![
+      LyricLine { time: Duration::from_millis(17120), text: "I feel your breath upon my neck".to_owned() },
+      LyricLine { time: Duration::from_millis(20410), text: "The clock won't stop".to_owned() }
+    ]);
+  }
+
+  #[tokio::test]
+  async fn find_falls_back_to_search() {
+    let base_url = stand_in_server(|target| {
+      if target.starts_with("/api/get?") {
+        (404, r#"{"code":404,"name":"TrackNotFound","message":"Failed to find specified track"}"#.to_owned())
+      } else if target == "/api/search?q=Borislav+Slavov+I+Want+to+Live" {
+        (200, format!("[{RECORD}]"))
+      } else {
+        (500, String::new())
+      }
+    }).await;
+
+    let client = LyricsClient::new(HttpClient::new(), base_url);
+    let lyrics = client.find(&query()).await.unwrap().unwrap();
+    assert_eq!(lyrics.track_name, "I Want to Live");
+  }
+
+  #[tokio::test]
+  async fn find_nothing() {
+    let base_url = stand_in_server(|target| match target.starts_with("/api/search?") {
+      true => (200, "[]".to_owned()),
+      false => (404, "{}".to_owned())
+    }).await;
+
+    let client = LyricsClient::new(HttpClient::new(), base_url);
+    assert_eq!(client.find(&query()).await.unwrap(), None);
+  }
+
+  #[tokio::test]
+  async fn find_server_error() {
+    let base_url = stand_in_server(|_| (500, String::new())).await;
+    let client = LyricsClient::new(HttpClient::new(), base_url);
+    assert!(matches!(client.find(&query()).await, Err(LyricsError::Http(..))));
+  }
+
+  #[test]
+  fn lrc_multiple_timestamps_and_tags() {
+    let lines = parse_lrc("[ar: Someone]\n[00:05.00][00:01.50]Chorus\n[00:03.00]\nnot a lyric");
+    assert_eq!(lines, vec![
+      LyricLine { time: Duration::from_millis(1500), text: "Chorus".to_owned() },
+      LyricLine { time: Duration::from_secs(3), text: String::new() },
+      LyricLine { time: Duration::from_secs(5), text: "Chorus".to_owned() }
+    ]);
+  }
+
+  #[test]
+  fn title_cleaning() {
+    assert_eq!(clean_title("Never Gonna Give You Up (Official Video) [4K Remaster]"), "Never Gonna Give You Up");
+  }
+}
@@ -9,6 +9,7 @@ use singlefile_formats::data::json_serde::original as serde_json;
 use songbird::input::{AuxMetadata, AudioStream, AudioStreamError, Compose, HttpRequest, Input};
 use songbird::input::core::io::MediaSource;
 use tokio::process::Command;
+use tokio::sync::Mutex;
 use url::Url;
 
 use std::fmt;
@@ -33,7 +34,7 @@ impl YtDlp {
       .map_err(|err| YtDlpError::Io(err, self.program.to_path_buf()))
       .and_then(|output| match output.status.success() {
         true => Ok(output.stdout),
-        false => Err(YtDlpError::Program(String::from_utf8_lossy(&output.stderr).into_owned()))
+        false => Err(YtDlpError::from_stderr(&output.stderr))
       })
   }
 
@@ -48,14 +49,17 @@ impl YtDlp {
       .map(|output| String::from_utf8_lossy(&output).into_owned())
   }
 
-  pub async fn get_video_info(&self, video_id: &str) -> Result<VideoInfo, YtDlpError> {
-    assert!(is_id_str(video_id, 16), "video id {video_id:?} was invalid");
-    let url = display_video_url(video_id).to_string();
-    self.run_json([url.as_str(), "-f", "ba[abr>0][vcodec=none]/best", "--no-playlist"]).await
+  /// Gets info for a single track from any URL that yt-dlp has an extractor for.
+  pub async fn get_video_info(&self, url: &str) -> Result<VideoInfo, YtDlpError> {
+    let url = parse_http_url(url).ok_or_else(|| YtDlpError::InvalidUrl(url.to_owned()))?;
+    self.run_json([url.as_str(), "-f", "ba[abr>0][vcodec=none]/ba/best", "--no-playlist"]).await
   }
 
   pub async fn get_playlist_info(&self, playlist_id: &str) -> Result<PlaylistInfo, YtDlpError> {
-    assert!(is_id_str(playlist_id, 40), "playlist id {playlist_id:?} was invalid");
+    if !is_id_str(playlist_id, 40) {
+      return Err(YtDlpError::InvalidUrl(playlist_id.to_owned()));
+    };
+
     let url = display_playlist_url(playlist_id).to_string();
     self.run_json([url.as_str(), "--compat-options", "no-youtube-unavailable-videos", "--yes-playlist"]).await
   }
@@ -67,18 +71,39 @@ pub enum YtDlpError {
   Io(std::io::Error, PathBuf),
   #[error("program error: {0}")]
   Program(String),
+  #[error("invalid url: {0}")]
+  InvalidUrl(String),
+  #[error("unsupported url: {0}")]
+  UnsupportedUrl(String),
   #[error(transparent)]
   Json(serde_json::Error)
 }
 
+impl YtDlpError {
+  fn from_stderr(stderr: &[u8]) -> Self {
+    let stderr = String::from_utf8_lossy(stderr).into_owned();
+    match stderr.find("Unsupported URL: ") {
+      Some(i) => YtDlpError::UnsupportedUrl(stderr[i + 17..].trim().to_owned()),
+      None => YtDlpError::Program(stderr)
+    }
+  }
+
+  /// Whether this error was caused by the URL rather than by yt-dlp itself,
+  /// such that it should be reported back to the user.
+  pub fn is_user_error(&self) -> bool {
+    matches!(self, YtDlpError::InvalidUrl(..) | YtDlpError::UnsupportedUrl(..))
+  }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct VideoInfo {
   pub id: String,
   pub title: String,
-  pub thumbnail: String,
-  pub description: String,
-  pub duration: f64,
+  pub thumbnail: Option<String>,
+  pub description: Option<String>,
+  pub duration: Option<f64>,
   pub webpage_url: String,
+  pub extractor: Option<String>,
   #[serde(with = "chrono::serde::ts_milliseconds_option", default)]
   pub timestamp: Option<DateTime<Utc>>,
   pub album: Option<String>,
@@ -111,6 +136,25 @@ pub struct PlaylistInfo {
 }
 
 impl VideoInfo {
+  /// A key that uniquely identifies this video across all of yt-dlp's extractors.
+  pub fn cache_key(&self) -> String {
+    cache_key(self.extractor.as_deref().unwrap_or("generic"), &self.id)
+  }
+
+  /// The headers that yt-dlp says should be sent when requesting [`VideoInfo::url`].
+  pub fn headers(&self) -> HeaderMap {
+    let mut headers = HeaderMap::default();
+    if let Some(video_headers) = &self.http_headers {
+      headers.extend(video_headers.iter().filter_map(|(k, v)| {
+        let header_name = HeaderName::from_bytes(k.as_bytes()).ok()?;
+        let header_value = HeaderValue::from_str(v).ok()?;
+        Some((header_name, header_value))
+      }));
+    };
+
+    headers
+  }
+
   pub fn into_aux_metadata(self) -> AuxMetadata {
     AuxMetadata {
       track: self.track,
@@ -119,11 +163,11 @@ impl VideoInfo {
       date: None,
       channels: Some(2),
       channel: self.channel,
-      duration: Some(Duration::from_secs_f64(self.duration)),
+      duration: self.duration.map(Duration::from_secs_f64),
       sample_rate: Some(48000),
       source_url: Some(self.webpage_url),
       title: Some(self.title),
-      thumbnail: Some(self.thumbnail),
+      thumbnail: self.thumbnail,
       ..AuxMetadata::default()
     }
   }
@@ -137,11 +181,15 @@ struct VideoInfoExtended {
 
 #[serenity::async_trait]
 impl CacheableAsync for VideoInfoExtended {
-  type Args<'a> = (&'a YtDlp, &'a str);
+  type Args<'a> = (&'a YtDlp, &'a str, Option<&'a VideoInfoStore>);
   type Error = YtDlpError;
 
-  async fn operation((yt_dlp, video_id): Self::Args<'_>) -> Result<Self, Self::Error> {
-    let video_info = yt_dlp.get_video_info(video_id).await?;
+  async fn operation((yt_dlp, url, video_info_store): Self::Args<'_>) -> Result<Self, Self::Error> {
+    let video_info = match video_info_store {
+      Some(video_info_store) => video_info_store.resolve(yt_dlp, url).await?,
+      None => yt_dlp.get_video_info(url).await?
+    };
+
     let timestamp = Utc::now();
     Ok(VideoInfoExtended { video_info, timestamp })
   }
@@ -151,26 +199,71 @@ impl CacheableAsync for VideoInfoExtended {
   }
 }
 
+/// Video info that has been resolved ahead of time, shared so that
+/// upcoming tracks can be resolved before they start playing.
+#[derive(Debug, Clone, Default)]
+pub struct VideoInfoStore {
+  entries: Arc<Mutex<HashMap<String, VideoInfoExtended>>>
+}
+
+impl VideoInfoStore {
+  /// Resolved stream URLs eventually expire, so entries are only kept for this long.
+  const LIFETIME: TimeDelta = TimeDelta::minutes(30);
+
+  pub fn new() -> Self {
+    VideoInfoStore::default()
+  }
+
+  pub async fn get(&self, url: &str) -> Option<VideoInfo> {
+    let mut entries = self.entries.lock().await;
+    let now = Utc::now();
+    entries.retain(|_, entry| now.signed_duration_since(entry.timestamp) < Self::LIFETIME);
+    entries.get(url).map(|entry| entry.video_info.clone())
+  }
+
+  /// Gets the video info for the given URL, running yt-dlp if it has not already been resolved.
+  pub async fn resolve(&self, yt_dlp: &YtDlp, url: &str) -> Result<VideoInfo, YtDlpError> {
+    if let Some(video_info) = self.get(url).await {
+      return Ok(video_info);
+    };
+
+    let video_info = yt_dlp.get_video_info(url).await?;
+    let timestamp = Utc::now();
+    self.entries.lock().await.insert(url.to_owned(), VideoInfoExtended { video_info: video_info.clone(), timestamp });
+    Ok(video_info)
+  }
+}
+
+/// A lazily-resolved audio source for any URL that yt-dlp understands.
 #[derive(Debug, Clone)]
 pub struct YtDlpSource {
   yt_dlp: YtDlp,
-  video_id: String,
+  url: String,
   http_client: HttpClient,
+  video_info_store: Option<VideoInfoStore>,
   video_info_cache: CacheAsync<VideoInfoExtended>
 }
 
 impl YtDlpSource {
-  pub fn new(yt_dlp: YtDlp, video_id: impl Into<String>, http_client: HttpClient) -> Self {
+  pub fn new(yt_dlp: YtDlp, url: impl Into<String>, http_client: HttpClient) -> Self {
     YtDlpSource {
       yt_dlp,
-      video_id: video_id.into(),
+      url: url.into(),
       http_client,
+      video_info_store: None,
       video_info_cache: CacheAsync::new()
     }
   }
 
+  /// Allows this source to use video info that was resolved ahead of time.
+  pub fn with_video_info_store(mut self, video_info_store: VideoInfoStore) -> Self {
+    self.video_info_store = Some(video_info_store);
+    self
+  }
+
   pub async fn get_video_info(&mut self) -> Result<&VideoInfo, YtDlpError> {
-    self.video_info_cache.try_get_or_init((&self.yt_dlp, &self.video_id)).await
+    let args = (&self.yt_dlp, self.url.as_str(), self.video_info_store.as_ref());
+    self.video_info_cache.try_get_or_init(args).await
       .map(|video_info_extended| &video_info_extended.video_info)
   }
 
@@ -198,20 +291,11 @@ impl Compose for YtDlpSource {
     let video_info = self.get_video_info().await
       .map_err(|err| AudioStreamError::Fail(Box::new(err)))?;
 
-    let mut headers = HeaderMap::default();
-    if let Some(video_headers) = &video_info.http_headers {
-      headers.extend(video_headers.iter().filter_map(|(k, v)| {
-        let header_name = HeaderName::from_bytes(k.as_bytes()).ok()?;
-        let header_value = HeaderValue::from_str(v).ok()?;
-        Some((header_name, header_value))
-      }));
-    };
-
     let mut req = HttpRequest {
       client: http_client,
       request: video_info.url.clone(),
       content_length: video_info.filesize,
-      headers
+      headers: video_info.headers()
     };
 
     req.create_async().await
@@ -226,6 +310,20 @@ impl Compose for YtDlpSource {
   }
 }
 
+/// Creates a key from an extractor name and video ID that is safe to use as a file name.
+pub fn cache_key(extractor: &str, id: &str) -> String {
+  format!("{extractor}-{id}").chars()
+    .map(|ch| if ch.is_ascii_alphanumeric() || ch == '_' || ch == '-' { ch } else { '_' })
+    .collect()
+}
+
+/// Parses a URL, only accepting the `http` and `https` schemes.
+pub fn parse_http_url(url: &str) -> Option<Url> {
+  let url = Url::parse(url).ok()?;
+  let ("http" | "https") = url.scheme() else { return None };
+  url.host().is_some().then_some(url)
+}
+
 fn is_id_str(s: &str, l: usize) -> bool {
   s.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-') && s.len() < l
 }